@@ -0,0 +1,340 @@
+//! Minimal FTP client, used to test the server on the loopback and to mirror remote directories.
+//!
+//! Only the passive modes and the binary type are supported.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpStream},
+    path::Path,
+};
+
+use crate::protocol::{facts, host_port, reply};
+
+/// Reply received from the server, with the text of all its lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reply {
+    pub code: u16,
+    pub message: String,
+}
+
+/// Entry of a remote directory, as described by MLSD.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+}
+
+/// What has been downloaded by [`Client::mirror`].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MirrorSummary {
+    pub directories: u64,
+    pub files: u64,
+    pub bytes: u64,
+}
+
+pub struct Client {
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Client {
+    /// Connect to the server at `address` and wait for its greeting.
+    pub fn connect(address: &str) -> Result<Client, String> {
+        let stream = match TcpStream::connect(address) {
+            Ok(s) => s,
+            Err(err) => return Err(format!("cannot connect to {address}: {err}")),
+        };
+
+        let reader = match stream.try_clone() {
+            Ok(s) => BufReader::new(s),
+            Err(err) => return Err(format!("cannot clone control connection: {err}")),
+        };
+
+        let mut client = Client {
+            writer: stream,
+            reader,
+        };
+
+        client.expect(220)?;
+
+        Ok(client)
+    }
+
+    /// Log in with `username` and `password`, the latter being only sent if the server asks
+    /// for it.
+    pub fn login(&mut self, username: &str, password: &str) -> Result<(), String> {
+        let reply = self.command(&format!("USER {username}"))?;
+
+        let reply = match reply.code {
+            331 => self.command(&format!("PASS {password}"))?,
+            _ => reply,
+        };
+
+        match reply.code {
+            230 | 202 => Ok(()),
+            _ => Err(format!("cannot log in as {username}: {}", reply.message)),
+        }
+    }
+
+    /// Send a command and read its reply, whatever its code.
+    pub fn command(&mut self, command: &str) -> Result<Reply, String> {
+        self.send(&format!("{command}\r\n"))?;
+
+        self.read_reply()
+    }
+
+    /// Send `text` as is, without waiting for a reply, to queue several commands or to send one in
+    /// pieces.
+    pub fn send(&mut self, text: &str) -> Result<(), String> {
+        match self.writer.write_all(text.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(format!("cannot send command: {err}")),
+        }
+    }
+
+    pub fn cwd(&mut self, path: &str) -> Result<(), String> {
+        self.command_expecting(&format!("CWD {path}"), 250)?;
+
+        Ok(())
+    }
+
+    /// Get the lines sent by LIST for `path`, or the working directory.
+    pub fn list(&mut self, path: Option<&str>) -> Result<Vec<String>, String> {
+        let command = match path {
+            Some(p) => format!("LIST {p}"),
+            None => "LIST".to_string(),
+        };
+
+        let mut content = Vec::new();
+        self.transfer(&command, |data| data.read_to_end(&mut content))?;
+
+        Ok(String::from_utf8_lossy(&content)
+            .lines()
+            .map(|line| line.to_string())
+            .collect())
+    }
+
+    /// Get the entries of the directory `path` with MLSD.
+    pub fn mlsd(&mut self, path: &str) -> Result<Vec<RemoteEntry>, String> {
+        let mut content = Vec::new();
+        self.transfer(&format!("MLSD {path}"), |data| {
+            data.read_to_end(&mut content)
+        })?;
+
+        Ok(String::from_utf8_lossy(&content)
+            .lines()
+            .filter_map(facts::parse_line)
+            .filter(|(name, _)| name != "." && name != "..")
+            .map(|(name, facts)| RemoteEntry {
+                name,
+                is_dir: facts.is_dir,
+                size: facts.size,
+            })
+            .collect())
+    }
+
+    /// Download the file `path` into `output`.
+    ///
+    /// # Return
+    /// The number of bytes received.
+    pub fn retr(&mut self, path: &str, output: &mut impl Write) -> Result<u64, String> {
+        self.transfer(&format!("RETR {path}"), |data| io::copy(data, output))
+    }
+
+    /// Upload the content of `input` to the file `path`.
+    ///
+    /// # Return
+    /// The number of bytes sent.
+    pub fn stor(&mut self, path: &str, input: &mut impl Read) -> Result<u64, String> {
+        self.transfer(&format!("STOR {path}"), |data| io::copy(input, data))
+    }
+
+    /// Append the content of `input` to the file `path`.
+    ///
+    /// # Return
+    /// The number of bytes sent.
+    pub fn appe(&mut self, path: &str, input: &mut impl Read) -> Result<u64, String> {
+        self.transfer(&format!("APPE {path}"), |data| io::copy(input, data))
+    }
+
+    /// Download the remote directory `remote_path` and everything below it into `local_path`.
+    pub fn mirror(
+        &mut self,
+        remote_path: &str,
+        local_path: &Path,
+    ) -> Result<MirrorSummary, String> {
+        let mut summary = MirrorSummary::default();
+
+        self.mirror_directory(remote_path, local_path, &mut summary)?;
+
+        Ok(summary)
+    }
+
+    pub fn quit(mut self) -> Result<(), String> {
+        self.command_expecting("QUIT", 221)?;
+
+        Ok(())
+    }
+
+    fn mirror_directory(
+        &mut self,
+        remote_path: &str,
+        local_path: &Path,
+        summary: &mut MirrorSummary,
+    ) -> Result<(), String> {
+        if let Err(err) = fs::create_dir_all(local_path) {
+            return Err(format!("cannot create {}: {err}", local_path.display()));
+        }
+
+        summary.directories += 1;
+
+        for entry in self.mlsd(remote_path)? {
+            // Un nom contenant un séparateur permettrait d'écrire en dehors du dossier local.
+            if entry.name.contains('/') || entry.name.contains('\\') {
+                eprintln!("Skipping {remote_path}/{}: invalid name.", entry.name);
+                continue;
+            }
+
+            let remote_entry = format!("{}/{}", remote_path.trim_end_matches('/'), entry.name);
+            let local_entry = local_path.join(&entry.name);
+
+            if entry.is_dir {
+                self.mirror_directory(&remote_entry, &local_entry, summary)?;
+            } else {
+                let mut file = match File::create(&local_entry) {
+                    Ok(f) => f,
+                    Err(err) => {
+                        return Err(format!("cannot create {}: {err}", local_entry.display()))
+                    }
+                };
+
+                summary.bytes += self.retr(&remote_entry, &mut file)?;
+                summary.files += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Open a passive data connection, send `command` and let `action` use the connection.
+    fn transfer<T>(
+        &mut self,
+        command: &str,
+        action: impl FnOnce(&mut TcpStream) -> io::Result<T>,
+    ) -> Result<T, String> {
+        // Le type binaire évite que le serveur convertisse les fins de ligne.
+        self.command_expecting("TYPE I", 200)?;
+
+        // PASV ne sait décrire qu'une adresse IPv4, EPSV ne donne que le port.
+        let address = match self.writer.peer_addr() {
+            Ok(peer) if peer.is_ipv6() => {
+                let reply = self.command_expecting("EPSV", 229)?;
+                SocketAddr::new(peer.ip(), host_port::decode_epsv_reply(&reply.message)?)
+            }
+            _ => {
+                let reply = self.command_expecting("PASV", 227)?;
+                SocketAddr::V4(host_port::decode_pasv_reply(&reply.message)?)
+            }
+        };
+
+        let reply = self.command(command)?;
+
+        if reply.code != 150 && reply.code != 125 {
+            return Err(format!(
+                "{command} failed: {} {}",
+                reply.code, reply.message
+            ));
+        }
+
+        let mut data = match TcpStream::connect(address) {
+            Ok(d) => d,
+            Err(err) => return Err(format!("cannot open data connection: {err}")),
+        };
+
+        let result = action(&mut data);
+
+        // Fermer la connexion indique au serveur la fin d'un envoi.
+        drop(data);
+
+        let reply = self.read_reply()?;
+
+        let value = match result {
+            Ok(v) => v,
+            Err(err) => return Err(format!("{command} failed: {err}")),
+        };
+
+        match reply.code {
+            226 | 250 => Ok(value),
+            _ => Err(format!(
+                "{command} failed: {} {}",
+                reply.code, reply.message
+            )),
+        }
+    }
+
+    fn command_expecting(&mut self, command: &str, code: u16) -> Result<Reply, String> {
+        let reply = self.command(command)?;
+
+        if reply.code != code {
+            return Err(format!(
+                "{command} failed: {} {}",
+                reply.code, reply.message
+            ));
+        }
+
+        Ok(reply)
+    }
+
+    fn expect(&mut self, code: u16) -> Result<Reply, String> {
+        let reply = self.read_reply()?;
+
+        if reply.code != code {
+            return Err(format!(
+                "unexpected reply {} {}, expected {code}",
+                reply.code, reply.message
+            ));
+        }
+
+        Ok(reply)
+    }
+
+    /// Read the next reply, for example to a command queued with [`Client::send`].
+    pub fn read_reply(&mut self) -> Result<Reply, String> {
+        let line = self.read_line()?;
+
+        let (code, mut more, text) = match reply::parse_reply_line(&line) {
+            Some(parsed) => parsed,
+            None => return Err(format!("invalid reply {line}")),
+        };
+
+        let mut message = text.to_string();
+
+        // Une réponse multi-lignes se termine par une ligne commençant par le même code suivi
+        // d'un espace.
+        while more {
+            let line = self.read_line()?;
+
+            match reply::parse_reply_line(&line) {
+                Some((c, false, _)) if c == code => more = false,
+                _ => {
+                    message.push('\n');
+                    message.push_str(&line);
+                }
+            }
+        }
+
+        Ok(Reply { code, message })
+    }
+
+    fn read_line(&mut self) -> Result<String, String> {
+        let mut line = String::new();
+
+        match self.reader.read_line(&mut line) {
+            Ok(0) => Err("connection closed by the server".to_string()),
+            Ok(_) => Ok(line.trim_end_matches(['\r', '\n']).to_string()),
+            Err(err) => Err(format!("cannot read reply: {err}")),
+        }
+    }
+}
@@ -1,6 +1,9 @@
 use std::{cell::RefCell, net::TcpListener, rc::Rc};
 
-use crate::options::ClientOptions;
+use crate::{
+    options::ClientOptions,
+    protocol::{command_args::CommandArgs, reply_code::ReplyCode},
+};
 
 pub enum CommandReturnType {
     None,
@@ -9,11 +12,9 @@ pub enum CommandReturnType {
     TcpListener(TcpListener),
 }
 
-pub type CommandResult = Result<(i32, String, bool, CommandReturnType), (i32, String)>;
+pub type CommandResult = Result<(ReplyCode, String, bool, CommandReturnType), (ReplyCode, String)>;
 
-pub type CommandJob =
-    Box<dyn Fn(Rc<RefCell<ClientOptions>>, Box<dyn Iterator<Item = String>>) -> CommandResult>;
+pub type CommandJob = Box<dyn Fn(Rc<RefCell<ClientOptions>>, &CommandArgs) -> CommandResult>;
 
-pub type DataCommandJob = Box<
-    dyn Fn(Rc<RefCell<Option<TcpListener>>>, Box<dyn Iterator<Item = String>>) -> CommandResult,
->;
+pub type DataCommandJob =
+    Box<dyn Fn(Rc<RefCell<Option<TcpListener>>>, &CommandArgs) -> CommandResult>;
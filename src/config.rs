@@ -1,31 +1,634 @@
-#[derive(Clone)]
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use chrono::DateTime;
+use serde::{Deserialize, Deserializer};
+
+use crate::server::{ftp_server::COMMANDS, listing::FixedListing};
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     hostname: String,
+    #[serde(deserialize_with = "deserialize_port")]
     port: String,
+    listeners: Vec<Listener>,
+    chown_uploads: bool,
+    system_users: BTreeMap<String, String>,
+    users_file: Option<String>,
+    root: String,
+    threads: usize,
+    max_queued_sessions: usize,
+    saturation_warning_secs: u64,
+    metadata_threads: usize,
+    interactive_priority: bool,
+    passive_ports: PortRange,
+    transcripts_dir: String,
+    transcript_ips: Vec<String>,
+    transcript_users: Vec<String>,
+    drop_directories: Vec<String>,
+    faults: FaultInjection,
+    passive_profiles: Vec<PassiveProfile>,
+    passive_external_start: Option<u16>,
+    epsv_only: bool,
+    pasv_reply: PasvReply,
+    greeting_delay_ms: u64,
+    max_delayed_connections: usize,
+    max_login_failures: u32,
+    ban_duration: u64,
+    max_account_failures: u32,
+    account_lockout_duration: u64,
+    login_backoff_secs: u64,
+    max_session_login_failures: u32,
+    min_free_space: u64,
+    preallocate_uploads: bool,
+    upload_receipts: bool,
+    upload_buffer_size: usize,
+    fsync_on_close: bool,
+    mdtm_setter: bool,
+    active_source_port: bool,
+    transfer_keepalive: u64,
+    passive_idle_timeout: u64,
+    socket_linger: Option<u64>,
+    session_hook: Option<String>,
+    hot_folders: HotFolders,
+    denial_reasons: bool,
+    motd: Option<String>,
+    disabled_commands: Vec<String>,
+    max_share_lifetime: u64,
+    max_open_files: usize,
+    max_upload_size: u64,
+    max_download_size: u64,
+    transfer_limits: Vec<TransferLimits>,
+    ignore_empty_lines: bool,
+    admins: Vec<String>,
+    require_tls_for_login: bool,
+    cleartext_anonymous: bool,
+    server_tokens: ServerTokens,
+    compression: CompressionSettings,
+    filenames: FilenameSettings,
+    content_rules: ContentRules,
+    checksum_cache: ChecksumCache,
+    directory_index: DirectoryIndex,
+    fixed_listing: FixedListingSettings,
+    virtual_hosts: Vec<VirtualHost>,
+    limits: SessionLimits,
+    rate_limit: RateLimit,
+    logging: LoggingSettings,
+    quirks: Vec<QuirkProfile>,
+}
+
+/// Faults artificially injected in the sessions, to test how clients react to them.
+///
+/// Rates are probabilities between 0 and 1. Everything is disabled by default.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FaultInjection {
+    /// Time waited before sending each reply.
+    pub reply_delay_ms: u64,
+    /// Probability for a command to fail with a 451 reply.
+    pub error_rate: f64,
+    /// Probability for a data connection to be closed before the transfer.
+    pub drop_data_rate: f64,
+    /// Probability for the data sent to the client to be cut.
+    pub truncate_rate: f64,
+}
+
+/// Bounds of the memory a single session can make the server allocate.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionLimits {
+    /// Longest request accepted on the control connection, in bytes.
+    pub max_request_length: usize,
+    /// Largest reply sent on the control connection, in bytes, longer ones are truncated.
+    pub max_reply_size: usize,
+    /// Most paths a wildcard can be expanded into.
+    pub max_expanded_paths: usize,
+    /// Most entries sent by LIST, NLST and MLSD, 0 for no limit.
+    pub max_listing_entries: usize,
+    /// Most transfers a session can have open at once, a passive listener counting as one, 0 for
+    /// no limit.
+    pub max_open_transfers: usize,
+    /// Most directories a path can go through below the root, 0 for no limit.
+    pub max_path_depth: usize,
+    /// Longest path on the system, in bytes, 0 for no limit.
+    pub max_path_length: usize,
+}
+
+/// Rate at which a session can send commands on the control connection, to slow down the scripts
+/// that walk the whole server with SIZE, MDTM or CWD.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimit {
+    /// Commands allowed per second once the burst is spent, 0 for no limit.
+    pub commands_per_second: f64,
+    /// Commands that can be sent at once after a quiet period.
+    pub burst: u32,
+    /// Commands delayed in a row after which the session is closed with a 421 reply, 0 to never
+    /// close it.
+    pub max_throttled: u32,
+}
+
+/// Whether the name and version of the software are given to the clients, in the 220 greeting and
+/// the status replies.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerTokens {
+    On,
+    /// Only the admins can see them, with SITE VERSION.
+    Off,
+}
+
+/// Directories whose new files are handed to a command, whether they were uploaded by FTP or
+/// copied on the server, so that both go through the same pipeline.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HotFolders {
+    /// Directories of the system watched, without their subdirectories.
+    pub directories: Vec<String>,
+    /// Command run for each new file, with a JSON record of the file on its standard input.
+    pub hook: Option<String>,
+    /// Time without any change after which a file is considered complete, in milliseconds.
+    pub settle_ms: u64,
+}
+
+impl Default for HotFolders {
+    fn default() -> HotFolders {
+        HotFolders {
+            directories: Vec::new(),
+            hook: None,
+            settle_ms: 2000,
+        }
+    }
+}
+
+/// Fixed owner, group and clock for the `ls -l` lines of LIST, so that tests can compare them
+/// with golden outputs whatever the machine they run on.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FixedListingSettings {
+    pub enabled: bool,
+    pub owner: String,
+    pub group: String,
+    /// Time the dates are compared with, in RFC 3339.
+    pub now: String,
+}
+
+impl Default for FixedListingSettings {
+    fn default() -> FixedListingSettings {
+        FixedListingSettings {
+            enabled: false,
+            owner: "ftp".to_string(),
+            group: "ftp".to_string(),
+            now: "2024-01-01T00:00:00Z".to_string(),
+        }
+    }
+}
+
+/// Passive mode settings used for some of the sessions, for example to advertise the LAN
+/// address to internal users and the NAT address to external ones.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PassiveProfile {
+    /// Local addresses of the control connection this profile applies to, any if empty.
+    pub local_addresses: Vec<String>,
+    /// Users this profile applies to, any if empty.
+    pub users: Vec<String>,
+    /// Address the passive listeners are bound to, the local address of the control connection
+    /// if not given.
+    pub bind_address: Option<String>,
+    /// Address sent to the client in the PASV reply, the hostname if not given.
+    pub address: Option<String>,
+    /// Ports used by the passive listeners, the passive ports of the config if not given.
+    pub ports: Option<PortRange>,
+    /// First port sent to the client, mapped one to one to `ports`, the one of the config if not
+    /// given.
+    pub external_start: Option<u16>,
+}
+
+/// Sizes of the files some users can transfer, instead of the ones of the config.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransferLimits {
+    /// Users these limits apply to.
+    pub users: Vec<String>,
+    /// Largest file uploaded, in bytes, 0 for no limit, the one of the config if not given.
+    pub max_upload_size: Option<u64>,
+    /// Largest file downloaded, in bytes, 0 for no limit, the one of the config if not given.
+    pub max_download_size: Option<u64>,
+}
+
+/// Compression of the files downloaded by the clients.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompressionSettings {
+    /// Accept MODE Z, in which the data connection carries a zlib stream.
+    pub mode_z: bool,
+    /// Extensions of the files compressed in MODE Z, every file if empty.
+    pub extensions: Vec<String>,
+    /// Extensions of the files that can be downloaded as `file.gz` even if only `file` exists.
+    pub implicit_gzip: Vec<String>,
+}
+
+/// Rules applied to the names of the files and directories created by the clients, with STOR,
+/// RNTO and MKD.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilenameSettings {
+    /// Changes applied to each new name, in order.
+    pub transforms: Vec<FilenameTransform>,
+    /// Extensions the files can have, any if empty. Files without an extension are then refused.
+    pub allowed_extensions: Vec<String>,
+}
+
+/// Change applied to the name of a new file or directory.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilenameTransform {
+    Lowercase,
+    /// Remove the accents and other combining marks, `Été` becoming `Ete`.
+    StripDiacritics,
+    SpacesToUnderscores,
+}
+
+/// Types of content refused in uploads, recognized by the first bytes of the files whatever their
+/// name.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContentRules {
+    /// Types of the files refused whatever their size.
+    pub reject: Vec<ContentType>,
+    /// Largest archive accepted, in bytes, 0 for no limit.
+    pub max_archive_size: u64,
+}
+
+/// Kind of file recognized by its magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentType {
+    /// ELF, PE or Mach-O program.
+    Executable,
+    /// Script starting with `#!`.
+    Script,
+    /// Zip, tar, gzip, bzip2, xz, zstd, 7z or rar archive.
+    Archive,
+}
+
+/// Workarounds applied to the sessions of some clients.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuirkProfile {
+    /// Beginnings of the names sent by CLNT that select this profile, ignoring the case, every
+    /// client if empty.
+    pub clients: Vec<String>,
+    /// Verbs accepted in place of the commands of the server, such as `XPWD = "PWD"`.
+    pub aliases: BTreeMap<String, String>,
+    /// Reply to SYST, such as `Windows_NT`, instead of `UNIX Type: L8`.
+    pub syst: Option<String>,
+}
+
+/// File the output of the server is written to, and when it is rotated.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingSettings {
+    /// Log file, the standard output if not given.
+    pub file: Option<String>,
+    /// Size in bytes after which the file is rotated, 0 for no limit.
+    pub max_size: u64,
+    /// Period after which the file is rotated, whatever its size.
+    pub rotate_every: LogRotation,
+    /// Number of rotated files kept, the oldest ones being deleted.
+    pub keep: usize,
+    /// Compress the rotated files with gzip.
+    pub compress: bool,
+}
+
+impl Default for LoggingSettings {
+    fn default() -> Self {
+        LoggingSettings {
+            file: None,
+            max_size: 0,
+            rotate_every: LogRotation::Never,
+            keep: 7,
+            compress: true,
+        }
+    }
+}
+
+/// Period of the time-based rotation of the log file.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Never,
+    Hourly,
+    Daily,
+}
+
+/// Address the server listens on in addition to the hostname and the port, sharing the rest of
+/// the config.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Listener {
+    pub address: SocketAddr,
+}
+
+/// How the address of the passive listener is written in the 227 reply to PASV.
+///
+/// The ALGs of some NAT routers rewrite that address, sometimes wrongly.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PasvReply {
+    /// `(h1,h2,h3,h4,p1,p2)`, the form of the RFC 959.
+    Standard,
+    /// `=h1,h2,h3,h4,p1,p2`, without the parentheses the ALGs look for, as the RFC 1123 lets the
+    /// clients find the numbers anywhere in the reply.
+    Unbracketed,
+}
+
+/// Where the checksums sent by HASH are kept, to avoid reading a file again if it did not change.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumCache {
+    /// Checksums are computed for every request.
+    None,
+    /// In an extended attribute of each file, on Linux, macOS and FreeBSD.
+    Xattr,
+    /// In a `.checksums` file in each directory.
+    Sidecar,
+}
+
+/// Document sent by RETR when it is given a directory.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DirectoryIndex {
+    /// RETR refuses directories.
+    None,
+    /// The `ls -l` lines of the entries.
+    Text,
+    /// A web page linking to the entries.
+    Html,
+}
+
+/// Site served with its own root, accounts and banner, selected with the HOST command or by the
+/// address the client connected to.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VirtualHost {
+    /// Names accepted by the HOST command, compared without case.
+    pub names: Vec<String>,
+    /// Local addresses of the control connection for which this host is selected by default.
+    pub local_addresses: Vec<String>,
+    /// Directory shown to the clients as `/`.
+    pub root: String,
+    /// Accounts allowed to log in, the users file of the config if not given.
+    pub users_file: Option<String>,
+    /// Text of the 220 reply sent when the host is selected.
+    pub banner: Option<String>,
+}
+
+/// Inclusive range of ports, written `start-end`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            hostname: String::new(),
+            port: String::new(),
+            listeners: Vec::new(),
+            chown_uploads: false,
+            system_users: BTreeMap::new(),
+            users_file: None,
+            root: "/".to_string(),
+            // On impose une limite de threads pour éviter une faille dans laquelle énormément de
+            // threads sont crées pour saturer la mémoire du serveur.
+            threads: 10,
+            max_queued_sessions: 10,
+            saturation_warning_secs: 30,
+            metadata_threads: 4,
+            interactive_priority: false,
+            passive_ports: PortRange {
+                start: 7000,
+                end: 65534,
+            },
+            transcripts_dir: "transcripts".to_string(),
+            transcript_ips: Vec::new(),
+            transcript_users: Vec::new(),
+            drop_directories: Vec::new(),
+            faults: FaultInjection::default(),
+            passive_profiles: Vec::new(),
+            passive_external_start: None,
+            epsv_only: false,
+            pasv_reply: PasvReply::Standard,
+            greeting_delay_ms: 0,
+            max_delayed_connections: 1000,
+            max_login_failures: 0,
+            ban_duration: 600,
+            max_account_failures: 0,
+            account_lockout_duration: 900,
+            login_backoff_secs: 0,
+            max_session_login_failures: 0,
+            min_free_space: 0,
+            preallocate_uploads: false,
+            upload_receipts: false,
+            upload_buffer_size: 65536,
+            fsync_on_close: true,
+            mdtm_setter: false,
+            active_source_port: false,
+            transfer_keepalive: 0,
+            passive_idle_timeout: 120,
+            socket_linger: None,
+            session_hook: None,
+            hot_folders: HotFolders::default(),
+            denial_reasons: false,
+            motd: None,
+            disabled_commands: Vec::new(),
+            max_share_lifetime: 24,
+            max_open_files: 0,
+            max_upload_size: 0,
+            max_download_size: 0,
+            transfer_limits: Vec::new(),
+            ignore_empty_lines: false,
+            admins: Vec::new(),
+            require_tls_for_login: false,
+            cleartext_anonymous: false,
+            server_tokens: ServerTokens::On,
+            compression: CompressionSettings::default(),
+            filenames: FilenameSettings::default(),
+            content_rules: ContentRules::default(),
+            checksum_cache: ChecksumCache::None,
+            directory_index: DirectoryIndex::None,
+            fixed_listing: FixedListingSettings::default(),
+            virtual_hosts: Vec::new(),
+            limits: SessionLimits::default(),
+            rate_limit: RateLimit::default(),
+            logging: LoggingSettings::default(),
+            quirks: Vec::new(),
+        }
+    }
+}
+
+impl Default for SessionLimits {
+    fn default() -> SessionLimits {
+        SessionLimits {
+            max_request_length: 4096,
+            max_reply_size: 65536,
+            max_expanded_paths: 10000,
+            max_listing_entries: 0,
+            max_open_transfers: 0,
+            // Au-delà, les sauvegardes et la plupart des outils ne suivent plus.
+            max_path_depth: 32,
+            max_path_length: 4096,
+        }
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> RateLimit {
+        RateLimit {
+            commands_per_second: 0.0,
+            burst: 30,
+            max_throttled: 100,
+        }
+    }
 }
 
 impl Config {
     pub fn new(hostname: String, port: String) -> Config {
-        Config { hostname, port }
+        Config {
+            hostname,
+            port,
+            ..Default::default()
+        }
     }
 
-    pub fn check(&self) -> Result<(), &'static str> {
-        // Vérifie que l'adresse soit bien une adresse IP valide.
-        let host: Vec<_> = self.hostname.split(".").collect();
+    /// Load the configuration from the TOML file located at `path`.
+    ///
+    /// Missing keys keep their default value.
+    pub fn load(path: &str) -> Result<Config, String> {
+        let content = match fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(err) => return Err(format!("cannot read config file {path}: {err}")),
+        };
 
-        if host.len() != 4 {
-            return Err("invalid number of decimal in hostname");
+        match toml::from_str(&content) {
+            Ok(config) => Ok(config),
+            Err(err) => Err(format!("invalid config file {path}: {}", err.message())),
         }
+    }
 
-        for dec in host {
-            if let Err(_) = dec.parse::<i32>() {
-                return Err("invalid format in hostname");
+    pub fn check(&self) -> Result<(), &'static str> {
+        // Vérifie que l'adresse et le port forment une adresse d'écoute valide.
+        let listeners = self.get_listeners()?;
+
+        for (index, address) in listeners.iter().enumerate() {
+            if listeners[..index].contains(address) {
+                return Err("two listeners use the same address");
             }
         }
 
-        // Vérifie que le port soit dans le bon format.
-        if let Err(_) = self.port.parse::<i32>() {
-            return Err("invalid port format");
+        // Sans AUTH TLS, aucun compte hors anonyme ne pourrait plus se connecter.
+        if self.require_tls_for_login {
+            return Err("require_tls_for_login needs FTPS, which is not supported yet");
+        }
+
+        if self.threads == 0 {
+            return Err("at least one thread is needed");
+        }
+
+        let rates = [
+            self.faults.error_rate,
+            self.faults.drop_data_rate,
+            self.faults.truncate_rate,
+        ];
+
+        if rates.iter().any(|rate| !(0.0..=1.0).contains(rate)) {
+            return Err("fault rates must be between 0 and 1");
+        }
+
+        if self.limits.max_request_length == 0
+            || self.limits.max_reply_size == 0
+            || self.limits.max_expanded_paths == 0
+        {
+            return Err("session limits must be greater than 0");
+        }
+
+        if self.rate_limit.commands_per_second < 0.0
+            || (self.rate_limit.commands_per_second > 0.0 && self.rate_limit.burst == 0)
+        {
+            return Err("rate limit needs a positive rate and burst");
+        }
+
+        if DateTime::parse_from_rfc3339(&self.fixed_listing.now).is_err() {
+            return Err("the clock of fixed listings must be an RFC 3339 time");
+        }
+
+        if !self.hot_folders.directories.is_empty() && self.hot_folders.hook.is_none() {
+            return Err("hot folders need a hook");
+        }
+
+        if self.virtual_hosts.iter().any(|host| host.root.is_empty()) {
+            return Err("every virtual host needs a root");
+        }
+
+        if self.max_share_lifetime > 24 * 366 {
+            return Err("max_share_lifetime cannot exceed one year");
+        }
+
+        // Les ports externes doivent tous exister, sans quoi certains listeners seraient
+        // injoignables.
+        let mut mappings = vec![(self.passive_external_start, self.passive_ports)];
+
+        for profile in &self.passive_profiles {
+            mappings.push((
+                profile.external_start.or(self.passive_external_start),
+                profile.ports.unwrap_or(self.passive_ports),
+            ));
+        }
+
+        let overflows = mappings.iter().any(|(external_start, ports)| {
+            external_start.is_some_and(|start| {
+                u32::from(start) + u32::from(ports.end - ports.start) > u32::from(u16::MAX)
+            })
+        });
+
+        if overflows {
+            return Err("external passive ports exceed 65535");
+        }
+
+        let unknown_alias = self.quirks.iter().any(|profile| {
+            profile
+                .aliases
+                .values()
+                .any(|command| !COMMANDS.contains(&&command.to_ascii_uppercase()[..]))
+        });
+
+        if unknown_alias {
+            return Err("quirk aliases must name commands of the server");
+        }
+
+        let logging = &self.logging;
+
+        if logging.file.is_none()
+            && (logging.max_size > 0 || logging.rotate_every != LogRotation::Never)
+        {
+            return Err("log rotation needs a log file");
+        }
+
+        // Sans ces commandes, plus aucun client ne pourrait se connecter ni partir proprement.
+        if ["USER", "PASS", "QUIT"]
+            .iter()
+            .any(|command| self.is_command_disabled(command))
+        {
+            return Err("USER, PASS and QUIT cannot be disabled");
         }
 
         Ok(())
@@ -35,7 +638,491 @@ impl Config {
         self.hostname.clone()
     }
 
+    pub fn set_hostname(&mut self, hostname: String) {
+        self.hostname = hostname;
+    }
+
+    /// Address the server listens on, made of the hostname and the port.
+    ///
+    /// The hostname can be an IPv4 or an IPv6 address, with or without brackets, and can also
+    /// contain the port, as in `[::]:21`.
+    pub fn get_listen_address(&self) -> Result<SocketAddr, &'static str> {
+        if let Ok(address) = self.hostname.parse() {
+            return Ok(address);
+        }
+
+        let ip: IpAddr = match self
+            .hostname
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+        {
+            Ok(ip) => ip,
+            Err(_) => return Err("invalid hostname, expected an IP address"),
+        };
+
+        match self.port.parse() {
+            Ok(port) => Ok(SocketAddr::new(ip, port)),
+            Err(_) => Err("invalid port format"),
+        }
+    }
+
+    /// Addresses the server listens on, starting with the one made of the hostname and the port.
+    pub fn get_listeners(&self) -> Result<Vec<SocketAddr>, &'static str> {
+        let mut listeners = vec![self.get_listen_address()?];
+
+        listeners.extend(self.listeners.iter().map(|listener| listener.address));
+
+        Ok(listeners)
+    }
+
     pub fn get_port(&self) -> String {
         self.port.clone()
     }
+
+    pub fn set_port(&mut self, port: String) {
+        self.port = port;
+    }
+
+    /// Tell if uploaded files must belong to the system user mapped to the session by
+    /// `system_users`.
+    ///
+    /// Only effective when the server is running with administrator rights, and never gives a
+    /// file to root.
+    pub fn get_chown_uploads(&self) -> bool {
+        self.chown_uploads
+    }
+
+    pub fn set_chown_uploads(&mut self, chown_uploads: bool) {
+        self.chown_uploads = chown_uploads;
+    }
+
+    /// Name of the system user that receives the files uploaded by `username`, if the config maps
+    /// one to it.
+    pub fn get_system_user(&self, username: &str) -> Option<&str> {
+        self.system_users.get(username).map(String::as_str)
+    }
+
+    /// Path of the file containing the accounts allowed to log in.
+    ///
+    /// When no file is given, any username is accepted without password.
+    pub fn get_users_file(&self) -> Option<String> {
+        self.users_file.clone()
+    }
+
+    pub fn set_users_file(&mut self, users_file: Option<String>) {
+        self.users_file = users_file;
+    }
+
+    /// Directory of the system shown to the clients as `/`.
+    pub fn get_root(&self) -> String {
+        self.root.clone()
+    }
+
+    pub fn set_root(&mut self, root: String) {
+        self.root = root;
+    }
+
+    /// Number of clients that can be served at the same time.
+    pub fn get_threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads;
+    }
+
+    /// Number of clients waiting for a free thread above which new clients are refused with a
+    /// 421 reply.
+    pub fn get_max_queued_sessions(&self) -> usize {
+        self.max_queued_sessions
+    }
+
+    /// Seconds during which all the threads can stay busy before a warning is logged, 0 to never
+    /// warn.
+    pub fn get_saturation_warning_secs(&self) -> u64 {
+        self.saturation_warning_secs
+    }
+
+    /// Threads reading the metadata of the entries of a single listing at once, 1 to read them one
+    /// by one.
+    pub fn get_metadata_threads(&self) -> usize {
+        self.metadata_threads
+    }
+
+    /// Slow down the RETR and STOR transfers between their chunks while listings and metadata
+    /// commands run, so that the clients browsing the server stay responsive.
+    pub fn get_interactive_priority(&self) -> bool {
+        self.interactive_priority
+    }
+
+    /// Ports on which the data connections of the passive mode can be opened.
+    pub fn get_passive_ports(&self) -> PortRange {
+        self.passive_ports
+    }
+
+    pub fn set_passive_ports(&mut self, passive_ports: PortRange) {
+        self.passive_ports = passive_ports;
+    }
+
+    /// Directory in which the session transcripts are written.
+    pub fn get_transcripts_dir(&self) -> String {
+        self.transcripts_dir.clone()
+    }
+
+    /// Tell if the sessions opened from `ip` must be recorded in a transcript.
+    pub fn is_transcript_ip(&self, ip: &str) -> bool {
+        self.transcript_ips.iter().any(|i| i == ip)
+    }
+
+    /// Tell if the sessions of `username` must be recorded in a transcript.
+    pub fn is_transcript_user(&self, username: &str) -> bool {
+        self.transcript_users.iter().any(|u| u == username)
+    }
+
+    /// Directories, seen from the root of the sessions, where files can be uploaded but neither
+    /// listed nor downloaded.
+    pub fn get_drop_directories(&self) -> &Vec<String> {
+        &self.drop_directories
+    }
+
+    pub fn set_drop_directories(&mut self, drop_directories: Vec<String>) {
+        self.drop_directories = drop_directories;
+    }
+
+    pub fn get_faults(&self) -> FaultInjection {
+        self.faults
+    }
+
+    /// Passive mode settings specific to some listeners or users, by order of priority.
+    pub fn get_passive_profiles(&self) -> &[PassiveProfile] {
+        &self.passive_profiles
+    }
+
+    /// First port sent to the clients in the replies to PASV and EPSV, mapped one to one to the
+    /// passive ports, for a load balancer forwarding each external port to a single instance.
+    ///
+    /// The ports are sent unchanged if not given.
+    pub fn get_passive_external_start(&self) -> Option<u16> {
+        self.passive_external_start
+    }
+
+    /// Refuse PASV with a 522 reply so that the clients use EPSV, whose reply holds no address for
+    /// a NAT router to rewrite.
+    pub fn get_epsv_only(&self) -> bool {
+        self.epsv_only
+    }
+
+    pub fn get_pasv_reply(&self) -> PasvReply {
+        self.pasv_reply
+    }
+
+    /// Sites served in addition to the default one.
+    pub fn get_virtual_hosts(&self) -> &[VirtualHost] {
+        &self.virtual_hosts
+    }
+
+    /// Workarounds for the clients that do not follow the protocol, the first matching profile
+    /// being used.
+    pub fn get_quirks(&self) -> &[QuirkProfile] {
+        &self.quirks
+    }
+
+    pub fn get_limits(&self) -> SessionLimits {
+        self.limits
+    }
+
+    pub fn get_rate_limit(&self) -> RateLimit {
+        self.rate_limit
+    }
+
+    /// Time waited before greeting a new client, to slow down scanners.
+    pub fn get_greeting_delay_ms(&self) -> u64 {
+        self.greeting_delay_ms
+    }
+
+    /// Number of new clients waiting for their greeting above which new clients are closed
+    /// without a reply.
+    pub fn get_max_delayed_connections(&self) -> usize {
+        self.max_delayed_connections
+    }
+
+    /// Number of failed logins after which an address is banned, 0 to never ban.
+    pub fn get_max_login_failures(&self) -> u32 {
+        self.max_login_failures
+    }
+
+    /// Duration of a ban, in seconds.
+    pub fn get_ban_duration(&self) -> u64 {
+        self.ban_duration
+    }
+
+    /// Number of wrong passwords, from any address, after which an account is locked, 0 to never
+    /// lock it.
+    pub fn get_max_account_failures(&self) -> u32 {
+        self.max_account_failures
+    }
+
+    /// Duration of an account lockout, in seconds.
+    pub fn get_account_lockout_duration(&self) -> u64 {
+        self.account_lockout_duration
+    }
+
+    /// Delay before the first 530 reply to a wrong password in a session, doubled after each new
+    /// failure, 0 to reply at once.
+    pub fn get_login_backoff_secs(&self) -> u64 {
+        self.login_backoff_secs
+    }
+
+    /// Number of failed logins after which a session is closed, 0 to keep it open. Unlike the
+    /// bans, the address can connect again right away.
+    pub fn get_max_session_login_failures(&self) -> u32 {
+        self.max_session_login_failures
+    }
+
+    /// Reserve on the disk the size announced by ALLO before an upload.
+    pub fn get_preallocate_uploads(&self) -> bool {
+        self.preallocate_uploads
+    }
+
+    /// Give the number of bytes received and their CRC32 in the reply to STOR, so that clients can
+    /// check the upload without another command.
+    pub fn get_upload_receipts(&self) -> bool {
+        self.upload_receipts
+    }
+
+    /// Bytes of an upload gathered before being written to the file, 0 to write them as they
+    /// arrive.
+    pub fn get_upload_buffer_size(&self) -> usize {
+        self.upload_buffer_size
+    }
+
+    /// Wait for an upload to be on the disk before reporting it as complete.
+    pub fn get_fsync_on_close(&self) -> bool {
+        self.fsync_on_close
+    }
+
+    /// Let `MDTM YYYYMMDDHHMMSS path` change the modification time, as some clients send it
+    /// instead of MFMT.
+    pub fn get_mdtm_setter(&self) -> bool {
+        self.mdtm_setter
+    }
+
+    /// Open the active data connections from the port before the control port, 20 when it is 21,
+    /// as some firewalls expect.
+    pub fn get_active_source_port(&self) -> bool {
+        self.active_source_port
+    }
+
+    /// Seconds without traffic on the control connection, during a transfer, after which a TCP
+    /// keep-alive probe is sent. 0 to never send any.
+    pub fn get_transfer_keepalive(&self) -> u64 {
+        self.transfer_keepalive
+    }
+
+    /// Seconds after which a passive listener the client never connected to is closed, giving its
+    /// port back. 0 to keep it until the next transfer or the end of the session.
+    pub fn get_passive_idle_timeout(&self) -> u64 {
+        self.passive_idle_timeout
+    }
+
+    /// Seconds a closed control or data connection waits for its last bytes to be acknowledged
+    /// (SO_LINGER), 0 resetting the connection instead. `None` keeps the default of the system.
+    pub fn get_socket_linger(&self) -> Option<u64> {
+        self.socket_linger
+    }
+
+    /// Command run at the end of each session, with the timeline of the session as JSON on its
+    /// standard input.
+    pub fn get_session_hook(&self) -> Option<&str> {
+        self.session_hook.as_deref()
+    }
+
+    pub fn get_hot_folders(&self) -> &HotFolders {
+        &self.hot_folders
+    }
+
+    /// Add the reason of a refusal, such as `path_outside_jail`, to the text of the reply.
+    pub fn get_denial_reasons(&self) -> bool {
+        self.denial_reasons
+    }
+
+    /// Message of the day, sent to the clients when they log in.
+    pub fn get_motd(&self) -> Option<&str> {
+        self.motd.as_deref()
+    }
+
+    /// Tell if the administrator disabled `command`, whatever its case.
+    pub fn is_command_disabled(&self, command: &str) -> bool {
+        self.disabled_commands
+            .iter()
+            .any(|disabled| disabled.eq_ignore_ascii_case(command))
+    }
+
+    /// Longest validity, in hours, of the credentials created by SITE SHARE, 0 to disable it.
+    pub fn get_max_share_lifetime(&self) -> u64 {
+        self.max_share_lifetime
+    }
+
+    /// Most file descriptors the transfers of all the sessions can hold, 0 for no limit.
+    pub fn get_max_open_files(&self) -> usize {
+        self.max_open_files
+    }
+
+    /// Largest file, in bytes, that `username` can upload with STOR, 0 for no limit.
+    pub fn get_max_upload_size(&self, username: Option<&str>) -> u64 {
+        self.find_transfer_limits(username)
+            .and_then(|limits| limits.max_upload_size)
+            .unwrap_or(self.max_upload_size)
+    }
+
+    /// Largest file, in bytes, that `username` can download with RETR, 0 for no limit.
+    pub fn get_max_download_size(&self, username: Option<&str>) -> u64 {
+        self.find_transfer_limits(username)
+            .and_then(|limits| limits.max_download_size)
+            .unwrap_or(self.max_download_size)
+    }
+
+    /// First transfer limits of the config applying to `username`.
+    fn find_transfer_limits(&self, username: Option<&str>) -> Option<&TransferLimits> {
+        let username = username?;
+
+        self.transfer_limits
+            .iter()
+            .find(|limits| limits.users.iter().any(|u| u == username))
+    }
+
+    /// Ignore the empty lines sent on the control connection instead of replying 500.
+    pub fn get_ignore_empty_lines(&self) -> bool {
+        self.ignore_empty_lines
+    }
+
+    /// Tell if `username` can see the state of the whole server, for example with SITE STATS.
+    pub fn is_admin(&self, username: &str) -> bool {
+        self.admins.iter().any(|admin| admin == username)
+    }
+
+    pub fn set_admins(&mut self, admins: Vec<String>) {
+        self.admins = admins;
+    }
+
+    pub fn get_server_tokens(&self) -> ServerTokens {
+        self.server_tokens
+    }
+
+    /// Refuse to log in on a control connection that is not protected by TLS.
+    ///
+    /// Refused by [`Config::check`] until the server supports FTPS.
+    pub fn get_require_tls_for_login(&self) -> bool {
+        self.require_tls_for_login
+    }
+
+    /// Still let `anonymous` and `ftp` log in without TLS, with a read-only session, when TLS is
+    /// required.
+    pub fn get_cleartext_anonymous(&self) -> bool {
+        self.cleartext_anonymous
+    }
+
+    pub fn get_checksum_cache(&self) -> ChecksumCache {
+        self.checksum_cache
+    }
+
+    /// Values used in the listings instead of those of the machine, if the fixed listings are
+    /// enabled.
+    pub fn get_fixed_listing(&self) -> Option<FixedListing> {
+        let settings = &self.fixed_listing;
+
+        if !settings.enabled {
+            return None;
+        }
+
+        Some(FixedListing {
+            owner: settings.owner.clone(),
+            group: settings.group.clone(),
+            now: DateTime::parse_from_rfc3339(&settings.now).ok()?.to_utc(),
+        })
+    }
+
+    pub fn get_directory_index(&self) -> DirectoryIndex {
+        self.directory_index
+    }
+
+    pub fn get_compression(&self) -> &CompressionSettings {
+        &self.compression
+    }
+
+    pub fn get_filenames(&self) -> &FilenameSettings {
+        &self.filenames
+    }
+
+    pub fn get_content_rules(&self) -> &ContentRules {
+        &self.content_rules
+    }
+
+    pub fn set_filenames(&mut self, filenames: FilenameSettings) {
+        self.filenames = filenames;
+    }
+
+    pub fn get_logging(&self) -> &LoggingSettings {
+        &self.logging
+    }
+
+    /// Bytes that must stay available on the disk for an upload to be accepted, 0 to disable.
+    pub fn get_min_free_space(&self) -> u64 {
+        self.min_free_space
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PortRange, String> {
+        let (start, end) = match s.split_once('-') {
+            Some(range) => range,
+            None => return Err(format!("invalid port range {s}, expected start-end")),
+        };
+
+        let (start, end) = match (start.trim().parse(), end.trim().parse()) {
+            (Ok(start), Ok(end)) => (start, end),
+            _ => return Err(format!("invalid port range {s}, expected start-end")),
+        };
+
+        if start == 0 || start > end {
+            return Err(format!("invalid port range {s}"));
+        }
+
+        Ok(PortRange { start, end })
+    }
+}
+
+impl TryFrom<String> for PortRange {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<PortRange, String> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+/// Accept the port written either as a number or as a string in the config file.
+fn deserialize_port<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Port {
+        Number(u16),
+        String(String),
+    }
+
+    match Port::deserialize(deserializer)? {
+        Port::Number(n) => Ok(n.to_string()),
+        Port::String(s) => Ok(s),
+    }
 }
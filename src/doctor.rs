@@ -0,0 +1,247 @@
+//! Diagnostics of a deployment, run by `ftp-paradise doctor` before the server goes live.
+
+use std::{
+    env, fs,
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener},
+};
+
+use crate::{
+    config::{ChecksumCache, Config},
+    platform::crossplatform,
+    server::data_channel,
+    user_store::UserStore,
+};
+
+/// Result of one of the checks made by [`run`].
+#[derive(Debug)]
+pub struct Check {
+    pub name: String,
+    /// What has been found if the check passed, what is wrong otherwise.
+    pub outcome: Result<String, String>,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, outcome: Result<String, String>) -> Check {
+        Check {
+            name: name.into(),
+            outcome,
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.outcome.is_ok()
+    }
+}
+
+/// Check everything the server needs from its environment with `config`.
+///
+/// Every check is made even if a previous one failed, so that all the problems are reported at
+/// once.
+pub fn run(config: &Config) -> Vec<Check> {
+    let mut checks = vec![Check::new(
+        "configuration",
+        config
+            .check()
+            .map(|_| "valid".to_string())
+            .map_err(String::from),
+    )];
+
+    let listen_address = config.get_listen_address();
+
+    checks.push(Check::new(
+        "control port",
+        match listen_address {
+            Ok(address) => check_bind(address),
+            Err(err) => Err(err.to_string()),
+        },
+    ));
+
+    // Le premier listener est celui de l'adresse d'écoute, déjà vérifié.
+    for address in config.get_listeners().unwrap_or_default().iter().skip(1) {
+        checks.push(Check::new(
+            format!("control port {address}"),
+            check_bind(*address),
+        ));
+    }
+
+    // Les ports passifs sont ouverts sur l'adresse d'écoute, ou sur toutes si elle est inconnue.
+    let passive_ip = match listen_address {
+        Ok(address) => address.ip(),
+        Err(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+    };
+
+    checks.push(Check::new(
+        "passive ports",
+        check_passive_ports(config, passive_ip),
+    ));
+
+    // Les connexions actives partent du port précédant celui de contrôle, souvent privilégié.
+    if config.get_active_source_port() {
+        checks.push(Check::new(
+            "active source port",
+            match listen_address {
+                Ok(address) => check_bind(SocketAddr::new(
+                    address.ip(),
+                    data_channel::get_source_port(address.port()),
+                )),
+                Err(err) => Err(err.to_string()),
+            },
+        ));
+    }
+
+    checks.push(Check::new("root", check_directory(&config.get_root())));
+
+    for host in config.get_virtual_hosts() {
+        checks.push(Check::new(
+            format!("root of virtual host {}", host.names.join(", ")),
+            check_directory(&host.root),
+        ));
+    }
+
+    if let Some(path) = config.get_users_file() {
+        checks.push(Check::new("users file", check_users_file(&path)));
+    }
+
+    for host in config.get_virtual_hosts() {
+        if let Some(path) = &host.users_file {
+            checks.push(Check::new(
+                format!("users file of virtual host {}", host.names.join(", ")),
+                check_users_file(path),
+            ));
+        }
+    }
+
+    checks.push(Check::new("platform", check_platform(config)));
+
+    // Le serveur ne gère pas encore TLS, il n'y a donc aucun certificat à vérifier.
+    checks.push(Check::new(
+        "TLS certificates",
+        Ok("none configured".to_string()),
+    ));
+
+    checks
+}
+
+fn check_bind(address: SocketAddr) -> Result<String, String> {
+    match TcpListener::bind(address) {
+        Ok(_) => Ok(format!("{address} is available")),
+        Err(err) => Err(format!("cannot listen on {address}: {err}")),
+    }
+}
+
+/// Count the ports of the passive range that can be opened right now.
+fn check_passive_ports(config: &Config, ip: IpAddr) -> Result<String, String> {
+    let range = config.get_passive_ports();
+
+    let available = (range.start..=range.end)
+        .filter(|&port| TcpListener::bind(SocketAddr::new(ip, port)).is_ok())
+        .count();
+
+    let total = usize::from(range.end - range.start) + 1;
+
+    match available {
+        0 => Err(format!(
+            "none of the ports {}-{} can be opened on {ip}",
+            range.start, range.end
+        )),
+        _ => Ok(format!(
+            "{available} of {total} ports of {}-{} available",
+            range.start, range.end
+        )),
+    }
+}
+
+/// Make sure the features enabled in `config` are provided by the operating system.
+fn check_platform(config: &Config) -> Result<String, String> {
+    let required = [
+        ("owner of uploads", config.get_chown_uploads()),
+        ("preallocation", config.get_preallocate_uploads()),
+        ("free space check", config.get_min_free_space() > 0),
+        ("TCP keep-alive", config.get_transfer_keepalive() > 0),
+        (
+            "extended attributes",
+            config.get_checksum_cache() == ChecksumCache::Xattr,
+        ),
+        ("log file", config.get_logging().file.is_some()),
+    ];
+
+    let is_supported = |feature: &str| {
+        crossplatform::FEATURES
+            .iter()
+            .any(|&(name, supported)| name == feature && supported)
+    };
+
+    let missing: Vec<&str> = required
+        .iter()
+        .filter(|&&(feature, enabled)| enabled && !is_supported(feature))
+        .map(|&(feature, _)| feature)
+        .collect();
+
+    if !missing.is_empty() {
+        return Err(format!(
+            "not supported on {}: {}",
+            env::consts::OS,
+            missing.join(", ")
+        ));
+    }
+
+    let unavailable: Vec<&str> = crossplatform::FEATURES
+        .iter()
+        .filter(|&&(_, supported)| !supported)
+        .map(|&(name, _)| name)
+        .collect();
+
+    match unavailable.is_empty() {
+        true => Ok(format!("every feature is available on {}", env::consts::OS)),
+        false => Ok(format!(
+            "unavailable on {}: {}",
+            env::consts::OS,
+            unavailable.join(", ")
+        )),
+    }
+}
+
+fn check_directory(path: &str) -> Result<String, String> {
+    match fs::read_dir(path) {
+        Ok(entries) => Ok(format!("{path} is readable ({} entries)", entries.count())),
+        Err(err) => Err(format!("cannot read {path}: {err}")),
+    }
+}
+
+fn check_users_file(path: &str) -> Result<String, String> {
+    let store = UserStore::load(path)?;
+
+    Ok(format!(
+        "{path} loaded ({} accounts)",
+        store.get_usernames().len()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn missing_root_fails() {
+        assert!(check_directory(env::temp_dir().to_str().unwrap()).is_ok());
+        assert!(check_directory("/nonexistent/ftp-paradise").is_err());
+    }
+
+    #[test]
+    fn default_config_runs_everywhere() {
+        assert!(check_platform(&Config::default()).is_ok());
+    }
+
+    #[test]
+    fn port_in_use_fails() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        assert!(check_bind(address).is_err());
+
+        drop(listener);
+        assert!(check_bind(address).is_ok());
+    }
+}
@@ -1,27 +1,73 @@
+pub mod client;
 pub mod commands;
 pub mod config;
+pub mod doctor;
+pub mod logging;
 pub mod options;
+pub mod platform;
+pub mod protocol;
 pub mod server;
 pub mod thread_pool;
-pub mod platform;
+pub mod user_store;
 
-use std::sync::OnceLock;
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+};
 
-use crate::server::ftp_server::FtpServer;
+use crate::server::{
+    extensions::Extensions, ftp_server::FtpServer, virtual_host::VirtualHostContext,
+};
 use config::Config;
+use user_store::UserStore;
 
-// Indique que la ligne du dessous ne sera incluse que sur des plateformes 'Linux'.
-#[cfg(target_os = "linux")]
-use std::{net::TcpStream, thread};
-#[cfg(target_os = "linux")]
+// Indique que la ligne du dessous ne sera incluse que sur des plateformes 'Unix'.
+#[cfg(unix)]
 use signal_hook::{
     consts::{SIGINT, SIGTERM},
     iterator::Signals,
 };
+#[cfg(unix)]
+use std::thread;
+
+/// Version of the server, given by `ftp-paradise version` and SITE VERSION.
+pub static VERSION: &str = "0.0.1";
 
 static CONFIG: OnceLock<Config> = OnceLock::new();
+static USER_STORE: OnceLock<UserStore> = OnceLock::new();
+static VIRTUAL_HOSTS: OnceLock<Vec<VirtualHostContext>> = OnceLock::new();
+static SHUTDOWN: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+static EXTENSIONS: OnceLock<Extensions> = OnceLock::new();
 
 pub fn run(config: Config) -> Result<(), String> {
+    run_with_extensions(config, Extensions::new())
+}
+
+/// Run the server like [`run`], with the commands added by `extensions`.
+pub fn run_with_extensions(config: Config, extensions: Extensions) -> Result<(), String> {
+    logging::start(config.get_logging())?;
+
+    if EXTENSIONS.set(extensions).is_err() {
+        return Err("cannot create singleton extensions".to_string());
+    }
+
+    if let Some(path) = config.get_users_file() {
+        let store = UserStore::load(&path)?;
+
+        if USER_STORE.set(store).is_err() {
+            return Err("cannot create singleton user store".to_string());
+        }
+    }
+
+    let virtual_hosts = VirtualHostContext::load_all(config.get_virtual_hosts())?;
+
+    if VIRTUAL_HOSTS.set(virtual_hosts).is_err() {
+        return Err("cannot create singleton virtual hosts".to_string());
+    }
+
     match CONFIG.set(config) {
         Ok(()) => (),
         Err(_) => return Err("cannot create singleton config".to_string()),
@@ -34,13 +80,15 @@ pub fn run(config: Config) -> Result<(), String> {
         }
     };
 
+    if SHUTDOWN.set(ftp_server.get_shutdown_rc()).is_err() {
+        return Err("cannot create singleton shutdown flag".to_string());
+    }
+
     // Indique que l'on veut intercepter les signaux SIGINT et SIGTERM.
-    // Uniquement sur les plateformes 'Linux'.
-    #[cfg(target_os = "linux")]
+    // Uniquement sur les plateformes 'Unix'.
+    #[cfg(unix)]
     {
-        let server_shutdown = ftp_server.get_shutdown_rc();
-
-        let mut signals = match Signals::new(&[SIGINT, SIGTERM]) {
+        let mut signals = match Signals::new([SIGINT, SIGTERM]) {
             Ok(sig) => sig,
             Err(err) => {
                 return Err(format!("cannot create signals handler: {err}"));
@@ -52,12 +100,7 @@ pub fn run(config: Config) -> Result<(), String> {
             for _ in signals.forever() {
                 println!("Interrupt signal received, cleaning up...");
 
-                server_shutdown.store(true, std::sync::atomic::Ordering::Relaxed);
-                let _ = TcpStream::connect(format!(
-                    "{}:{}",
-                    CONFIG.get().unwrap().get_hostname(),
-                    CONFIG.get().unwrap().get_port()
-                ));
+                request_shutdown();
 
                 println!("Server stopped.");
             }
@@ -69,5 +112,33 @@ pub fn run(config: Config) -> Result<(), String> {
     Ok(())
 }
 
+/// Stop accepting clients and make the sessions end, so that [`run`] returns once they are done.
+///
+/// Does nothing if the server has not been started.
+pub fn request_shutdown() {
+    let shutdown = match SHUTDOWN.get() {
+        Some(s) => s,
+        None => return,
+    };
+
+    shutdown.store(true, Ordering::Relaxed);
+
+    // Chaque listener attend sur 'accept', une connexion le réveille pour qu'il voie le drapeau.
+    // Windows refuse de se connecter à l'adresse non spécifiée, on passe alors par la boucle
+    // locale.
+    let listeners = CONFIG.get().unwrap().get_listeners().unwrap_or_default();
+
+    for mut address in listeners {
+        if address.ip().is_unspecified() {
+            address.set_ip(match address {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            });
+        }
+
+        let _ = TcpStream::connect(address);
+    }
+}
+
 #[cfg(test)]
 mod tests {}
@@ -0,0 +1,200 @@
+//! Writing of the output of the server to a log file, rotated by size or by period without
+//! needing an external tool such as logrotate.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use chrono::Local;
+use flate2::{write::GzEncoder, Compression};
+
+use crate::{
+    config::{LogRotation, LoggingSettings},
+    platform::crossplatform,
+};
+
+/// Time between two checks of the size and the age of the log file.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Send the output of the server to the log file of `settings`, if any, and rotate it in the
+/// background as configured.
+pub fn start(settings: &LoggingSettings) -> Result<(), String> {
+    let path = match &settings.file {
+        Some(f) => PathBuf::from(f),
+        None => return Ok(()),
+    };
+
+    open(&path).map_err(|err| format!("cannot open log file {}: {err}", path.display()))?;
+
+    if settings.max_size == 0 && settings.rotate_every == LogRotation::Never {
+        return Ok(());
+    }
+
+    let settings = settings.clone();
+
+    thread::Builder::new()
+        .name("log-rotation".to_string())
+        .spawn(move || watch(&path, &settings))
+        .map_err(|err| format!("cannot start log rotation: {err}"))?;
+
+    Ok(())
+}
+
+/// Open the log file at `path` and make it the output of the process.
+fn open(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    crossplatform::redirect_output(&file)
+}
+
+fn watch(path: &Path, settings: &LoggingSettings) {
+    let mut period = get_period(settings.rotate_every);
+
+    loop {
+        thread::sleep(CHECK_INTERVAL);
+
+        let current_period = get_period(settings.rotate_every);
+
+        let too_big = settings.max_size > 0
+            && fs::metadata(path).is_ok_and(|meta| meta.len() >= settings.max_size);
+
+        if !too_big && current_period == period {
+            continue;
+        }
+
+        period = current_period;
+
+        if let Err(err) = rotate(path, settings) {
+            eprintln!("Cannot rotate log file {}: {err}.", path.display());
+        }
+    }
+}
+
+/// Identifier of the period containing the current time, which changes when the log file must be
+/// rotated.
+fn get_period(rotation: LogRotation) -> String {
+    let format = match rotation {
+        LogRotation::Never => return String::new(),
+        LogRotation::Hourly => "%Y%m%d%H",
+        LogRotation::Daily => "%Y%m%d",
+    };
+
+    Local::now().format(format).to_string()
+}
+
+/// Move the log file at `path` to `path.1`, starting a new one, and compress it if needed.
+fn rotate(path: &Path, settings: &LoggingSettings) -> io::Result<()> {
+    shift(path, settings.keep)?;
+
+    // Le fichier renommé reste ouvert par le processus tant que la sortie n'est pas redirigée.
+    open(path)?;
+
+    let rotated = get_rotated_path(path, 1, false);
+
+    if settings.keep == 0 {
+        return fs::remove_file(rotated);
+    }
+
+    if settings.compress {
+        compress(&rotated)?;
+    }
+
+    Ok(())
+}
+
+/// Rename the log file at `path` and its rotated files so that `path.1` is free, deleting those
+/// beyond `keep`.
+fn shift(path: &Path, keep: usize) -> io::Result<()> {
+    for compressed in [false, true] {
+        let oldest = get_rotated_path(path, keep.max(1), compressed);
+
+        if oldest.exists() {
+            fs::remove_file(oldest)?;
+        }
+    }
+
+    for index in (1..keep).rev() {
+        for compressed in [false, true] {
+            let from = get_rotated_path(path, index, compressed);
+
+            if from.exists() {
+                fs::rename(from, get_rotated_path(path, index + 1, compressed))?;
+            }
+        }
+    }
+
+    fs::rename(path, get_rotated_path(path, 1, false))
+}
+
+/// Replace the file at `path` by `path.gz`.
+fn compress(path: &Path) -> io::Result<()> {
+    let mut compressed = path.as_os_str().to_owned();
+    compressed.push(".gz");
+
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut encoder = GzEncoder::new(File::create(&compressed)?, Compression::default());
+
+    io::copy(&mut reader, &mut encoder)?;
+    encoder.finish()?;
+
+    fs::remove_file(path)
+}
+
+fn get_rotated_path(path: &Path, index: usize, compressed: bool) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{index}"));
+
+    if compressed {
+        rotated.push(".gz");
+    }
+
+    PathBuf::from(rotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, io::Read, process};
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    #[test]
+    fn rotated_files_are_shifted_and_compressed() {
+        let directory = env::temp_dir().join(format!("ftp-paradise-logging-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let path = directory.join("server.log");
+
+        // Trois rotations avec deux fichiers gardés : le premier journal doit disparaître.
+        for content in ["first", "second", "third"] {
+            fs::write(&path, content).unwrap();
+            shift(&path, 2).unwrap();
+            compress(&get_rotated_path(&path, 1, false)).unwrap();
+        }
+
+        let read = |index| {
+            let mut content = String::new();
+            GzDecoder::new(File::open(get_rotated_path(&path, index, true)).unwrap())
+                .read_to_string(&mut content)
+                .unwrap();
+
+            content
+        };
+
+        assert_eq!(read(1), "third");
+        assert_eq!(read(2), "second");
+        assert!(!get_rotated_path(&path, 3, true).exists());
+        assert!(!get_rotated_path(&path, 1, false).exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,19 +1,331 @@
-use std::{env, error::Error, process};
+use std::{
+    env,
+    error::Error,
+    io::{self, BufRead},
+    net::SocketAddr,
+    path::{self, Path},
+    process,
+};
 
-use ftp_paradise::config::Config;
+use ftp_paradise::{
+    client::Client,
+    config::{Config, PortRange},
+    doctor,
+    platform::service,
+    user_store::{self, UserStore},
+    VERSION,
+};
 
-static VERSION: &str = "0.0.1";
+static USAGE: &str = "\
+Usage: ftp-paradise [SUBCOMMAND] [OPTIONS]
+       ftp-paradise mirror <ADDRESS:PORT> <REMOTE_DIR> <LOCAL_DIR> [MIRROR OPTIONS]
+       ftp-paradise service <install|uninstall|start|stop> [OPTIONS]
+
+Subcommands:
+    serve            Start the FTP server (default)
+    check-config     Check the configuration without starting the server
+    doctor           Check that the environment is ready to host the server
+    hash-password    Read a password on the standard input and print its hash
+    list-users       Print the accounts of the users file
+    mirror           Download a remote directory and everything below it
+    service          Manage the Windows service, installed with the given options
+    version          Print the version
+    help             Print this message
+
+Options:
+    -c, --config <PATH>            TOML file to load the configuration from
+    -h, --hostname <ADDRESS>       Address to host the server on, IPv4 or IPv6
+    -p, --port <PORT>              Port to host the server on
+    -u, --users <PATH>             File containing the accounts allowed to log in
+    -r, --root <PATH>              Directory shown to the clients as /
+    -t, --threads <COUNT>          Number of clients served at the same time
+        --passive-ports <START-END>
+                                   Ports used for the passive mode
+        --chown-uploads            Give uploaded files to the system user of the session
+    -v, --version                  Print the version
+        --help                     Print this message
+
+Options given on the command line override the ones of the config file.
+
+Mirror options:
+        --user <NAME>              User to log in as (default: anonymous)
+        --password <PASSWORD>      Password sent if the server asks for one";
+
+/// What the application has been asked to do from the command line.
+#[derive(Debug, PartialEq)]
+enum Command {
+    Serve(CliOptions),
+    CheckConfig(CliOptions),
+    Doctor(CliOptions),
+    HashPassword,
+    ListUsers(CliOptions),
+    Mirror(MirrorOptions),
+    Service(ServiceCommand),
+    Version,
+    Help,
+}
+
+/// Options given on the command line, applied on top of the config file.
+#[derive(Debug, Default, PartialEq)]
+struct CliOptions {
+    config_file: Option<String>,
+    hostname: Option<String>,
+    port: Option<String>,
+    users_file: Option<String>,
+    root: Option<String>,
+    threads: Option<usize>,
+    passive_ports: Option<PortRange>,
+    chown_uploads: bool,
+}
+
+/// Action of the subcommand `service`.
+#[derive(Debug, PartialEq)]
+enum ServiceCommand {
+    /// Register the service, started with these options.
+    Install(Vec<String>),
+    Uninstall,
+    Start,
+    Stop,
+    /// Serve as the service, when started by the service control manager.
+    Run(CliOptions),
+}
+
+/// Remote directory to download with the subcommand `mirror`.
+#[derive(Debug, PartialEq)]
+struct MirrorOptions {
+    address: String,
+    remote_path: String,
+    local_path: String,
+    username: String,
+    password: String,
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
-    // Récupère la configuration de l'application depuis la ligne de commande passée.
-    let config = parse_args(env::args()).unwrap_or_else(|err| {
+    // Récupère la sous-commande à exécuter depuis la ligne de commande passée.
+    let command = parse_args(env::args()).unwrap_or_else(|err| {
         eprintln!("Error parsing arguments: {err}.");
 
         process::exit(1);
     });
 
+    match command {
+        Command::Serve(options) => serve(build_config(options)),
+        Command::CheckConfig(options) => check_config(build_config(options)),
+        Command::Doctor(options) => doctor(build_config(options)),
+        Command::HashPassword => hash_password(),
+        Command::ListUsers(options) => list_users(build_config(options)),
+        Command::Mirror(options) => mirror(options),
+        Command::Service(command) => manage_service(command),
+        Command::Version => println!("FTP Paradise v{VERSION}"),
+        Command::Help => println!("{USAGE}"),
+    }
+
+    Ok(())
+}
+
+/// Parse the command line without touching the file system.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    // Saute le 1er argument car c'est normalement le nom du programme.
+    args.next();
+
+    let args: Vec<String> = args.collect();
+
+    // Ces options sont prioritaires quelle que soit la sous-commande.
+    if args.iter().any(|arg| arg == "--help") {
+        return Ok(Command::Help);
+    }
+
+    if args.iter().any(|arg| arg == "--version" || arg == "-v") {
+        return Ok(Command::Version);
+    }
+
+    let mut args = args.into_iter().peekable();
+
+    // Sans sous-commande, le serveur est démarré pour rester compatible avec l'ancienne syntaxe.
+    let subcommand = match args.peek() {
+        Some(arg) if !arg.starts_with('-') => args.next().unwrap(),
+        _ => "serve".to_string(),
+    };
+
+    match &subcommand[..] {
+        "serve" => Ok(Command::Serve(parse_options(args)?)),
+        "check-config" => Ok(Command::CheckConfig(parse_options(args)?)),
+        "doctor" => Ok(Command::Doctor(parse_options(args)?)),
+        "list-users" => Ok(Command::ListUsers(parse_options(args)?)),
+        "mirror" => Ok(Command::Mirror(parse_mirror_options(args)?)),
+        "service" => Ok(Command::Service(parse_service_command(args)?)),
+        "hash-password" => Ok(Command::HashPassword),
+        "version" => Ok(Command::Version),
+        "help" => Ok(Command::Help),
+        _ => Err(format!(
+            "unknown subcommand {subcommand}, use --help to list them"
+        )),
+    }
+}
+
+/// Parse the options following the subcommand.
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<CliOptions, String> {
+    let mut options = CliOptions::default();
+
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            // Récupère le fichier de configuration à charger.
+            "--config" | "-c" => options.config_file = Some(option_value(&mut args, &arg)?),
+            // Récupère l'adresse à utiliser pour héberger le serveur.
+            "--hostname" | "-h" => options.hostname = Some(option_value(&mut args, &arg)?),
+            // Récupère le port à utiliser pour héberger le serveur.
+            "--port" | "-p" => options.port = Some(option_value(&mut args, &arg)?),
+            // Récupère le fichier contenant les comptes autorisés à se connecter.
+            "--users" | "-u" => options.users_file = Some(option_value(&mut args, &arg)?),
+            // Récupère le dossier présenté aux clients comme étant la racine.
+            "--root" | "-r" => options.root = Some(option_value(&mut args, &arg)?),
+            "--threads" | "-t" => {
+                options.threads = match option_value(&mut args, &arg)?.parse() {
+                    Ok(t) if t > 0 => Some(t),
+                    _ => return Err(format!("{arg} expects a number greater than 0")),
+                }
+            }
+            "--passive-ports" => {
+                options.passive_ports = Some(option_value(&mut args, &arg)?.parse()?);
+            }
+            // Donne la propriété des fichiers envoyés à l'utilisateur système de la session.
+            "--chown-uploads" => options.chown_uploads = true,
+            // Ignorer silencieusement une option mal orthographiée laisserait l'utilisateur
+            // croire qu'elle a été prise en compte.
+            _ => return Err(format!("unknown option {arg}")),
+        }
+    }
+
+    Ok(options)
+}
+
+/// Parse the arguments of the subcommand `mirror`.
+fn parse_mirror_options(mut args: impl Iterator<Item = String>) -> Result<MirrorOptions, String> {
+    let mut positionals = Vec::new();
+    let mut username = "anonymous".to_string();
+    let mut password = "anonymous@".to_string();
+
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--user" => username = option_value(&mut args, &arg)?,
+            "--password" => password = option_value(&mut args, &arg)?,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {arg}")),
+            _ => positionals.push(arg),
+        }
+    }
+
+    let [address, remote_path, local_path]: [String; 3] = match positionals.try_into() {
+        Ok(p) => p,
+        Err(_) => {
+            return Err("mirror expects an address, a remote and a local directory".to_string())
+        }
+    };
+
+    Ok(MirrorOptions {
+        address,
+        remote_path,
+        local_path,
+        username,
+        password,
+    })
+}
+
+/// Parse the action of the subcommand `service` and its options.
+fn parse_service_command(mut args: impl Iterator<Item = String>) -> Result<ServiceCommand, String> {
+    let action = match args.next() {
+        Some(a) => a,
+        None => return Err("service expects install, uninstall, start, stop or run".to_string()),
+    };
+
+    let args: Vec<String> = args.collect();
+
+    let command = match &action[..] {
+        "install" => {
+            // Les options sont vérifiées maintenant plutôt qu'au démarrage du service.
+            parse_options(args.iter().cloned())?;
+
+            return Ok(ServiceCommand::Install(args));
+        }
+        "run" => return Ok(ServiceCommand::Run(parse_options(args.into_iter())?)),
+        "uninstall" => ServiceCommand::Uninstall,
+        "start" => ServiceCommand::Start,
+        "stop" => ServiceCommand::Stop,
+        _ => return Err(format!("unknown service action {action}")),
+    };
+
+    match args.first() {
+        Some(arg) => Err(format!("unexpected argument {arg} after service {action}")),
+        None => Ok(command),
+    }
+}
+
+/// Get the value following the option `name`.
+fn option_value(args: &mut impl Iterator<Item = String>, name: &str) -> Result<String, String> {
+    match args.next() {
+        Some(value) => Ok(value),
+        None => Err(format!("no value specified after {name}")),
+    }
+}
+
+/// Load the config file if one has been given, then apply the command line options on top.
+fn build_config(options: CliOptions) -> Config {
+    let mut config = match &options.config_file {
+        Some(path) => Config::load(path).unwrap_or_else(|err| {
+            eprintln!("Error in config: {err}.");
+
+            process::exit(1);
+        }),
+        None => Config::default(),
+    };
+
+    if let Some(hostname) = options.hostname {
+        config.set_hostname(hostname);
+    }
+
+    if let Some(port) = options.port {
+        config.set_port(port);
+    }
+
+    if let Some(users_file) = options.users_file {
+        config.set_users_file(Some(users_file));
+    }
+
+    if let Some(root) = options.root {
+        config.set_root(root);
+    }
+
+    if let Some(threads) = options.threads {
+        config.set_threads(threads);
+    }
+
+    if let Some(passive_ports) = options.passive_ports {
+        config.set_passive_ports(passive_ports);
+    }
+
+    if options.chown_uploads {
+        config.set_chown_uploads(true);
+    }
+
+    config
+}
+
+/// Make sure the options needed to host the server have been given.
+fn check_address(config: &Config) -> Result<(), &'static str> {
+    if config.get_hostname().is_empty() {
+        return Err("no hostname specified");
+    }
+
+    // Le port peut être donné avec l'adresse, comme dans '[::]:21'.
+    if config.get_port().is_empty() && config.get_hostname().parse::<SocketAddr>().is_err() {
+        return Err("no port specified");
+    }
+
+    Ok(())
+}
+
+fn serve(config: Config) {
     // Vérifie que la configuration est valide.
-    if let Err(err) = config.check() {
+    if let Err(err) = check_address(&config).and_then(|_| config.check()) {
         eprintln!("Error in config: {err}.");
 
         process::exit(1);
@@ -25,61 +337,398 @@ fn main() -> Result<(), Box<dyn Error>> {
 
         process::exit(1);
     }
-
-    Ok(())
 }
 
-fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
-    // Saute le 1er argument car c'est normalement le nom du programme.
-    args.next();
+fn manage_service(command: ServiceCommand) {
+    let (result, done) = match command {
+        ServiceCommand::Install(args) => (service::install(&absolute_paths(args)), "installed"),
+        ServiceCommand::Uninstall => (service::uninstall(), "uninstalled"),
+        ServiceCommand::Start => (service::start(), "started"),
+        ServiceCommand::Stop => (service::stop(), "asked to stop"),
+        ServiceCommand::Run(options) => {
+            let config = build_config(options);
 
-    let mut hostname = String::new();
-    let mut port = String::new();
+            if let Err(err) = check_address(&config).and_then(|_| config.check()) {
+                eprintln!("Error in config: {err}.");
 
-    while let Some(arg) = args.next() {
-        match &arg[..] {
-            // Récupère l'adresse à utiliser pour héberger le serveur.
-            "--hostname" | "-h" => {
-                // Il faut qu'il y ai un argument après celui-ci qui contient l'adresse en
-                // question.
-                if let Some(h) = args.next() {
-                    hostname = h;
-                } else {
-                    // S'il n'y a pas d'adresse spécifiée mais qu'une adresse avait déjà été
-                    // spécifiée auparavant, alors il n'y a pas d'erreur.
-                    if hostname.is_empty() {
-                        return Err("no hostname specified after --hostname argument");
-                    }
-                }
+                process::exit(1);
             }
-            // Récupère le port à utiliser pour héberger le serveur.
-            "--port" | "-p" => {
-                // Il faut qu'il y ai un argument après celui-ci qui contient le port en question.
-                if let Some(p) = args.next() {
-                    port = p;
-                } else {
-                    // S'il n'y a pas de port spécifié mais qu'un port avait déjà été spécifié
-                    // auparavant, alors il n'y a pas d'erreur.
-                    if port.is_empty() {
-                        return Err("no port number specified after --port argument");
-                    }
-                }
+
+            (service::run(config), "stopped")
+        }
+    };
+
+    match result {
+        Ok(()) => println!("Service {done}."),
+        Err(err) => {
+            eprintln!("Error: {err}.");
+
+            process::exit(1);
+        }
+    }
+}
+
+/// Make the paths given to the options absolute, because a service does not start in the current
+/// directory.
+fn absolute_paths(args: Vec<String>) -> Vec<String> {
+    let mut args = args.into_iter();
+    let mut absolute = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let is_path = matches!(
+            &arg[..],
+            "--config" | "-c" | "--users" | "-u" | "--root" | "-r"
+        );
+
+        absolute.push(arg);
+
+        if is_path {
+            if let Some(value) = args.next() {
+                absolute.push(match path::absolute(&value) {
+                    Ok(p) => p.to_string_lossy().into_owned(),
+                    Err(_) => value,
+                });
             }
-            "--version" | "-v" => {
-                eprintln!("FTP Paradise v{VERSION}");
-                process::exit(0);
+        }
+    }
+
+    absolute
+}
+
+fn check_config(config: Config) {
+    if let Err(err) = check_address(&config).and_then(|_| config.check()) {
+        eprintln!("Error in config: {err}.");
+
+        process::exit(1);
+    }
+
+    // Charge le fichier d'utilisateurs pour détecter les entrées invalides avant le démarrage.
+    if let Some(path) = config.get_users_file() {
+        if let Err(err) = UserStore::load(&path) {
+            eprintln!("Error in users file: {err}.");
+
+            process::exit(1);
+        }
+    }
+
+    for host in config.get_virtual_hosts() {
+        if let Some(path) = &host.users_file {
+            if let Err(err) = UserStore::load(path) {
+                eprintln!("Error in users file of virtual host {}: {err}.", host.root);
+
+                process::exit(1);
             }
-            _ => (),
         }
     }
 
-    if hostname.is_empty() {
-        return Err("no hostname specified");
+    println!("Configuration OK.");
+}
+
+/// Run every check of the environment and print a summary, failing if one of them did.
+fn doctor(config: Config) {
+    let checks = doctor::run(&config);
+
+    for check in &checks {
+        match &check.outcome {
+            Ok(details) => println!("[ OK ] {}: {details}", check.name),
+            Err(err) => println!("[FAIL] {}: {err}", check.name),
+        }
     }
 
-    if port.is_empty() {
-        return Err("no port specified");
+    let failed = checks.iter().filter(|check| !check.is_ok()).count();
+
+    println!("{} checks, {failed} failed.", checks.len());
+
+    if failed > 0 {
+        process::exit(1);
     }
+}
 
-    Ok(Config::new(hostname, port))
+fn list_users(config: Config) {
+    let path = match config.get_users_file() {
+        Some(p) => p,
+        None => {
+            eprintln!("Error: no users file specified, use --users.");
+
+            process::exit(1);
+        }
+    };
+
+    match UserStore::load(&path) {
+        Ok(store) => store
+            .get_usernames()
+            .iter()
+            .for_each(|username| println!("{username}")),
+        Err(err) => {
+            eprintln!("Error in users file: {err}.");
+
+            process::exit(1);
+        }
+    }
+}
+
+fn mirror(options: MirrorOptions) {
+    let result = Client::connect(&options.address).and_then(|mut client| {
+        client.login(&options.username, &options.password)?;
+
+        let summary = client.mirror(&options.remote_path, Path::new(&options.local_path))?;
+
+        client.quit()?;
+
+        Ok(summary)
+    });
+
+    match result {
+        Ok(summary) => println!(
+            "Mirrored {} directories and {} files ({} bytes).",
+            summary.directories, summary.files, summary.bytes
+        ),
+        Err(err) => {
+            eprintln!("Error: {err}.");
+
+            process::exit(1);
+        }
+    }
+}
+
+/// Read a password from the standard input and print its hash, ready to be copied in the users
+/// file.
+fn hash_password() {
+    eprint!("Password: ");
+
+    let mut password = String::new();
+
+    if let Err(err) = io::stdin().lock().read_line(&mut password) {
+        eprintln!("Error reading password: {err}.");
+
+        process::exit(1);
+    }
+
+    // Retire uniquement la fin de ligne pour conserver les espaces voulus dans le mot de passe.
+    let password = password.trim_end_matches(['\r', '\n']);
+
+    match user_store::hash_password(password) {
+        Ok(hash) => println!("{hash}"),
+        Err(err) => {
+            eprintln!("Error: {err}.");
+
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        let args = ["ftp-paradise"].iter().chain(args).map(|a| a.to_string());
+
+        parse_args(args)
+    }
+
+    #[test]
+    fn no_subcommand_means_serve() {
+        let expected = CliOptions {
+            hostname: Some("127.0.0.1".to_string()),
+            port: Some("21".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            parse(&["-h", "127.0.0.1", "-p", "21"]),
+            Ok(Command::Serve(expected))
+        );
+        assert_eq!(parse(&[]), Ok(Command::Serve(CliOptions::default())));
+    }
+
+    #[test]
+    fn subcommands() {
+        assert_eq!(parse(&["serve"]), Ok(Command::Serve(CliOptions::default())));
+        assert_eq!(
+            parse(&["check-config"]),
+            Ok(Command::CheckConfig(CliOptions::default()))
+        );
+        assert_eq!(
+            parse(&["list-users"]),
+            Ok(Command::ListUsers(CliOptions::default()))
+        );
+        assert_eq!(
+            parse(&["doctor"]),
+            Ok(Command::Doctor(CliOptions::default()))
+        );
+        assert_eq!(parse(&["hash-password"]), Ok(Command::HashPassword));
+        assert!(matches!(
+            parse(&["mirror", "127.0.0.1:21", "/pub", "pub"]),
+            Ok(Command::Mirror(_))
+        ));
+        assert_eq!(parse(&["version"]), Ok(Command::Version));
+        assert_eq!(parse(&["help"]), Ok(Command::Help));
+        assert!(parse(&["start"]).is_err());
+    }
+
+    #[test]
+    fn service_actions() {
+        assert_eq!(
+            parse(&["service", "install", "-c", "ftp.toml"]),
+            Ok(Command::Service(ServiceCommand::Install(vec![
+                "-c".to_string(),
+                "ftp.toml".to_string()
+            ])))
+        );
+        assert_eq!(
+            parse(&["service", "run", "-p", "21"]),
+            Ok(Command::Service(ServiceCommand::Run(CliOptions {
+                port: Some("21".to_string()),
+                ..Default::default()
+            })))
+        );
+        assert_eq!(
+            parse(&["service", "stop"]),
+            Ok(Command::Service(ServiceCommand::Stop))
+        );
+        assert!(parse(&["service"]).is_err());
+        assert!(parse(&["service", "install", "--bogus"]).is_err());
+        assert!(parse(&["service", "start", "now"]).is_err());
+    }
+
+    #[test]
+    fn help_and_version_take_priority() {
+        assert_eq!(parse(&["--help"]), Ok(Command::Help));
+        assert_eq!(parse(&["serve", "--bogus", "--help"]), Ok(Command::Help));
+        assert_eq!(parse(&["--version"]), Ok(Command::Version));
+        assert_eq!(parse(&["check-config", "-v"]), Ok(Command::Version));
+    }
+
+    #[test]
+    fn every_option() {
+        let expected = CliOptions {
+            config_file: Some("ftp.toml".to_string()),
+            hostname: Some("0.0.0.0".to_string()),
+            port: Some("2121".to_string()),
+            users_file: Some("users".to_string()),
+            root: Some("/srv/ftp".to_string()),
+            threads: Some(4),
+            passive_ports: Some(PortRange {
+                start: 50000,
+                end: 50100,
+            }),
+            chown_uploads: true,
+        };
+
+        assert_eq!(
+            parse(&[
+                "serve",
+                "--config",
+                "ftp.toml",
+                "--hostname",
+                "0.0.0.0",
+                "--port",
+                "2121",
+                "--users",
+                "users",
+                "--root",
+                "/srv/ftp",
+                "--threads",
+                "4",
+                "--passive-ports",
+                "50000-50100",
+                "--chown-uploads",
+            ]),
+            Ok(Command::Serve(expected))
+        );
+    }
+
+    #[test]
+    fn short_options() {
+        let expected = CliOptions {
+            config_file: Some("ftp.toml".to_string()),
+            hostname: Some("0.0.0.0".to_string()),
+            port: Some("2121".to_string()),
+            users_file: Some("users".to_string()),
+            root: Some("/srv/ftp".to_string()),
+            threads: Some(4),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            parse(&[
+                "-c", "ftp.toml", "-h", "0.0.0.0", "-p", "2121", "-u", "users", "-r", "/srv/ftp",
+                "-t", "4",
+            ]),
+            Ok(Command::Serve(expected))
+        );
+    }
+
+    #[test]
+    fn last_occurrence_wins() {
+        let expected = CliOptions {
+            port: Some("22".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            parse(&["-p", "21", "-p", "22"]),
+            Ok(Command::Serve(expected))
+        );
+    }
+
+    #[test]
+    fn unknown_options_are_rejected() {
+        assert!(parse(&["--hostnme", "127.0.0.1"]).is_err());
+        assert!(parse(&["serve", "extra"]).is_err());
+        assert!(parse(&["list-users", "-x"]).is_err());
+    }
+
+    #[test]
+    fn missing_values_are_rejected() {
+        for option in [
+            "--config",
+            "--hostname",
+            "--port",
+            "--users",
+            "--root",
+            "--threads",
+            "--passive-ports",
+        ] {
+            assert!(parse(&[option]).is_err(), "{option} without value");
+        }
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert!(parse(&["--threads", "0"]).is_err());
+        assert!(parse(&["--threads", "many"]).is_err());
+        assert!(parse(&["--passive-ports", "50000"]).is_err());
+        assert!(parse(&["--passive-ports", "50100-50000"]).is_err());
+        assert!(parse(&["--passive-ports", "0-10"]).is_err());
+        assert!(parse(&["--passive-ports", "a-b"]).is_err());
+        assert!(parse(&["--passive-ports", "1-70000"]).is_err());
+    }
+
+    #[test]
+    fn mirror_options() {
+        assert_eq!(
+            parse(&[
+                "mirror",
+                "127.0.0.1:2121",
+                "/pub",
+                "mirror",
+                "--user",
+                "alice",
+                "--password",
+                "secret",
+            ]),
+            Ok(Command::Mirror(MirrorOptions {
+                address: "127.0.0.1:2121".to_string(),
+                remote_path: "/pub".to_string(),
+                local_path: "mirror".to_string(),
+                username: "alice".to_string(),
+                password: "secret".to_string(),
+            }))
+        );
+
+        assert!(parse(&["mirror", "127.0.0.1:2121", "/pub"]).is_err());
+        assert!(parse(&["mirror", "127.0.0.1:2121", "/pub", "pub", "--port", "21"]).is_err());
+    }
 }
@@ -1,15 +1,153 @@
 pub mod data_representation;
 pub mod listen_mode;
 pub mod session;
+pub mod transfer_mode;
+pub mod transfer_statistics;
 
+use chrono::{DateTime, Utc};
+
+use crate::protocol::facts::{self, Fact};
 use session::SessionInformations;
+use transfer_mode::TransferMode;
+use transfer_statistics::TransferStatistics;
 
 use self::{data_representation::DataType, listen_mode::ListenMode};
 
 pub struct ClientOptions {
     pub session: Option<SessionInformations>,
+    /// Directory of the system shown as `/`, which depends on the virtual host.
+    pub root: String,
+    /// Index of the virtual host selected for the session, if any.
+    pub virtual_host: Option<usize>,
     pub working_directory: String,
     pub data_representation: DataType,
     pub local_bytes: i32,
     pub listen_mode: ListenMode,
+    pub transfer_mode: TransferMode,
+    /// Inclusive byte range set by RANG for the next download.
+    pub range: Option<(u64, u64)>,
+    /// Offset set by REST for the next download or upload.
+    pub restart: Option<u64>,
+    /// Size announced by ALLO for the next upload.
+    pub allocation: Option<u64>,
+    /// Modification time set by MFMT on a file that did not exist yet, applied once it has been
+    /// uploaded.
+    pub pending_modify: Option<(String, DateTime<Utc>)>,
+    /// Path given by RNFR, renamed by the command that follows if it is RNTO.
+    pub rename_from: Option<String>,
+    /// Facts sent by MLSD and MLST, in this order, chosen with `OPTS MLST`.
+    pub mlst_facts: Vec<Fact>,
+    /// Name of the client software, sent with CLNT.
+    pub client_name: Option<String>,
+    /// Set by `EPSV ALL`, after which only EPSV can open a data connection.
+    pub epsv_all: bool,
+    /// Send the SHA-256 of each upload in its 226 reply, set by `SITE HASHONUPLOAD`.
+    pub hash_on_upload: bool,
+    pub transfer_statistics: TransferStatistics,
+}
+
+impl ClientOptions {
+    /// Options of a session that just connected, serving the files of `root`.
+    pub fn new(root: String) -> ClientOptions {
+        ClientOptions {
+            session: None,
+            root,
+            virtual_host: None,
+            working_directory: "/".to_string(),
+            // Le protocole indique que le type par défaut est ASCII.
+            data_representation: DataType::ASCII,
+            local_bytes: 0,
+            listen_mode: ListenMode::Active,
+            transfer_mode: TransferMode::Stream,
+            range: None,
+            restart: None,
+            allocation: None,
+            pending_modify: None,
+            rename_from: None,
+            client_name: None,
+            mlst_facts: facts::SUPPORTED_FACTS.to_vec(),
+            epsv_all: false,
+            hash_on_upload: false,
+            transfer_statistics: TransferStatistics::new(),
+        }
+    }
+
+    /// Go back to the options of a session that just connected, as required by REIN.
+    ///
+    /// The account, the virtual host, TYPE, MODE, the data connection settings and the markers
+    /// set for the next transfer are all reset. Only the name sent by CLNT, which describes the
+    /// client rather than the user, and the statistics of the session are kept.
+    pub fn reinitialize(&mut self, root: String) {
+        let previous = std::mem::replace(self, ClientOptions::new(root));
+
+        self.client_name = previous.client_name;
+        self.transfer_statistics = previous.transfer_statistics;
+    }
+
+    /// Update the options before `verb` is executed.
+    ///
+    /// A path given by RNFR is only renamed by the command that immediately follows. The range
+    /// of RANG, the offset of REST and the size of ALLO are kept until the transfer that uses
+    /// them, so that PASV or TYPE can still be sent in between.
+    pub fn before_command(&mut self, verb: &str) {
+        if verb != "RNTO" {
+            self.rename_from = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rein_resets_everything_but_the_client() {
+        let mut options = ClientOptions::new("/srv/ftp".to_string());
+
+        options.root = "/srv/host".to_string();
+        options.virtual_host = Some(1);
+        options.working_directory = "/pub".to_string();
+        options.data_representation = DataType::Image;
+        options.transfer_mode = TransferMode::Deflate;
+        options.listen_mode = ListenMode::Passive;
+        options.range = Some((0, 10));
+        options.restart = Some(5);
+        options.allocation = Some(100);
+        options.rename_from = Some("/a".to_string());
+        options.mlst_facts = vec![Fact::Size];
+        options.epsv_all = true;
+        options.client_name = Some("mirror 1.0".to_string());
+
+        options.reinitialize("/srv/ftp".to_string());
+
+        assert_eq!(options.root, "/srv/ftp");
+        assert_eq!(options.virtual_host, None);
+        assert_eq!(options.working_directory, "/");
+        assert!(matches!(options.data_representation, DataType::ASCII));
+        assert_eq!(options.transfer_mode, TransferMode::Stream);
+        assert!(matches!(options.listen_mode, ListenMode::Active));
+        assert_eq!(options.range, None);
+        assert_eq!(options.restart, None);
+        assert_eq!(options.allocation, None);
+        assert_eq!(options.rename_from, None);
+        assert_eq!(options.mlst_facts, facts::SUPPORTED_FACTS.to_vec());
+        assert!(!options.epsv_all);
+        assert_eq!(options.client_name.as_deref(), Some("mirror 1.0"));
+    }
+
+    #[test]
+    fn rename_only_survives_until_rnto() {
+        let mut options = ClientOptions::new("/".to_string());
+
+        options.rename_from = Some("/a".to_string());
+        options.range = Some((0, 10));
+        options.restart = Some(5);
+        options.before_command("RNTO");
+        assert_eq!(options.rename_from.as_deref(), Some("/a"));
+
+        options.before_command("PWD");
+        assert_eq!(options.rename_from, None);
+        assert_eq!(options.range, Some((0, 10)));
+        assert_eq!(options.restart, Some(5));
+    }
 }
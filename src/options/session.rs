@@ -1,21 +1,43 @@
+use std::net::SocketAddr;
+
 #[derive(Debug)]
 pub struct SessionInformations {
     username: String,
+    peer_address: SocketAddr,
     password: Option<String>,
+    authenticated: bool,
+    temporary: bool,
+    read_only: bool,
 }
 
 impl SessionInformations {
-    pub fn new(username: String, password: Option<String>) -> SessionInformations {
-        SessionInformations { username, password }
+    pub fn new(
+        username: String,
+        password: Option<String>,
+        peer_address: SocketAddr,
+    ) -> SessionInformations {
+        SessionInformations {
+            username,
+            peer_address,
+            password,
+            authenticated: false,
+            temporary: false,
+            read_only: false,
+        }
     }
 
     pub fn get_username(&self) -> &str {
         &self.username
     }
 
+    /// Address of the client that sent USER.
+    pub fn get_peer_address(&self) -> SocketAddr {
+        self.peer_address
+    }
+
     pub fn get_password(&self) -> Option<&str> {
         match &self.password {
-            Some(pass) => Some(&pass),
+            Some(pass) => Some(pass),
             None => None,
         }
     }
@@ -23,4 +45,31 @@ impl SessionInformations {
     pub fn set_password(&mut self, password: String) {
         self.password = Some(password);
     }
+
+    /// Tell if the user has proven its identity with PASS.
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated
+    }
+
+    pub fn set_authenticated(&mut self, authenticated: bool) {
+        self.authenticated = authenticated;
+    }
+
+    /// Tell if the user logged in with temporary credentials, restricted to a shared directory.
+    pub fn is_temporary(&self) -> bool {
+        self.temporary
+    }
+
+    pub fn set_temporary(&mut self, temporary: bool) {
+        self.temporary = temporary;
+    }
+
+    /// Tell if the user can only download and list files.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
 }
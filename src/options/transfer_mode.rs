@@ -0,0 +1,7 @@
+/// Transmission mode chosen with the MODE command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransferMode {
+    Stream,
+    /// MODE Z, the data is sent as a zlib stream.
+    Deflate,
+}
@@ -0,0 +1,134 @@
+use std::time::{Duration, Instant};
+
+/// Amount of data transferred during a session.
+#[derive(Debug)]
+pub struct TransferStatistics {
+    started: Instant,
+    files_uploaded: u64,
+    bytes_uploaded: u64,
+    files_downloaded: u64,
+    bytes_downloaded: u64,
+    transfers_aborted: u64,
+}
+
+impl TransferStatistics {
+    pub fn new() -> TransferStatistics {
+        TransferStatistics {
+            started: Instant::now(),
+            files_uploaded: 0,
+            bytes_uploaded: 0,
+            files_downloaded: 0,
+            bytes_downloaded: 0,
+            transfers_aborted: 0,
+        }
+    }
+
+    /// Count a file received from the client.
+    pub fn record_upload(&mut self, bytes: u64) {
+        self.files_uploaded += 1;
+        self.bytes_uploaded += bytes;
+    }
+
+    /// Count a file sent to the client.
+    pub fn record_download(&mut self, bytes: u64) {
+        self.files_downloaded += 1;
+        self.bytes_downloaded += bytes;
+    }
+
+    /// Count a transfer interrupted before its end, by the client or by an error.
+    pub fn record_abort(&mut self) {
+        self.transfers_aborted += 1;
+    }
+
+    pub fn get_bytes_uploaded(&self) -> u64 {
+        self.bytes_uploaded
+    }
+
+    pub fn get_bytes_downloaded(&self) -> u64 {
+        self.bytes_downloaded
+    }
+
+    pub fn get_files_uploaded(&self) -> u64 {
+        self.files_uploaded
+    }
+
+    pub fn get_files_downloaded(&self) -> u64 {
+        self.files_downloaded
+    }
+
+    pub fn get_transfers_aborted(&self) -> u64 {
+        self.transfers_aborted
+    }
+
+    /// Time elapsed since the beginning of the session.
+    pub fn get_duration(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Describe the statistics as `key=value` fields for the line logged at the end of the session,
+    /// with the `commands` executed and the `errors` among them.
+    pub fn accounting(&self, commands: u64, errors: u64) -> String {
+        self.format_accounting(self.get_duration(), commands, errors)
+    }
+
+    fn format_accounting(&self, duration: Duration, commands: u64, errors: u64) -> String {
+        let bytes = self.bytes_uploaded + self.bytes_downloaded;
+
+        // Une session de moins d'une milliseconde n'a pas de débit significatif.
+        let rate = match duration.as_millis() {
+            0 => 0,
+            millis => (u128::from(bytes) * 1000 / millis) as u64,
+        };
+
+        format!(
+            "duration={}s commands={commands} errors={errors} files_up={} files_down={} bytes_up={} bytes_down={} aborted={} rate={rate}B/s",
+            duration.as_secs(),
+            self.files_uploaded,
+            self.files_downloaded,
+            self.bytes_uploaded,
+            self.bytes_downloaded,
+            self.transfers_aborted,
+        )
+    }
+
+    /// Describe the statistics in a single sentence.
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} files uploaded ({} bytes), {} files downloaded ({} bytes)",
+            self.files_uploaded, self.bytes_uploaded, self.files_downloaded, self.bytes_downloaded,
+        );
+
+        if self.transfers_aborted > 0 {
+            summary.push_str(&format!(", {} transfers aborted", self.transfers_aborted));
+        }
+
+        summary.push_str(&format!(" in {}s", self.get_duration().as_secs()));
+
+        summary
+    }
+}
+
+impl Default for TransferStatistics {
+    fn default() -> TransferStatistics {
+        TransferStatistics::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accounting_fits_on_one_line() {
+        let mut statistics = TransferStatistics::new();
+
+        statistics.record_upload(3000);
+        statistics.record_download(1000);
+        statistics.record_abort();
+
+        assert_eq!(
+            statistics.format_accounting(Duration::from_secs(2), 12, 3),
+            "duration=2s commands=12 errors=3 files_up=1 files_down=1 bytes_up=3000 bytes_down=1000 aborted=1 rate=2000B/s"
+        );
+    }
+}
@@ -1 +1,8 @@
-pub mod crossplatform;
\ No newline at end of file
+pub mod crossplatform;
+pub mod file_info;
+pub mod service;
+
+#[cfg(windows)]
+pub mod windows_service;
+
+pub use file_info::FileInfo;
@@ -1 +1,507 @@
+use std::{fs::File, io, net::TcpStream, path::Path, time::Duration};
 
+// Indique que la ligne du dessous ne sera incluse que sur des plateformes 'Unix' (Linux, macOS,
+// BSD).
+#[cfg(unix)]
+use std::{
+    ffi::{CStr, CString},
+    mem::MaybeUninit,
+    os::{fd::AsRawFd, unix::ffi::OsStrExt},
+    ptr,
+};
+
+/// Features that depend on the operating system, and whether the current one provides them.
+pub const FEATURES: [(&str, bool); 8] = [
+    ("system accounts", cfg!(unix)),
+    ("owner of uploads", cfg!(unix)),
+    ("free space check", cfg!(unix)),
+    (
+        "preallocation",
+        cfg!(any(target_os = "linux", target_os = "macos")),
+    ),
+    (
+        "TCP keep-alive",
+        cfg!(any(
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "freebsd"
+        )),
+    ),
+    (
+        "extended attributes",
+        cfg!(any(
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "freebsd"
+        )),
+    ),
+    ("shutdown on signals", cfg!(unix)),
+    ("log file", cfg!(unix)),
+];
+
+/// Identifiers of an account of the operating system.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemUser {
+    uid: u32,
+    gid: u32,
+}
+
+impl SystemUser {
+    pub fn get_uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// Primary group of the account.
+    pub fn get_gid(&self) -> u32 {
+        self.gid
+    }
+}
+
+/// Tell if the server is running with administrator rights.
+#[cfg(unix)]
+pub fn is_privileged() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+pub fn is_privileged() -> bool {
+    false
+}
+
+/// Find the account of the operating system named `username`.
+///
+/// # Return
+/// `None` if no account matches or if the name cannot be represented by the OS.
+#[cfg(unix)]
+pub fn get_system_user(username: &str) -> Option<SystemUser> {
+    // Un nom contenant un octet nul serait tronqué par la libc, donc on le refuse directement.
+    let name = CString::new(username).ok()?;
+
+    // La taille suggérée par le système n'est qu'une indication, on agrandit le buffer tant que
+    // 'getpwnam_r' indique qu'il est trop petit.
+    let mut buffer_size = match unsafe { libc::sysconf(libc::_SC_GETPW_R_SIZE_MAX) } {
+        size if size > 0 => size as usize,
+        _ => 1024,
+    };
+
+    loop {
+        let mut buffer: Vec<libc::c_char> = vec![0; buffer_size];
+        let mut passwd = MaybeUninit::<libc::passwd>::uninit();
+        let mut result = ptr::null_mut();
+
+        // La version réentrante est utilisée car plusieurs sessions peuvent faire la recherche en
+        // même temps depuis des threads différents.
+        let code = unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                passwd.as_mut_ptr(),
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+
+        if code == libc::ERANGE {
+            buffer_size *= 2;
+            continue;
+        }
+
+        if code != 0 || result.is_null() {
+            return None;
+        }
+
+        let passwd = unsafe { passwd.assume_init() };
+
+        return Some(SystemUser {
+            uid: passwd.pw_uid,
+            gid: passwd.pw_gid,
+        });
+    }
+}
+
+#[cfg(not(unix))]
+pub fn get_system_user(_username: &str) -> Option<SystemUser> {
+    None
+}
+
+/// Give the ownership of the file at `path` to `user` and its primary group.
+#[cfg(unix)]
+pub fn set_owner(path: &Path, user: &SystemUser) -> io::Result<()> {
+    std::os::unix::fs::chown(path, Some(user.uid), Some(user.gid))
+}
+
+#[cfg(not(unix))]
+pub fn set_owner(_path: &Path, _user: &SystemUser) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "changing the owner of a file is not supported on this platform",
+    ))
+}
+
+/// Get the number of bytes that an unprivileged user can still write on the filesystem
+/// containing `path`.
+#[cfg(unix)]
+pub fn get_available_space(path: &Path) -> io::Result<u64> {
+    let path = to_c_string(path.as_os_str().as_bytes())?;
+
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let stat = unsafe { stat.assume_init() };
+
+    // 'f_bavail' ne compte pas les blocs réservés à root, contrairement à 'f_bfree'.
+    // Les conversions ne sont inutiles que sur les systèmes 64 bits.
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn get_available_space(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "getting the free space of a disk is not supported on this platform",
+    ))
+}
+
+/// Make the standard output and the standard error of the process write to `file`.
+#[cfg(unix)]
+pub fn redirect_output(file: &File) -> io::Result<()> {
+    for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        if unsafe { libc::dup2(file.as_raw_fd(), fd) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn redirect_output(_file: &File) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "writing the output to a file is not supported on this platform",
+    ))
+}
+
+/// Reserve `size` bytes on the disk for `file`, without changing its length.
+#[cfg(target_os = "linux")]
+pub fn preallocate(file: &File, size: u64) -> io::Result<()> {
+    let size = match libc::off_t::try_from(size) {
+        Ok(s) => s,
+        Err(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "size too large",
+            ))
+        }
+    };
+
+    // 'FALLOC_FL_KEEP_SIZE' laisse la taille à 0, un envoi interrompu ne laisse donc pas un fichier
+    // rempli de zéros.
+    let code = unsafe { libc::fallocate(file.as_raw_fd(), libc::FALLOC_FL_KEEP_SIZE, 0, size) };
+
+    if code != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+pub fn preallocate(file: &File, size: u64) -> io::Result<()> {
+    let size = match libc::off_t::try_from(size) {
+        Ok(s) => s,
+        Err(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "size too large",
+            ))
+        }
+    };
+
+    // 'F_PREALLOCATE' réserve les blocs sans changer la taille, comme 'FALLOC_FL_KEEP_SIZE'.
+    let mut store = libc::fstore_t {
+        fst_flags: libc::F_ALLOCATEALL,
+        fst_posmode: libc::F_PEOFPOSMODE,
+        fst_offset: 0,
+        fst_length: size,
+        fst_bytesalloc: 0,
+    };
+
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_PREALLOCATE, &mut store) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+// 'posix_fallocate' des BSD agrandit le fichier, ce qui laisserait des zéros après un envoi
+// interrompu.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn preallocate(_file: &File, _size: u64) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "preallocating a file is not supported on this platform",
+    ))
+}
+
+/// Option giving the time without traffic before the first keep-alive probe.
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+const KEEPALIVE_IDLE_OPTION: libc::c_int = libc::TCP_KEEPIDLE;
+
+// macOS donne à cette option le nom que les autres systèmes donnent à SO_KEEPALIVE.
+#[cfg(target_os = "macos")]
+const KEEPALIVE_IDLE_OPTION: libc::c_int = libc::TCP_KEEPALIVE;
+
+/// Enable TCP keep-alive on `stream`, with a probe sent after `idle` without traffic, or disable
+/// it with `None`.
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+pub fn set_keepalive(stream: &TcpStream, idle: Option<Duration>) -> io::Result<()> {
+    let fd = stream.as_raw_fd();
+
+    set_socket_option(
+        fd,
+        libc::SOL_SOCKET,
+        libc::SO_KEEPALIVE,
+        idle.is_some() as libc::c_int,
+    )?;
+
+    if let Some(idle) = idle {
+        // Les sondes suivantes sont espacées du même délai, le noyau coupe après 'TCP_KEEPCNT'
+        // sondes sans réponse.
+        let seconds = idle.as_secs().clamp(1, i32::MAX as u64) as libc::c_int;
+
+        set_socket_option(fd, libc::IPPROTO_TCP, KEEPALIVE_IDLE_OPTION, seconds)?;
+        set_socket_option(fd, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, seconds)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+pub fn set_keepalive(_stream: &TcpStream, _idle: Option<Duration>) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "TCP keep-alive is not supported on this platform",
+    ))
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+fn set_socket_option(
+    fd: libc::c_int,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    let code = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+
+    if code != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Error given by the system when a file has no attribute with the requested name.
+#[cfg(target_os = "linux")]
+const MISSING_ATTRIBUTE: libc::c_int = libc::ENODATA;
+
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+const MISSING_ATTRIBUTE: libc::c_int = libc::ENOATTR;
+
+/// Read the extended attribute `name` of the file at `path`.
+///
+/// # Return
+/// `None` if the file has no such attribute.
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+pub fn get_extended_attribute(path: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+    let path = to_c_string(path.as_os_str().as_bytes())?;
+    let name = to_c_string(attribute_name(name).as_bytes())?;
+
+    // Une valeur plus grande que le buffer est signalée par ERANGE, ce qui n'arrive pas pour les
+    // attributs écrits par le serveur.
+    let mut buffer = vec![0u8; 256];
+
+    let size = unsafe { read_attribute(&path, &name, &mut buffer) };
+
+    if size < 0 {
+        let err = io::Error::last_os_error();
+
+        return match err.raw_os_error() {
+            Some(MISSING_ATTRIBUTE) => Ok(None),
+            _ => Err(err),
+        };
+    }
+
+    buffer.truncate(size as usize);
+
+    Ok(Some(buffer))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+pub fn get_extended_attribute(_path: &Path, _name: &str) -> io::Result<Option<Vec<u8>>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "extended attributes are not supported on this platform",
+    ))
+}
+
+/// Write the extended attribute `name` of the file at `path`.
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+pub fn set_extended_attribute(path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+    let path = to_c_string(path.as_os_str().as_bytes())?;
+    let name = to_c_string(attribute_name(name).as_bytes())?;
+
+    if unsafe { write_attribute(&path, &name, value) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+pub fn set_extended_attribute(_path: &Path, _name: &str, _value: &[u8]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "extended attributes are not supported on this platform",
+    ))
+}
+
+/// Remove the extended attribute `name` of the file at `path`, if it has one.
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+pub fn remove_extended_attribute(path: &Path, name: &str) -> io::Result<()> {
+    let path = to_c_string(path.as_os_str().as_bytes())?;
+    let name = to_c_string(attribute_name(name).as_bytes())?;
+
+    if unsafe { remove_attribute(&path, &name) } < 0 {
+        let err = io::Error::last_os_error();
+
+        return match err.raw_os_error() {
+            Some(MISSING_ATTRIBUTE) => Ok(()),
+            _ => Err(err),
+        };
+    }
+
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+pub fn remove_extended_attribute(_path: &Path, _name: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "extended attributes are not supported on this platform",
+    ))
+}
+
+/// Name of the attribute `name` for the system, `name` being in the form `user.xxx` of Linux.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn attribute_name(name: &str) -> &str {
+    name
+}
+
+// FreeBSD indique l'espace de noms à part, il ne fait donc pas partie du nom.
+#[cfg(target_os = "freebsd")]
+fn attribute_name(name: &str) -> &str {
+    name.strip_prefix("user.").unwrap_or(name)
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn read_attribute(path: &CStr, name: &CStr, buffer: &mut [u8]) -> isize {
+    libc::getxattr(
+        path.as_ptr(),
+        name.as_ptr(),
+        buffer.as_mut_ptr() as *mut libc::c_void,
+        buffer.len(),
+    )
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn read_attribute(path: &CStr, name: &CStr, buffer: &mut [u8]) -> isize {
+    libc::getxattr(
+        path.as_ptr(),
+        name.as_ptr(),
+        buffer.as_mut_ptr() as *mut libc::c_void,
+        buffer.len(),
+        0,
+        0,
+    )
+}
+
+#[cfg(target_os = "freebsd")]
+unsafe fn read_attribute(path: &CStr, name: &CStr, buffer: &mut [u8]) -> isize {
+    libc::extattr_get_file(
+        path.as_ptr(),
+        libc::EXTATTR_NAMESPACE_USER,
+        name.as_ptr(),
+        buffer.as_mut_ptr() as *mut libc::c_void,
+        buffer.len(),
+    )
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn write_attribute(path: &CStr, name: &CStr, value: &[u8]) -> isize {
+    libc::setxattr(
+        path.as_ptr(),
+        name.as_ptr(),
+        value.as_ptr() as *const libc::c_void,
+        value.len(),
+        0,
+    ) as isize
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn write_attribute(path: &CStr, name: &CStr, value: &[u8]) -> isize {
+    libc::setxattr(
+        path.as_ptr(),
+        name.as_ptr(),
+        value.as_ptr() as *const libc::c_void,
+        value.len(),
+        0,
+        0,
+    ) as isize
+}
+
+#[cfg(target_os = "freebsd")]
+unsafe fn write_attribute(path: &CStr, name: &CStr, value: &[u8]) -> isize {
+    libc::extattr_set_file(
+        path.as_ptr(),
+        libc::EXTATTR_NAMESPACE_USER,
+        name.as_ptr(),
+        value.as_ptr() as *const libc::c_void,
+        value.len(),
+    )
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn remove_attribute(path: &CStr, name: &CStr) -> libc::c_int {
+    libc::removexattr(path.as_ptr(), name.as_ptr())
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn remove_attribute(path: &CStr, name: &CStr) -> libc::c_int {
+    libc::removexattr(path.as_ptr(), name.as_ptr(), 0)
+}
+
+#[cfg(target_os = "freebsd")]
+unsafe fn remove_attribute(path: &CStr, name: &CStr) -> libc::c_int {
+    libc::extattr_delete_file(path.as_ptr(), libc::EXTATTR_NAMESPACE_USER, name.as_ptr())
+}
+
+#[cfg(unix)]
+fn to_c_string(bytes: &[u8]) -> io::Result<CString> {
+    match CString::new(bytes) {
+        Ok(s) => Ok(s),
+        Err(err) => Err(io::Error::new(io::ErrorKind::InvalidInput, err)),
+    }
+}
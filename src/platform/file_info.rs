@@ -0,0 +1,315 @@
+//! Metadata of a file as the listings show it, read the same way on every operating system.
+
+use std::{fs, io, path::Path, time::SystemTime};
+
+#[cfg(unix)]
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    fs::Metadata,
+    mem::MaybeUninit,
+    os::unix::fs::MetadataExt,
+    ptr,
+    sync::{LazyLock, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+#[cfg(not(unix))]
+use std::fs::Metadata;
+
+/// What the listings and the commands like MDTM need to know about a file.
+///
+/// Like `ls -lL`, the fields describe the target of a symbolic link.
+#[derive(Debug, Clone)]
+pub struct FileInfo {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub is_dir: bool,
+    /// Whether the path itself is a symbolic link.
+    pub is_symlink: bool,
+    /// Permission bits, in the form of `st_mode`.
+    pub mode: u32,
+    /// Permissions as `ls -l` shows them, like `rwxr-xr-x`.
+    pub permissions: String,
+    /// Name of the user owning the file, or its identifier if it has no name. Empty unless asked
+    /// for with [`Details::owner`].
+    pub owner: String,
+    pub group: String,
+    /// Volume and index of the file, the same for every hard link to it and kept when it is
+    /// renamed. Only read when asked for with [`Details::unique`].
+    pub unique: Option<(u64, u64)>,
+}
+
+/// Parts of a [`FileInfo`] that are slow to get and only read when needed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Details {
+    pub owner: bool,
+    pub unique: bool,
+}
+
+impl FileInfo {
+    /// Read the metadata of `system_path`, with the optional parts selected by `details`.
+    pub fn read(system_path: &Path, details: Details) -> io::Result<FileInfo> {
+        let link = fs::symlink_metadata(system_path)?;
+        let is_symlink = link.file_type().is_symlink();
+
+        // La cible n'est lue que pour un lien, un seul appel suffit pour les autres entrées.
+        let metadata = match is_symlink {
+            true => fs::metadata(system_path)?,
+            false => link,
+        };
+
+        let mode = get_mode(&metadata);
+
+        let (owner, group) = match details.owner {
+            true => get_owner_names(&metadata),
+            false => (String::new(), String::new()),
+        };
+
+        let unique = match details.unique {
+            true => get_file_id(system_path, &metadata),
+            false => None,
+        };
+
+        Ok(FileInfo {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            is_dir: metadata.is_dir(),
+            is_symlink,
+            mode,
+            permissions: format_permissions(mode),
+            owner,
+            group,
+            unique,
+        })
+    }
+}
+
+/// Format the permission bits of `mode` as `ls -l` does, without the type of the file.
+fn format_permissions(mode: u32) -> String {
+    [0o400, 0o200, 0o100, 0o40, 0o20, 0o10, 0o4, 0o2, 0o1]
+        .iter()
+        .zip("rwxrwxrwx".chars())
+        .map(|(bit, right)| if mode & bit > 0 { right } else { '-' })
+        .collect()
+}
+
+#[cfg(unix)]
+fn get_mode(metadata: &Metadata) -> u32 {
+    metadata.mode()
+}
+
+// Windows ne connaît que la lecture seule, les bits sont donc déduits de cet attribut.
+#[cfg(not(unix))]
+fn get_mode(metadata: &Metadata) -> u32 {
+    match (metadata.is_dir(), metadata.permissions().readonly()) {
+        (true, _) => 0o755,
+        (false, true) => 0o444,
+        (false, false) => 0o644,
+    }
+}
+
+/// Time during which a name found for an identifier is reused, so that an account renamed
+/// meanwhile shows up in the listings without restarting the server.
+#[cfg(unix)]
+const OWNER_NAME_TTL: Duration = Duration::from_secs(300);
+
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Owner {
+    User(u32),
+    Group(u32),
+}
+
+/// Names already found for the owners of the files, shared by every session.
+#[cfg(unix)]
+static OWNER_NAMES: LazyLock<Mutex<HashMap<Owner, (String, Instant)>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Names of the user and the group owning a file, or their identifiers if they have no name, as
+/// `ls` shows them.
+#[cfg(unix)]
+fn get_owner_names(metadata: &Metadata) -> (String, String) {
+    (
+        get_owner_name(Owner::User(metadata.uid())),
+        get_owner_name(Owner::Group(metadata.gid())),
+    )
+}
+
+#[cfg(unix)]
+fn get_owner_name(owner: Owner) -> String {
+    {
+        let names = OWNER_NAMES.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some((name, found)) = names.get(&owner) {
+            if found.elapsed() < OWNER_NAME_TTL {
+                return name.clone();
+            }
+        }
+    }
+
+    // La recherche se fait sans le verrou : avec LDAP ou NIS, elle peut prendre du temps.
+    let name = match owner {
+        Owner::User(uid) => lookup_name(
+            libc::_SC_GETPW_R_SIZE_MAX,
+            |entry, buffer, length, result| unsafe {
+                libc::getpwuid_r(uid, entry, buffer, length, result)
+            },
+            |passwd: &libc::passwd| passwd.pw_name,
+        )
+        .unwrap_or_else(|| uid.to_string()),
+        Owner::Group(gid) => lookup_name(
+            libc::_SC_GETGR_R_SIZE_MAX,
+            |entry, buffer, length, result| unsafe {
+                libc::getgrgid_r(gid, entry, buffer, length, result)
+            },
+            |group: &libc::group| group.gr_name,
+        )
+        .unwrap_or_else(|| gid.to_string()),
+    };
+
+    OWNER_NAMES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(owner, (name.clone(), Instant::now()));
+
+    name
+}
+
+/// Find a name with `call`, one of the reentrant functions of the libc like 'getpwuid_r', whose
+/// buffer is grown until it is big enough. `field` gives the name in the entry found.
+#[cfg(unix)]
+fn lookup_name<T>(
+    size_hint: libc::c_int,
+    call: impl Fn(*mut T, *mut libc::c_char, usize, *mut *mut T) -> libc::c_int,
+    field: impl Fn(&T) -> *const libc::c_char,
+) -> Option<String> {
+    // La taille suggérée par le système n'est qu'une indication, comme pour 'get_system_user'.
+    let mut buffer_size = match unsafe { libc::sysconf(size_hint) } {
+        size if size > 0 => size as usize,
+        _ => 1024,
+    };
+
+    loop {
+        let mut buffer: Vec<libc::c_char> = vec![0; buffer_size];
+        let mut entry = MaybeUninit::<T>::uninit();
+        let mut result = ptr::null_mut();
+
+        let code = call(
+            entry.as_mut_ptr(),
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        );
+
+        if code == libc::ERANGE {
+            buffer_size *= 2;
+            continue;
+        }
+
+        if code != 0 || result.is_null() {
+            return None;
+        }
+
+        // Le nom pointe dans 'buffer', il est copié avant que celui-ci soit libéré.
+        let entry = unsafe { entry.assume_init() };
+        let name = unsafe { CStr::from_ptr(field(&entry)) };
+
+        return Some(name.to_string_lossy().to_string());
+    }
+}
+
+#[cfg(not(unix))]
+fn get_owner_names(_metadata: &Metadata) -> (String, String) {
+    ("owner".to_string(), "group".to_string())
+}
+
+#[cfg(unix)]
+fn get_file_id(_system_path: &Path, metadata: &Metadata) -> Option<(u64, u64)> {
+    Some((metadata.dev(), metadata.ino()))
+}
+
+// L'index du fichier n'est donné par Windows qu'à travers un handle ouvert.
+#[cfg(windows)]
+fn get_file_id(system_path: &Path, _metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::{
+        fs::OpenOptions,
+        os::windows::{fs::OpenOptionsExt, io::AsRawHandle},
+    };
+
+    use winapi::um::{
+        fileapi::{GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION},
+        winbase::FILE_FLAG_BACKUP_SEMANTICS,
+        winnt::FILE_READ_ATTRIBUTES,
+    };
+
+    // FILE_FLAG_BACKUP_SEMANTICS permet aussi d'ouvrir les dossiers.
+    let file = OpenOptions::new()
+        .access_mode(FILE_READ_ATTRIBUTES)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(system_path)
+        .ok()?;
+
+    let mut info = std::mem::MaybeUninit::<BY_HANDLE_FILE_INFORMATION>::uninit();
+
+    if unsafe { GetFileInformationByHandle(file.as_raw_handle() as _, info.as_mut_ptr()) } == 0 {
+        return None;
+    }
+
+    let info = unsafe { info.assume_init() };
+
+    Some((
+        u64::from(info.dwVolumeSerialNumber),
+        (u64::from(info.nFileIndexHigh) << 32) | u64::from(info.nFileIndexLow),
+    ))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn get_file_id(_system_path: &Path, _metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn permissions_follow_ls() {
+        assert_eq!(format_permissions(0o755), "rwxr-xr-x");
+        assert_eq!(format_permissions(0o640), "rw-r-----");
+        assert_eq!(format_permissions(0o40000), "---------");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_describe_their_target() {
+        let directory = env::temp_dir().join(format!("ftp-paradise-info-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("a.txt"), b"hello").unwrap();
+        std::os::unix::fs::symlink("a.txt", directory.join("link")).unwrap();
+
+        let file = FileInfo::read(&directory.join("a.txt"), Details::default()).unwrap();
+        let link = FileInfo::read(&directory.join("link"), Details::default()).unwrap();
+
+        assert!(!file.is_symlink && link.is_symlink);
+        assert_eq!((file.size, link.size), (5, 5));
+        assert!(file.owner.is_empty() && file.unique.is_none());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unknown_owners_show_their_identifier() {
+        let owner = Owner::User(u32::MAX - 7);
+
+        assert_eq!(get_owner_name(owner), (u32::MAX - 7).to_string());
+        assert!(OWNER_NAMES.lock().unwrap().contains_key(&owner));
+        assert_eq!(
+            get_owner_name(Owner::Group(0)),
+            get_owner_name(Owner::Group(0))
+        );
+    }
+}
@@ -0,0 +1,38 @@
+//! Management of the server as a service of the operating system.
+//!
+//! Only Windows is supported, other platforms run the server from their init system.
+
+#[cfg(windows)]
+pub use super::windows_service::{install, run, start, stop, uninstall};
+
+#[cfg(not(windows))]
+use crate::config::Config;
+
+#[cfg(not(windows))]
+const UNSUPPORTED: &str =
+    "services are only supported on Windows, use the init system of the platform instead";
+
+#[cfg(not(windows))]
+pub fn install(_: &[String]) -> Result<(), String> {
+    Err(UNSUPPORTED.to_string())
+}
+
+#[cfg(not(windows))]
+pub fn uninstall() -> Result<(), String> {
+    Err(UNSUPPORTED.to_string())
+}
+
+#[cfg(not(windows))]
+pub fn start() -> Result<(), String> {
+    Err(UNSUPPORTED.to_string())
+}
+
+#[cfg(not(windows))]
+pub fn stop() -> Result<(), String> {
+    Err(UNSUPPORTED.to_string())
+}
+
+#[cfg(not(windows))]
+pub fn run(_: Config) -> Result<(), String> {
+    Err(UNSUPPORTED.to_string())
+}
@@ -0,0 +1,209 @@
+//! Integration with the service control manager of Windows, so the server can run at boot.
+
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    sync::{Mutex, OnceLock},
+    thread,
+    time::Duration,
+};
+
+use windows_service::{
+    define_windows_service,
+    service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+        ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+    },
+    service_control_handler::{self, ServiceControlHandlerResult, ServiceStatusHandle},
+    service_dispatcher,
+    service_manager::{ServiceManager, ServiceManagerAccess},
+};
+
+use crate::config::Config;
+
+/// Name the service is registered under.
+pub const SERVICE_NAME: &str = "ftp-paradise";
+
+const DISPLAY_NAME: &str = "FTP Paradise";
+
+const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
+
+/// Time given to the sessions to end after a stop has been requested.
+const STOP_WAIT_HINT: Duration = Duration::from_secs(30);
+
+/// Config handed to the thread started by the service control manager.
+static SERVICE_CONFIG: Mutex<Option<Config>> = Mutex::new(None);
+
+/// Handle given by the service control manager once the control handler is registered.
+static STATUS_HANDLE: OnceLock<ServiceStatusHandle> = OnceLock::new();
+
+/// Register the service, started at boot with `arguments` following `service run`.
+pub fn install(arguments: &[String]) -> Result<(), String> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .map_err(|err| format!("cannot open the service manager: {err}"))?;
+
+    let executable_path =
+        env::current_exe().map_err(|err| format!("cannot find the executable: {err}"))?;
+
+    let mut launch_arguments = vec![OsString::from("service"), OsString::from("run")];
+    launch_arguments.extend(arguments.iter().map(OsString::from));
+
+    let info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from(DISPLAY_NAME),
+        service_type: SERVICE_TYPE,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path,
+        launch_arguments,
+        dependencies: vec![],
+        // Le service tourne sous le compte LocalSystem.
+        account_name: None,
+        account_password: None,
+    };
+
+    let service = manager
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG)
+        .map_err(|err| format!("cannot create the service: {err}"))?;
+
+    service
+        .set_description("FTP server")
+        .map_err(|err| format!("cannot describe the service: {err}"))
+}
+
+/// Remove the service, stopping it first if it is running.
+pub fn uninstall() -> Result<(), String> {
+    let service =
+        open_service(ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE)?;
+
+    let status = service
+        .query_status()
+        .map_err(|err| format!("cannot query the service: {err}"))?;
+
+    if status.current_state != ServiceState::Stopped {
+        service
+            .stop()
+            .map_err(|err| format!("cannot stop the service: {err}"))?;
+    }
+
+    service
+        .delete()
+        .map_err(|err| format!("cannot delete the service: {err}"))
+}
+
+pub fn start() -> Result<(), String> {
+    open_service(ServiceAccess::START)?
+        .start(&[] as &[&OsStr])
+        .map_err(|err| format!("cannot start the service: {err}"))
+}
+
+/// Ask the service to stop, which lets the running sessions end first.
+pub fn stop() -> Result<(), String> {
+    open_service(ServiceAccess::STOP)?
+        .stop()
+        .map(|_| ())
+        .map_err(|err| format!("cannot stop the service: {err}"))
+}
+
+fn open_service(access: ServiceAccess) -> Result<windows_service::service::Service, String> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .map_err(|err| format!("cannot open the service manager: {err}"))?;
+
+    manager
+        .open_service(SERVICE_NAME, access)
+        .map_err(|err| format!("cannot open the service: {err}"))
+}
+
+/// Serve with `config` as a service, when the executable has been started by the service control
+/// manager.
+///
+/// Returns once the service has been stopped.
+pub fn run(config: Config) -> Result<(), String> {
+    *SERVICE_CONFIG.lock().unwrap() = Some(config);
+
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+        .map_err(|err| format!("cannot connect to the service manager: {err}"))
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+fn service_main(_: Vec<OsString>) {
+    if let Err(err) = run_service() {
+        eprintln!("Service error: {err}.");
+    }
+}
+
+fn run_service() -> Result<(), String> {
+    let config = match SERVICE_CONFIG.lock().unwrap().take() {
+        Some(c) => c,
+        None => return Err("service started without a config".to_string()),
+    };
+
+    let event_handler = |control| match control {
+        // L'arrêt du système est traité comme un arrêt du service : les sessions sont fermées
+        // proprement avant que le processus ne soit tué.
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            crate::request_shutdown();
+
+            if let Some(handle) = STATUS_HANDLE.get() {
+                let _ = set_state(
+                    handle,
+                    ServiceState::StopPending,
+                    ServiceControlAccept::empty(),
+                );
+            }
+
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    };
+
+    let status_handle = service_control_handler::register(SERVICE_NAME, event_handler)
+        .map_err(|err| format!("cannot register the control handler: {err}"))?;
+
+    let _ = STATUS_HANDLE.set(status_handle);
+
+    set_state(
+        &status_handle,
+        ServiceState::Running,
+        ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+    )?;
+
+    // Le serveur tourne dans son propre thread pour pouvoir signaler l'attente de l'arrêt.
+    let server = thread::spawn(move || crate::run(config));
+
+    let result = match server.join() {
+        Ok(r) => r,
+        Err(_) => Err("server thread panicked".to_string()),
+    };
+
+    set_state(
+        &status_handle,
+        ServiceState::Stopped,
+        ServiceControlAccept::empty(),
+    )?;
+
+    result
+}
+
+fn set_state(
+    status_handle: &ServiceStatusHandle,
+    current_state: ServiceState,
+    controls_accepted: ServiceControlAccept,
+) -> Result<(), String> {
+    status_handle
+        .set_service_status(ServiceStatus {
+            service_type: SERVICE_TYPE,
+            current_state,
+            controls_accepted,
+            exit_code: ServiceExitCode::Win32(0),
+            checkpoint: 0,
+            wait_hint: STOP_WAIT_HINT,
+            process_id: None,
+        })
+        .map_err(|err| format!("cannot update the service status: {err}"))
+}
@@ -0,0 +1,12 @@
+//! Pieces of the FTP protocol that do not depend on the network or the file system: parsing of
+//! the requests, formatting of the replies, encoding of the host-port arguments and
+//! serialization of the MLSx facts.
+//!
+//! Everything here works on strings and values only, so that it can be shared with a client.
+
+pub mod command_args;
+pub mod expected_replies;
+pub mod facts;
+pub mod host_port;
+pub mod reply;
+pub mod reply_code;
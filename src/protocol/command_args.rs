@@ -0,0 +1,182 @@
+use std::net::SocketAddrV4;
+
+use super::host_port;
+
+/// Arguments of a command, everything written after the verb on the request line.
+///
+/// The raw string is kept as sent by the client so that handlers can decide how to split it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandArgs {
+    raw: String,
+}
+
+impl CommandArgs {
+    pub fn new(raw: &str) -> CommandArgs {
+        CommandArgs {
+            raw: raw.to_string(),
+        }
+    }
+
+    /// Split a request line into its verb, in uppercase, and its arguments.
+    pub fn parse_request(request: &str) -> (String, CommandArgs) {
+        let (verb, raw) = request.split_once(' ').unwrap_or((request, ""));
+
+        // Le protocole indique que la requête est insensible à la casse.
+        (verb.to_uppercase(), CommandArgs::new(raw))
+    }
+
+    /// Arguments exactly as they were sent.
+    pub fn get_raw(&self) -> &str {
+        &self.raw
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.raw.is_empty()
+    }
+
+    /// Arguments separated by spaces, without the empty ones.
+    pub fn words(&self) -> impl Iterator<Item = &str> {
+        self.raw.split(' ').filter(|word| !word.is_empty())
+    }
+
+    pub fn get_word(&self, index: usize) -> Option<&str> {
+        self.words().nth(index)
+    }
+
+    /// Whole argument taken as a pathname, which can contain spaces.
+    pub fn get_path(&self) -> Option<&str> {
+        match self.raw.is_empty() {
+            true => None,
+            false => Some(&self.raw),
+        }
+    }
+
+    /// Split the arguments into their first word and the pathname following it, as in
+    /// `MFMT 20240315093000 file name`.
+    pub fn get_word_and_path(&self) -> Option<(&str, &str)> {
+        let (word, path) = self.raw.split_once(' ')?;
+
+        match word.is_empty() || path.is_empty() {
+            true => None,
+            false => Some((word, path)),
+        }
+    }
+
+    /// Pathname written after the 'ls' like options (`-l`, `-a`...) sent by some clients.
+    pub fn get_path_after_options(&self) -> Option<&str> {
+        let mut rest = self.raw.trim_start_matches(' ');
+
+        while rest.starts_with('-') {
+            rest = match rest.split_once(' ') {
+                Some((_, r)) => r.trim_start_matches(' '),
+                None => "",
+            };
+        }
+
+        match rest.is_empty() {
+            true => None,
+            false => Some(rest),
+        }
+    }
+
+    /// Parse a host-port argument written `h1,h2,h3,h4,p1,p2`, as sent with PORT.
+    pub fn parse_host_port(&self) -> Result<SocketAddrV4, String> {
+        host_port::decode(&self.raw)
+    }
+
+    /// Parse a byte offset, as sent with REST.
+    pub fn parse_offset(&self) -> Result<u64, String> {
+        match self.raw.trim().parse() {
+            Ok(offset) => Ok(offset),
+            Err(_) => Err(format!("invalid offset {}", self.raw)),
+        }
+    }
+
+    /// Parse an inclusive byte range written `start end`, as sent with RANG.
+    ///
+    /// # Return
+    /// `None` for `1 0`, which removes the range.
+    pub fn parse_range(&self) -> Result<Option<(u64, u64)>, String> {
+        let bounds: Vec<Result<u64, _>> = self.words().map(|word| word.parse()).collect();
+
+        match bounds[..] {
+            [Ok(1), Ok(0)] => Ok(None),
+            [Ok(start), Ok(end)] if start <= end => Ok(Some((start, end))),
+            _ => Err(format!("invalid range {}", self.raw)),
+        }
+    }
+
+    /// Parse a list of facts written `type;size;modify;`, as sent with OPTS MLST.
+    ///
+    /// Facts are case insensitive and returned in lowercase.
+    pub fn parse_facts(&self) -> Vec<String> {
+        self.raw
+            .trim()
+            .split(';')
+            .filter(|fact| !fact.is_empty())
+            .map(|fact| fact.to_lowercase())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn parse_request_keeps_raw_arguments() {
+        let (verb, args) = CommandArgs::parse_request("cwd My  Documents");
+
+        assert_eq!(verb, "CWD");
+        assert_eq!(args.get_path(), Some("My  Documents"));
+        assert_eq!(args.words().collect::<Vec<_>>(), ["My", "Documents"]);
+
+        let (verb, args) = CommandArgs::parse_request("PWD");
+
+        assert_eq!(verb, "PWD");
+        assert!(args.is_empty());
+        assert_eq!(args.get_path(), None);
+    }
+
+    #[test]
+    fn path_after_options() {
+        assert_eq!(
+            CommandArgs::new("-la sub dir").get_path_after_options(),
+            Some("sub dir")
+        );
+        assert_eq!(CommandArgs::new("-l").get_path_after_options(), None);
+        assert_eq!(CommandArgs::new("").get_path_after_options(), None);
+    }
+
+    #[test]
+    fn typed_parsers() {
+        assert_eq!(
+            CommandArgs::new("127,0,0,1,7,208").parse_host_port(),
+            Ok(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 2000))
+        );
+        assert!(CommandArgs::new("127,0,0,1,7").parse_host_port().is_err());
+        assert!(CommandArgs::new("127,0,0,1,7,300")
+            .parse_host_port()
+            .is_err());
+
+        assert_eq!(CommandArgs::new("1024").parse_offset(), Ok(1024));
+        assert!(CommandArgs::new("-1").parse_offset().is_err());
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(CommandArgs::new("0 99").parse_range(), Ok(Some((0, 99))));
+        assert_eq!(CommandArgs::new("5 5").parse_range(), Ok(Some((5, 5))));
+        assert_eq!(CommandArgs::new("1 0").parse_range(), Ok(None));
+        assert!(CommandArgs::new("10 2").parse_range().is_err());
+        assert!(CommandArgs::new("10").parse_range().is_err());
+        assert!(CommandArgs::new("0 -1").parse_range().is_err());
+
+        assert_eq!(
+            CommandArgs::new("Type;SIZE;modify;").parse_facts(),
+            ["type", "size", "modify"]
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! Reply codes each command can be answered with, from the RFC 959 and the RFCs extending it
+//! (2389, 2428, 3659 and 7151), used to catch the handlers answering with an unexpected code.
+
+/// Codes that can answer any command: the service closing, the syntax errors, the commands not
+/// implemented, the sessions not logged in and the local errors.
+const ANY_COMMAND: [u16; 6] = [421, 451, 500, 501, 502, 530];
+
+/// Codes answering the transfers, from the opening of the data connection to its end.
+const TRANSFER: [u16; 9] = [125, 150, 226, 250, 425, 426, 450, 550, 504];
+
+/// Codes expected for each command, besides [`ANY_COMMAND`].
+const EXPECTED_REPLIES: [(&str, &[u16]); 40] = [
+    ("USER", &[230, 331, 332]),
+    ("PASS", &[202, 230, 332, 503]),
+    ("HOST", &[220, 503, 504]),
+    ("CLNT", &[200]),
+    ("REIN", &[120, 220]),
+    ("QUIT", &[221]),
+    ("SYST", &[215]),
+    ("FEAT", &[211]),
+    ("OPTS", &[200, 202, 504]),
+    // Les sous-commandes de SITE répondent chacune à leur manière.
+    ("SITE", &[150, 200, 202, 211, 214, 226, 425, 426, 504, 550]),
+    ("HELP", &[211, 214]),
+    ("PWD", &[257, 550]),
+    ("TYPE", &[200, 504]),
+    ("MODE", &[200, 504]),
+    ("PASV", &[227, 425, 503, 522]),
+    ("PORT", &[200, 425, 503, 522]),
+    ("EPRT", &[200, 425, 503, 522]),
+    ("EPSV", &[200, 229, 425, 522]),
+    ("LIST", &TRANSFER),
+    ("NLST", &TRANSFER),
+    ("RETR", &[125, 150, 226, 250, 425, 426, 450, 550, 554]),
+    ("DELE", &[250, 450, 550]),
+    ("HASH", &[213, 450, 504, 550]),
+    ("MLSD", &TRANSFER),
+    ("MLST", &[250, 550]),
+    ("ABOR", &[225, 226]),
+    ("STAT", &[211, 212, 213, 450, 550]),
+    ("CWD", &[250, 550]),
+    ("CDUP", &[200, 250, 550]),
+    (
+        "STOR",
+        &[
+            125, 150, 226, 250, 425, 426, 450, 451, 452, 504, 532, 550, 551, 552, 553, 554,
+        ],
+    ),
+    (
+        "APPE",
+        &[
+            125, 150, 226, 250, 425, 426, 450, 452, 503, 504, 532, 550, 551, 552, 553,
+        ],
+    ),
+    ("ALLO", &[200, 202, 504]),
+    ("RANG", &[350, 504]),
+    ("REST", &[350]),
+    ("MDTM", &[213, 550]),
+    ("MFMT", &[213, 550]),
+    ("MKD", &[257, 550]),
+    ("RMD", &[250, 550]),
+    ("RNFR", &[350, 450, 550]),
+    ("RNTO", &[250, 503, 532, 553]),
+];
+
+/// Codes `command` can be answered with besides those of any command, `None` if the command is
+/// not known.
+pub fn get_expected(command: &str) -> Option<&'static [u16]> {
+    EXPECTED_REPLIES
+        .iter()
+        .find(|(verb, _)| *verb == command)
+        .map(|(_, codes)| *codes)
+}
+
+/// Tell if `code` is a valid answer to `command`, every code being accepted for the commands
+/// that are not known.
+pub fn is_expected(command: &str, code: u16) -> bool {
+    ANY_COMMAND.contains(&code) || get_expected(command).is_none_or(|codes| codes.contains(&code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_follow_the_rfcs() {
+        assert!(is_expected("USER", 331));
+        assert!(is_expected("PWD", 257));
+        assert!(is_expected("LIST", 226));
+        assert!(is_expected("RETR", 421));
+
+        assert!(!is_expected("USER", 550));
+        assert!(!is_expected("MKD", 553));
+        assert!(!is_expected("LIST", 200));
+        assert!(!is_expected("CWD", 450));
+
+        assert!(is_expected("XYZ", 200));
+    }
+}
@@ -0,0 +1,325 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+/// Facts describing a file in the MLSx replies, as defined by the RFC 3659.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Facts {
+    pub is_dir: bool,
+    pub size: u64,
+    /// Last modification, omitted when unknown.
+    pub modify: Option<DateTime<Utc>>,
+    pub perm: String,
+    /// Identifier of the file, the same for all its hard links, omitted when unknown.
+    pub unique: Option<String>,
+}
+
+/// Fact that the server can send in the MLSx replies.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fact {
+    Type,
+    Size,
+    Modify,
+    Perm,
+    Unique,
+}
+
+/// Every fact the server supports, sent by default.
+pub const SUPPORTED_FACTS: [Fact; 5] = [
+    Fact::Type,
+    Fact::Size,
+    Fact::Modify,
+    Fact::Perm,
+    Fact::Unique,
+];
+
+impl Fact {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Fact::Type => "type",
+            Fact::Size => "size",
+            Fact::Modify => "modify",
+            Fact::Perm => "perm",
+            Fact::Unique => "unique",
+        }
+    }
+
+    /// Find the fact named `name`, ignoring the case.
+    pub fn from_name(name: &str) -> Option<Fact> {
+        SUPPORTED_FACTS
+            .into_iter()
+            .find(|fact| fact.get_name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Parse the list of facts sent with `OPTS MLST`, such as `type;size;`, keeping their order.
+///
+/// The facts that are not supported are ignored, as required by the RFC 3659.
+pub fn parse_selection(list: &str) -> Vec<Fact> {
+    let mut selection = Vec::new();
+
+    for fact in list.split(';').filter_map(Fact::from_name) {
+        if !selection.contains(&fact) {
+            selection.push(fact);
+        }
+    }
+
+    selection
+}
+
+/// Format `selection` the way FEAT and `OPTS MLST` list the facts, as in `type;size;`.
+pub fn format_selection(selection: &[Fact]) -> String {
+    selection
+        .iter()
+        .map(|fact| format!("{};", fact.get_name()))
+        .collect()
+}
+
+/// Format the facts supported by the server for FEAT, those in `selection` followed by a `*`.
+pub fn format_feature(selection: &[Fact]) -> String {
+    SUPPORTED_FACTS
+        .iter()
+        .map(|fact| match selection.contains(fact) {
+            true => format!("{}*;", fact.get_name()),
+            false => format!("{};", fact.get_name()),
+        })
+        .collect()
+}
+
+impl Facts {
+    /// Format the MLSx line of the file `name`, without the line ending.
+    pub fn format(&self, name: &str) -> String {
+        self.format_selected(name, &SUPPORTED_FACTS)
+    }
+
+    /// Format the MLSx line of the file `name` with the facts of `selection`, in that order.
+    pub fn format_selected(&self, name: &str, selection: &[Fact]) -> String {
+        let mut line = String::new();
+
+        for fact in selection {
+            match fact {
+                Fact::Type => {
+                    let kind = if self.is_dir { "dir" } else { "file" };
+
+                    line.push_str(&format!("type={kind};"));
+                }
+                Fact::Size => line.push_str(&format!("size={};", self.size)),
+                // Le protocole impose que la date soit exprimée en UTC.
+                Fact::Modify => {
+                    if let Some(modify) = self.modify {
+                        line.push_str(&format!("modify={};", format_time_val(modify)));
+                    }
+                }
+                Fact::Perm => line.push_str(&format!("perm={};", self.perm)),
+                Fact::Unique => {
+                    if let Some(unique) = &self.unique {
+                        line.push_str(&format!("unique={unique};"));
+                    }
+                }
+            }
+        }
+
+        format!("{line} {name}")
+    }
+}
+
+/// Parse a MLSx line into the name of the file and its facts.
+///
+/// The lines describing the listed directory itself or its parent (`cdir` and `pdir`) give
+/// `None`, as well as malformed lines.
+pub fn parse_line(line: &str) -> Option<(String, Facts)> {
+    let (list, name) = line.split_once(' ')?;
+
+    let mut facts = Facts {
+        is_dir: false,
+        size: 0,
+        modify: None,
+        perm: String::new(),
+        unique: None,
+    };
+
+    for fact in list.split(';').filter(|f| !f.is_empty()) {
+        let (key, value) = fact.split_once('=')?;
+
+        // Les noms des faits sont insensibles à la casse.
+        match &key.to_lowercase()[..] {
+            "type" => match &value.to_lowercase()[..] {
+                "dir" => facts.is_dir = true,
+                "file" => facts.is_dir = false,
+                _ => return None,
+            },
+            "size" => facts.size = value.parse().ok()?,
+            "modify" => facts.modify = parse_time_val(value),
+            "perm" => facts.perm = value.to_string(),
+            "unique" => facts.unique = Some(value.to_string()),
+            _ => (),
+        }
+    }
+
+    Some((name.to_string(), facts))
+}
+
+/// Format `time` the way the RFC 3659 writes dates, as in `20240315093000`.
+pub fn format_time_val(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%d%H%M%S").to_string()
+}
+
+/// Parse a date written `YYYYMMDDHHMMSS[.sss]`, in UTC, as defined by the RFC 3659.
+pub fn parse_time_val(value: &str) -> Option<DateTime<Utc>> {
+    // Les fractions de seconde sont facultatives.
+    let (seconds, fraction) = match value.split_once('.') {
+        Some((seconds, fraction)) => (seconds, Some(fraction)),
+        None => (value, None),
+    };
+
+    if seconds.len() != 14 || !seconds.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    if fraction.is_some_and(|f| f.is_empty() || !f.bytes().all(|b| b.is_ascii_digit())) {
+        return None;
+    }
+
+    NaiveDateTime::parse_from_str(seconds, "%Y%m%d%H%M%S")
+        .ok()
+        .map(|time| time.and_utc())
+}
+
+/// Format the `unique` fact of the file `index` of the volume `device`.
+pub fn format_unique(device: u64, index: u64) -> String {
+    format!("{device:x}U{index:x}")
+}
+
+/// Compute the `perm` fact from the Unix permissions `mode`, using the rights of the owner.
+pub fn perm_from_mode(is_dir: bool, mode: u32) -> String {
+    let readable = (mode & 0o400) > 0;
+    let writable = (mode & 0o200) > 0;
+
+    let mut perm = String::new();
+
+    if is_dir {
+        if (mode & 0o100) > 0 {
+            perm.push('e');
+        }
+        if readable {
+            perm.push('l');
+        }
+        if writable {
+            perm.push_str("cmpdf");
+        }
+    } else {
+        if readable {
+            perm.push('r');
+        }
+        if writable {
+            perm.push_str("wadf");
+        }
+    }
+
+    perm
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn format_file() {
+        let facts = Facts {
+            is_dir: false,
+            size: 42,
+            modify: Some(Utc.with_ymd_and_hms(2024, 3, 5, 7, 8, 9).unwrap()),
+            perm: perm_from_mode(false, 0o644),
+            unique: Some(format_unique(0x803, 1234)),
+        };
+
+        assert_eq!(
+            facts.format("a.txt"),
+            "type=file;size=42;modify=20240305070809;perm=rwadf;unique=803U4d2; a.txt"
+        );
+    }
+
+    #[test]
+    fn format_without_date() {
+        let facts = Facts {
+            is_dir: true,
+            size: 4096,
+            modify: None,
+            perm: perm_from_mode(true, 0o555),
+            unique: None,
+        };
+
+        assert_eq!(facts.format("sub"), "type=dir;size=4096;perm=el; sub");
+    }
+
+    #[test]
+    fn selected_facts_keep_their_order() {
+        let selection = parse_selection("Perm;media-type;SIZE;perm;");
+
+        assert_eq!(selection, [Fact::Perm, Fact::Size]);
+        assert_eq!(format_selection(&selection), "perm;size;");
+        assert_eq!(
+            format_feature(&selection),
+            "type;size*;modify;perm*;unique;"
+        );
+
+        let facts = Facts {
+            is_dir: false,
+            size: 42,
+            modify: None,
+            perm: "r".to_string(),
+            unique: None,
+        };
+
+        assert_eq!(
+            facts.format_selected("a.txt", &selection),
+            "perm=r;size=42; a.txt"
+        );
+        assert_eq!(facts.format_selected("a.txt", &[]), " a.txt");
+    }
+
+    #[test]
+    fn perm_depends_on_owner_rights() {
+        assert_eq!(perm_from_mode(true, 0o755), "elcmpdf");
+        assert_eq!(perm_from_mode(false, 0o400), "r");
+        assert_eq!(perm_from_mode(false, 0o077), "");
+    }
+
+    #[test]
+    fn parse_lines() {
+        let (name, facts) =
+            parse_line("type=file;size=42;modify=20240305070809;perm=rwadf; my file.txt").unwrap();
+
+        assert_eq!(name, "my file.txt");
+        assert_eq!(
+            facts,
+            Facts {
+                is_dir: false,
+                size: 42,
+                modify: Some(Utc.with_ymd_and_hms(2024, 3, 5, 7, 8, 9).unwrap()),
+                perm: "rwadf".to_string(),
+                unique: None,
+            }
+        );
+
+        let (name, facts) = parse_line("Type=DIR;Size=4096;UNIX.mode=0755; sub").unwrap();
+
+        assert_eq!(name, "sub");
+        assert!(facts.is_dir);
+
+        assert_eq!(parse_line("type=cdir;perm=el; /pub"), None);
+        assert_eq!(parse_line("type=file;size=abc; a.txt"), None);
+        assert_eq!(parse_line("no facts"), None);
+    }
+
+    #[test]
+    fn time_vals() {
+        let time = parse_time_val("20240315093000.123").unwrap();
+
+        assert_eq!(format_time_val(time), "20240315093000");
+        assert_eq!(parse_time_val("20240315093000"), Some(time));
+        assert_eq!(parse_time_val("2024031509300"), None);
+        assert_eq!(parse_time_val("20241315093000"), None);
+        assert_eq!(parse_time_val("20240315093000."), None);
+    }
+}
@@ -0,0 +1,192 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+
+/// Encode `address` as `h1,h2,h3,h4,p1,p2`, the format of PORT and of the PASV reply.
+pub fn encode(address: SocketAddrV4) -> String {
+    let [h1, h2, h3, h4] = address.ip().octets();
+    let [p1, p2] = address.port().to_be_bytes();
+
+    format!("{h1},{h2},{h3},{h4},{p1},{p2}")
+}
+
+/// Decode an address written `h1,h2,h3,h4,p1,p2`.
+pub fn decode(host_port: &str) -> Result<SocketAddrV4, String> {
+    let numbers: Vec<u8> = match host_port
+        .trim()
+        .split(',')
+        .map(|n| n.trim().parse())
+        .collect()
+    {
+        Ok(n) => n,
+        Err(_) => return Err(format!("invalid host-port {host_port}")),
+    };
+
+    if numbers.len() != 6 {
+        return Err(format!("invalid host-port {host_port}"));
+    }
+
+    let ip = Ipv4Addr::new(numbers[0], numbers[1], numbers[2], numbers[3]);
+    let port = u16::from_be_bytes([numbers[4], numbers[5]]);
+
+    Ok(SocketAddrV4::new(ip, port))
+}
+
+/// Find the address in a PASV reply such as `Entering passive mode (h1,h2,h3,h4,p1,p2)`.
+pub fn decode_pasv_reply(message: &str) -> Result<SocketAddrV4, String> {
+    // Les parenthèses sont facultatives, certains serveurs ne les envoient pas.
+    let start = match message.find(|c: char| c.is_ascii_digit()) {
+        Some(s) => s,
+        None => return Err(format!("no address in PASV reply {message}")),
+    };
+
+    let end = message[start..]
+        .find(|c: char| !c.is_ascii_digit() && c != ',')
+        .map_or(message.len(), |e| start + e);
+
+    decode(&message[start..end])
+}
+
+/// Encode `port` in the reply of EPSV, as `(|||port|)`.
+pub fn encode_extended_port(port: u16) -> String {
+    format!("(|||{port}|)")
+}
+
+/// Find the port in an EPSV reply such as `Entering extended passive mode (|||port|)`.
+pub fn decode_epsv_reply(message: &str) -> Result<u16, String> {
+    let start = match message.find('(') {
+        Some(s) => s + 1,
+        None => return Err(format!("no port in EPSV reply {message}")),
+    };
+
+    let end = match message[start..].find(')') {
+        Some(e) => start + e,
+        None => return Err(format!("no port in EPSV reply {message}")),
+    };
+
+    // Le délimiteur est le premier caractère, '|' par défaut, et se répète quatre fois.
+    let content = &message[start..end];
+    let delimiter = match content.chars().next() {
+        Some(d) => d,
+        None => return Err(format!("no port in EPSV reply {message}")),
+    };
+
+    let fields: Vec<&str> = content.split(delimiter).collect();
+
+    match fields[..] {
+        ["", "", "", port, ""] => match port.parse() {
+            Ok(port) => Ok(port),
+            Err(_) => Err(format!("invalid port in EPSV reply {message}")),
+        },
+        _ => Err(format!("invalid EPSV reply {message}")),
+    }
+}
+
+/// Decode the argument of EPRT, such as `|2|::1|6275|`, the delimiter being its first character.
+pub fn decode_extended(argument: &str) -> Result<SocketAddr, String> {
+    let argument = argument.trim();
+
+    let delimiter = match argument.chars().next() {
+        Some(d) => d,
+        None => return Err("missing address".to_string()),
+    };
+
+    let fields: Vec<&str> = argument.split(delimiter).collect();
+
+    let (protocol, address, port) = match fields[..] {
+        ["", protocol, address, port, ""] => (protocol, address, port),
+        _ => return Err(format!("invalid address {argument}")),
+    };
+
+    let ip: IpAddr = match address.parse() {
+        Ok(ip) => ip,
+        Err(_) => return Err(format!("invalid address {argument}")),
+    };
+
+    // La famille annoncée doit être celle de l'adresse.
+    if protocol != network_protocol(ip).to_string() {
+        return Err(format!("invalid network protocol in {argument}"));
+    }
+
+    match port.parse() {
+        Ok(port) => Ok(SocketAddr::new(ip, port)),
+        Err(_) => Err(format!("invalid port in {argument}")),
+    }
+}
+
+/// Number used by EPSV and EPRT for the family of `address`, 1 for IPv4 and 2 for IPv6.
+pub fn network_protocol(address: IpAddr) -> u8 {
+    match address {
+        IpAddr::V4(_) => 1,
+        IpAddr::V6(_) => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode() {
+        let address = SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 2), 7005);
+
+        assert_eq!(encode(address), "192,168,1,2,27,93");
+        assert_eq!(decode("192,168,1,2,27,93"), Ok(address));
+        assert_eq!(decode(" 192, 168, 1, 2, 27, 93 "), Ok(address));
+    }
+
+    #[test]
+    fn decode_rejects_invalid_addresses() {
+        assert!(decode("").is_err());
+        assert!(decode("127,0,0,1,7").is_err());
+        assert!(decode("127,0,0,1,7,208,1").is_err());
+        assert!(decode("127,0,0,256,7,208").is_err());
+        assert!(decode("127,0,0,a,7,208").is_err());
+    }
+
+    #[test]
+    fn decode_pasv_replies() {
+        let address = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 2000);
+
+        assert_eq!(
+            decode_pasv_reply("Entering passive mode (127,0,0,1,7,208)"),
+            Ok(address)
+        );
+        assert_eq!(
+            decode_pasv_reply("Entering Passive Mode 127,0,0,1,7,208."),
+            Ok(address)
+        );
+        assert_eq!(
+            decode_pasv_reply("Entering passive mode =127,0,0,1,7,208"),
+            Ok(address)
+        );
+        assert!(decode_pasv_reply("Entering passive mode").is_err());
+    }
+
+    #[test]
+    fn extended_port() {
+        assert_eq!(encode_extended_port(7005), "(|||7005|)");
+        assert_eq!(
+            decode_epsv_reply("Entering extended passive mode (|||7005|)"),
+            Ok(7005)
+        );
+        assert_eq!(decode_epsv_reply("Entering (!!!21!)"), Ok(21));
+        assert!(decode_epsv_reply("Entering (|1|127.0.0.1|21|)").is_err());
+        assert!(decode_epsv_reply("Entering (|||port|)").is_err());
+        assert!(decode_epsv_reply("Entering").is_err());
+    }
+
+    #[test]
+    fn decode_eprt_arguments() {
+        assert_eq!(
+            decode_extended("|1|132.235.1.2|6275|"),
+            Ok("132.235.1.2:6275".parse().unwrap())
+        );
+        assert_eq!(
+            decode_extended("!2!1080::8:800:200c:417a!5282!"),
+            Ok("[1080::8:800:200c:417a]:5282".parse().unwrap())
+        );
+        assert!(decode_extended("|2|132.235.1.2|6275|").is_err());
+        assert!(decode_extended("|1|132.235.1.2|6275").is_err());
+        assert!(decode_extended("|1|132.235.1.2|port|").is_err());
+        assert!(decode_extended("").is_err());
+    }
+}
@@ -0,0 +1,75 @@
+use super::reply_code::ReplyCode;
+
+/// Format a reply ready to be sent on the control connection.
+///
+/// A multiline `message` must already contain its line endings, the closing line is added here.
+pub fn format_reply(code: ReplyCode, message: &str, multiline: bool) -> String {
+    match multiline {
+        true => format!("{code}-{message}\r\n{code} End\r\n"),
+        false => format!("{code} {message}\r\n"),
+    }
+}
+
+/// Quote `path` for a 257 reply, the quotes it contains being doubled as the RFC 959 requires.
+pub fn quote_pathname(path: &str) -> String {
+    format!("\"{}\"", path.replace('"', "\"\""))
+}
+
+/// Split a line received from a server into its code, whether more lines follow, and its text.
+///
+/// Lines in the middle of a multiline reply, which do not start with a code, give `None`.
+pub fn parse_reply_line(line: &str) -> Option<(u16, bool, &str)> {
+    let code = line.get(..3)?;
+
+    if !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let code = code.parse().ok()?;
+
+    match line.get(3..4) {
+        Some("-") => Some((code, true, &line[4..])),
+        Some(" ") => Some((code, false, &line[4..])),
+        None => Some((code, false, "")),
+        Some(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_line() {
+        assert_eq!(
+            format_reply(ReplyCode::CommandOk, "command OK", false),
+            "200 command OK\r\n"
+        );
+    }
+
+    #[test]
+    fn multiline() {
+        assert_eq!(
+            format_reply(ReplyCode::SystemStatus, "Features\r\n UTF8", true),
+            "211-Features\r\n UTF8\r\n211 End\r\n"
+        );
+    }
+
+    #[test]
+    fn quoted_pathnames() {
+        assert_eq!(quote_pathname("/pub"), "\"/pub\"");
+        assert_eq!(quote_pathname("/say \"hi\""), "\"/say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn parse_lines() {
+        assert_eq!(parse_reply_line("220 ready"), Some((220, false, "ready")));
+        assert_eq!(
+            parse_reply_line("211-Features"),
+            Some((211, true, "Features"))
+        );
+        assert_eq!(parse_reply_line("200"), Some((200, false, "")));
+        assert_eq!(parse_reply_line(" UTF8"), None);
+        assert_eq!(parse_reply_line("2x0 nope"), None);
+    }
+}
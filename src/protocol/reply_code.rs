@@ -0,0 +1,123 @@
+use std::fmt;
+
+/// Reply codes sent by the server, as defined by the RFC 959.
+///
+/// The first digit of a code tells its category, see [`ReplyClass`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyCode {
+    FileStatusOk = 150,
+    CommandOk = 200,
+    CommandSuperfluous = 202,
+    SystemStatus = 211,
+    DirectoryStatus = 212,
+    FileStatus = 213,
+    HelpMessage = 214,
+    SystemType = 215,
+    ServiceReady = 220,
+    ClosingControlConnection = 221,
+    DataConnectionOpen = 225,
+    ClosingDataConnection = 226,
+    EnteringPassiveMode = 227,
+    EnteringExtendedPassiveMode = 229,
+    UserLoggedIn = 230,
+    FileActionOk = 250,
+    PathnameCreated = 257,
+    UserNameOk = 331,
+    FileActionPending = 350,
+    ServiceNotAvailable = 421,
+    CannotOpenDataConnection = 425,
+    ConnectionClosed = 426,
+    FileActionNotTaken = 450,
+    LocalError = 451,
+    InsufficientStorage = 452,
+    SyntaxError = 500,
+    SyntaxErrorInArguments = 501,
+    CommandNotImplemented = 502,
+    BadSequence = 503,
+    ParameterNotImplemented = 504,
+    NetworkProtocolNotSupported = 522,
+    NotLoggedIn = 530,
+    FileUnavailable = 550,
+    ExceededStorageAllocation = 552,
+    FileNameNotAllowed = 553,
+    ActionNotTaken = 554,
+}
+
+/// Category of a reply, given by the first digit of its code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyClass {
+    /// 1xx, another reply will follow.
+    PositivePreliminary,
+    /// 2xx, the command succeeded.
+    PositiveCompletion,
+    /// 3xx, the command needs more information.
+    PositiveIntermediate,
+    /// 4xx, the command failed but can be retried.
+    TransientNegative,
+    /// 5xx, the command failed.
+    PermanentNegative,
+}
+
+impl ReplyCode {
+    pub fn get_code(self) -> u16 {
+        self as u16
+    }
+
+    pub fn get_class(self) -> ReplyClass {
+        match self.get_code() / 100 {
+            1 => ReplyClass::PositivePreliminary,
+            2 => ReplyClass::PositiveCompletion,
+            3 => ReplyClass::PositiveIntermediate,
+            4 => ReplyClass::TransientNegative,
+            _ => ReplyClass::PermanentNegative,
+        }
+    }
+
+    /// Tell if the reply means that the command did not fail.
+    pub fn is_positive(self) -> bool {
+        !self.is_negative()
+    }
+
+    /// Tell if the reply means that the command failed.
+    pub fn is_negative(self) -> bool {
+        matches!(
+            self.get_class(),
+            ReplyClass::TransientNegative | ReplyClass::PermanentNegative
+        )
+    }
+}
+
+impl fmt::Display for ReplyCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get_code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes_follow_first_digit() {
+        assert_eq!(
+            ReplyCode::FileStatusOk.get_class(),
+            ReplyClass::PositivePreliminary
+        );
+        assert_eq!(
+            ReplyCode::UserNameOk.get_class(),
+            ReplyClass::PositiveIntermediate
+        );
+        assert_eq!(
+            ReplyCode::LocalError.get_class(),
+            ReplyClass::TransientNegative
+        );
+        assert!(ReplyCode::ClosingDataConnection.is_positive());
+        assert!(ReplyCode::FileUnavailable.is_negative());
+    }
+
+    #[test]
+    fn displays_numeric_code() {
+        assert_eq!(ReplyCode::ServiceReady.to_string(), "220");
+        assert_eq!(format!("{} ok", ReplyCode::FileStatusOk), "150 ok");
+    }
+}
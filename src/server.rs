@@ -1,2 +1,34 @@
+pub mod brute_force;
+pub mod checksum;
+pub mod command_stats;
+pub mod compression;
+pub mod data_channel;
+pub mod denial;
+pub mod extensions;
+pub mod fault_injection;
+pub mod features;
+pub mod file_error;
+pub mod filename;
 pub mod ftp_client;
 pub mod ftp_server;
+pub mod hook;
+pub mod hot_folder;
+pub mod limits;
+pub mod listing;
+pub mod metrics;
+pub mod partial_upload;
+pub mod passive;
+pub mod path_resolver;
+pub mod priority;
+pub mod quirks;
+pub mod rate_limit;
+pub mod resources;
+pub mod session_state;
+pub mod sniffing;
+pub mod tar;
+pub mod tarpit;
+pub mod temporary_credentials;
+pub mod timeline;
+pub mod transcript;
+pub mod upload;
+pub mod virtual_host;
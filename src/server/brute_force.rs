@@ -0,0 +1,185 @@
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    hash::Hash,
+    net::IpAddr,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::CONFIG;
+
+/// Longest delay before a 530 reply, however many times the password was wrong.
+const MAX_LOGIN_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Failed logins of an address or an account and, once it has too many of them, the end of its
+/// ban.
+struct Failures {
+    count: u32,
+    banned_until: Option<Instant>,
+}
+
+/// Addresses that failed to log in, shared by all the sessions.
+static FAILURES: LazyLock<Mutex<HashMap<IpAddr, Failures>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Accounts whose password was refused, whatever the address the attempts came from.
+static ACCOUNT_FAILURES: LazyLock<Mutex<HashMap<String, Failures>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Count a failed login from `ip`, and ban it if it reached the limit of the config.
+///
+/// # Return
+/// `true` if the address is now banned.
+pub fn record_login_failure(ip: IpAddr) -> bool {
+    let config = CONFIG.get().unwrap();
+
+    let banned = record_failure(
+        &FAILURES,
+        ip,
+        config.get_max_login_failures(),
+        config.get_ban_duration(),
+    );
+
+    if banned {
+        println!(
+            "Banning {ip} after {} failed logins.",
+            config.get_max_login_failures()
+        );
+    }
+
+    banned
+}
+
+/// Forget the failed logins of `ip` after a successful one.
+pub fn record_login_success(ip: IpAddr) {
+    record_success(&FAILURES, &ip);
+}
+
+/// Tell if `ip` is currently banned.
+pub fn is_banned(ip: IpAddr) -> bool {
+    is_locked(&FAILURES, &ip)
+}
+
+/// Count a wrong password for `username`, and lock the account if it reached the limit of the
+/// config.
+///
+/// # Return
+/// `true` if the account is now locked.
+pub fn record_account_failure(username: &str) -> bool {
+    let config = CONFIG.get().unwrap();
+
+    let locked = record_failure(
+        &ACCOUNT_FAILURES,
+        username.to_string(),
+        config.get_max_account_failures(),
+        config.get_account_lockout_duration(),
+    );
+
+    if locked {
+        println!(
+            "Locking account {username} after {} failed logins.",
+            config.get_max_account_failures()
+        );
+    }
+
+    locked
+}
+
+/// Forget the wrong passwords of `username` after a successful login.
+pub fn record_account_success(username: &str) {
+    record_success(&ACCOUNT_FAILURES, username);
+}
+
+/// Tell if `username` is currently locked, its password being refused even if right.
+pub fn is_account_locked(username: &str) -> bool {
+    is_locked(&ACCOUNT_FAILURES, username)
+}
+
+/// Delay before replying to the failed login number `failures` of a session, `first_secs` for the
+/// first one and twice as long for each of the next ones.
+pub fn get_login_backoff(first_secs: u64, failures: u32) -> Duration {
+    if failures == 0 {
+        return Duration::ZERO;
+    }
+
+    // Le décalage est borné pour ne pas déborder, le plafond est atteint bien avant.
+    let factor = 1u64 << (failures - 1).min(16);
+
+    Duration::from_secs(first_secs.saturating_mul(factor)).min(MAX_LOGIN_BACKOFF)
+}
+
+fn record_failure<K: Hash + Eq>(
+    failures: &Mutex<HashMap<K, Failures>>,
+    key: K,
+    max_failures: u32,
+    ban_duration: u64,
+) -> bool {
+    // Une limite à 0 désactive la protection.
+    if max_failures == 0 {
+        return false;
+    }
+
+    let mut failures = failures.lock().unwrap();
+
+    let entry = failures.entry(key).or_insert(Failures {
+        count: 0,
+        banned_until: None,
+    });
+
+    entry.count += 1;
+
+    if entry.count >= max_failures {
+        entry.count = 0;
+        entry.banned_until = Some(Instant::now() + Duration::from_secs(ban_duration));
+
+        return true;
+    }
+
+    false
+}
+
+fn record_success<K, Q>(failures: &Mutex<HashMap<K, Failures>>, key: &Q)
+where
+    K: Borrow<Q> + Hash + Eq,
+    Q: Hash + Eq + ?Sized,
+{
+    let mut failures = failures.lock().unwrap();
+
+    if failures.get(key).is_some_and(|f| f.banned_until.is_none()) {
+        failures.remove(key);
+    }
+}
+
+fn is_locked<K, Q>(failures: &Mutex<HashMap<K, Failures>>, key: &Q) -> bool
+where
+    K: Borrow<Q> + Hash + Eq,
+    Q: Hash + Eq + ?Sized,
+{
+    let mut failures = failures.lock().unwrap();
+
+    match failures.get(key).and_then(|f| f.banned_until) {
+        Some(until) if until > Instant::now() => true,
+        // Le ban a expiré, la clé repart de zéro.
+        Some(_) => {
+            failures.remove(key);
+
+            false
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn login_backoff_doubles_up_to_the_maximum() {
+        assert_eq!(get_login_backoff(1, 0), Duration::ZERO);
+        assert_eq!(get_login_backoff(1, 1), Duration::from_secs(1));
+        assert_eq!(get_login_backoff(1, 3), Duration::from_secs(4));
+        assert_eq!(get_login_backoff(0, 5), Duration::ZERO);
+        assert_eq!(get_login_backoff(1, 40), MAX_LOGIN_BACKOFF);
+    }
+}
@@ -0,0 +1,271 @@
+//! Checksums of files sent by HASH, optionally cached next to the files so that a large file is
+//! only read again once it has changed.
+
+use std::{
+    fs::{self, File, Metadata},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{config::ChecksumCache, platform::crossplatform};
+
+/// Name of the file holding the checksums of a directory with [`ChecksumCache::Sidecar`].
+pub const SIDECAR_NAME: &str = ".checksums";
+
+/// Name of the algorithm, as written in the replies and the cache.
+pub const ALGORITHM: &str = "SHA-256";
+
+const XATTR_NAME: &str = "user.ftp-paradise.sha-256";
+
+/// Sessions updating the same sidecar file at the same time would lose entries.
+static SIDECAR_LOCK: Mutex<()> = Mutex::new(());
+
+/// Get the checksum of the file at `path`, from the cache when it is still valid.
+pub fn get(path: &Path, cache: ChecksumCache) -> io::Result<String> {
+    let metadata = fs::metadata(path)?;
+    let key = cache_key(&metadata);
+
+    let cached = match cache {
+        ChecksumCache::None => None,
+        ChecksumCache::Xattr => read_xattr(path, &key),
+        ChecksumCache::Sidecar => read_sidecar(path, &key),
+    };
+
+    if let Some(checksum) = cached {
+        return Ok(checksum);
+    }
+
+    let checksum = compute(path)?;
+
+    // Un cache qui ne peut pas être écrit ne doit pas faire échouer la commande.
+    let stored = match cache {
+        ChecksumCache::None => Ok(()),
+        ChecksumCache::Xattr => write_xattr(path, &key, &checksum),
+        ChecksumCache::Sidecar => write_sidecar(path, &key, &checksum),
+    };
+
+    if let Err(err) = stored {
+        eprintln!("Cannot cache the checksum of {}: {err}.", path.display());
+    }
+
+    Ok(checksum)
+}
+
+/// Read the whole file at `path` to get its checksum, in lowercase hexadecimal.
+pub fn compute(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+
+        if read == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(to_hex(hasher))
+}
+
+/// Reader computing the checksum of what is read through it, when enabled.
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Option<Sha256>,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R, enabled: bool) -> HashingReader<R> {
+        HashingReader {
+            inner,
+            hasher: enabled.then(Sha256::new),
+        }
+    }
+
+    /// Checksum of the bytes read so far, in lowercase hexadecimal, if enabled.
+    pub fn checksum(&self) -> Option<String> {
+        self.hasher.clone().map(to_hex)
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..read]);
+        }
+
+        Ok(read)
+    }
+}
+
+fn to_hex(hasher: Sha256) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Identify the content of a file: a cached checksum is only used if the key did not change.
+fn cache_key(metadata: &Metadata) -> String {
+    let modified = match metadata.modified().map(|m| m.duration_since(UNIX_EPOCH)) {
+        Ok(Ok(m)) => format!("{}.{:09}", m.as_secs(), m.subsec_nanos()),
+        _ => "0".to_string(),
+    };
+
+    format!("{} {modified}", metadata.len())
+}
+
+fn read_xattr(path: &Path, key: &str) -> Option<String> {
+    let value = crossplatform::get_extended_attribute(path, XATTR_NAME).ok()??;
+    let value = String::from_utf8(value).ok()?;
+
+    parse_entry(&value, key)
+}
+
+fn write_xattr(path: &Path, key: &str, checksum: &str) -> io::Result<()> {
+    crossplatform::set_extended_attribute(path, XATTR_NAME, format_entry(key, checksum).as_bytes())
+}
+
+fn sidecar_path(path: &Path) -> Option<(PathBuf, String)> {
+    let name = path.file_name()?.to_str()?.to_string();
+
+    Some((path.with_file_name(SIDECAR_NAME), name))
+}
+
+fn read_sidecar(path: &Path, key: &str) -> Option<String> {
+    let (sidecar, name) = sidecar_path(path)?;
+
+    let _lock = SIDECAR_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    let content = fs::read_to_string(sidecar).ok()?;
+
+    content
+        .lines()
+        .find_map(|line| match line.split_once('\t') {
+            Some((n, entry)) if n == name => parse_entry(entry, key),
+            _ => None,
+        })
+}
+
+fn write_sidecar(path: &Path, key: &str, checksum: &str) -> io::Result<()> {
+    let (sidecar, name) = match sidecar_path(path) {
+        Some(s) => s,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "file name not supported",
+            ))
+        }
+    };
+
+    // Le nom sert de séparateur de champs et de lignes.
+    if name.contains(['\t', '\r', '\n']) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "file name not supported",
+        ));
+    }
+
+    let _lock = SIDECAR_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+    let content = match fs::read_to_string(&sidecar) {
+        Ok(c) => c,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+
+    // L'ancienne entrée du fichier est remplacée, celles des autres fichiers sont gardées.
+    let mut lines: Vec<&str> = content
+        .lines()
+        .filter(|line| !matches!(line.split_once('\t'), Some((n, _)) if n == name))
+        .collect();
+
+    let entry = format!("{name}\t{}", format_entry(key, checksum));
+    lines.push(&entry);
+
+    fs::write(&sidecar, format!("{}\n", lines.join("\n")))
+}
+
+fn format_entry(key: &str, checksum: &str) -> String {
+    format!("{ALGORITHM} {key} {checksum}")
+}
+
+/// Get the checksum of an entry written by `format_entry`, if its key is still `key`.
+fn parse_entry(entry: &str, key: &str) -> Option<String> {
+    let rest = entry.strip_prefix(ALGORITHM)?.strip_prefix(' ')?;
+    let checksum = rest.strip_prefix(key)?.strip_prefix(' ')?;
+
+    match checksum.len() == 64 && checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+        true => Some(checksum.to_string()),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn entries_depend_on_the_key() {
+        let entry = format_entry("12 1700000000.000000000", EMPTY_SHA256);
+
+        assert_eq!(
+            parse_entry(&entry, "12 1700000000.000000000"),
+            Some(EMPTY_SHA256.to_string())
+        );
+        assert_eq!(parse_entry(&entry, "13 1700000000.000000000"), None);
+        assert_eq!(parse_entry("SHA-256 12 1 abc", "12 1"), None);
+    }
+
+    #[test]
+    fn hashing_reader_matches_the_file() {
+        let mut reader = HashingReader::new(&b""[..], true);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(reader.checksum().as_deref(), Some(EMPTY_SHA256));
+
+        assert_eq!(HashingReader::new(&b"abc"[..], false).checksum(), None);
+    }
+
+    #[test]
+    fn sidecar_is_invalidated_by_changes() {
+        let directory = env::temp_dir().join(format!("ftp-paradise-checksum-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let empty = directory.join("empty");
+        let other = directory.join("other");
+        fs::write(&empty, b"").unwrap();
+        fs::write(&other, b"abc").unwrap();
+
+        assert_eq!(get(&empty, ChecksumCache::Sidecar).unwrap(), EMPTY_SHA256);
+        get(&other, ChecksumCache::Sidecar).unwrap();
+
+        let content = fs::read_to_string(directory.join(SIDECAR_NAME)).unwrap();
+        assert_eq!(content.lines().count(), 2);
+
+        // Une entrée dont la clé ne correspond plus est ignorée puis remplacée.
+        fs::write(&empty, b"abc").unwrap();
+        let key = cache_key(&fs::metadata(&empty).unwrap());
+        assert_eq!(read_sidecar(&empty, &key), None);
+        assert_eq!(
+            get(&empty, ChecksumCache::Sidecar).unwrap(),
+            get(&other, ChecksumCache::None).unwrap()
+        );
+
+        let content = fs::read_to_string(directory.join(SIDECAR_NAME)).unwrap();
+        assert_eq!(content.lines().count(), 2);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+//! Number of times each verb has been executed, and how often it succeeded, for a session and for
+//! the whole server.
+
+use std::{
+    collections::BTreeMap,
+    sync::{LazyLock, Mutex},
+};
+
+/// Replies sent to one verb.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct VerbCounters {
+    pub succeeded: u64,
+    pub failed: u64,
+}
+
+/// Counters of the verbs that have been executed at least once.
+#[derive(Debug, Default, Clone)]
+pub struct CommandStats {
+    verbs: BTreeMap<String, VerbCounters>,
+}
+
+impl CommandStats {
+    pub fn record(&mut self, verb: &str, success: bool) {
+        let counters = self.verbs.entry(verb.to_string()).or_default();
+
+        match success {
+            true => counters.succeeded += 1,
+            false => counters.failed += 1,
+        }
+    }
+
+    pub fn get(&self, verb: &str) -> Option<VerbCounters> {
+        self.verbs.get(verb).copied()
+    }
+
+    /// Replies sent to all the verbs together.
+    pub fn get_totals(&self) -> VerbCounters {
+        self.verbs
+            .values()
+            .fold(VerbCounters::default(), |totals, counters| VerbCounters {
+                succeeded: totals.succeeded + counters.succeeded,
+                failed: totals.failed + counters.failed,
+            })
+    }
+
+    /// Describe each verb on a line, by alphabetical order.
+    pub fn format_lines(&self) -> Vec<String> {
+        self.verbs
+            .iter()
+            .map(|(verb, counters)| {
+                let total = counters.succeeded + counters.failed;
+
+                format!(
+                    "{verb} {total} ({} succeeded, {} failed, {}% success)",
+                    counters.succeeded,
+                    counters.failed,
+                    counters.succeeded * 100 / total
+                )
+            })
+            .collect()
+    }
+}
+
+/// Counters of all the sessions since the server started.
+static GLOBAL: LazyLock<Mutex<CommandStats>> =
+    LazyLock::new(|| Mutex::new(CommandStats::default()));
+
+pub fn record_global(verb: &str, success: bool) {
+    GLOBAL.lock().unwrap().record(verb, success);
+}
+
+pub fn get_global() -> CommandStats {
+    GLOBAL.lock().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbs_are_counted_separately() {
+        let mut stats = CommandStats::default();
+
+        stats.record("RETR", true);
+        stats.record("RETR", false);
+        stats.record("RETR", true);
+        stats.record("CWD", false);
+
+        assert_eq!(
+            stats.get("RETR"),
+            Some(VerbCounters {
+                succeeded: 2,
+                failed: 1
+            })
+        );
+        assert_eq!(
+            stats.get_totals(),
+            VerbCounters {
+                succeeded: 2,
+                failed: 2
+            }
+        );
+        assert_eq!(
+            stats.format_lines(),
+            vec![
+                "CWD 1 (0 succeeded, 1 failed, 0% success)",
+                "RETR 3 (2 succeeded, 1 failed, 66% success)",
+            ]
+        );
+    }
+}
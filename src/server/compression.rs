@@ -0,0 +1,161 @@
+//! Compression of the data sent to the clients, with MODE Z or by serving `file.gz` from `file`.
+
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{
+    read::{GzEncoder, ZlibDecoder, ZlibEncoder},
+    write, Compression,
+};
+
+use crate::config::CompressionSettings;
+
+/// Tell if the content of `path` is worth compressing, according to its extension.
+///
+/// Every file is compressible when no extension is given.
+pub fn is_compressible(path: &Path, extensions: &[String]) -> bool {
+    if extensions.is_empty() {
+        return true;
+    }
+
+    match path.extension() {
+        Some(extension) => extensions
+            .iter()
+            .any(|e| extension.eq_ignore_ascii_case(e.trim_start_matches('.'))),
+        None => false,
+    }
+}
+
+/// Find the file from which `system_path`, ending with `.gz` but missing, can be produced.
+pub fn find_implicit_gzip(system_path: &Path, settings: &CompressionSettings) -> Option<PathBuf> {
+    if settings.implicit_gzip.is_empty() || system_path.exists() {
+        return None;
+    }
+
+    let name = system_path.file_name()?.to_str()?;
+    let source_name = name.strip_suffix(".gz")?;
+
+    // 'file.gz' seul ne désigne aucun fichier source.
+    if source_name.is_empty() {
+        return None;
+    }
+
+    let source = system_path.with_file_name(source_name);
+
+    if source.is_file() && is_compressible(&source, &settings.implicit_gzip) {
+        Some(source)
+    } else {
+        None
+    }
+}
+
+/// Level used to compress `path` in MODE Z.
+///
+/// The files which would not shrink are only wrapped in the deflate format, the client expects
+/// compressed data for every transfer.
+pub fn get_level(path: &Path, settings: &CompressionSettings) -> Compression {
+    if is_compressible(path, &settings.extensions) {
+        Compression::default()
+    } else {
+        Compression::none()
+    }
+}
+
+/// Wrap `reader` so that its content is read as a gzip file.
+pub fn gzip<'a>(reader: impl Read + 'a) -> Box<dyn Read + 'a> {
+    Box::new(GzEncoder::new(reader, Compression::default()))
+}
+
+/// Wrap `reader` so that its content is read as a zlib stream, as sent in MODE Z.
+pub fn deflate<'a>(reader: impl Read + 'a, level: Compression) -> Box<dyn Read + 'a> {
+    Box::new(ZlibEncoder::new(reader, level))
+}
+
+/// Wrap `writer` so that what is written is sent as a zlib stream, ended when dropped.
+pub fn deflate_writer<'a>(writer: impl Write + 'a) -> Box<dyn Write + 'a> {
+    Box::new(write::ZlibEncoder::new(writer, Compression::default()))
+}
+
+/// Wrap `reader`, receiving a zlib stream, so that the original content is read.
+pub fn inflate<'a>(reader: impl Read + 'a) -> Box<dyn Read + 'a> {
+    Box::new(ZlibDecoder::new(reader))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    fn settings(implicit_gzip: &[&str]) -> CompressionSettings {
+        CompressionSettings {
+            mode_z: true,
+            extensions: Vec::new(),
+            implicit_gzip: implicit_gzip.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn compressible_extensions() {
+        let extensions = vec!["txt".to_string(), ".tar".to_string()];
+
+        assert!(is_compressible(Path::new("a/notes.TXT"), &extensions));
+        assert!(is_compressible(Path::new("backup.tar"), &extensions));
+        assert!(!is_compressible(Path::new("photo.jpg"), &extensions));
+        assert!(!is_compressible(Path::new("README"), &extensions));
+        assert!(is_compressible(Path::new("photo.jpg"), &[]));
+    }
+
+    #[test]
+    fn implicit_gzip_needs_a_source() {
+        let directory = env::temp_dir().join(format!("ftp-paradise-gzip-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("backup.tar"), b"content").unwrap();
+        fs::write(directory.join("notes.txt"), b"content").unwrap();
+        fs::write(directory.join("real.tar.gz"), b"content").unwrap();
+        fs::write(directory.join("real.tar"), b"content").unwrap();
+
+        let settings = settings(&["tar"]);
+
+        assert_eq!(
+            find_implicit_gzip(&directory.join("backup.tar.gz"), &settings),
+            Some(directory.join("backup.tar"))
+        );
+        assert_eq!(
+            find_implicit_gzip(&directory.join("notes.txt.gz"), &settings),
+            None
+        );
+        assert_eq!(
+            find_implicit_gzip(&directory.join("real.tar.gz"), &settings),
+            None
+        );
+        assert_eq!(
+            find_implicit_gzip(&directory.join("missing.tar.gz"), &settings),
+            None
+        );
+        assert_eq!(find_implicit_gzip(&directory.join(".gz"), &settings), None);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn deflate_round_trip() {
+        let content = "line\r\n".repeat(1000);
+
+        let mut compressed = Vec::new();
+        deflate(content.as_bytes(), Compression::default())
+            .read_to_end(&mut compressed)
+            .unwrap();
+
+        assert!(compressed.len() < content.len());
+
+        let mut decompressed = String::new();
+        inflate(&compressed[..])
+            .read_to_string(&mut decompressed)
+            .unwrap();
+
+        assert_eq!(decompressed, content);
+    }
+}
@@ -0,0 +1,143 @@
+//! Data connection of the next transfer, opened by the client in passive mode or by the server in
+//! active mode.
+
+use std::{
+    io::{self, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    time::Duration,
+};
+
+use socket2::{Domain, SockRef, Socket, Type};
+
+/// Time given to the client to accept an active data connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How the data connection of the next transfer is opened, set by PASV, EPSV, PORT or EPRT.
+#[derive(Debug)]
+pub enum DataChannel {
+    /// The client connects to this listener.
+    Passive(TcpListener),
+    /// The server connects to the client at `address`, from `local_address`.
+    Active {
+        address: SocketAddr,
+        local_address: SocketAddr,
+    },
+}
+
+impl DataChannel {
+    /// Wait for the client, or connect to it, depending on the mode.
+    pub fn open(&self) -> io::Result<TcpStream> {
+        match self {
+            DataChannel::Passive(listener) => listener.accept().map(|(stream, _)| stream),
+            DataChannel::Active {
+                address,
+                local_address,
+            } => connect(*address, *local_address),
+        }
+    }
+}
+
+/// End what is sent on `stream`: the buffered bytes leave, then the client is told that no more
+/// data follows.
+///
+/// A transfer must only be reported as complete once this succeeded, or the client could read the
+/// 226 reply before the last bytes of the file.
+pub fn finish(mut stream: &TcpStream) -> io::Result<()> {
+    stream.flush()?;
+
+    match stream.shutdown(Shutdown::Write) {
+        // Le client a déjà fermé la connexion après avoir tout reçu.
+        Err(err) if err.kind() == io::ErrorKind::NotConnected => Ok(()),
+        result => result,
+    }
+}
+
+/// Make the closing of `stream` wait up to `seconds` for the bytes not yet acknowledged by the
+/// client (SO_LINGER), 0 resetting the connection instead.
+pub fn set_linger(stream: &TcpStream, seconds: u64) -> io::Result<()> {
+    SockRef::from(stream).set_linger(Some(Duration::from_secs(seconds)))
+}
+
+/// Connect to `address` from the IP of `local_address`, and from the port it gives when it is not
+/// 0, falling back to any port if that one cannot be used.
+fn connect(address: SocketAddr, local_address: SocketAddr) -> io::Result<TcpStream> {
+    if local_address.port() != 0 {
+        match connect_from(address, local_address) {
+            Ok(stream) => return Ok(stream),
+            // Un port inférieur à 1024 demande les droits de root, ou CAP_NET_BIND_SERVICE.
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => eprintln!(
+                "Cannot open active data connection from port {}, which needs privileges: {err}, using any port.",
+                local_address.port()
+            ),
+            Err(err) => eprintln!(
+                "Cannot open active data connection from port {}: {err}, using any port.",
+                local_address.port()
+            ),
+        }
+    }
+
+    connect_from(address, SocketAddr::new(local_address.ip(), 0))
+}
+
+fn connect_from(address: SocketAddr, local_address: SocketAddr) -> io::Result<TcpStream> {
+    let socket = Socket::new(Domain::for_address(address), Type::STREAM, None)?;
+
+    // Le même port source sert à toutes les sessions, vers des clients différents.
+    socket.set_reuse_address(true)?;
+    socket.bind(&local_address.into())?;
+    socket.connect_timeout(&address.into(), CONNECT_TIMEOUT)?;
+
+    Ok(socket.into())
+}
+
+/// Port active data connections are opened from when the control port is `control_port`, as in
+/// `ftp-data` being 20 for `ftp` on 21.
+pub fn get_source_port(control_port: u16) -> u16 {
+    control_port.saturating_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busy_source_port_falls_back() {
+        let client = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = client.local_addr().unwrap();
+
+        // Un listener occupe le port source : la connexion part alors d'un autre port.
+        let busy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let local_address = busy.local_addr().unwrap();
+
+        let channel = DataChannel::Active {
+            address,
+            local_address,
+        };
+
+        let stream = channel.open().unwrap();
+        let (accepted, peer) = client.accept().unwrap();
+
+        assert_eq!(peer, stream.local_addr().unwrap());
+        assert_ne!(peer.port(), local_address.port());
+
+        drop(accepted);
+    }
+
+    #[test]
+    fn finished_stream_ends_with_the_data() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+
+        set_linger(&stream, 5).unwrap();
+        stream.write_all(b"last bytes").unwrap();
+        finish(&stream).unwrap();
+
+        // Le client lit toutes les données puis la fin de la connexion, qui reste ouverte.
+        let mut received = String::new();
+        io::Read::read_to_string(&mut client, &mut received).unwrap();
+        assert_eq!(received, "last bytes");
+
+        client.write_all(b"still open").unwrap();
+    }
+}
@@ -0,0 +1,66 @@
+//! Reasons for which an operation is refused, logged with a stable tag so that a "permission
+//! denied" reported by a user can be explained from the logs.
+
+/// Why an operation was refused.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DenialReason {
+    /// The command needs a session but the client has not logged in.
+    NotLoggedIn,
+    /// The password, or the account, was refused.
+    LoginIncorrect,
+    /// The account is locked after too many wrong passwords.
+    AccountLocked,
+    /// The path leads, through a symbolic link, outside of the directory of the session.
+    PathOutsideJail,
+    /// PORT gives the address of another host than the client, as in the FTP bounce attack.
+    ForeignDataAddress,
+    /// The files of an upload-only directory cannot be read back.
+    DropDirectory,
+}
+
+impl DenialReason {
+    /// Tag identifying the reason in the logs and, if enabled, in the replies.
+    pub fn get_tag(self) -> &'static str {
+        match self {
+            DenialReason::NotLoggedIn => "not_logged_in",
+            DenialReason::LoginIncorrect => "login_incorrect",
+            DenialReason::AccountLocked => "account_locked",
+            DenialReason::PathOutsideJail => "path_outside_jail",
+            DenialReason::ForeignDataAddress => "foreign_data_address",
+            DenialReason::DropDirectory => "drop_directory",
+        }
+    }
+}
+
+/// Add the tag of `reason` to the text of a reply, when `with_tag` is set.
+pub fn format_message(message: String, reason: DenialReason, with_tag: bool) -> String {
+    match with_tag {
+        true => format!("{message} (reason: {})", reason.get_tag()),
+        false => message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag_is_optional() {
+        assert_eq!(
+            format_message(
+                "login incorrect".to_string(),
+                DenialReason::LoginIncorrect,
+                true
+            ),
+            "login incorrect (reason: login_incorrect)"
+        );
+        assert_eq!(
+            format_message(
+                "/etc: permission denied".to_string(),
+                DenialReason::PathOutsideJail,
+                false
+            ),
+            "/etc: permission denied"
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Commands added by the programs embedding the server, without changing its dispatcher: SITE
+//! subcommands, new verbs, and aliases of the existing verbs.
+//!
+//! They are registered in an [`Extensions`] given to [`crate::run_with_extensions`], and cannot
+//! replace the commands of the server.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::{
+    protocol::{command_args::CommandArgs, reply::format_reply, reply_code::ReplyCode},
+    server::{
+        ftp_client::{FtpClient, SITE_COMMANDS},
+        ftp_server, path_resolver,
+    },
+};
+
+/// Final reply of a command added by an embedder.
+///
+/// A message made of several lines separated by `\r\n` is sent as a multiline reply.
+pub type ExtensionResult = Result<(ReplyCode, String), (ReplyCode, String)>;
+
+/// Handler of a command added by an embedder.
+pub type ExtensionHandler =
+    Box<dyn Fn(&mut CommandContext, &CommandArgs) -> ExtensionResult + Send + Sync>;
+
+/// Commands added to the server.
+#[derive(Default)]
+pub struct Extensions {
+    site_commands: HashMap<String, ExtensionHandler>,
+    commands: HashMap<String, ExtensionHandler>,
+    aliases: HashMap<String, String>,
+}
+
+impl Extensions {
+    pub fn new() -> Extensions {
+        Extensions::default()
+    }
+
+    /// Add the subcommand `SITE name`, executed by `handler` for the logged in users.
+    pub fn add_site_command(
+        &mut self,
+        name: &str,
+        handler: impl Fn(&mut CommandContext, &CommandArgs) -> ExtensionResult + Send + Sync + 'static,
+    ) -> Result<(), String> {
+        let name = check_name(name)?;
+
+        if SITE_COMMANDS.contains(&&name[..]) || self.site_commands.contains_key(&name) {
+            return Err(format!("SITE {name} already exists"));
+        }
+
+        self.site_commands.insert(name, Box::new(handler));
+
+        Ok(())
+    }
+
+    /// Add the command `verb`, executed by `handler` for the logged in users.
+    pub fn add_command(
+        &mut self,
+        verb: &str,
+        handler: impl Fn(&mut CommandContext, &CommandArgs) -> ExtensionResult + Send + Sync + 'static,
+    ) -> Result<(), String> {
+        let verb = check_name(verb)?;
+
+        if self.is_known(&verb) {
+            return Err(format!("{verb} already exists"));
+        }
+
+        self.commands.insert(verb, Box::new(handler));
+
+        Ok(())
+    }
+
+    /// Make `alias` stand for `verb`, a command of the server or one added before.
+    pub fn add_alias(&mut self, alias: &str, verb: &str) -> Result<(), String> {
+        let alias = check_name(alias)?;
+        let verb = verb.to_ascii_uppercase();
+
+        if self.is_known(&alias) {
+            return Err(format!("{alias} already exists"));
+        }
+
+        if !ftp_server::COMMANDS.contains(&&verb[..]) && !self.commands.contains_key(&verb) {
+            return Err(format!("{verb} is not a command of the server"));
+        }
+
+        self.aliases.insert(alias, verb);
+
+        Ok(())
+    }
+
+    /// Command that `verb` stands for, `verb` itself if it is not an alias.
+    pub fn resolve_alias(&self, verb: String) -> String {
+        match self.aliases.get(&verb) {
+            Some(command) => command.clone(),
+            None => verb,
+        }
+    }
+
+    pub fn get_site_command(&self, name: &str) -> Option<&ExtensionHandler> {
+        self.site_commands.get(&name.to_ascii_uppercase())
+    }
+
+    pub fn get_command(&self, verb: &str) -> Option<&ExtensionHandler> {
+        self.commands.get(verb)
+    }
+
+    fn is_known(&self, verb: &str) -> bool {
+        ftp_server::is_builtin_command(verb)
+            || self.commands.contains_key(verb)
+            || self.aliases.contains_key(verb)
+    }
+}
+
+/// Name of a command in upper case, if it is made of 3 to 16 ASCII letters.
+fn check_name(name: &str) -> Result<String, String> {
+    if !(3..=16).contains(&name.len()) || !name.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return Err(format!("{name:?} is not a valid command name"));
+    }
+
+    Ok(name.to_ascii_uppercase())
+}
+
+/// Session executing a command added by an embedder.
+pub struct CommandContext<'a> {
+    client: &'a mut FtpClient,
+}
+
+impl<'a> CommandContext<'a> {
+    pub fn new(client: &'a mut FtpClient) -> CommandContext<'a> {
+        CommandContext { client }
+    }
+
+    /// Name of the logged in user.
+    pub fn get_username(&self) -> Option<String> {
+        let options = self.client.get_options();
+        let options = options.borrow();
+
+        options
+            .session
+            .as_ref()
+            .filter(|sess| sess.is_authenticated())
+            .map(|sess| sess.get_username().to_string())
+    }
+
+    pub fn get_session_id(&self) -> &str {
+        self.client.get_session_id()
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.client.is_admin()
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.client
+            .get_options()
+            .borrow()
+            .session
+            .as_ref()
+            .is_some_and(|sess| sess.is_read_only())
+    }
+
+    /// Path of the file `path`, relative to the working directory, on the disk of the server.
+    ///
+    /// A path leading outside of the directory of the session is refused with the reply to give.
+    pub fn get_system_path(&self, path: &str) -> Result<PathBuf, (ReplyCode, String)> {
+        let (root, working_directory) = self.client.get_location();
+
+        let path = path_resolver::resolve(&working_directory, path);
+        let system_path = path_resolver::to_system_path(&root, &path);
+
+        self.client.check_jail(&root, &path, &system_path)?;
+
+        Ok(system_path)
+    }
+
+    /// Send an intermediate reply, for example a 1xx reply before a long operation.
+    pub fn send_reply(&mut self, code: ReplyCode, message: &str) -> Result<(), String> {
+        match self
+            .client
+            .write(format_reply(code, message, false).as_bytes())
+        {
+            Ok(_) => Ok(()),
+            Err(err) => Err(format!("cannot send reply: {err}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ingest(_: &mut CommandContext, _: &CommandArgs) -> ExtensionResult {
+        Ok((ReplyCode::CommandOk, "ingested".to_string()))
+    }
+
+    #[test]
+    fn extensions_cannot_replace_the_commands_of_the_server() {
+        let mut extensions = Extensions::new();
+
+        assert!(extensions.add_site_command("ingest", ingest).is_ok());
+        assert!(extensions.add_site_command("INGEST", ingest).is_err());
+        assert!(extensions.add_site_command("GETTAR", ingest).is_err());
+        assert!(extensions.add_command("XCRC", ingest).is_ok());
+        assert!(extensions.add_command("RETR", ingest).is_err());
+        assert!(extensions.add_command("XPWD", ingest).is_err());
+        assert!(extensions.add_command("X-1", ingest).is_err());
+
+        assert!(extensions.add_alias("GET", "retr").is_ok());
+        assert!(extensions.add_alias("CRC", "XCRC").is_ok());
+        assert!(extensions.add_alias("PUT", "SEND").is_err());
+        assert!(extensions.add_alias("LIST", "NLST").is_err());
+
+        assert_eq!(extensions.resolve_alias("GET".to_string()), "RETR");
+        assert_eq!(extensions.resolve_alias("CRC".to_string()), "XCRC");
+        assert_eq!(extensions.resolve_alias("STOR".to_string()), "STOR");
+        assert!(extensions.get_site_command("Ingest").is_some());
+        assert!(extensions.get_command("CRC").is_none());
+    }
+}
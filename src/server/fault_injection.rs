@@ -0,0 +1,65 @@
+use std::{cell::Cell, thread, time::Duration};
+
+use crate::config::FaultInjection;
+
+/// Randomly disturbs a session according to the `faults` section of the config, so that client
+/// authors can exercise their retry logic against a controllable server.
+pub struct FaultInjector {
+    settings: FaultInjection,
+    // État du générateur pseudo-aléatoire, un xorshift suffit largement pour ce besoin.
+    state: Cell<u64>,
+}
+
+impl FaultInjector {
+    pub fn new(settings: FaultInjection, seed: u64) -> FaultInjector {
+        FaultInjector {
+            settings,
+            // Le xorshift reste bloqué à 0 si l'état y est initialisé.
+            state: Cell::new(seed | 1),
+        }
+    }
+
+    /// Wait before sending a reply, to simulate a slow network or server.
+    pub fn delay_reply(&self) {
+        if self.settings.reply_delay_ms > 0 {
+            thread::sleep(Duration::from_millis(self.settings.reply_delay_ms));
+        }
+    }
+
+    /// Tell if the current command must fail with a transient error instead of being executed.
+    pub fn inject_error(&self) -> bool {
+        self.roll(self.settings.error_rate)
+    }
+
+    /// Tell if the data connection must be closed before anything is transferred.
+    pub fn drop_data_connection(&self) -> bool {
+        self.roll(self.settings.drop_data_rate)
+    }
+
+    /// Tell if the data sent to the client must be cut before its end.
+    pub fn truncate_transfer(&self) -> bool {
+        self.roll(self.settings.truncate_rate)
+    }
+
+    fn roll(&self, rate: f64) -> bool {
+        if rate <= 0.0 {
+            return false;
+        }
+
+        let mut x = self.state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state.set(x);
+
+        // Les 53 bits de poids fort donnent un nombre uniforme entre 0 et 1.
+        let sample = (x >> 11) as f64 / (1u64 << 53) as f64;
+        let injected = sample < rate;
+
+        if injected {
+            println!("Injecting fault.");
+        }
+
+        injected
+    }
+}
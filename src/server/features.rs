@@ -0,0 +1,71 @@
+//! Extensions announced by FEAT, depending on what the configuration enables.
+
+use crate::{
+    config::Config,
+    protocol::facts::{self, Fact},
+};
+
+use super::checksum;
+
+/// Extensions the server supports, each with the command that provides it so that an extension is
+/// not announced when its command is disabled.
+///
+/// `mlst_facts` are the facts selected by the session, marked with a `*`.
+fn registry(config: &Config, mlst_facts: &[Fact]) -> Vec<(&'static str, String)> {
+    let mut features = vec![
+        ("OPTS", "UTF8".to_string()),
+        ("HOST", "HOST".to_string()),
+        ("EPRT", "EPRT".to_string()),
+        ("EPSV", "EPSV".to_string()),
+        ("HASH", format!("HASH {}*", checksum::ALGORITHM)),
+        ("RANG", "RANG STREAM".to_string()),
+        ("REST", "REST STREAM".to_string()),
+        (
+            "MLSD",
+            format!("MLST {}", facts::format_feature(mlst_facts)),
+        ),
+        ("MDTM", "MDTM".to_string()),
+        ("MFMT", "MFMT".to_string()),
+    ];
+
+    if config.get_compression().mode_z {
+        features.push(("MODE", "MODE Z".to_string()));
+    }
+
+    features
+}
+
+/// Lines of the FEAT reply for `config`, to a session that selected `mlst_facts`.
+pub fn list(config: &Config, mlst_facts: &[Fact]) -> Vec<String> {
+    registry(config, mlst_facts)
+        .into_iter()
+        .filter(|(command, _)| !config.is_command_disabled(command))
+        .map(|(_, feature)| feature)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::facts::SUPPORTED_FACTS;
+
+    use super::*;
+
+    #[test]
+    fn features_follow_the_config() {
+        let config = Config::default();
+        let list = |config: &Config| list(config, &SUPPORTED_FACTS);
+        assert!(list(&config).contains(&"MLST type*;size*;modify*;perm*;unique*;".to_string()));
+        assert!(!list(&config).contains(&"MODE Z".to_string()));
+        assert!(super::list(&config, &[Fact::Size])
+            .contains(&"MLST type;size*;modify;perm;unique;".to_string()));
+
+        let config: Config =
+            toml::from_str("disabled_commands = [\"mlsd\"]\n[compression]\nmode_z = true\n")
+                .unwrap();
+        assert!(list(&config).contains(&"MODE Z".to_string()));
+        assert!(!list(&config)
+            .iter()
+            .any(|feature| feature.starts_with("MLST")));
+        assert!(list(&config).contains(&"HASH SHA-256*".to_string()));
+    }
+}
@@ -0,0 +1,67 @@
+//! Reasons given in the 550 replies of the commands working on files.
+//!
+//! They are worded like the messages of `strerror` and the same for every command, as some
+//! clients, mostly on Windows, parse the text of these replies to tell a missing file from a
+//! refused one.
+
+use std::io;
+
+use crate::protocol::reply_code::ReplyCode;
+
+pub const NO_SUCH_FILE: &str = "No such file or directory";
+pub const PERMISSION_DENIED: &str = "Permission denied";
+pub const IS_A_DIRECTORY: &str = "Is a directory";
+pub const NOT_A_DIRECTORY: &str = "Not a directory";
+pub const FILE_EXISTS: &str = "File exists";
+pub const DIRECTORY_NOT_EMPTY: &str = "Directory not empty";
+
+/// Reason matching the kind of `err`, `None` for the kinds without a reason of their own.
+pub fn get_reason(err: &io::Error) -> Option<&'static str> {
+    match err.kind() {
+        io::ErrorKind::NotFound => Some(NO_SUCH_FILE),
+        io::ErrorKind::PermissionDenied => Some(PERMISSION_DENIED),
+        io::ErrorKind::IsADirectory => Some(IS_A_DIRECTORY),
+        io::ErrorKind::NotADirectory => Some(NOT_A_DIRECTORY),
+        io::ErrorKind::AlreadyExists => Some(FILE_EXISTS),
+        io::ErrorKind::DirectoryNotEmpty => Some(DIRECTORY_NOT_EMPTY),
+        _ => None,
+    }
+}
+
+/// 550 reply refusing `path` for `reason`.
+pub fn unavailable(path: &str, reason: &str) -> (ReplyCode, String) {
+    (ReplyCode::FileUnavailable, format!("{path}: {reason}"))
+}
+
+/// 550 reply to the failure `err` on `path`, explained by `fallback` when the error has no
+/// reason of its own.
+pub fn from_io(path: &str, err: &io::Error, fallback: &str) -> (ReplyCode, String) {
+    unavailable(path, get_reason(err).unwrap_or(fallback))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reasons_follow_the_kind_of_error() {
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        let busy = io::Error::from(io::ErrorKind::ResourceBusy);
+
+        assert_eq!(
+            from_io("/a.txt", &missing, "cannot delete file"),
+            (
+                ReplyCode::FileUnavailable,
+                "/a.txt: No such file or directory".to_string()
+            )
+        );
+        assert_eq!(
+            from_io("/a.txt", &busy, "cannot delete file").1,
+            "/a.txt: cannot delete file"
+        );
+        assert_eq!(
+            get_reason(&io::Error::from(io::ErrorKind::DirectoryNotEmpty)),
+            Some(DIRECTORY_NOT_EMPTY)
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Transformations applied to the names of the files and directories created by the clients.
+
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use crate::config::{FilenameSettings, FilenameTransform};
+
+/// Why a new name is refused.
+#[derive(Debug, PartialEq)]
+pub enum NameError {
+    /// Nothing is left of the name once transformed.
+    Empty,
+    /// The extension of the file is not in the allow-list.
+    ExtensionNotAllowed,
+}
+
+/// Apply the transformations of `settings` to the last component of `path`, a path resolved by
+/// the path resolver, and check the extension of the result if it names a file.
+pub fn transform_path(
+    settings: &FilenameSettings,
+    path: &str,
+    is_directory: bool,
+) -> Result<String, NameError> {
+    let (parent, name) = match path.rsplit_once('/') {
+        Some(split) => split,
+        None => ("", path),
+    };
+
+    let name = settings
+        .transforms
+        .iter()
+        .fold(name.to_string(), |name, transform| apply(*transform, &name));
+
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(NameError::Empty);
+    }
+
+    if !is_directory && !is_extension_allowed(&name, &settings.allowed_extensions) {
+        return Err(NameError::ExtensionNotAllowed);
+    }
+
+    Ok(format!("{parent}/{name}"))
+}
+
+fn apply(transform: FilenameTransform, name: &str) -> String {
+    match transform {
+        FilenameTransform::Lowercase => name.to_lowercase(),
+        // Les lettres sont décomposées pour séparer les accents, qui sont ensuite retirés.
+        FilenameTransform::StripDiacritics => name
+            .nfd()
+            .filter(|c| !is_combining_mark(*c))
+            .nfc()
+            .collect(),
+        FilenameTransform::SpacesToUnderscores => name.replace(' ', "_"),
+    }
+}
+
+fn is_extension_allowed(name: &str, allowed_extensions: &[String]) -> bool {
+    if allowed_extensions.is_empty() {
+        return true;
+    }
+
+    // Un nom comme '.profile' n'a pas d'extension.
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => allowed_extensions
+            .iter()
+            .any(|e| extension.eq_ignore_ascii_case(e.trim_start_matches('.'))),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transforms_are_chained() {
+        let settings = FilenameSettings {
+            transforms: vec![
+                FilenameTransform::StripDiacritics,
+                FilenameTransform::Lowercase,
+                FilenameTransform::SpacesToUnderscores,
+            ],
+            allowed_extensions: vec!["txt".to_string(), ".pdf".to_string()],
+        };
+
+        assert_eq!(
+            transform_path(&settings, "/Été 2024/Compte Rendu.PDF", false),
+            Ok("/Été 2024/compte_rendu.pdf".to_string())
+        );
+        assert_eq!(
+            transform_path(&settings, "/Été 2024", true),
+            Ok("/ete_2024".to_string())
+        );
+        assert_eq!(
+            transform_path(&settings, "/setup.exe", false),
+            Err(NameError::ExtensionNotAllowed)
+        );
+        assert_eq!(
+            transform_path(&settings, "/README", false),
+            Err(NameError::ExtensionNotAllowed)
+        );
+        assert_eq!(
+            transform_path(&settings, "/\u{301}", true),
+            Err(NameError::Empty)
+        );
+    }
+}
@@ -1,39 +1,126 @@
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     error::Error,
-    ffi::CStr,
-    fs,
-    io::{self, BufRead, BufReader, BufWriter, Write},
-    net::{TcpListener, TcpStream},
-    path::Path,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write},
+    net::{SocketAddr, SocketAddrV4, TcpListener, TcpStream},
+    path::{Path, PathBuf},
     rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::RecvTimeoutError,
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
-// Indique que la ligne du dessous ne sera incluse que sur des plateformes 'Linux'.
-#[cfg(target_os = "linux")]
-use std::os::{linux::fs::MetadataExt as _, unix::fs::MetadataExt};
-
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, Utc};
+use flate2::CrcReader;
 
 use crate::{
     commands::{CommandResult, CommandReturnType},
+    config::{DirectoryIndex, PasvReply, QuirkProfile, ServerTokens},
     options::{
         data_representation::DataType, listen_mode::ListenMode, session::SessionInformations,
-        ClientOptions,
+        transfer_mode::TransferMode, ClientOptions,
+    },
+    platform::{crossplatform, file_info::Details, FileInfo},
+    protocol::{
+        command_args::CommandArgs,
+        facts::{self, Fact},
+        host_port,
+        reply::{format_reply, quote_pathname},
+        reply_code::ReplyCode,
+    },
+    server::{
+        brute_force,
+        checksum::{self, HashingReader},
+        command_stats::{self, CommandStats},
+        compression,
+        data_channel::{self, DataChannel},
+        denial::{self, DenialReason},
+        extensions::{CommandContext, ExtensionHandler},
+        fault_injection::FaultInjector,
+        features, file_error,
+        filename::{self, NameError},
+        ftp_server, hot_folder, limits,
+        listing::{self, Lines},
+        metrics, partial_upload, passive,
+        path_resolver::{self, TargetError},
+        priority, quirks,
+        resources::{self, TransferSlot},
+        session_state::{Direction, SessionState},
+        sniffing,
+        tar::TarStream,
+        temporary_credentials,
+        timeline::{self, Event, Timeline, TransferOutcome},
+        transcript::Transcript,
+        upload::UploadWriter,
+        virtual_host,
     },
-    CONFIG,
+    user_store::UserStore,
+    CONFIG, EXTENSIONS, USER_STORE, VERSION,
 };
 
+/// Subcommands of SITE provided by the server.
+pub const SITE_COMMANDS: [&str; 8] = [
+    "SHARE",
+    "GETTAR",
+    "STATS",
+    "UPTIME",
+    "WHO",
+    "CHPASS",
+    "HASHONUPLOAD",
+    "VERSION",
+];
+
+/// Number of entries sent between two flushes of the data connection during a listing, and
+/// between two checks for an ABOR on the control connection.
+const LISTING_BATCH_SIZE: usize = 256;
+
+/// Longest time a listing waits for its next line before checking the control connection.
+const LISTING_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Longest time a session waits for its client before checking if the server is shutting down.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Counter used to give a different identifier to each session.
+static SESSION_COUNTER: AtomicU64 = AtomicU64::new(1);
+
 pub struct FtpClient {
     stream_writer: TcpStream,
     stream_reader: BufReader<TcpStream>,
     // TODO: Se renseigner sur comment utiliser une référence au lieu d'un RC.
     options: Rc<RefCell<ClientOptions>>,
-    pub data_listener: Rc<RefCell<Option<TcpListener>>>,
+    pub data_channel: Rc<RefCell<Option<DataChannel>>>,
+    // Place du listener passif, ou de la connexion active, dans les limites de descripteurs,
+    // suivie jusqu'à la fin du transfert qui l'utilise.
+    data_slot: RefCell<Option<TransferSlot>>,
+    // Moment où le listener passif actuel a été ouvert, pour fermer ceux qui ne servent pas.
+    passive_since: Cell<Option<Instant>>,
+    open_transfers: Rc<Cell<usize>>,
+    command_stats: RefCell<CommandStats>,
+    /// Wrong passwords sent in the session, to delay the next replies.
+    failed_logins: Cell<u32>,
+    shutdown: Arc<AtomicBool>,
+    session_id: String,
+    peer_address: SocketAddr,
+    local_address: SocketAddr,
+    transcript: RefCell<Option<Transcript>>,
+    timeline: RefCell<Timeline>,
+    // Partagé avec SITE WHO, qui le lit depuis les autres sessions.
+    state: Arc<SessionState>,
+    pub faults: FaultInjector,
+    // Requête reçue pendant un transfert, qui sera traitée une fois celui-ci terminé.
+    pending_request: Option<String>,
 }
 
 impl FtpClient {
-    pub fn build(stream: TcpStream) -> Result<FtpClient, Box<dyn Error>> {
+    pub fn build(
+        stream: TcpStream,
+        shutdown: Arc<AtomicBool>,
+    ) -> Result<FtpClient, Box<dyn Error>> {
         // 'stream_writer' permet d'écrire dans le stream du client.
         // 'try_clone' fait une copie de la référence vers le stream.
         //
@@ -43,6 +130,25 @@ impl FtpClient {
         // Donc je passe directement par le stream lui-même pour éviter les problèmes de buffers.
         let stream_copy = stream.try_clone()?;
 
+        // Les lectures sont interrompues régulièrement pour remarquer l'arrêt du serveur même
+        // quand le client ne dit rien.
+        stream.set_read_timeout(Some(SHUTDOWN_POLL_INTERVAL))?;
+        // Un client IPv4 accepté sur une socket IPv6 apparaît comme '::ffff:a.b.c.d', ce qui
+        // fausserait les logs, les bannissements et la famille des connexions de données.
+        let peer_address = canonical(stream.peer_addr()?);
+        let local_address = canonical(stream.local_addr()?);
+
+        let now = Local::now();
+        let counter = SESSION_COUNTER.fetch_add(1, Ordering::Relaxed);
+
+        // L'heure de connexion évite que les identifiants se répètent d'une exécution à l'autre.
+        let session_id = format!("{}-{counter}", now.format("%Y%m%d%H%M%S"));
+
+        let faults = FaultInjector::new(
+            CONFIG.get().unwrap().get_faults(),
+            now.timestamp_micros() as u64 ^ counter,
+        );
+
         Ok(FtpClient {
             stream_writer: stream,
             // Afin de faciliter la lecture des requêtes, 'BufReader' est utilisée pour lire des lignes
@@ -54,339 +160,3612 @@ impl FtpClient {
             // qu'il sature la mémoire du serveur.
             // TODO: Se renseigner sur ce potentiel problème.
             stream_reader: BufReader::new(stream_copy),
-            options: Rc::new(RefCell::new(ClientOptions {
-                session: None,
-                working_directory: "/".to_string(),
-                // Le protocole indique que le type par défaut est ASCII.
-                data_representation: DataType::ASCII,
-                local_bytes: 0,
-                listen_mode: ListenMode::Active,
-            })),
-            data_listener: Rc::new(RefCell::new(None)),
+            options: Rc::new(RefCell::new(ClientOptions::new(
+                CONFIG.get().unwrap().get_root(),
+            ))),
+            data_channel: Rc::new(RefCell::new(None)),
+            data_slot: RefCell::new(None),
+            passive_since: Cell::new(None),
+            open_transfers: Rc::new(Cell::new(0)),
+            command_stats: RefCell::new(CommandStats::default()),
+            failed_logins: Cell::new(0),
+            shutdown,
+            transcript: RefCell::new(None),
+            timeline: RefCell::new(Timeline::new(&session_id, &peer_address.to_string())),
+            state: Arc::new(SessionState::new()),
+            session_id,
+            peer_address,
+            local_address,
+            faults,
+            pending_request: None,
         })
     }
 
     pub fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        if let Some(transcript) = self.transcript.get_mut() {
+            transcript.record_reply(&String::from_utf8_lossy(buffer));
+        }
+
         self.stream_writer.write(buffer)
     }
 
-    pub fn read_line(&mut self) -> Result<String, String> {
-        let mut line = String::new();
+    pub fn read_line(&mut self) -> Result<Option<String>, String> {
+        if let Some(request) = self.pending_request.take() {
+            return Ok(Some(request));
+        }
+
+        let max_length = CONFIG.get().unwrap().get_limits().max_request_length;
+
+        // Une ligne peut arriver en plusieurs morceaux séparés par l'expiration des lectures.
+        let mut line = Vec::new();
+
+        loop {
+            // La lecture est bornée pour qu'un client envoyant une ligne sans fin ne puisse pas
+            // saturer la mémoire du serveur.
+            let limit = (max_length + 1).saturating_sub(line.len()) as u64;
+            let mut reader = (&mut self.stream_reader).take(limit);
+
+            // Les octets sont lus tels quels car certains clients préfixent ABOR de commandes
+            // Telnet qui ne sont pas de l'UTF-8 valide.
+            match reader.read_until(b'\n', &mut line) {
+                // Le client a fermé la connexion.
+                Ok(0) if line.is_empty() => return Ok(None),
+                Ok(_) => (),
+                Err(err) if is_timeout(&err) => {
+                    if self.is_shutting_down() {
+                        return Err("server shutting down".to_string());
+                    }
+
+                    self.reap_idle_listener();
+
+                    continue;
+                }
+                Err(err) => return Err(err.to_string()),
+            }
+
+            if line.len() > max_length && line.last() != Some(&b'\n') {
+                self.discard_line()?;
+
+                limits::record_request_too_long();
 
-        match self.stream_reader.read_line(&mut line) {
-            Ok(_) => Ok(line.trim().to_string()),
-            Err(err) => Err(err.to_string()),
+                let reply = format_reply(ReplyCode::SyntaxError, "command line too long", false);
+                let _ = self.write(reply.as_bytes());
+
+                line.clear();
+
+                continue;
+            }
+
+            let line = String::from_utf8_lossy(&line).trim().to_string();
+
+            if let Some(transcript) = self.transcript.get_mut() {
+                transcript.record_request(&line);
+            }
+
+            return Ok(Some(line));
         }
     }
 
-    /// Execute the FTP command USER.
-    pub fn exec_user_command(&self, args: Box<dyn Iterator<Item = String>>) -> CommandResult {
-        let mut username = String::new();
+    /// Skip what remains of the request being read, without keeping it in memory.
+    fn discard_line(&mut self) -> Result<(), String> {
+        loop {
+            let mut chunk = Vec::new();
+
+            match (&mut self.stream_reader)
+                .take(4096)
+                .read_until(b'\n', &mut chunk)
+            {
+                Ok(0) => return Ok(()),
+                Ok(_) if chunk.last() == Some(&b'\n') => return Ok(()),
+                Ok(_) => (),
+                Err(err) if is_timeout(&err) && !self.is_shutting_down() => (),
+                Err(err) => return Err(err.to_string()),
+            }
+        }
+    }
+
+    pub fn get_session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Tell if the server is stopping, in which case the session must end as soon as possible.
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.load(Ordering::Relaxed)
+    }
+
+    /// Count the execution of `verb` for the session and for the server.
+    pub fn record_command(&self, verb: &str, success: bool) {
+        self.command_stats.borrow_mut().record(verb, success);
+        command_stats::record_global(verb, success);
+        self.state.record_command(verb);
+    }
+
+    /// What the session is doing, shared with the other sessions.
+    pub fn get_state(&self) -> Arc<SessionState> {
+        Arc::clone(&self.state)
+    }
+
+    /// Beginning of the log lines of the session, which identifies it and its client.
+    pub fn get_log_context(&self) -> String {
+        format!("Session {} from {}", self.session_id, self.peer_address)
+    }
+
+    pub fn get_peer_address(&self) -> SocketAddr {
+        self.peer_address
+    }
+
+    /// Print the line summarizing the session once it is over.
+    pub fn log_session_end(&self) {
         let options = self.get_options();
+        let options = RefCell::borrow(&options);
 
-        // Récupère tous les arguments pour en faire un nom d'utilisateur.
-        args.for_each(|arg| username.push_str(&format!("{arg} ")));
-        username = username.trim().to_string();
+        let username = match &options.session {
+            Some(sess) => sess.get_username().to_string(),
+            None => "-".to_string(),
+        };
 
-        let session = SessionInformations::new(username, None);
+        let commands = self.command_stats.borrow().get_totals();
+
+        // Une seule ligne par session suffit pour la comptabilité, sans croiser les autres logs.
+        println!(
+            "Session {} of {username} from {} closed: {}",
+            self.session_id,
+            self.peer_address,
+            options
+                .transfer_statistics
+                .accounting(commands.succeeded + commands.failed, commands.failed)
+        );
+    }
 
-        let mut opt = RefCell::borrow_mut(&options);
-        opt.session = Some(session);
+    /// Start recording the session in a transcript if it isn't already.
+    pub fn start_transcript(&self) {
+        let mut transcript = self.transcript.borrow_mut();
 
-        Ok((
-            230,
-            "user connected".to_string(),
-            false,
-            CommandReturnType::None,
-        ))
+        if transcript.is_some() {
+            return;
+        }
+
+        let dir = CONFIG.get().unwrap().get_transcripts_dir();
+
+        match Transcript::create(&dir, &self.session_id) {
+            Ok(t) => {
+                println!("Recording session {} in {dir}.", self.session_id);
+
+                *transcript = Some(t);
+            }
+            Err(err) => eprintln!("Cannot create transcript in {dir}: {err}."),
+        }
     }
 
-    /// Execute the FTP command SYST.
-    pub fn exec_syst_command(&self, _: Box<dyn Iterator<Item = String>>) -> CommandResult {
+    /// Check, without blocking, if the client sent ABOR on the control connection during a
+    /// transfer.
+    ///
+    /// Any other request is kept to be executed once the transfer is over, as are the ones
+    /// following it.
+    fn abort_requested(&mut self) -> bool {
+        if self.pending_request.is_none() {
+            if self.stream_reader.buffer().is_empty() {
+                if self.stream_writer.set_nonblocking(true).is_err() {
+                    return false;
+                }
+
+                let available =
+                    matches!(self.stream_reader.fill_buf(), Ok(buffer) if !buffer.is_empty());
+
+                let _ = self.stream_writer.set_nonblocking(false);
+
+                if !available {
+                    return false;
+                }
+            }
+
+            // Une ligne incomplète attend la fin du transfert, qui ne doit pas dépendre de
+            // l'arrivée de la suite.
+            if !self.stream_reader.buffer().contains(&b'\n') {
+                return false;
+            }
+
+            match self.read_line() {
+                Ok(Some(line)) if !line.is_empty() => self.pending_request = Some(line),
+                _ => return false,
+            }
+        }
+
+        // Les caractères Telnet 'Interrupt Process' et 'Synch' peuvent précéder la commande.
+        let verb = self.pending_request.as_deref().map(|request| {
+            request
+                .trim_start_matches(|c: char| !c.is_ascii_alphabetic())
+                .to_ascii_uppercase()
+        });
+
+        match verb.as_deref() {
+            Some("ABOR") => {
+                self.pending_request = None;
+
+                true
+            }
+            // Un STAT sans argument reçoit l'état du transfert tout de suite, comme le prévoit
+            // la RFC 959.
+            Some("STAT") => {
+                self.pending_request = None;
+
+                let _ = self.write(
+                    format!(
+                        "{} {}\r\n",
+                        ReplyCode::FileStatus,
+                        self.state.get().describe()
+                    )
+                    .as_bytes(),
+                );
+
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Send `lines` on the data connection opened with PASV or PORT.
+    ///
+    /// The lines are produced by another thread and flushed by batches so that huge listings
+    /// never sit in memory, and the transfer stops as soon as the client sends ABOR, even while a
+    /// slow filesystem keeps the next line from coming.
+    fn send_lines(&mut self, command: &str, path: &str, lines: Lines) -> CommandResult {
+        let (data_channel, _slot) = self.take_data_channel()?;
+
+        let _ = self.write(format!("{} ok\r\n", ReplyCode::FileStatusOk).as_bytes());
+
+        let connection = self.open_data_connection(&data_channel)?;
+
+        let _keepalive = self.keep_control_alive();
+        let _transfer = self
+            .state
+            .start_transfer(Direction::Download, command, path);
+
+        if self.faults.drop_data_connection() {
+            return Err((
+                ReplyCode::ConnectionClosed,
+                "connection closed, transfer aborted".to_string(),
+            ));
+        }
+
+        // Une liste tronquée s'arrête après la première entrée.
+        let truncate = self.faults.truncate_transfer();
+
+        let started = Instant::now();
+        let mut sent = 0;
+
+        let mut writer: Box<dyn Write> = match RefCell::borrow(&self.options).transfer_mode {
+            TransferMode::Deflate => compression::deflate_writer(BufWriter::new(&connection)),
+            TransferMode::Stream => Box::new(BufWriter::new(&connection)),
+        };
+
+        let max_entries = CONFIG.get().unwrap().get_limits().max_listing_entries;
+        let mut capped = false;
+        let mut count = 0;
+
+        let lines = match listing::in_background(lines, LISTING_BATCH_SIZE) {
+            Ok(l) => l,
+            Err(err) => return self.abort_transfer(command, path, 0, started, &err),
+        };
+
+        loop {
+            let line = match lines.recv_timeout(LISTING_POLL_INTERVAL) {
+                Ok(l) => l,
+                Err(RecvTimeoutError::Timeout) => {
+                    // Les lignes déjà prêtes partent pendant que les suivantes se font attendre.
+                    if let Err(err) = writer.flush() {
+                        return self.abort_transfer(command, path, sent as u64, started, &err);
+                    }
+
+                    if let Some(result) = self.interrupt_listing(command, path, sent, started) {
+                        return result;
+                    }
+
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            // L'entrée en trop n'est lue que pour savoir si le dossier dépasse la limite.
+            if max_entries != 0 && count == max_entries {
+                capped = true;
+                break;
+            }
+
+            let response = format!("{line}\r\n");
+
+            // Envoie au client la ligne contenant les informations du fichiers.
+            if let Err(err) = writer.write_all(response.as_bytes()) {
+                return self.abort_transfer(command, path, sent as u64, started, &err);
+            }
+
+            sent += response.len();
+            count += 1;
+            self.state.add_bytes(response.len() as u64);
+
+            if truncate {
+                break;
+            }
+
+            if count % LISTING_BATCH_SIZE == 0 {
+                // Une connexion coupée par le client n'est détectée qu'à l'écriture.
+                if let Err(err) = writer.flush() {
+                    return self.abort_transfer(command, path, sent as u64, started, &err);
+                }
+
+                if let Some(result) = self.interrupt_listing(command, path, sent, started) {
+                    return result;
+                }
+            }
+        }
+
+        // Vide le buffer avant de mesurer la durée du transfert.
+        if let Err(err) = writer.flush() {
+            return self.abort_transfer(command, path, sent as u64, started, &err);
+        }
+
+        drop(writer);
+
+        if let Err(err) = data_channel::finish(&connection) {
+            return self.abort_transfer(command, path, sent as u64, started, &err);
+        }
+
+        self.record_transfer(&format!(
+            "{command} {path}: {sent} bytes in {} ms",
+            started.elapsed().as_millis()
+        ));
+
+        self.timeline.borrow_mut().record_transfer(
+            command,
+            path,
+            sent as u64,
+            started,
+            TransferOutcome::Completed,
+        );
+
+        if capped {
+            limits::record_listing_truncated();
+
+            eprintln!(
+                "{}: {command} {path} truncated after {max_entries} entries.",
+                self.get_log_context()
+            );
+
+            return Ok((
+                ReplyCode::ClosingDataConnection,
+                format!("closing data connection, listing truncated after {max_entries} entries"),
+                false,
+                CommandReturnType::None,
+            ));
+        }
+
         Ok((
-            215,
-            "UNIX Type: L8".to_string(),
+            ReplyCode::ClosingDataConnection,
+            "closing data connection".to_string(),
             false,
             CommandReturnType::None,
         ))
     }
 
-    /// Execute the FTP command FEAT.
-    pub fn exec_feat_command(&self, _: Box<dyn Iterator<Item = String>>) -> CommandResult {
-        Ok((
-            211,
-            "-Features\r\nUTF8".to_string(),
-            true,
+    /// Stop the listing being sent if the server is shutting down or if the client sent ABOR,
+    /// giving the result of the command in that case.
+    fn interrupt_listing(
+        &mut self,
+        command: &str,
+        path: &str,
+        sent: usize,
+        started: Instant,
+    ) -> Option<CommandResult> {
+        if self.is_shutting_down() {
+            let err = io::Error::other("server shutting down");
+
+            return Some(self.abort_transfer(command, path, sent as u64, started, &err));
+        }
+
+        if !self.abort_requested() {
+            return None;
+        }
+
+        self.record_transfer(&format!(
+            "{command} {path}: aborted by the client after {sent} bytes"
+        ));
+
+        self.timeline.borrow_mut().record_transfer(
+            command,
+            path,
+            sent as u64,
+            started,
+            TransferOutcome::Aborted,
+        );
+
+        RefCell::borrow_mut(&self.options)
+            .transfer_statistics
+            .record_abort();
+
+        let _ =
+            self.write(format!("{} transfer aborted\r\n", ReplyCode::ConnectionClosed).as_bytes());
+
+        Some(Ok((
+            ReplyCode::ClosingDataConnection,
+            "ABOR successful".to_string(),
+            false,
             CommandReturnType::None,
-        ))
+        )))
     }
 
-    /// Execute the FTP command OPTS.
-    pub fn exec_opts_command(&self, mut args: Box<dyn Iterator<Item = String>>) -> CommandResult {
-        let arg = match args.next() {
-            Some(a) => a,
-            None => return Err((501, "Syntax error in arguments".to_string())),
+    /// Stop a transfer whose data connection failed after `sent` bytes, usually because the
+    /// client closed or reset it.
+    fn abort_transfer(
+        &mut self,
+        command: &str,
+        path: &str,
+        sent: u64,
+        started: Instant,
+        err: &io::Error,
+    ) -> CommandResult {
+        let reason = match err.kind() {
+            io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe => "connection closed by the client".to_string(),
+            _ => err.to_string(),
         };
 
-        match &arg[..] {
-            "UTF8" => {
-                return Ok((
-                    202,
-                    "UTF8 mode is always ON".to_string(),
-                    false,
-                    CommandReturnType::None,
-                ))
+        eprintln!(
+            "{}: {command} {path} aborted after {sent} bytes: {reason}.",
+            self.get_log_context()
+        );
+
+        self.record_transfer(&format!(
+            "{command} {path}: aborted after {sent} bytes ({reason})"
+        ));
+
+        self.timeline.borrow_mut().record_transfer(
+            command,
+            path,
+            sent,
+            started,
+            TransferOutcome::Failed,
+        );
+
+        RefCell::borrow_mut(&self.options)
+            .transfer_statistics
+            .record_abort();
+
+        let code = match err.kind() {
+            io::ErrorKind::FileTooLarge => ReplyCode::ExceededStorageAllocation,
+            _ if sniffing::is_rejected(err) => ReplyCode::LocalError,
+            _ => ReplyCode::ConnectionClosed,
+        };
+
+        Err((code, format!("{reason}, transfer aborted")))
+    }
+
+    /// Add the summary of a finished transfer to the transcript, if the session is recorded.
+    fn record_transfer(&self, summary: &str) {
+        if let Some(transcript) = self.transcript.borrow_mut().as_mut() {
+            transcript.record_transfer(summary);
+        }
+    }
+
+    /// Enable TCP keep-alive on the control connection while a transfer is running, so that a
+    /// NAT does not forget the connection before the final reply.
+    ///
+    /// The keep-alive is disabled again when the returned guard is dropped.
+    fn keep_control_alive(&self) -> Option<ControlKeepAlive> {
+        let idle = CONFIG.get().unwrap().get_transfer_keepalive();
+
+        if idle == 0 {
+            return None;
+        }
+
+        let stream = self.stream_writer.try_clone().ok()?;
+
+        match crossplatform::set_keepalive(&stream, Some(Duration::from_secs(idle))) {
+            Ok(()) => Some(ControlKeepAlive { stream }),
+            Err(err) => {
+                eprintln!("Cannot enable keep-alive on the control connection: {err}.");
+
+                None
             }
-            _ => return Err((504, "command not implemented for this option".to_string())),
         }
     }
 
-    /// Execute the FTP command PWD.
-    pub fn exec_pwd_command(&self, _: Box<dyn Iterator<Item = String>>) -> CommandResult {
-        let options = self.get_options();
+    /// Log why an operation is refused and build the reply, with the reason if the config asks
+    /// for it.
+    pub fn deny(
+        &self,
+        code: ReplyCode,
+        message: String,
+        reason: DenialReason,
+        subject: &str,
+    ) -> (ReplyCode, String) {
+        eprintln!(
+            "{}: {subject} denied ({}).",
+            self.get_log_context(),
+            reason.get_tag()
+        );
+
+        let with_tag = CONFIG.get().unwrap().get_denial_reasons();
+
+        (code, denial::format_message(message, reason, with_tag))
+    }
 
-        let options = RefCell::borrow(&options);
+    /// Tell if the control connection is protected by TLS, which is not supported yet.
+    fn is_control_encrypted(&self) -> bool {
+        false
+    }
 
-        Ok((
-            257,
-            format!("\"{}\"", options.working_directory),
-            false,
-            CommandReturnType::None,
+    /// Refuse the commands that modify the files to the read-only sessions.
+    fn check_writable(&self) -> Result<(), (ReplyCode, String)> {
+        let read_only = RefCell::borrow(&self.options)
+            .session
+            .as_ref()
+            .is_some_and(|sess| sess.is_read_only());
+
+        match read_only {
+            true => Err((
+                ReplyCode::FileUnavailable,
+                format!("{}, read-only session", file_error::PERMISSION_DENIED),
+            )),
+            false => Ok(()),
+        }
+    }
+
+    /// Refuse `path` if a symbolic link makes it lead outside of the directory of the session.
+    pub fn check_jail(
+        &self,
+        root: &str,
+        path: &str,
+        system_path: &Path,
+    ) -> Result<(), (ReplyCode, String)> {
+        if path_resolver::is_inside_root(root, system_path) {
+            return Ok(());
+        }
+
+        Err(self.deny(
+            ReplyCode::FileUnavailable,
+            format!("{path}: {}", file_error::PERMISSION_DENIED),
+            DenialReason::PathOutsideJail,
+            &format!("access to {path}"),
         ))
     }
 
-    /// Execute the FTP command TYPE.
-    pub fn exec_type_command(&self, mut args: Box<dyn Iterator<Item = String>>) -> CommandResult {
-        let options = self.get_options();
+    /// Tell if the content of `path` is hidden because it is in an upload-only directory.
+    fn is_in_drop_directory(&self, path: &str) -> bool {
+        CONFIG
+            .get()
+            .unwrap()
+            .get_drop_directories()
+            .iter()
+            .any(|directory| {
+                path_resolver::is_within(path, &path_resolver::resolve("/", directory))
+            })
+    }
 
-        let typee = match args.next() {
-            Some(t) => t,
-            None => return Err((501, "Syntax error in arguments".to_string())),
+    /// Tell if a wildcard match is hidden, its directory being upload-only.
+    fn is_hidden_match(&self, path: &str) -> bool {
+        let parent = match path.rsplit_once('/') {
+            Some((parent, _)) => parent,
+            None => "/",
         };
 
-        let mut options = RefCell::borrow_mut(&options);
+        self.is_in_drop_directory(parent)
+    }
 
-        match &typee[..] {
-            "A" => options.data_representation = DataType::ASCII,
-            "E" => options.data_representation = DataType::EBCDIC,
-            "I" => options.data_representation = DataType::Image,
-            "L" => {
-                if let Some(byte_size) = args.next() {
-                    options.data_representation = DataType::Local;
-                    options.local_bytes = match byte_size.parse() {
-                        Ok(size) => size,
-                        Err(_) => return Err((501, "Syntax error in arguments".to_string())),
-                    }
-                } else {
-                    return Err((501, "Syntax error in arguments".to_string()));
+    /// Refuse `path` if it names a file managed by the server, such as the checksums cache.
+    fn check_not_protected(&self, path: &str) -> Result<(), (ReplyCode, String)> {
+        match path_resolver::is_protected(path) {
+            true => Err(file_error::unavailable(path, file_error::PERMISSION_DENIED)),
+            false => Ok(()),
+        }
+    }
+
+    /// Apply the policy of the upload-only directories to `path`, used by a command as `access`:
+    /// a file can be sent there once and whole, but never read, described, changed, moved or
+    /// deleted afterwards.
+    ///
+    /// The listings hide the content of these directories instead of refusing them.
+    fn check_drop_policy(&self, path: &str, access: DropAccess) -> Result<(), (ReplyCode, String)> {
+        let dropped = self.is_in_drop_directory(path);
+
+        let refusal = match access {
+            DropAccess::Existing | DropAccess::Tree if dropped => file_error::PERMISSION_DENIED,
+            DropAccess::Tree => {
+                // Le dossier contiendrait les fichiers déposés.
+                let contains_drop =
+                    CONFIG
+                        .get()
+                        .unwrap()
+                        .get_drop_directories()
+                        .iter()
+                        .any(|directory| {
+                            path_resolver::is_within(&path_resolver::resolve("/", directory), path)
+                        });
+
+                match contains_drop {
+                    true => "contains an upload-only directory",
+                    false => return Ok(()),
                 }
             }
-            _ => return Err((504, "command not implemented for this option".to_string())),
-        }
+            DropAccess::Upload { resume: true, .. } if dropped => "uploads cannot be resumed here",
+            DropAccess::Upload { replace: true, .. } if dropped => "file already exists",
+            _ => return Ok(()),
+        };
 
-        Ok((
-            200,
-            "command OK".to_string(),
-            false,
-            CommandReturnType::None,
+        Err(self.deny(
+            ReplyCode::FileUnavailable,
+            format!("{path}: {refusal}"),
+            DenialReason::DropDirectory,
+            &format!("access to {path}"),
         ))
     }
 
-    /// Execute the FTP command PASV.
-    pub fn exec_pasv_command(&self, _: Box<dyn Iterator<Item = String>>) -> CommandResult {
+    /// Execute the FTP command USER.
+    pub fn exec_user_command(&self, args: &CommandArgs) -> CommandResult {
         let options = self.get_options();
-        let mut options = RefCell::borrow_mut(&options);
 
-        // TODO: Pour le moment cela ne fonctionne que dans un réseau local, faire en sorte que cela
-        // fonctionne aussi avec l'adresse IP publique.
-        let hostname = CONFIG.get().unwrap().get_hostname();
+        // Tous les arguments forment le nom d'utilisateur.
+        let username = args.get_raw().trim().to_string();
 
-        options.listen_mode = ListenMode::Passive;
+        if CONFIG.get().unwrap().is_transcript_user(&username) {
+            self.start_transcript();
+        }
 
-        let mut port = 0;
+        let mut session = SessionInformations::new(username.clone(), None, self.peer_address);
 
-        let mut data_listener = None;
+        let config = CONFIG.get().unwrap();
 
-        for p in 7000..65535 {
-            if let Ok(listener) = TcpListener::bind(format!("{hostname}:{p}")) {
-                data_listener = Some(listener);
+        // Un mot de passe envoyé en clair pourrait être intercepté, seul l'accès anonyme reste
+        // possible si la config l'autorise.
+        if config.get_require_tls_for_login() && !self.is_control_encrypted() {
+            if !(config.get_cleartext_anonymous() && is_anonymous(&username)) {
+                return Err((ReplyCode::FileUnavailable, "Use AUTH TLS first".to_string()));
+            }
 
-                port = p;
+            session.set_authenticated(true);
+            session.set_read_only(true);
+        }
 
-                break;
-            }
+        // Sans fichier d'utilisateurs, n'importe quel nom est accepté sans mot de passe, sauf ceux
+        // des accès temporaires.
+        if self.get_user_store().is_none() && !temporary_credentials::is_pending(&username) {
+            session.set_authenticated(true);
         }
 
-        if data_listener.is_some() {
-            let p1 = port / 256;
-            let p2 = port - (p1 * 256);
+        let authenticated = session.is_authenticated();
+
+        let mut opt = RefCell::borrow_mut(&options);
+        opt.session = Some(session);
+
+        if authenticated {
+            self.timeline
+                .borrow_mut()
+                .record(Event::Authenticated { username });
 
+            Ok(logged_in_reply("user connected"))
+        } else {
             Ok((
-                227,
-                format!(
-                    "Entering passive mode ({},{p1},{p2})",
-                    hostname.replace(".", ","),
-                ),
+                ReplyCode::UserNameOk,
+                "password required".to_string(),
                 false,
-                CommandReturnType::TcpListener(data_listener.unwrap()),
+                CommandReturnType::None,
             ))
-        } else {
-            Err((425, "cannot open data connection".to_string()))
         }
     }
 
-    /// Execute the FTP command LIST.
-    pub fn exec_list_command(&mut self, _args: Box<dyn Iterator<Item = String>>) -> CommandResult {
-        // TODO: Gérer les arguments de la commande LIST.
-        let data_listener = Rc::clone(&self.data_listener);
-        let data_listener = RefCell::borrow_mut(&data_listener);
-        let data_listener = data_listener.as_ref().unwrap();
+    /// Execute the FTP command PASS.
+    pub fn exec_pass_command(&self, args: &CommandArgs) -> CommandResult {
+        let store = self.get_user_store();
 
         let options = self.get_options();
+        let mut opt = RefCell::borrow_mut(&options);
 
-        let options = RefCell::borrow_mut(&options);
-
-        let pwd = &options.working_directory;
-
-        let paths = match fs::read_dir(pwd) {
-            Ok(p) => p,
-            Err(_) => return Err((550, "cannot access directory".to_string())),
+        let session = match opt.session.as_mut() {
+            Some(sess) => sess,
+            None => return Err((ReplyCode::BadSequence, "login with USER first".to_string())),
         };
 
-        let _ = self.write("150 ok\r\n".as_bytes());
+        if session.is_authenticated() {
+            return Ok((
+                ReplyCode::CommandSuperfluous,
+                "already logged in".to_string(),
+                false,
+                CommandReturnType::None,
+            ));
+        }
 
-        let connection = match data_listener.accept() {
-            Ok((stream, _)) => stream,
-            Err(_) => return Err((425, "cannot open data connection".to_string())),
-        };
+        // Un mot de passe peut contenir des espaces, il est donc pris en entier.
+        let password = args.get_raw();
 
-        let mut writer = BufWriter::new(&connection);
+        if let Some(root) = temporary_credentials::redeem(session.get_username(), password) {
+            session.set_authenticated(true);
+            session.set_temporary(true);
+            // Le partage ne donne qu'à télécharger.
+            session.set_read_only(true);
 
-        // Itère à travers le dossier pour envoyer au client la liste des fichiers / dossiers
-        // présents.
-        for path in paths {
-            match path {
-                Ok(entry) => {
-                    let absolute_path = entry.path();
+            let username = session.get_username().to_string();
 
-                    if let Ok(path) = entry.file_name().into_string() {
-                        if let Ok(metadata) = fs::metadata(absolute_path) {
-                            if let Ok(modified) = metadata.modified() {
-                                let date_time: DateTime<Local> = modified.into();
+            // La session ne voit que le dossier partagé, qui devient sa racine.
+            opt.root = root;
+            opt.working_directory = "/".to_string();
 
-                                let perms = metadata.mode();
+            brute_force::record_login_success(self.peer_address.ip());
 
-                                let user_read = if (perms & 0o400) > 0 { 'r' } else { '-' };
-                                let user_write = if (perms & 0o200) > 0 { 'w' } else { '-' };
-                                let user_execute = if (perms & 0o100) > 0 { 'x' } else { '-' };
+            println!(
+                "{}: temporary credentials {username} used.",
+                self.get_log_context()
+            );
 
-                                let group_read = if (perms & 0o40) > 0 { 'r' } else { '-' };
-                                let group_write = if (perms & 0o20) > 0 { 'w' } else { '-' };
-                                let group_execute = if (perms & 0o10) > 0 { 'x' } else { '-' };
+            self.timeline
+                .borrow_mut()
+                .record(Event::Authenticated { username });
 
-                                let others_read = if (perms & 0o4) > 0 { 'r' } else { '-' };
-                                let others_write = if (perms & 0o2) > 0 { 'w' } else { '-' };
-                                let others_execute = if (perms & 0o1) > 0 { 'x' } else { '-' };
+            return Ok(logged_in_reply("user logged in"));
+        }
 
-                                // Récupère le nom d'utilisateur et le nom du groupe auquel le fichier
-                                // appartient.
-                                let (username, group) = unsafe {
-                                    // TODO: Faire une structure cross-plateforme pour récupérer ces infos.
-                                    let passwd = libc::getpwuid(metadata.st_uid());
-                                    let grp = libc::getgrgid(metadata.st_gid());
+        let store = match store {
+            Some(s) => s,
+            None => return Err((ReplyCode::BadSequence, "login with USER first".to_string())),
+        };
 
-                                    (
-                                        CStr::from_ptr((*passwd).pw_name).to_str().unwrap(),
-                                        CStr::from_ptr((*grp).gr_name).to_str().unwrap(),
-                                    )
-                                };
+        // Le mot de passe n'est pas vérifié, la réponse ne doit pas révéler s'il était juste.
+        if brute_force::is_account_locked(session.get_username()) {
+            let subject = format!("login as {}", session.get_username());
 
-                                let response = format!("{}{user_read}{user_write}{user_execute}{group_read}{group_write}{group_execute}{others_read}{others_write}{others_execute} {username} {group} {} {:>5} {path}\r\n",
-                                    if metadata.is_dir() { "d" } else { "-" },
-                                    metadata.len(),
-                                    date_time.format("%b %d %H:%M")
-                                );
+            opt.session = None;
 
-                                // Envoie au client la ligne contenant les informations du fichiers.
-                                match writer.write(response.as_bytes()) {
-                                    Ok(_) => (),
-                                    Err(err) => {
-                                        eprintln!("Error when writting to data connection: {err}.")
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                Err(_) => (),
-            }
+            brute_force::record_login_failure(self.peer_address.ip());
+            self.delay_failed_login();
+
+            return Err(self.deny(
+                ReplyCode::NotLoggedIn,
+                "login incorrect".to_string(),
+                DenialReason::AccountLocked,
+                &subject,
+            ));
         }
 
-        let _ = self.write("226 closing data connection\r\n".as_bytes());
+        if store.verify(session.get_username(), password) {
+            session.set_authenticated(true);
 
-        Ok((250, "ok".to_string(), false, CommandReturnType::None))
-    }
+            brute_force::record_login_success(self.peer_address.ip());
+            brute_force::record_account_success(session.get_username());
+            self.failed_logins.set(0);
 
-    pub fn exec_cwd_command(
-        &mut self,
-        mut args: Box<dyn Iterator<Item = String>>,
-    ) -> CommandResult {
-        let mut path = match args.next() {
-            Some(p) => p,
-            None => return Err((501, "missing pathname".to_string())),
-        };
+            self.timeline.borrow_mut().record(Event::Authenticated {
+                username: session.get_username().to_string(),
+            });
 
-        let options = self.get_options();
-        let mut options = RefCell::borrow_mut(&options);
+            Ok(logged_in_reply("user logged in"))
+        } else {
+            let username = session.get_username().to_string();
+            let subject = format!("login as {username}");
 
-        // Si le client n'envoie pas de chemin absolu, alors il faut partir du dossier actuel.
-        if !path.starts_with("/") {
-            let wd = options.working_directory.trim_end_matches("/").to_string();
-
-            // Si le client veut aller dans le dossier parent.
-            if path == ".." {
-                path = match wd.rfind("/") {
-                    Some(idx) => {
-                        if idx > 0 {
-                            wd[..idx].to_string()
-                        } else {
-                            "/".to_string()
-                        }
-                    }
-                    None => "/".to_string(),
-                }
-            } else {
-                path.insert_str(0, &format!("{wd}/"));
+            opt.session = None;
+
+            brute_force::record_login_failure(self.peer_address.ip());
 
-                path = path.trim_end_matches("/").to_string();
+            // Seuls les comptes existants sont suivis, sinon n'importe quel nom occuperait de la
+            // mémoire.
+            if store.has_user(&username) {
+                brute_force::record_account_failure(&username);
             }
+
+            self.delay_failed_login();
+
+            Err(self.deny(
+                ReplyCode::NotLoggedIn,
+                "login incorrect".to_string(),
+                DenialReason::LoginIncorrect,
+                &subject,
+            ))
         }
+    }
 
-        let folder = Path::new(&path);
+    /// Count a failed login of the session and wait before replying, longer after each failure.
+    fn delay_failed_login(&self) {
+        let failures = self.failed_logins.get() + 1;
+        self.failed_logins.set(failures);
 
-        match folder.try_exists() {
-            Ok(res) => {
-                if !res {
-                    return Err((550, format!("{path} inexistant path")));
-                }
+        let delay = brute_force::get_login_backoff(
+            CONFIG.get().unwrap().get_login_backoff_secs(),
+            failures,
+        );
+        let deadline = Instant::now() + delay;
+
+        // L'attente est découpée pour ne pas retarder l'arrêt du serveur.
+        while !self.is_shutting_down() {
+            let left = deadline.saturating_duration_since(Instant::now());
+
+            if left.is_zero() {
+                break;
             }
-            Err(_) => return Err((450, "error".to_string())),
+
+            thread::sleep(left.min(SHUTDOWN_POLL_INTERVAL));
         }
+    }
 
-        options.working_directory = path;
+    /// Tell if the session failed to log in too many times and must be closed.
+    pub fn has_too_many_failed_logins(&self) -> bool {
+        let max = CONFIG.get().unwrap().get_max_session_login_failures();
 
-        Ok((250, "ok".to_string(), false, CommandReturnType::None))
+        max > 0 && self.failed_logins.get() >= max
     }
 
-    pub fn exec_cdup_command(&mut self, _: Box<dyn Iterator<Item = String>>) -> CommandResult {
-        let args = vec!["..".to_string()];
+    /// Tell if the client is allowed to use commands that require to be logged in.
+    pub fn is_logged_in(&self) -> bool {
+        let options = self.get_options();
+        let opt = RefCell::borrow(&options);
 
-        self.exec_cwd_command(Box::new(args.into_iter()))
+        // Un accès temporaire en attente de son mot de passe n'est pas identifié, même sans
+        // fichier d'utilisateurs.
+        match &opt.session {
+            Some(sess) => sess.is_authenticated(),
+            None => self.get_user_store().is_none(),
+        }
     }
 
-    pub fn get_options(&self) -> Rc<RefCell<ClientOptions>> {
-        Rc::clone(&self.options)
-    }
+    /// Get the accounts of the virtual host of the session, or the ones of the config.
+    fn get_user_store(&self) -> Option<&'static UserStore> {
+        let virtual_host = RefCell::borrow(&self.options).virtual_host;
+
+        match virtual_host
+            .and_then(virtual_host::get)
+            .and_then(|host| host.get_user_store())
+        {
+            Some(store) => Some(store),
+            None => USER_STORE.get(),
+        }
+    }
+
+    /// Serve the virtual host at `index` for the rest of the session.
+    fn select_virtual_host(&self, index: usize) {
+        let host = match virtual_host::get(index) {
+            Some(h) => h,
+            None => return,
+        };
+
+        let mut options = RefCell::borrow_mut(&self.options);
+
+        options.virtual_host = Some(index);
+        options.root = host.get_root().to_string();
+        options.working_directory = "/".to_string();
+    }
+
+    /// Select the virtual host served by default on the address the client connected to.
+    pub fn select_virtual_host_by_address(&self) {
+        if let Some(index) = virtual_host::find_by_address(self.local_address.ip()) {
+            self.select_virtual_host(index);
+        }
+    }
+
+    /// Text of the 220 reply, which depends on the virtual host.
+    pub fn get_banner(&self) -> String {
+        let virtual_host = RefCell::borrow(&self.options).virtual_host;
+
+        match virtual_host
+            .and_then(virtual_host::get)
+            .and_then(|host| host.get_banner())
+        {
+            Some(banner) => banner.to_string(),
+            None if show_server_tokens() => format!("FTP Paradise v{VERSION} ready"),
+            None => "ready".to_string(),
+        }
+    }
+
+    /// Execute the FTP command CLNT, with which the client tells its name.
+    pub fn exec_clnt_command(&self, args: &CommandArgs) -> CommandResult {
+        let name = match args.get_path() {
+            Some(n) => n,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "missing client name".to_string(),
+                ))
+            }
+        };
+
+        RefCell::borrow_mut(&self.options).client_name = Some(name.to_string());
+
+        Ok((
+            ReplyCode::CommandOk,
+            "client name noted".to_string(),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Compatibility profile that applies to the client of this session.
+    fn get_quirks(&self) -> Option<&'static QuirkProfile> {
+        let options = RefCell::borrow(&self.options);
+
+        quirks::find(CONFIG.get().unwrap(), options.client_name.as_deref())
+    }
+
+    /// Command of the server that `verb` stands for, according to the compatibility profile of
+    /// the client.
+    pub fn resolve_alias(&self, verb: String) -> String {
+        quirks::resolve_alias(self.get_quirks(), verb)
+    }
+
+    /// Execute the FTP command HOST, which selects the virtual host to log into.
+    pub fn exec_host_command(&self, args: &CommandArgs) -> CommandResult {
+        let name = match args.get_word(0) {
+            Some(n) => n,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "missing host name".to_string(),
+                ))
+            }
+        };
+
+        // La RFC 7151 impose de choisir l'hôte avant l'identification, dont dépend le compte.
+        if RefCell::borrow(&self.options).session.is_some() {
+            return Err((
+                ReplyCode::BadSequence,
+                "HOST must be sent before USER".to_string(),
+            ));
+        }
+
+        // Les adresses IP sont envoyées entre crochets.
+        let name = name.trim_start_matches('[').trim_end_matches(']');
+
+        // Sans hôte virtuel configuré, le site par défaut répond à tous les noms.
+        if !virtual_host::is_enabled() {
+            return Ok((
+                ReplyCode::ServiceReady,
+                self.get_banner(),
+                false,
+                CommandReturnType::None,
+            ));
+        }
+
+        let index = match virtual_host::find_by_name(name) {
+            Some(i) => i,
+            None => {
+                return Err((
+                    ReplyCode::ParameterNotImplemented,
+                    format!("unknown host {name}"),
+                ))
+            }
+        };
+
+        self.select_virtual_host(index);
+
+        println!("{} selected host {name}.", self.get_log_context());
+
+        Ok((
+            ReplyCode::ServiceReady,
+            self.get_banner(),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute the FTP command SYST.
+    pub fn exec_syst_command(&self, _: &CommandArgs) -> CommandResult {
+        // Certains clients ne savent lire que les listes du système qu'ils attendent.
+        let system = match self.get_quirks().and_then(|profile| profile.syst.clone()) {
+            Some(s) => s,
+            None => "UNIX Type: L8".to_string(),
+        };
+
+        Ok((
+            ReplyCode::SystemType,
+            system,
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute the FTP command FEAT.
+    pub fn exec_feat_command(&self, _: &CommandArgs) -> CommandResult {
+        let mut message = "Features".to_string();
+
+        let mlst_facts = RefCell::borrow(&self.options).mlst_facts.clone();
+
+        for feature in features::list(CONFIG.get().unwrap(), &mlst_facts) {
+            message.push_str(&format!("\r\n {feature}"));
+        }
+
+        Ok((
+            ReplyCode::SystemStatus,
+            message,
+            true,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute the FTP command HELP.
+    pub fn exec_help_command(&self, args: &CommandArgs) -> CommandResult {
+        let config = CONFIG.get().unwrap();
+
+        let enabled: Vec<&str> = ftp_server::COMMANDS
+            .iter()
+            .copied()
+            .filter(|command| !config.is_command_disabled(command))
+            .collect();
+
+        if let Some(command) = args.get_word(0) {
+            let command = command.to_ascii_uppercase();
+
+            return match enabled.contains(&&command[..]) {
+                true => Ok((
+                    ReplyCode::HelpMessage,
+                    format!("{command} is supported"),
+                    false,
+                    CommandReturnType::None,
+                )),
+                false => Err((
+                    ReplyCode::CommandNotImplemented,
+                    format!("{command} is not supported"),
+                )),
+            };
+        }
+
+        let mut message = "The following commands are recognized:".to_string();
+
+        for line in enabled.chunks(8) {
+            message.push_str(&format!("\r\n {}", line.join(" ")));
+        }
+
+        Ok((
+            ReplyCode::HelpMessage,
+            message,
+            true,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute the FTP command SITE, whose first word selects the server specific command.
+    pub fn exec_site_command(&mut self, args: &CommandArgs) -> CommandResult {
+        let raw = args.get_raw().trim_start();
+
+        let (subcommand, rest) = match raw.split_once(' ') {
+            Some((subcommand, rest)) => (subcommand, rest),
+            None => (raw, ""),
+        };
+
+        let rest = CommandArgs::new(rest);
+
+        match &subcommand.to_ascii_uppercase()[..] {
+            "SHARE" => self.exec_site_share_command(&rest),
+            "GETTAR" => self.exec_site_gettar_command(&rest),
+            "STATS" => self.exec_site_stats_command(&rest),
+            "UPTIME" => self.exec_site_uptime_command(&rest),
+            "WHO" => self.exec_site_who_command(&rest),
+            "CHPASS" => self.exec_site_chpass_command(&rest),
+            "HASHONUPLOAD" => self.exec_site_hashonupload_command(&rest),
+            // Sans 'server_tokens', SITE VERSION se comporte comme une commande inconnue pour ne
+            // rien révéler aux autres que les admins.
+            "VERSION" if show_server_tokens() || self.is_admin() => {
+                self.exec_site_version_command(&rest)
+            }
+            "" => Err((
+                ReplyCode::SyntaxErrorInArguments,
+                "missing SITE command".to_string(),
+            )),
+            other => match EXTENSIONS.get().and_then(|e| e.get_site_command(other)) {
+                Some(handler) => self.exec_extension(handler, &rest),
+                None => Err((
+                    ReplyCode::ParameterNotImplemented,
+                    format!("SITE {other} not implemented"),
+                )),
+            },
+        }
+    }
+
+    /// Execute a command added by the program embedding the server.
+    pub fn exec_extension(
+        &mut self,
+        handler: &ExtensionHandler,
+        args: &CommandArgs,
+    ) -> CommandResult {
+        let (code, message) = handler(&mut CommandContext::new(self), args)?;
+
+        // Un message de plusieurs lignes est envoyé comme une réponse multi-lignes.
+        let multiline = message.contains("\r\n");
+
+        Ok((code, message, multiline, CommandReturnType::None))
+    }
+
+    /// Execute SITE SHARE, which creates single-use, read-only credentials restricted to a
+    /// directory. Only the administrators can share a directory.
+    ///
+    /// The arguments are the path of the directory and, optionally, the validity in hours.
+    fn exec_site_share_command(&self, args: &CommandArgs) -> CommandResult {
+        let max_lifetime = CONFIG.get().unwrap().get_max_share_lifetime();
+
+        if max_lifetime == 0 {
+            return Err((
+                ReplyCode::ParameterNotImplemented,
+                "SITE SHARE is disabled on this server".to_string(),
+            ));
+        }
+
+        if !self.is_admin() {
+            return Err((
+                ReplyCode::FileUnavailable,
+                format!(
+                    "{}, SITE SHARE is reserved to administrators",
+                    file_error::PERMISSION_DENIED
+                ),
+            ));
+        }
+
+        let options = self.get_options();
+        let opt = RefCell::borrow(&options);
+
+        // Un accès temporaire ne doit pas pouvoir prolonger sa propre durée de vie.
+        if opt
+            .session
+            .as_ref()
+            .is_some_and(|sess| sess.is_temporary() || sess.is_read_only())
+        {
+            return Err((
+                ReplyCode::FileUnavailable,
+                "temporary sessions cannot share directories".to_string(),
+            ));
+        }
+
+        let path = match args.get_word(0) {
+            Some(p) => p,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "missing pathname".to_string(),
+                ))
+            }
+        };
+
+        let lifetime = match args.get_word(1) {
+            Some(hours) => match hours.parse::<u64>() {
+                Ok(h) if h > 0 && h <= max_lifetime => h,
+                _ => {
+                    return Err((
+                        ReplyCode::SyntaxErrorInArguments,
+                        format!("lifetime must be between 1 and {max_lifetime} hours"),
+                    ))
+                }
+            },
+            None => max_lifetime,
+        };
+
+        let path = path_resolver::resolve(&opt.working_directory, path);
+        let system_path = path_resolver::to_system_path(&opt.root, &path);
+
+        self.check_jail(&opt.root, &path, &system_path)?;
+        self.check_drop_policy(&path, DropAccess::Tree)?;
+
+        if !system_path.is_dir() {
+            return Err(file_error::unavailable(&path, file_error::NOT_A_DIRECTORY));
+        }
+
+        let root = match system_path.to_str() {
+            Some(r) => r,
+            None => {
+                return Err((
+                    ReplyCode::FileUnavailable,
+                    format!("{path}: unsupported name"),
+                ))
+            }
+        };
+
+        let credentials =
+            temporary_credentials::mint(root, Duration::from_secs(lifetime * 60 * 60));
+
+        println!(
+            "{}: {path} shared as {} for {lifetime} hours.",
+            self.get_log_context(),
+            credentials.username
+        );
+
+        Ok((
+            ReplyCode::CommandOk,
+            format!(
+                "user {} password {} valid once for {lifetime} hours",
+                credentials.username, credentials.password
+            ),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Tell if the session is logged in with an account that can see the state of the whole
+    /// server.
+    pub fn is_admin(&self) -> bool {
+        match &RefCell::borrow(&self.options).session {
+            Some(sess) if sess.is_authenticated() && !sess.is_temporary() => {
+                CONFIG.get().unwrap().is_admin(sess.get_username())
+            }
+            _ => false,
+        }
+    }
+
+    /// Execute SITE STATS, which counts the commands of the session, and of the whole server for
+    /// the admins.
+    fn exec_site_stats_command(&self, _: &CommandArgs) -> CommandResult {
+        let is_admin = self.is_admin();
+
+        let mut message = "Commands of this session:".to_string();
+
+        for line in self.command_stats.borrow().format_lines() {
+            message.push_str(&format!("\r\n {line}"));
+        }
+
+        if is_admin {
+            message.push_str("\r\n Commands of all sessions:");
+
+            for line in command_stats::get_global().format_lines() {
+                message.push_str(&format!("\r\n  {line}"));
+            }
+        }
+
+        Ok((
+            ReplyCode::SystemStatus,
+            message,
+            true,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute SITE VERSION, which gives the name and version of the server.
+    fn exec_site_version_command(&self, _: &CommandArgs) -> CommandResult {
+        Ok((
+            ReplyCode::SystemStatus,
+            format!("FTP Paradise v{VERSION}"),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute SITE UPTIME, which reports the counters of the whole server.
+    fn exec_site_uptime_command(&self, _: &CommandArgs) -> CommandResult {
+        let mut message = format!("{} uptime:", get_server_name());
+
+        for line in metrics::get().format_lines() {
+            message.push_str(&format!("\r\n {line}"));
+        }
+
+        Ok((
+            ReplyCode::SystemStatus,
+            message,
+            true,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute SITE HASHONUPLOAD, which makes the 226 reply of each following upload of the
+    /// session give the SHA-256 of what was written, computed while receiving it.
+    ///
+    /// `SITE HASHONUPLOAD OFF` stops it.
+    fn exec_site_hashonupload_command(&self, args: &CommandArgs) -> CommandResult {
+        let enabled = match args.get_word(0).map(|word| word.to_ascii_uppercase()) {
+            Some(word) if word == "SHA256" || word == "SHA-256" => true,
+            Some(word) if word == "OFF" => false,
+            _ => {
+                return Err((
+                    ReplyCode::ParameterNotImplemented,
+                    "only SHA256 and OFF are supported".to_string(),
+                ))
+            }
+        };
+
+        RefCell::borrow_mut(&self.options).hash_on_upload = enabled;
+
+        let message = match enabled {
+            true => "SHA-256 of the uploads enabled",
+            false => "SHA-256 of the uploads disabled",
+        };
+
+        Ok((
+            ReplyCode::CommandOk,
+            message.to_string(),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute SITE CHPASS, which changes the password of the account of the session.
+    ///
+    /// The whole argument is the new password, spaces included, as with PASS.
+    fn exec_site_chpass_command(&self, args: &CommandArgs) -> CommandResult {
+        let username = match &RefCell::borrow(&self.options).session {
+            Some(sess) if sess.is_authenticated() && !sess.is_temporary() => {
+                sess.get_username().to_string()
+            }
+            _ => {
+                return Err((
+                    ReplyCode::FileUnavailable,
+                    "this session has no password to change".to_string(),
+                ))
+            }
+        };
+
+        let password = args.get_raw();
+
+        if password.is_empty() {
+            return Err((
+                ReplyCode::SyntaxErrorInArguments,
+                "missing new password".to_string(),
+            ));
+        }
+
+        let store = match self.get_user_store() {
+            Some(s) if s.has_user(&username) => s,
+            _ => {
+                return Err((
+                    ReplyCode::FileUnavailable,
+                    "this session has no password to change".to_string(),
+                ))
+            }
+        };
+
+        if let Err(err) = store.change_password(&username, password) {
+            eprintln!("{}: {err}.", self.get_log_context());
+
+            return Err((ReplyCode::LocalError, "cannot change password".to_string()));
+        }
+
+        println!(
+            "{}: password of {username} changed.",
+            self.get_log_context()
+        );
+
+        Ok((
+            ReplyCode::CommandOk,
+            "password changed".to_string(),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute SITE WHO, which reports the load of the server, and the connected sessions to the
+    /// admins.
+    fn exec_site_who_command(&self, _: &CommandArgs) -> CommandResult {
+        let is_admin = self.is_admin();
+
+        let metrics = metrics::get();
+        let sessions = metrics::get_sessions();
+
+        let mut message = format!("{} sessions connected:", sessions.len());
+
+        if let Some(pool) = metrics.pool {
+            message.push_str(&format!(
+                "\r\n {} of {} threads busy, {} sessions queued",
+                pool.busy, pool.workers, pool.queued
+            ));
+        }
+
+        if is_admin {
+            for (id, session) in sessions {
+                message.push_str(&format!(
+                    "\r\n {id} {} {} since {}: {}",
+                    session.peer,
+                    session.username.as_deref().unwrap_or("-"),
+                    session.connected.format("%Y-%m-%d %H:%M:%S"),
+                    session.state.get().describe()
+                ));
+            }
+        }
+
+        Ok((
+            ReplyCode::SystemStatus,
+            message,
+            true,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute SITE GETTAR, which sends a directory as a tar archive over the data connection.
+    fn exec_site_gettar_command(&mut self, args: &CommandArgs) -> CommandResult {
+        let path = match args.get_path() {
+            Some(p) => p,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "missing pathname".to_string(),
+                ))
+            }
+        };
+
+        let (root, working_directory) = self.get_location();
+        let transfer_mode = RefCell::borrow(&self.options).transfer_mode;
+
+        let path = path_resolver::resolve(&working_directory, path);
+        let system_path = path_resolver::to_system_path(&root, &path);
+
+        self.check_jail(&root, &path, &system_path)?;
+        self.check_drop_policy(&path, DropAccess::Tree)?;
+
+        if !system_path.is_dir() {
+            return Err(file_error::unavailable(&path, file_error::NOT_A_DIRECTORY));
+        }
+
+        self.check_data_channel()?;
+
+        // Les entrées de l'archive sont rangées sous le nom du dossier, comme avec 'tar -c'.
+        let name = match path.rsplit('/').find(|component| !component.is_empty()) {
+            Some(n) => n.to_string(),
+            None => "root".to_string(),
+        };
+
+        let (data_channel, _slot) = self.take_data_channel()?;
+
+        let _ = self.write(format!("{} ok\r\n", ReplyCode::FileStatusOk).as_bytes());
+
+        let mut connection = self.open_data_connection(&data_channel)?;
+
+        let _keepalive = self.keep_control_alive();
+
+        if self.faults.drop_data_connection() {
+            return Err((
+                ReplyCode::ConnectionClosed,
+                "connection closed, transfer aborted".to_string(),
+            ));
+        }
+
+        let started = Instant::now();
+
+        let mut reader: Box<dyn Read> = Box::new(TarStream::new(&root, system_path, &name));
+
+        if transfer_mode == TransferMode::Deflate {
+            reader = compression::deflate(reader, flate2::Compression::default());
+        }
+
+        let _transfer = self
+            .state
+            .start_transfer(Direction::Download, "GETTAR", &path);
+
+        let sent = match copy_data(&mut reader, &mut connection, &self.shutdown, &self.state) {
+            Ok(bytes) => bytes,
+            Err((bytes, err)) => return self.abort_transfer("GETTAR", &path, bytes, started, &err),
+        };
+
+        if let Err(err) = data_channel::finish(&connection) {
+            return self.abort_transfer("GETTAR", &path, sent, started, &err);
+        }
+
+        self.record_transfer(&format!(
+            "GETTAR {path}: {sent} bytes in {} ms",
+            started.elapsed().as_millis()
+        ));
+
+        self.timeline.borrow_mut().record_transfer(
+            "GETTAR",
+            &path,
+            sent,
+            started,
+            TransferOutcome::Completed,
+        );
+
+        RefCell::borrow_mut(&self.options)
+            .transfer_statistics
+            .record_download(sent);
+        metrics::record_sent(sent);
+
+        Ok((
+            ReplyCode::ClosingDataConnection,
+            "closing data connection".to_string(),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute the FTP command OPTS.
+    pub fn exec_opts_command(&self, args: &CommandArgs) -> CommandResult {
+        let arg = match args.get_word(0) {
+            Some(a) => a,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "Syntax error in arguments".to_string(),
+                ))
+            }
+        };
+
+        match &arg.to_ascii_uppercase()[..] {
+            "UTF8" => Ok((
+                ReplyCode::CommandSuperfluous,
+                "UTF8 mode is always ON".to_string(),
+                false,
+                CommandReturnType::None,
+            )),
+            // Un seul algorithme est disponible, il ne peut donc pas être changé.
+            "HASH" => match args.get_word(1) {
+                None => Ok((
+                    ReplyCode::CommandOk,
+                    checksum::ALGORITHM.to_string(),
+                    false,
+                    CommandReturnType::None,
+                )),
+                Some(algorithm) if algorithm.eq_ignore_ascii_case(checksum::ALGORITHM) => Ok((
+                    ReplyCode::CommandOk,
+                    checksum::ALGORITHM.to_string(),
+                    false,
+                    CommandReturnType::None,
+                )),
+                Some(_) => Err((
+                    ReplyCode::ParameterNotImplemented,
+                    "unsupported hash algorithm".to_string(),
+                )),
+            },
+            // Sans liste, plus aucun fait n'est envoyé.
+            "MLST" => {
+                let selection = facts::parse_selection(args.get_word(1).unwrap_or_default());
+                let message = format!("MLST OPTS {}", facts::format_selection(&selection))
+                    .trim_end()
+                    .to_string();
+
+                RefCell::borrow_mut(&self.options).mlst_facts = selection;
+
+                Ok((
+                    ReplyCode::CommandOk,
+                    message,
+                    false,
+                    CommandReturnType::None,
+                ))
+            }
+            _ => Err((
+                ReplyCode::ParameterNotImplemented,
+                "command not implemented for this option".to_string(),
+            )),
+        }
+    }
+
+    /// Execute the FTP command PWD.
+    pub fn exec_pwd_command(&self, _: &CommandArgs) -> CommandResult {
+        let options = self.get_options();
+
+        let options = RefCell::borrow(&options);
+
+        Ok((
+            ReplyCode::PathnameCreated,
+            format!(
+                "{} is the current directory",
+                quote_pathname(&options.working_directory)
+            ),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute the FTP command TYPE.
+    pub fn exec_type_command(&self, args: &CommandArgs) -> CommandResult {
+        let options = self.get_options();
+
+        let typee = match args.get_word(0) {
+            Some(t) => t,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "Syntax error in arguments".to_string(),
+                ))
+            }
+        };
+
+        let mut options = RefCell::borrow_mut(&options);
+
+        match typee {
+            "A" => options.data_representation = DataType::ASCII,
+            "E" => options.data_representation = DataType::EBCDIC,
+            "I" => options.data_representation = DataType::Image,
+            "L" => {
+                if let Some(byte_size) = args.get_word(1) {
+                    options.data_representation = DataType::Local;
+                    options.local_bytes = match byte_size.parse() {
+                        Ok(size) => size,
+                        Err(_) => {
+                            return Err((
+                                ReplyCode::SyntaxErrorInArguments,
+                                "Syntax error in arguments".to_string(),
+                            ))
+                        }
+                    }
+                } else {
+                    return Err((
+                        ReplyCode::SyntaxErrorInArguments,
+                        "Syntax error in arguments".to_string(),
+                    ));
+                }
+            }
+            _ => {
+                return Err((
+                    ReplyCode::ParameterNotImplemented,
+                    "command not implemented for this option".to_string(),
+                ))
+            }
+        }
+
+        Ok((
+            ReplyCode::CommandOk,
+            "command OK".to_string(),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute the FTP command MODE.
+    pub fn exec_mode_command(&self, args: &CommandArgs) -> CommandResult {
+        let mode = match args.get_word(0) {
+            Some(m) => m.to_ascii_uppercase(),
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "Syntax error in arguments".to_string(),
+                ))
+            }
+        };
+
+        let transfer_mode = match &mode[..] {
+            "S" => TransferMode::Stream,
+            "Z" if CONFIG.get().unwrap().get_compression().mode_z => TransferMode::Deflate,
+            // Les modes bloc et compressé de la RFC 959 ne sont utilisés par aucun client.
+            "Z" | "B" | "C" => {
+                return Err((
+                    ReplyCode::ParameterNotImplemented,
+                    format!("MODE {mode} not implemented"),
+                ))
+            }
+            _ => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    format!("unknown mode {mode}"),
+                ))
+            }
+        };
+
+        RefCell::borrow_mut(&self.options).transfer_mode = transfer_mode;
+
+        Ok((
+            ReplyCode::CommandOk,
+            format!("Mode set to {mode}"),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Count the passive listener about to be opened in the limits of the config.
+    fn open_data_slot(&self) -> Result<(), (ReplyCode, String)> {
+        // Le listener précédent va être remplacé, sa place est libérée avant de compter le
+        // nouveau.
+        self.data_slot.take();
+        RefCell::borrow_mut(&self.data_channel).take();
+
+        let config = CONFIG.get().unwrap();
+
+        let slot = resources::reserve(1, config.get_max_open_files()).and_then(|reservation| {
+            TransferSlot::open(
+                &self.open_transfers,
+                config.get_limits().max_open_transfers,
+                reservation,
+            )
+        });
+
+        match slot {
+            Some(s) => {
+                *self.data_slot.borrow_mut() = Some(s);
+
+                Ok(())
+            }
+            None => Err(self.too_many_transfers()),
+        }
+    }
+
+    /// Keep `listener`, opened by PASV or EPSV, for the next transfer.
+    pub fn set_passive_listener(&self, listener: TcpListener) {
+        *RefCell::borrow_mut(&self.data_channel) = Some(DataChannel::Passive(listener));
+        self.passive_since.set(Some(Instant::now()));
+    }
+
+    /// Close the passive listener if the client has not used it for `passive_idle_timeout`
+    /// seconds, so that its port and its descriptor go back to the other sessions.
+    fn reap_idle_listener(&self) {
+        let timeout = CONFIG.get().unwrap().get_passive_idle_timeout();
+
+        let expired = self
+            .passive_since
+            .get()
+            .is_some_and(|since| timeout > 0 && since.elapsed() >= Duration::from_secs(timeout));
+
+        if !expired {
+            return;
+        }
+
+        self.passive_since.set(None);
+
+        let mut data_channel = RefCell::borrow_mut(&self.data_channel);
+
+        // Une connexion active ouverte depuis par PORT n'est pas concernée.
+        if let Some(DataChannel::Passive(listener)) = data_channel.as_ref() {
+            let port = listener
+                .local_addr()
+                .map(|address| address.port())
+                .unwrap_or(0);
+
+            *data_channel = None;
+            self.data_slot.take();
+
+            println!(
+                "{}: passive listener on port {port} unused for {timeout} s, closed.",
+                self.get_log_context()
+            );
+        }
+    }
+
+    /// Take the data channel prepared by PASV, EPSV or PORT for a transfer, counting the
+    /// descriptors of its data connection and of the file it reads or writes.
+    fn take_data_channel(
+        &self,
+    ) -> Result<(DataChannel, Option<TransferSlot>), (ReplyCode, String)> {
+        // Le canal n'est utilisable que pour un seul transfert.
+        let channel = match RefCell::borrow_mut(&self.data_channel).take() {
+            Some(c) => c,
+            None => {
+                return Err((
+                    ReplyCode::CannotOpenDataConnection,
+                    "use PORT or PASV first".to_string(),
+                ))
+            }
+        };
+
+        let mut slot = self.data_slot.take();
+
+        if let Some(slot) = slot.as_mut() {
+            if !slot
+                .reservation
+                .grow(2, CONFIG.get().unwrap().get_max_open_files())
+            {
+                return Err(self.too_many_transfers());
+            }
+        }
+
+        Ok((channel, slot))
+    }
+
+    /// Write the partial file of an upload cut by the shutdown to the disk and record its size.
+    ///
+    /// # Return
+    /// The size of the partial file, or `None` if it could not be kept.
+    fn keep_partial_upload(
+        &self,
+        file: UploadWriter,
+        system_path: &Path,
+        path: &str,
+    ) -> Option<u64> {
+        let kept = file
+            .keep_partial()
+            .and_then(|size| partial_upload::mark(system_path, size).map(|_| size));
+
+        match kept {
+            Ok(size) => {
+                println!(
+                    "{}: partial upload {path} kept with {size} bytes.",
+                    self.get_log_context()
+                );
+
+                Some(size)
+            }
+            Err(err) => {
+                eprintln!(
+                    "{}: cannot keep the partial upload {path}: {err}.",
+                    self.get_log_context()
+                );
+
+                None
+            }
+        }
+    }
+
+    /// Open the data connection of `data_channel`, closed by the caller once the transfer is done.
+    fn open_data_connection(
+        &self,
+        data_channel: &DataChannel,
+    ) -> Result<TcpStream, (ReplyCode, String)> {
+        let stream = match data_channel.open() {
+            Ok(s) => s,
+            Err(_) => {
+                return Err((
+                    ReplyCode::CannotOpenDataConnection,
+                    "cannot open data connection".to_string(),
+                ))
+            }
+        };
+
+        if let Some(seconds) = CONFIG.get().unwrap().get_socket_linger() {
+            if let Err(err) = data_channel::set_linger(&stream, seconds) {
+                eprintln!(
+                    "{}: cannot set SO_LINGER on the data connection: {err}.",
+                    self.get_log_context()
+                );
+            }
+        }
+
+        Ok(stream)
+    }
+
+    /// Refuse a transfer early when no data channel was prepared by PORT, PASV or EPSV.
+    fn check_data_channel(&self) -> Result<(), (ReplyCode, String)> {
+        match RefCell::borrow(&self.data_channel).is_some() {
+            true => Ok(()),
+            false => Err((
+                ReplyCode::CannotOpenDataConnection,
+                "use PORT or PASV first".to_string(),
+            )),
+        }
+    }
+
+    fn too_many_transfers(&self) -> (ReplyCode, String) {
+        eprintln!(
+            "{}: transfer refused, {} descriptors already held by transfers.",
+            self.get_log_context(),
+            resources::get_open_descriptors()
+        );
+
+        (
+            ReplyCode::LocalError,
+            "too many open transfers, try again later".to_string(),
+        )
+    }
+
+    /// Execute the FTP command PORT, after which the server connects to the client for the next
+    /// transfer.
+    pub fn exec_port_command(&self, args: &CommandArgs) -> CommandResult {
+        // PORT ne peut donner qu'une adresse IPv4.
+        if self.peer_address.ip().to_canonical().is_ipv6() {
+            return Err((
+                ReplyCode::NetworkProtocolNotSupported,
+                "PORT is not supported over IPv6, use EPRT".to_string(),
+            ));
+        }
+
+        let address = match host_port::decode(args.get_raw()) {
+            Ok(a) => a,
+            Err(err) => return Err((ReplyCode::SyntaxErrorInArguments, err)),
+        };
+
+        self.set_active_address(SocketAddr::V4(address), "PORT")
+    }
+
+    /// Execute the FTP command EPRT, the PORT of the RFC 2428 which also takes IPv6 addresses.
+    pub fn exec_eprt_command(&self, args: &CommandArgs) -> CommandResult {
+        let address = match host_port::decode_extended(args.get_raw()) {
+            Ok(a) => a,
+            Err(err) => return Err((ReplyCode::SyntaxErrorInArguments, err)),
+        };
+
+        // La famille doit être celle de la connexion de contrôle.
+        let protocol = host_port::network_protocol(self.peer_address.ip().to_canonical());
+
+        if host_port::network_protocol(address.ip()) != protocol {
+            return Err((
+                ReplyCode::NetworkProtocolNotSupported,
+                format!("Network protocol not supported, use ({protocol})"),
+            ));
+        }
+
+        self.set_active_address(address, "EPRT")
+    }
+
+    /// Connect the data connection of the next transfer to `address`, given by PORT or EPRT.
+    fn set_active_address(&self, address: SocketAddr, command: &str) -> CommandResult {
+        let options = self.get_options();
+        let mut options = RefCell::borrow_mut(&options);
+
+        if options.epsv_all {
+            return Err((
+                ReplyCode::BadSequence,
+                "only EPSV is allowed after EPSV ALL".to_string(),
+            ));
+        }
+
+        // Sans cette vérification, le serveur pourrait être utilisé pour se connecter à une
+        // autre machine, comme dans l'attaque FTP bounce.
+        if address.ip().to_canonical() != self.peer_address.ip().to_canonical() {
+            return Err(self.deny(
+                ReplyCode::SyntaxErrorInArguments,
+                format!("{command} must give the address of the client"),
+                DenialReason::ForeignDataAddress,
+                &format!("{command} {address}"),
+            ));
+        }
+
+        if address.port() < 1024 {
+            return Err((
+                ReplyCode::SyntaxErrorInArguments,
+                format!("{command} cannot use a privileged port"),
+            ));
+        }
+
+        self.open_data_slot()?;
+
+        options.listen_mode = ListenMode::Active;
+
+        let source_port = match CONFIG.get().unwrap().get_active_source_port() {
+            true => data_channel::get_source_port(self.local_address.port()),
+            false => 0,
+        };
+
+        *RefCell::borrow_mut(&self.data_channel) = Some(DataChannel::Active {
+            address,
+            local_address: SocketAddr::new(self.local_address.ip(), source_port),
+        });
+
+        Ok((
+            ReplyCode::CommandOk,
+            format!("{command} command successful"),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute the FTP command PASV.
+    pub fn exec_pasv_command(&self, _: &CommandArgs) -> CommandResult {
+        // La réponse de PASV ne peut contenir qu'une adresse IPv4.
+        if self.local_address.is_ipv6() {
+            return Err((
+                ReplyCode::NetworkProtocolNotSupported,
+                "PASV is not supported over IPv6, use EPSV".to_string(),
+            ));
+        }
+
+        let options = self.get_options();
+        let mut options = RefCell::borrow_mut(&options);
+
+        if options.epsv_all {
+            return Err((
+                ReplyCode::BadSequence,
+                "only EPSV is allowed after EPSV ALL".to_string(),
+            ));
+        }
+
+        let config = CONFIG.get().unwrap();
+
+        // Un routeur NAT ne peut pas abîmer la réponse de EPSV, qui ne contient que le port.
+        if config.get_epsv_only() {
+            return Err((
+                ReplyCode::NetworkProtocolNotSupported,
+                "PASV is disabled, use EPSV".to_string(),
+            ));
+        }
+
+        options.listen_mode = ListenMode::Passive;
+
+        let username = options.session.as_ref().map(|sess| sess.get_username());
+
+        self.open_data_slot()?;
+
+        let passive = match passive::open_listener(self.local_address.ip(), username) {
+            Some(p) => p,
+            None => {
+                self.data_slot.take();
+
+                return Err((
+                    ReplyCode::CannotOpenDataConnection,
+                    "cannot open data connection".to_string(),
+                ));
+            }
+        };
+
+        let advertised_address = match passive.advertised_address.parse() {
+            Ok(ip) => SocketAddrV4::new(ip, passive.advertised_port),
+            Err(_) => {
+                return Err((
+                    ReplyCode::CannotOpenDataConnection,
+                    "cannot open data connection".to_string(),
+                ))
+            }
+        };
+
+        let message = match config.get_pasv_reply() {
+            PasvReply::Standard => format!(
+                "Entering passive mode ({})",
+                host_port::encode(advertised_address)
+            ),
+            PasvReply::Unbracketed => format!(
+                "Entering passive mode ={}",
+                host_port::encode(advertised_address)
+            ),
+        };
+
+        Ok((
+            ReplyCode::EnteringPassiveMode,
+            message,
+            false,
+            CommandReturnType::TcpListener(passive.listener),
+        ))
+    }
+
+    /// Execute the FTP command EPSV.
+    pub fn exec_epsv_command(&self, args: &CommandArgs) -> CommandResult {
+        let protocol = host_port::network_protocol(self.local_address.ip());
+
+        let options = self.get_options();
+        let mut options = RefCell::borrow_mut(&options);
+
+        match args.get_word(0) {
+            None => (),
+            Some(arg) if arg.eq_ignore_ascii_case("ALL") => {
+                options.epsv_all = true;
+
+                return Ok((
+                    ReplyCode::CommandOk,
+                    "EPSV ALL command successful".to_string(),
+                    false,
+                    CommandReturnType::None,
+                ));
+            }
+            // Le client demande une famille d'adresses, qui doit être celle de la connexion de
+            // contrôle.
+            Some(arg) if arg == protocol.to_string() => (),
+            Some(arg) if arg == "1" || arg == "2" => {
+                return Err((
+                    ReplyCode::NetworkProtocolNotSupported,
+                    format!("Network protocol not supported, use ({protocol})"),
+                ))
+            }
+            Some(_) => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "unknown network protocol".to_string(),
+                ))
+            }
+        }
+
+        options.listen_mode = ListenMode::Passive;
+
+        let username = options.session.as_ref().map(|sess| sess.get_username());
+
+        self.open_data_slot()?;
+
+        let passive = match passive::open_listener(self.local_address.ip(), username) {
+            Some(p) => p,
+            None => {
+                self.data_slot.take();
+
+                return Err((
+                    ReplyCode::CannotOpenDataConnection,
+                    "cannot open data connection".to_string(),
+                ));
+            }
+        };
+
+        // Seul le port est envoyé, le client se connecte à l'adresse de la connexion de contrôle.
+        Ok((
+            ReplyCode::EnteringExtendedPassiveMode,
+            format!(
+                "Entering extended passive mode {}",
+                host_port::encode_extended_port(passive.advertised_port)
+            ),
+            false,
+            CommandReturnType::TcpListener(passive.listener),
+        ))
+    }
+
+    /// Execute the FTP command LIST.
+    pub fn exec_list_command(&mut self, args: &CommandArgs) -> CommandResult {
+        // Sans canal de données, le dossier n'est même pas lu.
+        self.check_data_channel()?;
+
+        let (root, working_directory) = self.get_location();
+
+        // Les options à la 'ls' (-l, -a...) envoyées par certains clients sont ignorées.
+        let arg = args.get_path_after_options();
+
+        let (path, lines): (String, Lines) = match arg {
+            // Chaque entrée correspondant au motif est décrite sur une ligne, comme 'ls -d'.
+            Some(pattern) if path_resolver::has_wildcards(pattern) => {
+                let mut matches = self.expand(&root, &working_directory, pattern);
+                matches.retain(|m| !self.is_hidden_match(m));
+
+                if matches.is_empty() {
+                    return Err(file_error::unavailable(pattern, file_error::NO_SUCH_FILE));
+                }
+
+                (
+                    pattern.to_string(),
+                    Box::new(matches.into_iter().filter_map(move |m| {
+                        listing::describe(&path_resolver::to_system_path(&root, &m)).ok()
+                    })),
+                )
+            }
+            _ => {
+                let path = match arg {
+                    Some(p) => path_resolver::resolve(&working_directory, p),
+                    None => working_directory,
+                };
+
+                let system_path = path_resolver::to_system_path(&root, &path);
+
+                self.check_jail(&root, &path, &system_path)?;
+
+                // Le contenu d'un dossier de dépôt reste invisible, même pour qui y dépose.
+                if self.is_in_drop_directory(&path) {
+                    return self.send_lines("LIST", &path, Box::new(std::iter::empty()));
+                }
+
+                match listing::list(&system_path, get_metadata_threads()) {
+                    Ok(l) => (path, l),
+                    Err(err) => {
+                        return Err(file_error::from_io(&path, &err, "cannot access directory"))
+                    }
+                }
+            }
+        };
+
+        self.send_lines("LIST", &path, lines)
+    }
+
+    /// Execute the FTP command MLSD.
+    pub fn exec_mlsd_command(&mut self, args: &CommandArgs) -> CommandResult {
+        self.check_data_channel()?;
+
+        let (root, working_directory) = self.get_location();
+
+        let path = match args.get_path() {
+            Some(p) => path_resolver::resolve(&working_directory, p),
+            None => working_directory,
+        };
+
+        let system_path = path_resolver::to_system_path(&root, &path);
+
+        self.check_jail(&root, &path, &system_path)?;
+
+        if self.is_in_drop_directory(&path) {
+            return self.send_lines("MLSD", &path, Box::new(std::iter::empty()));
+        }
+
+        let selection = RefCell::borrow(&self.options).mlst_facts.clone();
+
+        let lines = match listing::list_facts(&system_path, selection, get_metadata_threads()) {
+            Ok(l) => l,
+            Err(err) if err.kind() == io::ErrorKind::NotADirectory => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    format!("{path} is not a directory"),
+                ))
+            }
+            Err(err) => return Err(file_error::from_io(&path, &err, "cannot access directory")),
+        };
+
+        self.send_lines("MLSD", &path, lines)
+    }
+
+    /// Execute the FTP command MLST, which describes a single file on the control connection.
+    pub fn exec_mlst_command(&self, args: &CommandArgs) -> CommandResult {
+        let (root, working_directory) = self.get_location();
+
+        let path = match args.get_path() {
+            Some(p) => path_resolver::resolve(&working_directory, p),
+            None => working_directory,
+        };
+
+        let system_path = path_resolver::to_system_path(&root, &path);
+
+        self.check_jail(&root, &path, &system_path)?;
+        self.check_drop_policy(&path, DropAccess::Existing)?;
+
+        let selection = RefCell::borrow(&self.options).mlst_facts.clone();
+
+        let details = Details {
+            owner: false,
+            unique: selection.contains(&Fact::Unique),
+        };
+
+        let info = match FileInfo::read(&system_path, details) {
+            Ok(i) => i,
+            Err(err) => return Err(file_error::from_io(&path, &err, file_error::NO_SUCH_FILE)),
+        };
+
+        // La ligne des faits commence par un espace, comme l'exige la RFC 3659.
+        Ok((
+            ReplyCode::FileActionOk,
+            format!(
+                "Listing {path}\r\n {}",
+                listing::format_facts(&path, &info, &selection)
+            ),
+            true,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute the FTP command ABOR outside of a transfer.
+    pub fn exec_abor_command(&self, _: &CommandArgs) -> CommandResult {
+        Ok((
+            ReplyCode::DataConnectionOpen,
+            "no transfer to abort".to_string(),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute the FTP command NLST.
+    pub fn exec_nlst_command(&mut self, args: &CommandArgs) -> CommandResult {
+        self.check_data_channel()?;
+
+        let (root, working_directory) = self.get_location();
+
+        let arg = args.get_path_after_options();
+
+        let (path, names): (String, Lines) = match arg {
+            // Les chemins sont renvoyés tels que le client a écrit le motif.
+            Some(pattern) if path_resolver::has_wildcards(pattern) => {
+                let prefix = match pattern.rfind('/') {
+                    Some(idx) => pattern[..=idx].to_string(),
+                    None => String::new(),
+                };
+
+                let mut matches = self.expand(&root, &working_directory, pattern);
+                matches.retain(|m| !self.is_hidden_match(m));
+
+                let names = matches
+                    .into_iter()
+                    .filter_map(move |m| Some(format!("{prefix}{}", m.rsplit('/').next()?)));
+
+                (pattern.to_string(), Box::new(names))
+            }
+            _ => {
+                let path = match arg {
+                    Some(p) => path_resolver::resolve(&working_directory, p),
+                    None => working_directory,
+                };
+
+                let system_path = path_resolver::to_system_path(&root, &path);
+
+                self.check_jail(&root, &path, &system_path)?;
+
+                if self.is_in_drop_directory(&path) {
+                    return self.send_lines("NLST", &path, Box::new(std::iter::empty()));
+                }
+
+                match fs::read_dir(&system_path) {
+                    Ok(entries) => (
+                        path,
+                        Box::new(
+                            entries
+                                .flatten()
+                                .filter_map(|entry| entry.file_name().into_string().ok())
+                                .filter(|name| path_resolver::is_safe_name(name)),
+                        ),
+                    ),
+                    Err(_) if system_path.is_file() => {
+                        (path, Box::new(std::iter::once(arg.unwrap().to_string())))
+                    }
+                    Err(err) => {
+                        return Err(file_error::from_io(&path, &err, "cannot access directory"))
+                    }
+                }
+            }
+        };
+
+        self.send_lines("NLST", &path, names)
+    }
+
+    /// Execute the FTP command RETR.
+    pub fn exec_retr_command(&mut self, args: &CommandArgs) -> CommandResult {
+        let path = match args.get_path() {
+            Some(p) => p,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "missing pathname".to_string(),
+                ))
+            }
+        };
+
+        if path_resolver::has_wildcards(path) {
+            return Err((
+                ReplyCode::FileUnavailable,
+                "wildcards are not allowed".to_string(),
+            ));
+        }
+
+        let (root, working_directory) = self.get_location();
+        let transfer_mode = RefCell::borrow(&self.options).transfer_mode;
+
+        // La plage de RANG et le décalage de REST ne valent que pour un seul transfert, même s'il
+        // échoue. Un décalage revient à une plage allant jusqu'à la fin du fichier.
+        let range = {
+            let mut options = RefCell::borrow_mut(&self.options);
+            let restart = options.restart.take().filter(|offset| *offset > 0);
+
+            options
+                .range
+                .take()
+                .or(restart.map(|offset| (offset, u64::MAX - 1)))
+        };
+
+        let path = path_resolver::resolve(&working_directory, path);
+        let system_path = path_resolver::to_system_path(&root, &path);
+
+        self.check_jail(&root, &path, &system_path)?;
+        self.check_not_protected(&path)?;
+        self.check_drop_policy(&path, DropAccess::Existing)?;
+        self.check_data_channel()?;
+
+        let config = CONFIG.get().unwrap();
+        let settings = config.get_compression();
+
+        // Un dossier est envoyé sous la forme d'un index si la config le permet.
+        let index = match config.get_directory_index() {
+            DirectoryIndex::None => None,
+            _ if !system_path.is_dir() => None,
+            format => match listing::index(
+                &system_path,
+                &path,
+                format,
+                config.get_limits().max_listing_entries,
+            ) {
+                Ok(document) => Some(document),
+                Err(err) => {
+                    eprintln!("Cannot index {path}: {err}.");
+
+                    return Err((ReplyCode::FileUnavailable, format!("{path}: cannot read")));
+                }
+            },
+        };
+
+        // Un 'fichier.gz' absent est produit à la volée depuis 'fichier' si la config le permet.
+        let (source_path, gzip) = match compression::find_implicit_gzip(&system_path, settings) {
+            Some(source) if index.is_none() => (source, true),
+            _ => (system_path.clone(), false),
+        };
+
+        let (source, size, range_length): (Box<dyn Read>, u64, u64) = match index {
+            Some(_) if range.is_some() => {
+                return Err((
+                    ReplyCode::ActionNotTaken,
+                    "RANG and REST are not supported on directory indexes".to_string(),
+                ))
+            }
+            Some(document) => {
+                let size = document.len() as u64;
+
+                (Box::new(Cursor::new(document.into_bytes())), size, u64::MAX)
+            }
+            None => {
+                let (file, range_length) = self.open_download(&path, &source_path, gzip, range)?;
+                let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+                (Box::new(file), size, range_length)
+            }
+        };
+
+        let max_size = {
+            let options = RefCell::borrow(&self.options);
+
+            config.get_max_download_size(options.session.as_ref().map(|sess| sess.get_username()))
+        };
+
+        if max_size != 0 && size.min(range_length) > max_size {
+            return Err((
+                ReplyCode::FileUnavailable,
+                format!("{path}: larger than the {max_size} bytes that can be downloaded"),
+            ));
+        }
+
+        // Le canal de données n'est pris qu'une fois le fichier prêt à être envoyé.
+        let (data_channel, _slot) = self.take_data_channel()?;
+
+        let _ = self.write(format!("{} ok\r\n", ReplyCode::FileStatusOk).as_bytes());
+
+        let mut connection = self.open_data_connection(&data_channel)?;
+
+        let _keepalive = self.keep_control_alive();
+
+        if self.faults.drop_data_connection() {
+            return Err((
+                ReplyCode::ConnectionClosed,
+                "connection closed, transfer aborted".to_string(),
+            ));
+        }
+
+        // Un transfert tronqué n'envoie que la première moitié du fichier.
+        let limit = match self.faults.truncate_transfer() {
+            true => size / 2,
+            false => u64::MAX,
+        }
+        .min(range_length);
+
+        let started = Instant::now();
+
+        let mut reader: Box<dyn Read> = Box::new(source.take(limit));
+
+        if gzip {
+            reader = compression::gzip(reader);
+        }
+
+        if transfer_mode == TransferMode::Deflate {
+            reader = compression::deflate(reader, compression::get_level(&system_path, settings));
+        }
+
+        let _transfer = self
+            .state
+            .start_transfer(Direction::Download, "RETR", &path);
+
+        let sent = match copy_data(&mut reader, &mut connection, &self.shutdown, &self.state) {
+            Ok(bytes) => bytes,
+            Err((bytes, err)) => return self.abort_transfer("RETR", &path, bytes, started, &err),
+        };
+
+        if let Err(err) = data_channel::finish(&connection) {
+            return self.abort_transfer("RETR", &path, sent, started, &err);
+        }
+
+        self.record_transfer(&format!(
+            "RETR {path}: {sent} bytes in {} ms",
+            started.elapsed().as_millis()
+        ));
+
+        self.timeline.borrow_mut().record_transfer(
+            "RETR",
+            &path,
+            sent,
+            started,
+            TransferOutcome::Completed,
+        );
+
+        RefCell::borrow_mut(&self.options)
+            .transfer_statistics
+            .record_download(sent);
+        metrics::record_sent(sent);
+
+        Ok((
+            ReplyCode::ClosingDataConnection,
+            "closing data connection".to_string(),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Open the file `source_path` to download, placed at the start of `range` if one is given.
+    ///
+    /// # Return
+    /// The file and the number of bytes to send from it.
+    fn open_download(
+        &self,
+        path: &str,
+        source_path: &Path,
+        gzip: bool,
+        range: Option<(u64, u64)>,
+    ) -> Result<(File, u64), (ReplyCode, String)> {
+        let mut file = match File::open(source_path) {
+            Ok(_) if source_path.is_dir() => {
+                return Err(file_error::unavailable(path, file_error::IS_A_DIRECTORY))
+            }
+            Ok(f) => f,
+            Err(err) => return Err(file_error::from_io(path, &err, file_error::NO_SUCH_FILE)),
+        };
+
+        // Une plage est envoyée telle quelle, sans que la taille totale du fichier change.
+        let range_length = match range {
+            Some(_) if gzip => {
+                return Err((
+                    ReplyCode::ActionNotTaken,
+                    "RANG and REST are not supported on compressed files".to_string(),
+                ))
+            }
+            Some((start, end)) => {
+                let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+                if start >= size {
+                    return Err((
+                        ReplyCode::ActionNotTaken,
+                        format!("range starts after the end of {path}"),
+                    ));
+                }
+
+                if let Err(err) = file.seek(SeekFrom::Start(start)) {
+                    eprintln!("Cannot seek {path} to {start}: {err}.");
+
+                    return Err((
+                        ReplyCode::LocalError,
+                        "local error in processing".to_string(),
+                    ));
+                }
+
+                end - start + 1
+            }
+            None => u64::MAX,
+        };
+
+        Ok((file, range_length))
+    }
+
+    /// Execute the FTP command MDTM, which gives the modification time of a file.
+    ///
+    /// When the config allows it, `MDTM YYYYMMDDHHMMSS path` changes the time instead, unless the
+    /// whole argument is the name of an existing file.
+    pub fn exec_mdtm_command(&mut self, args: &CommandArgs) -> CommandResult {
+        let argument = match args.get_path() {
+            Some(p) => p,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "missing pathname".to_string(),
+                ))
+            }
+        };
+
+        let (root, working_directory) = self.get_location();
+
+        if CONFIG.get().unwrap().get_mdtm_setter() {
+            let setter = args
+                .get_word_and_path()
+                .and_then(|(time, path)| Some((facts::parse_time_val(time)?, path)));
+
+            if let Some((time, path)) = setter {
+                let whole = path_resolver::resolve(&working_directory, argument);
+
+                if !path_resolver::to_system_path(&root, &whole).is_file() {
+                    return self.set_modify_time(time, path);
+                }
+            }
+        }
+
+        let path = path_resolver::resolve(&working_directory, argument);
+        let system_path = path_resolver::to_system_path(&root, &path);
+
+        self.check_jail(&root, &path, &system_path)?;
+        self.check_drop_policy(&path, DropAccess::Existing)?;
+
+        let modified = match FileInfo::read(&system_path, Details::default()) {
+            Ok(info) if info.is_dir => {
+                return Err(file_error::unavailable(&path, file_error::IS_A_DIRECTORY))
+            }
+            Ok(info) => info.modified,
+            Err(err) => return Err(file_error::from_io(&path, &err, file_error::NO_SUCH_FILE)),
+        };
+
+        match modified {
+            Some(time) => Ok((
+                ReplyCode::FileStatus,
+                facts::format_time_val(time.into()),
+                false,
+                CommandReturnType::None,
+            )),
+            None => Err((
+                ReplyCode::FileUnavailable,
+                format!("{path}: modification time unavailable"),
+            )),
+        }
+    }
+
+    /// Execute the FTP command MFMT, which changes the modification time of a file.
+    pub fn exec_mfmt_command(&mut self, args: &CommandArgs) -> CommandResult {
+        let (time, path) = match args.get_word_and_path() {
+            Some(a) => a,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "expected a time and a pathname".to_string(),
+                ))
+            }
+        };
+
+        let time = match facts::parse_time_val(time) {
+            Some(t) => t,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "invalid time, expected YYYYMMDDHHMMSS".to_string(),
+                ))
+            }
+        };
+
+        self.set_modify_time(time, path)
+    }
+
+    /// Set the modification time of the file `path` to `time`.
+    ///
+    /// Some clients send it before uploading the file: the time of a file that does not exist
+    /// yet is then kept until STOR has received it.
+    fn set_modify_time(&mut self, time: DateTime<Utc>, path: &str) -> CommandResult {
+        self.check_writable()?;
+
+        let (root, working_directory) = self.get_location();
+
+        let path = path_resolver::resolve(&working_directory, path);
+        let system_path = path_resolver::to_system_path(&root, &path);
+
+        self.check_jail(&root, &path, &system_path)?;
+        self.check_drop_policy(&path, DropAccess::Existing)?;
+
+        let reply = format!("Modify={}; {path}", facts::format_time_val(time));
+
+        if !system_path.exists() {
+            RefCell::borrow_mut(&self.options).pending_modify = Some((path, time));
+
+            return Ok((ReplyCode::FileStatus, reply, false, CommandReturnType::None));
+        }
+
+        if system_path.is_dir() {
+            return Err(file_error::unavailable(&path, file_error::IS_A_DIRECTORY));
+        }
+
+        if !system_path.is_file() {
+            return Err((ReplyCode::FileUnavailable, format!("{path}: not a file")));
+        }
+
+        if let Err(err) = set_modified(&system_path, time) {
+            eprintln!(
+                "{}: cannot set the modification time of {path}: {err}.",
+                self.get_log_context()
+            );
+
+            return Err((
+                ReplyCode::FileUnavailable,
+                format!("{path}: cannot change the modification time"),
+            ));
+        }
+
+        Ok((ReplyCode::FileStatus, reply, false, CommandReturnType::None))
+    }
+
+    /// Execute the FTP command HASH, which gives the checksum of a whole file.
+    pub fn exec_hash_command(&self, args: &CommandArgs) -> CommandResult {
+        let path = match args.get_path() {
+            Some(p) => p,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "missing pathname".to_string(),
+                ))
+            }
+        };
+
+        let (root, working_directory) = self.get_location();
+
+        let path = path_resolver::resolve(&working_directory, path);
+        let system_path = path_resolver::to_system_path(&root, &path);
+
+        self.check_jail(&root, &path, &system_path)?;
+        self.check_drop_policy(&path, DropAccess::Existing)?;
+
+        let size = match FileInfo::read(&system_path, Details::default()) {
+            Ok(info) if info.is_dir => {
+                return Err(file_error::unavailable(&path, file_error::IS_A_DIRECTORY))
+            }
+            Ok(info) => info.size,
+            Err(err) => return Err(file_error::from_io(&path, &err, file_error::NO_SUCH_FILE)),
+        };
+
+        let checksum = match checksum::get(&system_path, CONFIG.get().unwrap().get_checksum_cache())
+        {
+            Ok(c) => c,
+            Err(err) => {
+                eprintln!("Cannot hash {path}: {err}.");
+
+                return Err((
+                    ReplyCode::LocalError,
+                    "local error in processing".to_string(),
+                ));
+            }
+        };
+
+        // Le format suit le brouillon 'draft-bryan-ftpext-hash' : algorithme, plage, hash, nom.
+        Ok((
+            ReplyCode::FileStatus,
+            format!(
+                "{} 0-{} {checksum} {path}",
+                checksum::ALGORITHM,
+                size.saturating_sub(1)
+            ),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute the FTP command RANG, which restricts the next RETR to a range of bytes.
+    pub fn exec_rang_command(&self, args: &CommandArgs) -> CommandResult {
+        let range = match args.parse_range() {
+            Ok(r) => r,
+            Err(err) => return Err((ReplyCode::SyntaxErrorInArguments, err)),
+        };
+
+        // Une plage remplace le décalage que REST aurait donné avant elle.
+        {
+            let mut options = RefCell::borrow_mut(&self.options);
+
+            options.range = range;
+            options.restart = None;
+        }
+
+        let message = match range {
+            Some((start, end)) => format!("Restarting at {start}. Ending byte at {end}"),
+            None => "Resetting the range".to_string(),
+        };
+
+        Ok((
+            ReplyCode::FileActionPending,
+            message,
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute the FTP command REST, which makes the next RETR or STOR start at a byte offset.
+    pub fn exec_rest_command(&self, args: &CommandArgs) -> CommandResult {
+        let offset = match args.parse_offset() {
+            Ok(o) => o,
+            Err(err) => return Err((ReplyCode::SyntaxErrorInArguments, err)),
+        };
+
+        {
+            let mut options = RefCell::borrow_mut(&self.options);
+
+            options.restart = Some(offset);
+            options.range = None;
+        }
+
+        Ok((
+            ReplyCode::FileActionPending,
+            format!("Restarting at {offset}. Send STOR or RETR to initiate transfer"),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute the FTP command ALLO, which announces the size of the next upload.
+    pub fn exec_allo_command(&self, args: &CommandArgs) -> CommandResult {
+        // La forme 'ALLO <taille> R <enregistrement>' n'a de sens que pour les fichiers à
+        // enregistrements, seule la taille est gardée.
+        let size = match args.get_word(0).map(|s| s.parse::<u64>()) {
+            Some(Ok(s)) => s,
+            _ => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "Syntax error in arguments".to_string(),
+                ))
+            }
+        };
+
+        let (root, working_directory) = self.get_location();
+
+        check_free_space(
+            &path_resolver::to_system_path(&root, &working_directory),
+            size,
+        )?;
+
+        RefCell::borrow_mut(&self.options).allocation = Some(size);
+
+        if CONFIG.get().unwrap().get_preallocate_uploads() {
+            Ok((
+                ReplyCode::CommandOk,
+                format!("{size} bytes will be reserved for the next upload"),
+                false,
+                CommandReturnType::None,
+            ))
+        } else {
+            Ok((
+                ReplyCode::CommandSuperfluous,
+                "ALLO not needed, storage is allocated on demand".to_string(),
+                false,
+                CommandReturnType::None,
+            ))
+        }
+    }
+
+    /// Execute the FTP command DELE.
+    pub fn exec_dele_command(&self, args: &CommandArgs) -> CommandResult {
+        self.check_writable()?;
+
+        let path = match args.get_path() {
+            Some(p) => p,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "missing pathname".to_string(),
+                ))
+            }
+        };
+
+        // Supprimer '*' supprimerait un fichier portant ce nom, ce qui n'est jamais ce que le
+        // client attend.
+        if path_resolver::has_wildcards(path) {
+            return Err((
+                ReplyCode::FileUnavailable,
+                "wildcards are not allowed".to_string(),
+            ));
+        }
+
+        let options = self.get_options();
+        let options = RefCell::borrow(&options);
+
+        let path = path_resolver::resolve(&options.working_directory, path);
+        let system_path = path_resolver::to_system_path(&options.root, &path);
+
+        self.check_jail(&options.root, &path, &system_path)?;
+        self.check_not_protected(&path)?;
+        self.check_drop_policy(&path, DropAccess::Existing)?;
+
+        match fs::remove_file(system_path) {
+            Ok(_) => Ok((
+                ReplyCode::FileActionOk,
+                format!("{path} deleted"),
+                false,
+                CommandReturnType::None,
+            )),
+            Err(err) => Err(file_error::from_io(&path, &err, "cannot delete file")),
+        }
+    }
+
+    /// Execute the FTP command MKD.
+    pub fn exec_mkd_command(&self, args: &CommandArgs) -> CommandResult {
+        self.check_writable()?;
+
+        let path = match args.get_path() {
+            Some(p) => p,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "missing pathname".to_string(),
+                ))
+            }
+        };
+
+        if !path_resolver::is_safe_name(path) {
+            return Err((
+                ReplyCode::FileUnavailable,
+                "file names cannot contain line breaks".to_string(),
+            ));
+        }
+
+        let options = self.get_options();
+        let options = RefCell::borrow(&options);
+
+        let path = path_resolver::resolve(&options.working_directory, path);
+        let path = self.transform_name(path, true).map_err(as_mkd_failure)?;
+        let system_path = self
+            .check_target(&options.root, &path)
+            .map_err(as_mkd_failure)?;
+
+        // 553 n'est pas une réponse de MKD, les limites sont signalées comme les autres refus.
+        limits::check_path(&path, &system_path, &CONFIG.get().unwrap().get_limits())
+            .map_err(|reason| (ReplyCode::FileUnavailable, reason))?;
+
+        // Un seul niveau est créé, les dossiers intermédiaires ne sont jamais créés implicitement.
+        match fs::create_dir(system_path) {
+            Ok(_) => Ok((
+                ReplyCode::PathnameCreated,
+                format!("{} created", quote_pathname(&path)),
+                false,
+                CommandReturnType::None,
+            )),
+            Err(err) => Err(file_error::from_io(&path, &err, "cannot create directory")),
+        }
+    }
+
+    /// Execute the FTP command RMD, which only removes empty directories.
+    pub fn exec_rmd_command(&self, args: &CommandArgs) -> CommandResult {
+        self.check_writable()?;
+
+        let path = match args.get_path() {
+            Some(p) => p,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "missing pathname".to_string(),
+                ))
+            }
+        };
+
+        let options = self.get_options();
+        let options = RefCell::borrow(&options);
+
+        let path = path_resolver::resolve(&options.working_directory, path);
+        let system_path = path_resolver::to_system_path(&options.root, &path);
+
+        self.check_jail(&options.root, &path, &system_path)?;
+        self.check_drop_policy(&path, DropAccess::Existing)?;
+
+        if path == "/" {
+            return Err((
+                ReplyCode::FileUnavailable,
+                format!("{path}: {}", file_error::PERMISSION_DENIED),
+            ));
+        }
+
+        match fs::remove_dir(system_path) {
+            Ok(_) => Ok((
+                ReplyCode::FileActionOk,
+                format!("{path} removed"),
+                false,
+                CommandReturnType::None,
+            )),
+            Err(err) => Err(file_error::from_io(&path, &err, "cannot remove directory")),
+        }
+    }
+
+    /// Execute the FTP command RNFR, the source of the rename done by the next RNTO.
+    pub fn exec_rnfr_command(&self, args: &CommandArgs) -> CommandResult {
+        self.check_writable()?;
+
+        let path = match args.get_path() {
+            Some(p) => p,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "missing pathname".to_string(),
+                ))
+            }
+        };
+
+        let options = self.get_options();
+        let mut options = RefCell::borrow_mut(&options);
+
+        let path = path_resolver::resolve(&options.working_directory, path);
+        let system_path = path_resolver::to_system_path(&options.root, &path);
+
+        self.check_jail(&options.root, &path, &system_path)?;
+        // Déplacer un parent d'un dossier de dépôt rendrait ses fichiers lisibles.
+        self.check_drop_policy(&path, DropAccess::Tree)?;
+
+        // La racine de la session et les fichiers gérés par le serveur ne bougent pas.
+        if path == "/" || path_resolver::is_protected(&path) {
+            return Err((
+                ReplyCode::FileUnavailable,
+                format!("{path}: {}", file_error::PERMISSION_DENIED),
+            ));
+        }
+
+        if let Err(err) = fs::symlink_metadata(&system_path) {
+            return Err(file_error::from_io(&path, &err, file_error::NO_SUCH_FILE));
+        }
+
+        options.rename_from = Some(path);
+
+        Ok((
+            ReplyCode::FileActionPending,
+            "ready for destination name".to_string(),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute the FTP command RNTO, which must directly follow RNFR.
+    pub fn exec_rnto_command(&self, args: &CommandArgs) -> CommandResult {
+        let options = self.get_options();
+
+        let rename_from = RefCell::borrow_mut(&options).rename_from.take();
+
+        let from = match rename_from {
+            Some(f) => f,
+            None => return Err((ReplyCode::BadSequence, "send RNFR first".to_string())),
+        };
+
+        self.check_writable()?;
+
+        let path = match args.get_path() {
+            Some(p) => p,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "missing pathname".to_string(),
+                ))
+            }
+        };
+
+        if !path_resolver::is_safe_name(path) {
+            return Err((
+                ReplyCode::FileNameNotAllowed,
+                "file names cannot contain line breaks".to_string(),
+            ));
+        }
+
+        let options = RefCell::borrow(&options);
+
+        let source = path_resolver::to_system_path(&options.root, &from);
+
+        let path = path_resolver::resolve(&options.working_directory, path);
+        let path = self.transform_name(path, source.is_dir())?;
+        let system_path = self.check_target(&options.root, &path)?;
+        self.check_drop_policy(&path, DropAccess::Existing)?;
+
+        // Un dossier ne peut pas être déplacé dans lui-même.
+        if path.starts_with(&format!("{}/", from.trim_end_matches('/'))) {
+            return Err((
+                ReplyCode::FileNameNotAllowed,
+                format!("{path}: cannot move {from} inside itself"),
+            ));
+        }
+
+        match fs::rename(source, system_path) {
+            Ok(_) => Ok((
+                ReplyCode::FileActionOk,
+                format!("{from} renamed to {path}"),
+                false,
+                CommandReturnType::None,
+            )),
+            Err(err) => Err(file_error::from_io(
+                &path,
+                &err,
+                &format!("cannot rename {from}"),
+            )),
+        }
+    }
+
+    /// Apply the filename rules of the config to `path`, the name of a new file or directory.
+    fn transform_name(
+        &self,
+        path: String,
+        is_directory: bool,
+    ) -> Result<String, (ReplyCode, String)> {
+        let settings = CONFIG.get().unwrap().get_filenames();
+
+        match filename::transform_path(settings, &path, is_directory) {
+            Ok(p) => Ok(p),
+            Err(NameError::Empty) => Err((
+                ReplyCode::FileNameNotAllowed,
+                format!("{path}: file name not allowed"),
+            )),
+            Err(NameError::ExtensionNotAllowed) => Err((
+                ReplyCode::FileNameNotAllowed,
+                format!("{path}: file type not allowed"),
+            )),
+        }
+    }
+
+    /// Check that `path` can be created or replaced, see [`path_resolver::check_target`].
+    fn check_target(&self, root: &str, path: &str) -> Result<PathBuf, (ReplyCode, String)> {
+        match path_resolver::check_target(root, path) {
+            Ok(system_path) => Ok(system_path),
+            Err(TargetError::OutsideRoot) => Err(self.deny(
+                ReplyCode::FileUnavailable,
+                format!("{path}: {}", file_error::PERMISSION_DENIED),
+                DenialReason::PathOutsideJail,
+                &format!("creation of {path}"),
+            )),
+            Err(TargetError::Root | TargetError::Protected) => Err((
+                ReplyCode::FileNameNotAllowed,
+                format!("{path}: {}", file_error::PERMISSION_DENIED),
+            )),
+            Err(TargetError::MissingParent) => {
+                Err(file_error::unavailable(path, file_error::NO_SUCH_FILE))
+            }
+        }
+    }
+
+    /// Execute the FTP command STAT.
+    ///
+    /// With a pathname, the listing is sent on the control connection instead of a data
+    /// connection.
+    pub fn exec_stat_command(&self, args: &CommandArgs) -> CommandResult {
+        let options = self.get_options();
+        let options = RefCell::borrow(&options);
+
+        let arg = match args.get_path() {
+            Some(a) => a,
+            None => {
+                let username = match &options.session {
+                    Some(sess) => sess.get_username().to_string(),
+                    None => "nobody".to_string(),
+                };
+
+                let mut message = format!(
+                    "{} status:\r\n Connected from {}\r\n Logged in as {username}\r\n TYPE: {:?}",
+                    get_server_name(),
+                    self.peer_address,
+                    options.data_representation
+                );
+
+                if let Some(verb) = self.state.get().last_command {
+                    message.push_str(&format!("\r\n Last command: {verb}"));
+                }
+
+                for line in metrics::get().format_lines() {
+                    message.push_str(&format!("\r\n {line}"));
+                }
+
+                return Ok((
+                    ReplyCode::SystemStatus,
+                    message,
+                    true,
+                    CommandReturnType::None,
+                ));
+            }
+        };
+
+        let path = path_resolver::resolve(&options.working_directory, arg);
+
+        if self.is_in_drop_directory(&path) {
+            return Ok((
+                ReplyCode::DirectoryStatus,
+                format!("Status of {path}:"),
+                false,
+                CommandReturnType::None,
+            ));
+        }
+
+        let system_path = path_resolver::to_system_path(&options.root, &path);
+
+        self.check_jail(&options.root, &path, &system_path)?;
+
+        let lines = match listing::list(&system_path, get_metadata_threads()) {
+            Ok(l) => l,
+            Err(err) => return Err(file_error::from_io(&path, &err, file_error::NO_SUCH_FILE)),
+        };
+
+        // Chaque ligne commence par un espace pour ne jamais être confondue avec la fin de la
+        // réponse multi-lignes.
+        let mut message = format!("Status of {path}:");
+
+        let max_size = CONFIG.get().unwrap().get_limits().max_reply_size;
+
+        for line in lines {
+            message.push_str(&format!("\r\n {line}"));
+
+            // La réponse sera tronquée à l'envoi, inutile de lire le reste du dossier.
+            if message.len() > max_size {
+                break;
+            }
+        }
+
+        // Le client sait ainsi d'où reprendre un envoi coupé par un arrêt du serveur.
+        if let Some(size) = partial_upload::get(&system_path) {
+            message.push_str(&format!(
+                "\r\n Partial upload: {size} bytes, send REST {size} to resume"
+            ));
+        }
+
+        let code = match system_path.is_dir() {
+            true => ReplyCode::DirectoryStatus,
+            false => ReplyCode::FileStatus,
+        };
+
+        Ok((code, message, true, CommandReturnType::None))
+    }
+
+    /// Execute the FTP command STOR, which writes from the offset given by REST if any.
+    pub fn exec_stor_command(&mut self, args: &CommandArgs) -> CommandResult {
+        self.store(args, "STOR")
+    }
+
+    /// Execute the FTP command APPE, which writes at the end of the file.
+    pub fn exec_appe_command(&mut self, args: &CommandArgs) -> CommandResult {
+        self.store(args, "APPE")
+    }
+
+    /// Receive a file for STOR or APPE, given by `command`.
+    fn store(&mut self, args: &CommandArgs, command: &str) -> CommandResult {
+        self.check_writable()?;
+
+        let path = match args.get_path() {
+            Some(p) => p,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "missing pathname".to_string(),
+                ))
+            }
+        };
+
+        // Un tel fichier ne pourrait plus apparaître correctement dans les listes.
+        if !path_resolver::is_safe_name(path) {
+            return Err((
+                ReplyCode::FileNameNotAllowed,
+                "file names cannot contain line breaks".to_string(),
+            ));
+        }
+
+        let options = self.get_options();
+        let mut options = RefCell::borrow_mut(&options);
+
+        let path = path_resolver::resolve(&options.working_directory, path);
+        let path = self.transform_name(path, false)?;
+        let system_path = path_resolver::to_system_path(&options.root, &path);
+
+        self.check_jail(&options.root, &path, &system_path)?;
+        self.check_not_protected(&path)?;
+
+        limits::check_path(&path, &system_path, &CONFIG.get().unwrap().get_limits())
+            .map_err(|reason| (ReplyCode::FileNameNotAllowed, reason))?;
+
+        // Le décalage de REST et la taille annoncée par ALLO ne valent que pour un seul envoi,
+        // même s'il échoue.
+        let restart = options.restart.take();
+        let allocation = options.allocation.take();
+
+        // APPE écrit toujours à la fin du fichier, un décalage n'y aurait pas de sens.
+        if command == "APPE" && restart.is_some() {
+            return Err((
+                ReplyCode::BadSequence,
+                "REST cannot be used with APPE".to_string(),
+            ));
+        }
+
+        if options.range.take().is_some() {
+            return Err((
+                ReplyCode::ParameterNotImplemented,
+                "RANG is only supported with RETR".to_string(),
+            ));
+        }
+
+        let dropped = self.is_in_drop_directory(&path);
+
+        self.check_drop_policy(
+            &path,
+            DropAccess::Upload {
+                resume: command == "APPE" || restart.is_some(),
+                replace: system_path.exists(),
+            },
+        )?;
+
+        let config = CONFIG.get().unwrap();
+
+        let max_size =
+            config.get_max_upload_size(options.session.as_ref().map(|sess| sess.get_username()));
+
+        // Un envoi annoncé trop gros par ALLO est refusé avant le moindre octet.
+        if max_size != 0 && allocation.is_some_and(|size| size > max_size) {
+            return Err((
+                ReplyCode::ExceededStorageAllocation,
+                format!("files cannot exceed {max_size} bytes"),
+            ));
+        }
+
+        check_free_space(&system_path, allocation.unwrap_or(0))?;
+
+        // Sans canal de données, l'envoi est refusé avant de toucher au fichier.
+        self.check_data_channel()?;
+
+        // Les hot folders attendent la fin de l'envoi pour livrer le fichier.
+        let _uploading = hot_folder::start_upload(&system_path);
+
+        // Le fichier n'est vidé qu'une fois le canal de données obtenu, pour qu'un envoi refusé
+        // ne fasse pas perdre son contenu.
+        let file = match command {
+            "APPE" => OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&system_path),
+            // Un fichier déposé entre-temps ne doit pas être remplacé.
+            _ => OpenOptions::new()
+                .write(true)
+                .create(true)
+                .create_new(dropped)
+                .truncate(false)
+                .open(&system_path),
+        };
+
+        // Le code reste 553, celui de la RFC 959 pour un nom de fichier refusé par STOR.
+        let mut file = match file {
+            Ok(f) => f,
+            Err(err) => {
+                let (_, message) = file_error::from_io(&path, &err, "cannot create file");
+
+                return Err((ReplyCode::FileNameNotAllowed, message));
+            }
+        };
+
+        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+        // Nombre d'octets du fichier gardés avant ceux reçus.
+        let start = match (command, restart) {
+            ("APPE", _) => size,
+            (_, Some(offset)) if offset > size => {
+                return Err((
+                    ReplyCode::ActionNotTaken,
+                    format!("REST {offset} is after the end of {path}"),
+                ))
+            }
+            (_, restart) => restart.unwrap_or(0),
+        };
+
+        let (data_channel, _slot) = self.take_data_channel()?;
+
+        // Ce qui suit le décalage est remplacé par les octets reçus.
+        if command != "APPE" {
+            if let Err(err) = file
+                .set_len(start)
+                .and_then(|_| file.seek(SeekFrom::End(0)))
+            {
+                eprintln!("Cannot truncate {path} to {start}: {err}.");
+
+                return Err((
+                    ReplyCode::LocalError,
+                    "local error in processing".to_string(),
+                ));
+            }
+        }
+
+        if let Some(size) = allocation.filter(|_| start == 0) {
+            if config.get_preallocate_uploads() {
+                if let Err(err) = crossplatform::preallocate(&file, size) {
+                    eprintln!("Cannot preallocate {size} bytes for {path}: {err}.");
+                }
+            }
+        }
+
+        let mut file = UploadWriter::new(
+            file,
+            config.get_upload_buffer_size(),
+            config.get_fsync_on_close(),
+        )
+        .with_max_size(max_size)
+        .with_content_rules(config.get_content_rules());
+
+        // La taille maximale et les règles de contenu valent pour le fichier entier.
+        if start > 0 {
+            let header = sniffing::read_header(&system_path).unwrap_or_default();
+
+            file = file.resuming(start, &header);
+        }
+
+        let _ = self.write(format!("{} ok\r\n", ReplyCode::FileStatusOk).as_bytes());
+
+        let connection = self.open_data_connection(&data_channel)?;
+
+        let _keepalive = self.keep_control_alive();
+
+        if self.faults.drop_data_connection() {
+            return Err((
+                ReplyCode::ConnectionClosed,
+                "connection closed, transfer aborted".to_string(),
+            ));
+        }
+
+        let started = Instant::now();
+
+        let reader: Box<dyn Read> = match options.transfer_mode {
+            TransferMode::Deflate => compression::inflate(&connection),
+            TransferMode::Stream => Box::new(&connection),
+        };
+
+        // Les sommes sont calculées sur les octets écrits dans le fichier, après décompression.
+        let mut reader = CrcReader::new(HashingReader::new(reader, options.hash_on_upload));
+
+        let _transfer = self.state.start_transfer(Direction::Upload, command, &path);
+
+        let received = match copy_data(&mut reader, &mut file, &self.shutdown, &self.state) {
+            Ok(bytes) => bytes,
+            Err((bytes, err)) => {
+                // Les statistiques de la session sont mises à jour par 'abort_transfer'.
+                drop(options);
+
+                // Le début d'un fichier trop gros ou refusé ne doit pas occuper le disque.
+                if err.kind() == io::ErrorKind::FileTooLarge || sniffing::is_rejected(&err) {
+                    drop(file);
+                    discard_upload(&system_path, start);
+                } else if self.shutdown.load(Ordering::Relaxed) {
+                    // Coupé par l'arrêt du serveur, l'envoi pourra être repris avec REST.
+                    if let Some(size) = self.keep_partial_upload(file, &system_path, &path) {
+                        return self
+                            .abort_transfer(command, &path, bytes, started, &err)
+                            .map_err(|(code, message)| {
+                                (
+                                    code,
+                                    format!(
+                                        "{message}, {size} bytes kept, send REST {size} to resume"
+                                    ),
+                                )
+                            });
+                    }
+                }
+
+                return self.abort_transfer(command, &path, bytes, started, &err);
+            }
+        };
+
+        // Le client a tout envoyé puis fermé son côté : tous les octets reçus ont été lus, la
+        // connexion est fermée du nôtre avant le 226.
+        let _ = data_channel::finish(&connection);
+
+        // Le 226 n'est envoyé qu'une fois le fichier écrit, et sur le disque si la config le
+        // demande.
+        if let Err(err) = file.finish() {
+            drop(options);
+
+            if sniffing::is_rejected(&err) {
+                discard_upload(&system_path, start);
+            }
+
+            return self.abort_transfer(command, &path, received, started, &err);
+        }
+
+        // Le fichier est complet, un envoi coupé auparavant a été repris ou remplacé.
+        partial_upload::clear(&system_path);
+
+        // MFMT a pu être envoyé avant le fichier.
+        if let Some((_, time)) = options
+            .pending_modify
+            .take_if(|(pending, _)| *pending == path)
+        {
+            if let Err(err) = set_modified(&system_path, time) {
+                eprintln!(
+                    "{}: cannot set the modification time of {path}: {err}.",
+                    self.get_log_context()
+                );
+            }
+        }
+
+        self.record_transfer(&format!(
+            "{command} {path}: {received} bytes in {} ms",
+            started.elapsed().as_millis()
+        ));
+
+        self.timeline.borrow_mut().record_transfer(
+            command,
+            &path,
+            received,
+            started,
+            TransferOutcome::Completed,
+        );
+
+        options.transfer_statistics.record_upload(received);
+        metrics::record_received(received);
+
+        // Quand le serveur tourne en tant que root, le fichier appartiendrait à root : on le donne
+        // à l'utilisateur système associé à la session par la config. Un nom FTP n'est jamais pris
+        // pour un compte système, sans quoi 'root' ou 'daemon' recevraient des fichiers.
+        let system_user = options
+            .session
+            .as_ref()
+            .and_then(|sess| config.get_system_user(sess.get_username()));
+
+        if let Some(name) = system_user.filter(|_| config.get_chown_uploads()) {
+            if crossplatform::is_privileged() {
+                match crossplatform::get_system_user(name) {
+                    Some(user) if user.get_uid() == 0 => {
+                        eprintln!("System user {name} is root, {path} keeps its owner.")
+                    }
+                    Some(user) => {
+                        if let Err(err) = crossplatform::set_owner(&system_path, &user) {
+                            eprintln!("Cannot change owner of {path}: {err}.");
+                        }
+                    }
+                    None => eprintln!("No system user named {name}, {path} keeps its owner."),
+                }
+            }
+        }
+
+        let mut message = match config.get_upload_receipts() {
+            true => format!(
+                "closing data connection, {received} bytes received, CRC32 {:08x}",
+                reader.crc().sum()
+            ),
+            false => "closing data connection".to_string(),
+        };
+
+        if let Some(checksum) = reader.get_ref().checksum() {
+            message.push_str(&format!(", {} {checksum}", checksum::ALGORITHM));
+        }
+
+        Ok((
+            ReplyCode::ClosingDataConnection,
+            message,
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute the FTP command REIN, which logs the user out and resets the session as if the
+    /// client had just connected, without closing the control connection.
+    pub fn exec_rein_command(&mut self, _: &CommandArgs) -> CommandResult {
+        RefCell::borrow_mut(&self.options).reinitialize(CONFIG.get().unwrap().get_root());
+
+        // Un listener passif ouvert par l'utilisateur précédent ne doit pas servir au suivant.
+        *self.data_channel.borrow_mut() = None;
+        *self.data_slot.borrow_mut() = None;
+
+        self.select_virtual_host_by_address();
+        metrics::record_logout(&self.session_id);
+
+        println!("{}: session reinitialized.", self.get_log_context());
+
+        Ok((
+            ReplyCode::ServiceReady,
+            self.get_banner(),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    /// Execute the FTP command QUIT.
+    ///
+    /// The connection is closed by the caller once the reply has been sent.
+    pub fn exec_quit_command(&self, _: &CommandArgs) -> CommandResult {
+        let options = self.get_options();
+        let options = RefCell::borrow(&options);
+
+        Ok((
+            ReplyCode::ClosingControlConnection,
+            format!("Goodbye. {}.", options.transfer_statistics.summary()),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    pub fn exec_cwd_command(&mut self, args: &CommandArgs) -> CommandResult {
+        let path = match args.get_path() {
+            Some(p) => p,
+            None => {
+                return Err((
+                    ReplyCode::SyntaxErrorInArguments,
+                    "missing pathname".to_string(),
+                ))
+            }
+        };
+
+        let options = self.get_options();
+        let mut options = RefCell::borrow_mut(&options);
+
+        let path = path_resolver::resolve(&options.working_directory, path);
+        let system_path = path_resolver::to_system_path(&options.root, &path);
+
+        self.check_jail(&options.root, &path, &system_path)?;
+
+        limits::check_path(&path, &system_path, &CONFIG.get().unwrap().get_limits())
+            .map_err(|reason| (ReplyCode::FileUnavailable, reason))?;
+
+        match fs::metadata(&system_path) {
+            Ok(metadata) if !metadata.is_dir() => {
+                return Err(file_error::unavailable(&path, file_error::NOT_A_DIRECTORY))
+            }
+            Ok(_) => (),
+            Err(err) => return Err(file_error::from_io(&path, &err, "cannot access directory")),
+        }
+
+        options.working_directory = path;
+
+        Ok((
+            ReplyCode::FileActionOk,
+            "ok".to_string(),
+            false,
+            CommandReturnType::None,
+        ))
+    }
+
+    pub fn exec_cdup_command(&mut self, _: &CommandArgs) -> CommandResult {
+        self.exec_cwd_command(&CommandArgs::new(".."))
+    }
+
+    /// Expand the wildcards of `pattern` within the limit of the config.
+    fn expand(&self, root: &str, working_directory: &str, pattern: &str) -> Vec<String> {
+        let limit = CONFIG.get().unwrap().get_limits().max_expanded_paths;
+
+        let (directory, _) = path_resolver::split_pattern(working_directory, pattern);
+        let system_directory = path_resolver::to_system_path(root, &directory);
+
+        if self
+            .check_jail(root, &directory, &system_directory)
+            .is_err()
+        {
+            return Vec::new();
+        }
+
+        let (mut matches, truncated) =
+            path_resolver::expand(root, working_directory, pattern, limit);
+
+        // Une entrée peut être un lien vers l'extérieur de la racine.
+        matches.retain(|m| {
+            self.check_jail(root, m, &path_resolver::to_system_path(root, m))
+                .is_ok()
+        });
+
+        if truncated {
+            limits::record_expansion_truncated();
+
+            eprintln!(
+                "{}: {pattern} matches more than {limit} paths, the others are ignored.",
+                self.get_log_context()
+            );
+        }
+
+        matches
+    }
+
+    /// Get the root of the session and its working directory.
+    pub fn get_location(&self) -> (String, String) {
+        let options = RefCell::borrow(&self.options);
+
+        (options.root.clone(), options.working_directory.clone())
+    }
+
+    pub fn get_options(&self) -> Rc<RefCell<ClientOptions>> {
+        Rc::clone(&self.options)
+    }
 
     pub fn set_session(&mut self, session: SessionInformations) {
         let options = Rc::clone(&self.options);
@@ -396,3 +3775,211 @@ impl FtpClient {
         opt.session = Some(session);
     }
 }
+
+/// Tell if `username` is one of the names conventionally used for anonymous access.
+fn is_anonymous(username: &str) -> bool {
+    username.eq_ignore_ascii_case("anonymous") || username.eq_ignore_ascii_case("ftp")
+}
+
+/// Give the 550 reply of MKD for a name refused with 553, a code the RFC 959 only allows for the
+/// commands that name files.
+fn as_mkd_failure((code, message): (ReplyCode, String)) -> (ReplyCode, String) {
+    match code {
+        ReplyCode::FileNameNotAllowed => (ReplyCode::FileUnavailable, message),
+        _ => (code, message),
+    }
+}
+
+/// Build the 230 reply of a successful login, followed by the message of the day if there is
+/// one.
+fn logged_in_reply(message: &str) -> (ReplyCode, String, bool, CommandReturnType) {
+    match CONFIG.get().unwrap().get_motd() {
+        Some(motd) => {
+            // Chaque ligne est indentée pour qu'aucune ne soit prise pour la fin de la réponse.
+            let lines: Vec<String> = motd.lines().map(|line| format!(" {line}")).collect();
+
+            (
+                ReplyCode::UserLoggedIn,
+                format!("{message}\r\n{}", lines.join("\r\n")),
+                true,
+                CommandReturnType::None,
+            )
+        }
+        None => (
+            ReplyCode::UserLoggedIn,
+            message.to_string(),
+            false,
+            CommandReturnType::None,
+        ),
+    }
+}
+
+/// Remove what an interrupted upload wrote to `system_path`, whose first `start` bytes were
+/// already there before it.
+fn discard_upload(system_path: &Path, start: u64) {
+    // Un fichier repris ou complété garde ce qu'il contenait avant l'envoi.
+    let _ = match start {
+        0 => fs::remove_file(system_path),
+        _ => OpenOptions::new()
+            .write(true)
+            .open(system_path)
+            .and_then(|file| file.set_len(start)),
+    };
+}
+
+/// Refuse an upload of `size` bytes when the disk containing `system_path` would be almost full,
+/// so that the clients cannot take the space needed by the other services of the machine.
+fn check_free_space(system_path: &Path, size: u64) -> Result<(), (ReplyCode, String)> {
+    let required = CONFIG
+        .get()
+        .unwrap()
+        .get_min_free_space()
+        .saturating_add(size);
+
+    if required == 0 {
+        return Ok(());
+    }
+
+    // Le fichier n'existe pas forcément encore, c'est son dossier qui indique le disque.
+    let directory = match system_path.is_dir() {
+        true => system_path,
+        false => system_path.parent().unwrap_or(system_path),
+    };
+
+    match crossplatform::get_available_space(directory) {
+        Ok(available) if available < required => {
+            eprintln!(
+                "Upload to {} refused: {available} bytes available, {required} required.",
+                system_path.display()
+            );
+
+            Err((
+                ReplyCode::InsufficientStorage,
+                "insufficient storage space".to_string(),
+            ))
+        }
+        Ok(_) => Ok(()),
+        // Sans moyen de connaître l'espace libre, l'envoi est accepté comme avant.
+        Err(err) => {
+            eprintln!(
+                "Cannot get the free space of {}: {err}.",
+                directory.display()
+            );
+
+            Ok(())
+        }
+    }
+}
+
+impl Drop for FtpClient {
+    fn drop(&mut self) {
+        let timeline = self.timeline.get_mut();
+        timeline.record(Event::Disconnected {
+            last_command: self.state.get().last_command,
+        });
+
+        // La session peut se terminer par un QUIT comme par une erreur, le hook est appelé dans
+        // tous les cas.
+        if let Some(command) = CONFIG.get().unwrap().get_session_hook() {
+            timeline::deliver(command, timeline);
+        }
+    }
+}
+
+/// How a command uses a path, for the policy of the upload-only directories.
+#[derive(Clone, Copy)]
+enum DropAccess {
+    /// Read, describe, change, move or delete a file or a directory.
+    Existing,
+    /// Expose or move the whole tree under the path, as SITE GETTAR, SITE SHARE and RNFR do.
+    Tree,
+    /// Upload a file, `resume` being set by REST and APPE and `replace` by an existing file.
+    Upload { resume: bool, replace: bool },
+}
+
+/// Keep-alive enabled on the control connection during a transfer.
+struct ControlKeepAlive {
+    stream: TcpStream,
+}
+
+impl Drop for ControlKeepAlive {
+    fn drop(&mut self) {
+        // Hors transfert, un client inactif doit pouvoir être oublié comme avant.
+        let _ = crossplatform::set_keepalive(&self.stream, None);
+    }
+}
+
+fn get_metadata_threads() -> usize {
+    CONFIG.get().unwrap().get_metadata_threads()
+}
+
+/// Tell if the name and version of the server can be given to any client.
+fn show_server_tokens() -> bool {
+    CONFIG.get().unwrap().get_server_tokens() == ServerTokens::On
+}
+
+/// Name given to the server in the status replies, which hides the software unless
+/// `server_tokens` is on.
+fn get_server_name() -> String {
+    match show_server_tokens() {
+        true => format!("FTP Paradise v{VERSION}"),
+        false => "Server".to_string(),
+    }
+}
+
+/// Turn an IPv4-mapped IPv6 address into the IPv4 address it represents.
+fn canonical(address: SocketAddr) -> SocketAddr {
+    SocketAddr::new(address.ip().to_canonical(), address.port())
+}
+
+/// Tell if `err` only means that a read with a timeout did not receive anything in time.
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// Change the modification time of the file at `system_path`.
+fn set_modified(system_path: &Path, time: DateTime<Utc>) -> io::Result<()> {
+    File::options()
+        .write(true)
+        .open(system_path)?
+        .set_modified(time.into())
+}
+
+/// Copy `reader` into `writer` like `io::copy`, but give the number of bytes copied before an
+/// error, and count them in `state` as they go.
+fn copy_data(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    shutdown: &AtomicBool,
+    state: &SessionState,
+) -> Result<u64, (u64, io::Error)> {
+    let mut buffer = [0; 8192];
+    let mut copied = 0;
+
+    loop {
+        if shutdown.load(Ordering::Relaxed) {
+            return Err((copied, io::Error::other("server shutting down")));
+        }
+
+        priority::yield_to_interactive();
+
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(r) => r,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err((copied, err)),
+        };
+
+        if let Err(err) = writer.write_all(&buffer[..read]) {
+            return Err((copied, err));
+        }
+
+        copied += read as u64;
+        state.add_bytes(read as u64);
+    }
+
+    Ok(copied)
+}
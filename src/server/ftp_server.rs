@@ -1,20 +1,65 @@
 use std::{
     cell::RefCell,
     error::Error,
-    net::{TcpListener, TcpStream},
+    io::Write,
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
     thread,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    commands::CommandReturnType, server::ftp_client::FtpClient, thread_pool::ThreadPool, CONFIG,
+    commands::CommandReturnType,
+    protocol::{
+        command_args::CommandArgs, expected_replies, reply::format_reply, reply_code::ReplyCode,
+    },
+    server::{
+        brute_force, command_stats, data_channel,
+        denial::DenialReason,
+        ftp_client::FtpClient,
+        hot_folder, limits, metrics, priority,
+        rate_limit::{RateLimiter, Verdict},
+        tarpit::Tarpit,
+        transcript,
+    },
+    thread_pool::{LoadCounters, SaturationMonitor, ThreadPool},
+    CONFIG, EXTENSIONS,
 };
 
+/// Commands that can be used before being logged in.
+const PUBLIC_COMMANDS: [&str; 9] = [
+    "USER", "PASS", "HOST", "SYST", "FEAT", "OPTS", "HELP", "QUIT", "CLNT",
+];
+
+/// Commands recognized by the server, as listed by HELP.
+pub const COMMANDS: [&str; 40] = [
+    "USER", "PASS", "HOST", "REIN", "QUIT", "SYST", "FEAT", "OPTS", "HELP", "PWD", "TYPE", "MODE",
+    "PASV", "EPSV", "LIST", "NLST", "RETR", "DELE", "HASH", "MLSD", "ABOR", "STAT", "CWD", "CDUP",
+    "STOR", "APPE", "ALLO", "RANG", "REST", "SITE", "MDTM", "MFMT", "MKD", "RNFR", "RNTO", "CLNT",
+    "RMD", "MLST", "PORT", "EPRT",
+];
+
+/// Verbs of RFC 775, still sent by some old clients and embedded devices, and the commands they
+/// stand for.
+const LEGACY_COMMANDS: [(&str, &str); 5] = [
+    ("XPWD", "PWD"),
+    ("XCWD", "CWD"),
+    ("XMKD", "MKD"),
+    ("XRMD", "RMD"),
+    ("XCUP", "CDUP"),
+];
+
+/// Tell if `verb` is a command of the server, or a name it knows it by.
+pub fn is_builtin_command(verb: &str) -> bool {
+    COMMANDS.contains(&verb) || LEGACY_COMMANDS.iter().any(|&(legacy, _)| legacy == verb)
+}
+
 pub struct FtpServer {
-    listener: TcpListener,
+    /// Listeners of the control connections.
+    listeners: Vec<TcpListener>,
     shutdown: Arc<AtomicBool>,
 }
 
@@ -24,14 +69,16 @@ impl FtpServer {
     /// # Return
     /// If no error occured it will return the `FtpServer`, otherwise the error.
     pub fn build() -> Result<FtpServer, Box<dyn Error>> {
-        let listener = TcpListener::bind(format!(
-            "{}:{}",
-            CONFIG.get().unwrap().get_hostname(),
-            CONFIG.get().unwrap().get_port()
-        ))?;
+        let mut listeners = Vec::new();
+
+        for address in CONFIG.get().unwrap().get_listeners()? {
+            listeners.push(TcpListener::bind(address)?);
+        }
+
+        metrics::record_start();
 
         Ok(FtpServer {
-            listener,
+            listeners,
             shutdown: Arc::new(AtomicBool::new(false)),
         })
     }
@@ -40,45 +87,71 @@ impl FtpServer {
     pub fn start(&mut self) -> Result<(), String> {
         // On impose une limite de threads pour éviter une faille dans laquelle énormément de
         // threads sont crées pour saturer la mémoire du serveur.
-        // TODO: Définir le nombre de threads dans la config au lieu d'écrire en dur.
-        let pool = ThreadPool::build(10)?;
+        let pool = Arc::new(ThreadPool::build(CONFIG.get().unwrap().get_threads())?);
 
-        let server_shutdown = Arc::clone(&self.shutdown);
-        let server = match self.listener.try_clone() {
-            Ok(s) => s,
-            Err(err) => {
-                return Err(format!("cannot clone server listener: {err}"));
-            }
+        metrics::record_pool(pool.get_load_counters());
+        watch_saturation(pool.get_load_counters(), Arc::clone(&self.shutdown))?;
+        hot_folder::watch(Arc::clone(&self.shutdown))?;
+
+        // Fait patienter les clients pour ralentir les scanners qui ouvrent énormément de
+        // connexions, sans occuper les threads du pool.
+        let greeting_delay = CONFIG.get().unwrap().get_greeting_delay_ms();
+
+        let tarpit = match greeting_delay {
+            0 => None,
+            delay => Some(Arc::new(Tarpit::start(
+                Duration::from_millis(delay),
+                CONFIG.get().unwrap().get_max_delayed_connections(),
+            )?)),
         };
 
-        // Thread du serveur qui s'occupe d'accepter et traiter les requêtes clients.
-        let server_thread = thread::spawn(move || {
-            // Boucle qui récupère un client à chaque demande de connexion,
-            // la boucle s'arrête quand le serveur est coupé.
-            for client in server.incoming() {
-                if server_shutdown.load(Ordering::Relaxed) {
-                    return;
+        let mut server_threads = Vec::new();
+
+        // Chaque listener a son thread, les sessions de tous se partagent le même pool.
+        for listener in &self.listeners {
+            let server = match listener.try_clone() {
+                Ok(s) => s,
+                Err(err) => {
+                    return Err(format!("cannot clone server listener: {err}"));
                 }
+            };
 
-                // S'assure qu'aucune erreur n'est survenue pendant la connexion avec le client.
-                // Utiliser 'match' permet de dé-structurer le résultat.
-                let stream = match client {
-                    Ok(s) => s,
-                    Err(err) => {
-                        eprintln!("Error establishing connection: {err}.");
-                        continue;
-                    }
-                };
+            let pool = Arc::clone(&pool);
+            let tarpit = tarpit.clone();
+            let server_shutdown = Arc::clone(&self.shutdown);
 
-                pool.execute(|| {
-                    handle_connection(stream).unwrap_or_else(|err| {
-                        eprintln!("Error occured when handling connection: {err}.")
-                    })
-                });
-            }
-        });
+            server_threads.push(thread::spawn(move || {
+                accept_clients(server, pool, tarpit.as_deref(), server_shutdown)
+            }));
+        }
+
+        for server_thread in server_threads {
+            server_thread.join().unwrap();
+        }
+
+        let metrics = pool.get_metrics();
+
+        println!(
+            "{} sessions served, longest wait in queue: {} ms, longest session: {} s.",
+            metrics.jobs_finished,
+            metrics.max_wait.as_millis(),
+            metrics.max_duration.as_secs()
+        );
 
-        server_thread.join().unwrap();
+        let hits = limits::get_hits();
+
+        println!(
+            "Limits reached: {} requests too long, {} replies truncated, {} wildcards truncated, {} listings truncated, {} paths too deep.",
+            hits.requests_too_long,
+            hits.replies_truncated,
+            hits.expansions_truncated,
+            hits.listings_truncated,
+            hits.paths_too_deep
+        );
+
+        for line in command_stats::get_global().format_lines() {
+            println!("Command {line}.");
+        }
 
         Ok(())
     }
@@ -88,190 +161,537 @@ impl FtpServer {
     }
 }
 
+/// Accept the clients of `server` until the server is shut down, and handle their sessions in
+/// `pool`, after their delay in `tarpit` if any.
+fn accept_clients(
+    server: TcpListener,
+    pool: Arc<ThreadPool>,
+    tarpit: Option<&Tarpit>,
+    shutdown: Arc<AtomicBool>,
+) {
+    // Boucle qui récupère un client à chaque demande de connexion,
+    // la boucle s'arrête quand le serveur est coupé.
+    for client in server.incoming() {
+        if shutdown.load(Ordering::Relaxed) {
+            return;
+        }
+
+        // S'assure qu'aucune erreur n'est survenue pendant la connexion avec le client.
+        // Utiliser 'match' permet de dé-structurer le résultat.
+        let stream = match client {
+            Ok(s) => s,
+            Err(err) => {
+                eprintln!("Error establishing connection: {err}.");
+                continue;
+            }
+        };
+
+        if let Some(seconds) = CONFIG.get().unwrap().get_socket_linger() {
+            if let Err(err) = data_channel::set_linger(&stream, seconds) {
+                eprintln!("Cannot set SO_LINGER on a control connection: {err}.");
+            }
+        }
+
+        // Les adresses bannies sont coupées tout de suite pour ne pas occuper un thread.
+        if let Ok(peer) = stream.peer_addr() {
+            if brute_force::is_banned(peer.ip().to_canonical()) {
+                continue;
+            }
+        }
+
+        let shutdown = Arc::clone(&shutdown);
+
+        let tarpit = match tarpit {
+            Some(t) => t,
+            None => {
+                start_session(stream, &pool, shutdown);
+                continue;
+            }
+        };
+
+        let pool = Arc::clone(&pool);
+
+        // Trop de connexions en attente ressemblent à un scan, elles sont coupées sans réponse.
+        if !tarpit.hold(move || start_session(stream, &pool, shutdown)) {
+            eprintln!("Too many connections waiting for their greeting, closing a new one.");
+        }
+    }
+}
+
+/// Handle the session of the client `stream` in `pool`.
+fn start_session(stream: TcpStream, pool: &ThreadPool, shutdown: Arc<AtomicBool>) {
+    // Sans place dans la file, le client est prévenu au lieu d'attendre sans réponse.
+    if pool.is_saturated(CONFIG.get().unwrap().get_max_queued_sessions()) {
+        refuse_connection(stream);
+        return;
+    }
+
+    let peer = stream
+        .peer_addr()
+        .ok()
+        .map(|peer| SocketAddr::new(peer.ip().to_canonical(), peer.port()));
+
+    let label = match peer {
+        Some(peer) => format!("session {peer}"),
+        None => "session".to_string(),
+    };
+
+    pool.execute(&label, move || {
+        let _guard = SessionGuard { peer };
+
+        handle_connection(stream, shutdown).unwrap_or_else(|err| match peer {
+            Some(peer) => eprintln!("Error occured when handling connection of {peer}: {err}."),
+            None => eprintln!("Error occured when handling connection: {err}."),
+        })
+    });
+}
+
+/// Warn in the background when all the threads of the pool stay busy for longer than configured,
+/// before the clients start being refused.
+fn watch_saturation(counters: LoadCounters, shutdown: Arc<AtomicBool>) -> Result<(), String> {
+    let threshold = CONFIG.get().unwrap().get_saturation_warning_secs();
+
+    if threshold == 0 {
+        return Ok(());
+    }
+
+    let mut monitor = SaturationMonitor::new(Duration::from_secs(threshold));
+
+    thread::Builder::new()
+        .name("saturation-monitor".to_string())
+        .spawn(move || {
+            while !shutdown.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_secs(1));
+
+                if let Some(message) = monitor.observe(counters.get(), Instant::now()) {
+                    eprintln!("{message}");
+                }
+            }
+        })
+        .map_err(|err| format!("cannot start saturation monitor: {err}"))?;
+
+    Ok(())
+}
+
+/// Tell the client that the server is full and close the connection.
+fn refuse_connection(mut stream: TcpStream) {
+    // Le client ne doit pas pouvoir bloquer le thread qui accepte les connexions.
+    let _ = stream.set_write_timeout(Some(Duration::from_secs(1)));
+
+    let reply = format_reply(
+        ReplyCode::ServiceNotAvailable,
+        "Service not available, too many users",
+        false,
+    );
+
+    let _ = stream.write_all(reply.as_bytes());
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+/// Report the client whose session panicked, the worker only knows the panic itself.
+struct SessionGuard {
+    peer: Option<SocketAddr>,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            match self.peer {
+                Some(peer) => eprintln!("Session of {peer} crashed."),
+                None => eprintln!("Session of an unknown client crashed."),
+            }
+        }
+    }
+}
+
 /// Function called just after a client has been connected into the server.
-fn handle_connection(stream: TcpStream) -> Result<(), String> {
-    let mut ftp_client = match FtpClient::build(stream) {
+///
+/// The commands of a session are executed one at a time, in the order they were received. Those
+/// sent during a transfer, like the PASV and STOR that clients queue while uploading a directory,
+/// are kept and answered once the transfer reply has been sent. Only ABOR and STAT, when nothing
+/// was queued before them, are answered while a listing is being sent.
+fn handle_connection(stream: TcpStream, shutdown: Arc<AtomicBool>) -> Result<(), String> {
+    let mut ftp_client = match FtpClient::build(stream, shutdown) {
         Ok(client) => client,
         Err(err) => return Err(err.to_string()),
     };
 
+    let _connected = metrics::open_session(
+        ftp_client.get_session_id(),
+        ftp_client.get_peer_address(),
+        ftp_client.get_state(),
+    );
+
+    // Les applications qui embarquent le serveur branchent leur propre 'subscriber' pour suivre
+    // les sessions, sans lui rien ne se passe.
+    let session_span = tracing::info_span!(
+        "session",
+        session_id = %ftp_client.get_session_id(),
+        peer = %ftp_client.get_peer_address(),
+        user = tracing::field::Empty,
+    );
+    let _session = session_span.enter();
+
+    let peer_ip = ftp_client.get_peer_address().ip().to_string();
+
+    if CONFIG.get().unwrap().is_transcript_ip(&peer_ip) {
+        ftp_client.start_transcript();
+    }
+
+    ftp_client.select_virtual_host_by_address();
+
     // Initialise la connexion.
     // Souvent appelé 'Greetings' ou 'Welcome message'.
-    println!("Sending greetings...");
-    match ftp_client.write(b"220 ready\r\n") {
+    println!("{}: sending greetings...", ftp_client.get_log_context());
+    match ftp_client
+        .write(format_reply(ReplyCode::ServiceReady, &ftp_client.get_banner(), false).as_bytes())
+    {
         Ok(_) => (),
         Err(err) => return Err(err.to_string()),
     }
 
+    let mut rate_limiter = RateLimiter::new(CONFIG.get().unwrap().get_rate_limit(), Instant::now());
+
     // Boucle qui reçoit les requêtes de contrôles du client jusqu'à ce que la connexion soit
     // interrompu.
     loop {
         let request = match ftp_client.read_line() {
-            Ok(line) => {
-                if !line.is_empty() {
-                    line
-                } else {
-                    return Err("EOF reached".to_string());
-                }
+            Ok(Some(line)) => line,
+            Ok(None) => {
+                ftp_client.log_session_end();
+
+                return Err("EOF reached".to_string());
             }
+            // L'arrêt du serveur est vérifié entre deux commandes, même si la lecture a réussi.
+            Err(_) if ftp_client.is_shutting_down() => return end_on_shutdown(&mut ftp_client),
             Err(err) => {
                 return Err(format!("cannot read client request: {err}"));
             }
         };
 
-        println!("Request: {request}");
+        if ftp_client.is_shutting_down() {
+            return end_on_shutdown(&mut ftp_client);
+        }
+
+        // Toutes les lignes comptent, même vides, sinon elles permettraient d'inonder le serveur.
+        match rate_limiter.check(Instant::now()) {
+            Verdict::Allow => (),
+            Verdict::Delay(delay) => thread::sleep(delay),
+            Verdict::Disconnect => return end_on_abuse(&mut ftp_client),
+        }
+
+        // Certains clients envoient des lignes vides pour garder la connexion ouverte.
+        if request.is_empty() {
+            if !CONFIG.get().unwrap().get_ignore_empty_lines() {
+                let reply = format_reply(ReplyCode::SyntaxError, "empty command", false);
+                let _ = ftp_client.write(reply.as_bytes());
+            }
 
-        let args = request.split(' ');
-        let args: Vec<String> = args.map(|arg| arg.to_string()).collect();
+            continue;
+        }
 
-        let mut it_args = args.into_iter();
+        println!(
+            "{}: Request: {}",
+            ftp_client.get_log_context(),
+            transcript::mask_password(&request)
+        );
 
-        // Le protocole indique que la requête est insensible à la casse.
-        // Donc pour simplifier le traitement, met la valeur en majuscule.
-        let command = it_args.next().unwrap().to_uppercase();
+        let (command, args) = CommandArgs::parse_request(&request);
 
-        let (code, message);
-        let mut multilines = false;
+        // Les alias du profil de compatibilité du client, et ceux ajoutés par le programme qui
+        // embarque le serveur, sont remplacés par leur commande.
+        let command = ftp_client.resolve_alias(command);
+        let command = match EXTENSIONS.get() {
+            Some(extensions) => extensions.resolve_alias(command),
+            None => command,
+        };
 
-        match &command[..] {
-            "USER" => match ftp_client.exec_user_command(Box::new(it_args)) {
-                Ok((c, m, l, _)) => {
-                    (code, message) = (c, m);
-                    multilines = l;
+        let command = match LEGACY_COMMANDS
+            .iter()
+            .find(|&&(legacy, _)| legacy == command)
+        {
+            Some((_, modern)) => modern.to_string(),
+            None => command,
+        };
 
-                    let options = ftp_client.get_options();
-                    let opt = RefCell::borrow(&options);
+        let command_span =
+            tracing::info_span!("command", verb = %command, code = tracing::field::Empty);
+        let _command = command_span.enter();
 
-                    match &opt.session {
-                        Some(sess) => {
-                            println!("Session changed: {:?}", sess);
-                        }
-                        None => (),
-                    }
-                }
-                Err((c, m)) => {
-                    (code, message) = (c, m);
-                }
-            },
-            /*
-            "PASS" => match ftp_client.exec_pass_command(Box::new(it_args)) {
-                Ok((c, m, l, _)) => {
-                    (code, message) = (c, m);
-                    multilines = l;
-                }
-                Err((c, m)) => {
-                    (code, message) = (c, m);
+        RefCell::borrow_mut(&ftp_client.get_options()).before_command(&command);
 
-                    success = false;
-                }
-            },
-            */
-            "SYST" => match ftp_client.exec_syst_command(Box::new(it_args)) {
-                Ok((c, m, l, _)) => {
-                    (code, message) = (c, m);
-                    multilines = l;
-                }
-                Err((c, m)) => {
-                    (code, message) = (c, m);
-                }
-            },
-            "FEAT" => match ftp_client.exec_feat_command(Box::new(it_args)) {
-                Ok((c, m, l, _)) => {
-                    (code, message) = (c, m);
-                    multilines = l;
-                }
-                Err((c, m)) => {
-                    (code, message) = (c, m);
-                }
-            },
-            "OPTS" => match ftp_client.exec_opts_command(Box::new(it_args)) {
-                Ok((c, m, l, _)) => {
-                    (code, message) = (c, m);
-                    multilines = l;
-                }
-                Err((c, m)) => {
-                    (code, message) = (c, m);
-                }
+        // Les transferts en cours des autres sessions laissent passer cette commande.
+        let _priority = priority::enter(&command);
+
+        let result = match &command[..] {
+            // Les commandes désactivées par la config se comportent comme si elles n'existaient pas.
+            _ if CONFIG.get().unwrap().is_command_disabled(&command) => Err((
+                ReplyCode::CommandNotImplemented,
+                format!("{command} is disabled on this server"),
+            )),
+            // Tant que le client ne s'est pas identifié, seules quelques commandes sont permises.
+            _ if !ftp_client.is_logged_in() && !PUBLIC_COMMANDS.contains(&&command[..]) => {
+                Err(ftp_client.deny(
+                    ReplyCode::NotLoggedIn,
+                    "please login with USER and PASS".to_string(),
+                    DenialReason::NotLoggedIn,
+                    &command,
+                ))
+            }
+            _ if ftp_client.faults.inject_error() => Err((
+                ReplyCode::LocalError,
+                "local error in processing".to_string(),
+            )),
+            "USER" => ftp_client.exec_user_command(&args),
+            "PASS" => ftp_client.exec_pass_command(&args),
+            "HOST" => ftp_client.exec_host_command(&args),
+            "CLNT" => ftp_client.exec_clnt_command(&args),
+            "REIN" => ftp_client.exec_rein_command(&args),
+            "QUIT" => ftp_client.exec_quit_command(&args),
+            "SYST" => ftp_client.exec_syst_command(&args),
+            "FEAT" => ftp_client.exec_feat_command(&args),
+            "OPTS" => ftp_client.exec_opts_command(&args),
+            "SITE" => ftp_client.exec_site_command(&args),
+            "HELP" => ftp_client.exec_help_command(&args),
+            "PWD" => ftp_client.exec_pwd_command(&args),
+            "TYPE" => ftp_client.exec_type_command(&args),
+            "MODE" => ftp_client.exec_mode_command(&args),
+            "PASV" => ftp_client.exec_pasv_command(&args),
+            "PORT" => ftp_client.exec_port_command(&args),
+            "EPRT" => ftp_client.exec_eprt_command(&args),
+            "EPSV" => ftp_client.exec_epsv_command(&args),
+            "LIST" => ftp_client.exec_list_command(&args),
+            "NLST" => ftp_client.exec_nlst_command(&args),
+            "RETR" => ftp_client.exec_retr_command(&args),
+            "DELE" => ftp_client.exec_dele_command(&args),
+            "HASH" => ftp_client.exec_hash_command(&args),
+            "MLSD" => ftp_client.exec_mlsd_command(&args),
+            "MLST" => ftp_client.exec_mlst_command(&args),
+            "ABOR" => ftp_client.exec_abor_command(&args),
+            "STAT" => ftp_client.exec_stat_command(&args),
+            "CWD" => ftp_client.exec_cwd_command(&args),
+            "CDUP" => ftp_client.exec_cdup_command(&args),
+            "STOR" => ftp_client.exec_stor_command(&args),
+            "APPE" => ftp_client.exec_appe_command(&args),
+            "ALLO" => ftp_client.exec_allo_command(&args),
+            "RANG" => ftp_client.exec_rang_command(&args),
+            "REST" => ftp_client.exec_rest_command(&args),
+            "MDTM" => ftp_client.exec_mdtm_command(&args),
+            "MFMT" => ftp_client.exec_mfmt_command(&args),
+            "MKD" => ftp_client.exec_mkd_command(&args),
+            "RMD" => ftp_client.exec_rmd_command(&args),
+            "RNFR" => ftp_client.exec_rnfr_command(&args),
+            "RNTO" => ftp_client.exec_rnto_command(&args),
+            _ => match EXTENSIONS.get().and_then(|e| e.get_command(&command)) {
+                Some(handler) => ftp_client.exec_extension(handler, &args),
+                None => Err((
+                    ReplyCode::CommandNotImplemented,
+                    "no implementation".to_string(),
+                )),
             },
-            "PWD" => match ftp_client.exec_pwd_command(Box::new(it_args)) {
-                Ok((c, m, l, _)) => {
-                    (code, message) = (c, m);
-                    multilines = l;
-                }
-                Err((c, m)) => {
-                    (code, message) = (c, m);
+        };
+
+        let (code, message, multilines) = match result {
+            Ok((c, m, l, returned)) => {
+                // Seuls PASV et EPSV renvoient un listener, qui servira à la prochaine connexion de
+                // données.
+                if let CommandReturnType::TcpListener(ls) = returned {
+                    ftp_client.set_passive_listener(ls);
                 }
-            },
-            "TYPE" => match ftp_client.exec_type_command(Box::new(it_args)) {
-                Ok((c, m, l, _)) => {
-                    (code, message) = (c, m);
-                    multilines = l;
 
-                    let options = ftp_client.get_options();
-                    let opt = RefCell::borrow(&options);
+                let (c, m) = checked_success(&ftp_client, c, m);
 
-                    println!("Data type changed: {:?}", opt.data_representation);
-                }
-                Err((c, m)) => {
-                    (code, message) = (c, m);
-                }
-            },
-            "PASV" => match ftp_client.exec_pasv_command(Box::new(it_args)) {
-                Ok((c, m, l, listener)) => {
-                    (code, message) = (c, m);
-                    multilines = l;
-
-                    // Normalement il n'est pas censé avoir une autre variant de cette énum.
-                    if let CommandReturnType::TcpListener(ls) = listener {
-                        let mut data_listener = RefCell::borrow_mut(&ftp_client.data_listener);
-                        *data_listener = Some(ls);
+                (c, m, l)
+            }
+            Err((c, m)) => {
+                let (c, m) = checked_failure(&ftp_client, c, m);
+
+                (c, m, false)
+            }
+        };
+
+        if !expected_replies::is_expected(&command, code.get_code()) {
+            eprintln!(
+                "{}: {command} answered with {code}, which the RFCs do not expect.",
+                ftp_client.get_log_context()
+            );
+        }
+
+        // Les verbes inconnus sont regroupés, sinon un client pourrait faire grossir les compteurs
+        // sans fin.
+        let known = COMMANDS.contains(&&command[..])
+            || EXTENSIONS
+                .get()
+                .is_some_and(|e| e.get_command(&command).is_some());
+
+        let verb = match known {
+            true => &command[..],
+            false => "UNKNOWN",
+        };
+
+        ftp_client.record_command(verb, code.is_positive());
+
+        let quit = command == "QUIT" && code.is_positive();
+
+        if code.is_positive() {
+            let options = ftp_client.get_options();
+            let opt = RefCell::borrow(&options);
+
+            match &command[..] {
+                "USER" => {
+                    if let Some(sess) = &opt.session {
+                        println!(
+                            "{}: session changed: {:?}",
+                            ftp_client.get_log_context(),
+                            sess
+                        );
                     }
                 }
-                Err((c, m)) => {
-                    (code, message) = (c, m);
-                }
-            },
-            "LIST" => match ftp_client.exec_list_command(Box::new(it_args)) {
-                Ok((c, m, l, _)) => {
-                    (code, message) = (c, m);
-                    multilines = l;
-                }
-                Err((c, m)) => {
-                    (code, message) = (c, m);
-                }
-            },
-            "CWD" => match ftp_client.exec_cwd_command(Box::new(it_args)) {
-                Ok((c, m, l, _)) => {
-                    (code, message) = (c, m);
-                    multilines = l;
-                }
-                Err((c, m)) => {
-                    (code, message) = (c, m);
-                }
-            },
-            "CDUP" => match ftp_client.exec_cdup_command(Box::new(it_args)) {
-                Ok((c, m, l, _)) => {
-                    (code, message) = (c, m);
-                    multilines = l;
-                }
-                Err((c, m)) => {
-                    (code, message) = (c, m);
+                "TYPE" => println!(
+                    "{}: data type changed: {:?}",
+                    ftp_client.get_log_context(),
+                    opt.data_representation
+                ),
+                _ => (),
+            }
+
+            // Le compte n'est connu du span qu'une fois l'identification réussie.
+            if let ("USER" | "PASS", Some(sess)) = (&command[..], &opt.session) {
+                if sess.is_authenticated() {
+                    session_span.record("user", sess.get_username());
+                    metrics::record_login(ftp_client.get_session_id(), sess.get_username());
                 }
-            },
-            _ => {
-                (code, message) = (502, "no implementation".to_string());
             }
         }
 
-        let reply = match multilines {
-            true => format!("{code}-{message}\r\n{code} End\r\n"),
-            false => format!("{code} {message}\r\n"),
-        };
+        // Certaines réponses, comme STAT sur un dossier, grossissent avec le contenu du disque.
+        let message = limits::cap_reply(message, CONFIG.get().unwrap().get_limits().max_reply_size);
+
+        command_span.record("code", code.get_code());
+        tracing::debug!("{command} answered with {code}");
+
+        let reply = format_reply(code, &message, multilines);
+
+        ftp_client.faults.delay_reply();
 
         // Envoie la réponse de contrôle finale au client.
         match ftp_client.write(reply.as_bytes()) {
             Ok(_) => (),
-            Err(err) => eprintln!("Error when sending reply: {err}."),
+            Err(err) => eprintln!(
+                "{}: error when sending reply: {err}.",
+                ftp_client.get_log_context()
+            ),
         }
+
+        if quit {
+            ftp_client.log_session_end();
+
+            return Ok(());
+        }
+
+        if command == "PASS" && ftp_client.has_too_many_failed_logins() {
+            return end_on_login_failures(&mut ftp_client);
+        }
+
+        if ftp_client.is_shutting_down() {
+            return end_on_shutdown(&mut ftp_client);
+        }
+    }
+}
+
+/// Tell the client that the server is stopping and end the session.
+fn end_on_shutdown(ftp_client: &mut FtpClient) -> Result<(), String> {
+    let reply = format_reply(
+        ReplyCode::ServiceNotAvailable,
+        "Service shutting down, closing control connection",
+        false,
+    );
+
+    let _ = ftp_client.write(reply.as_bytes());
+
+    ftp_client.log_session_end();
+
+    Ok(())
+}
+
+/// Close the session of a client that kept sending commands faster than the rate limit.
+fn end_on_abuse(ftp_client: &mut FtpClient) -> Result<(), String> {
+    println!(
+        "{}: too many commands, closing the session.",
+        ftp_client.get_log_context()
+    );
+
+    let reply = format_reply(
+        ReplyCode::ServiceNotAvailable,
+        "Too many commands, closing control connection",
+        false,
+    );
+
+    let _ = ftp_client.write(reply.as_bytes());
+
+    ftp_client.log_session_end();
+
+    Ok(())
+}
+
+/// Close the session of a client that sent too many wrong passwords.
+fn end_on_login_failures(ftp_client: &mut FtpClient) -> Result<(), String> {
+    println!(
+        "{}: too many failed logins, closing the session.",
+        ftp_client.get_log_context()
+    );
+
+    let reply = format_reply(
+        ReplyCode::ServiceNotAvailable,
+        "Too many failed logins, closing control connection",
+        false,
+    );
+
+    let _ = ftp_client.write(reply.as_bytes());
+
+    ftp_client.log_session_end();
+
+    Ok(())
+}
+
+/// Make sure a handler returned a positive reply for a command that succeeded.
+fn checked_success(
+    ftp_client: &FtpClient,
+    code: ReplyCode,
+    message: String,
+) -> (ReplyCode, String) {
+    if code.is_negative() {
+        eprintln!(
+            "{}: handler succeeded with the negative reply {code}: {message}.",
+            ftp_client.get_log_context()
+        );
+
+        return (
+            ReplyCode::LocalError,
+            "local error in processing".to_string(),
+        );
     }
+
+    (code, message)
+}
+
+/// Make sure a handler returned a negative reply for a command that failed.
+fn checked_failure(
+    ftp_client: &FtpClient,
+    code: ReplyCode,
+    message: String,
+) -> (ReplyCode, String) {
+    if code.is_positive() {
+        eprintln!(
+            "{}: handler failed with the positive reply {code}: {message}.",
+            ftp_client.get_log_context()
+        );
+
+        return (
+            ReplyCode::LocalError,
+            "local error in processing".to_string(),
+        );
+    }
+
+    (code, message)
 }
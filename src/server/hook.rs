@@ -0,0 +1,46 @@
+//! External commands run when something happens on the server, receiving a JSON record on their
+//! standard input.
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    thread,
+};
+
+/// Run `command` with `record` on its standard input, without waiting for it. `kind` names the
+/// hook in the logs.
+pub fn run(kind: &str, command: &str, record: String) {
+    let mut child = match Command::new(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+    {
+        Ok(c) => c,
+        Err(err) => {
+            eprintln!("Cannot run {kind} {command}: {err}.");
+            return;
+        }
+    };
+
+    let kind = kind.to_string();
+    let command = command.to_string();
+
+    // Le hook peut être lent, il ne doit pas retenir le thread qui l'a déclenché.
+    let _ = thread::Builder::new()
+        .name("hook".to_string())
+        .spawn(move || {
+            if let Some(mut stdin) = child.stdin.take() {
+                let _ = stdin.write_all(record.as_bytes());
+                let _ = stdin.write_all(b"\n");
+            }
+
+            // Attendre le processus évite qu'il reste à l'état de zombie.
+            match child.wait() {
+                Ok(status) if !status.success() => {
+                    eprintln!("{kind} {command} failed: {status}.")
+                }
+                Ok(_) => (),
+                Err(err) => eprintln!("Cannot wait for {kind} {command}: {err}."),
+            }
+        });
+}
@@ -0,0 +1,333 @@
+//! Hot folders: the files appearing in some directories are handed to a hook once complete,
+//! whether they were uploaded by FTP or copied on the server.
+//!
+//! A file is complete once it stopped changing for a while and no upload is writing it. An upload
+//! interrupted by a shutdown of the server is left aside until it is resumed.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+#[cfg(target_os = "linux")]
+use std::{
+    ffi::{CString, OsStr},
+    io, mem,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+#[cfg(not(target_os = "linux"))]
+use std::sync::{atomic::AtomicBool, Arc};
+
+use serde::Serialize;
+
+use crate::{
+    server::{hook, partial_upload, path_resolver},
+    CONFIG,
+};
+
+/// Files being written by an upload, which are not complete whatever the time since their last
+/// change.
+static UPLOADING: LazyLock<Mutex<HashSet<PathBuf>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// Upload in progress until dropped.
+pub struct UploadGuard {
+    path: PathBuf,
+}
+
+impl Drop for UploadGuard {
+    fn drop(&mut self) {
+        lock_uploading().remove(&self.path);
+    }
+}
+
+/// Record that an upload writes `system_path` until the returned guard is dropped.
+pub fn start_upload(system_path: &Path) -> UploadGuard {
+    let path = canonical(system_path);
+
+    lock_uploading().insert(path.clone());
+
+    UploadGuard { path }
+}
+
+fn is_uploading(path: &Path) -> bool {
+    lock_uploading().contains(path)
+}
+
+fn lock_uploading() -> MutexGuard<'static, HashSet<PathBuf>> {
+    UPLOADING.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Path with its directory resolved like the watched ones, so that both can be compared.
+fn canonical(path: &Path) -> PathBuf {
+    match (path.parent().map(fs::canonicalize), path.file_name()) {
+        (Some(Ok(parent)), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Files that changed recently, waiting for their changes to end.
+#[derive(Debug, Default)]
+struct Pending {
+    changed: HashMap<PathBuf, Instant>,
+}
+
+impl Pending {
+    fn record(&mut self, path: PathBuf, now: Instant) {
+        self.changed.insert(path, now);
+    }
+
+    /// Remove and give the files unchanged for `settle`, except the ones still `busy`.
+    fn take_settled(
+        &mut self,
+        now: Instant,
+        settle: Duration,
+        busy: impl Fn(&Path) -> bool,
+    ) -> Vec<PathBuf> {
+        let settled: Vec<PathBuf> = self
+            .changed
+            .iter()
+            .filter(|(path, &changed)| now.duration_since(changed) >= settle && !busy(path))
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        for path in &settled {
+            self.changed.remove(path);
+        }
+
+        settled
+    }
+}
+
+/// Record sent to the hook for each complete file.
+#[derive(Debug, Serialize)]
+struct FileReady<'a> {
+    event: &'static str,
+    path: &'a str,
+    size: u64,
+}
+
+/// Watch the hot folders of the config in the background until the server is shut down.
+#[cfg(target_os = "linux")]
+pub fn watch(shutdown: Arc<AtomicBool>) -> Result<(), String> {
+    let settings = CONFIG.get().unwrap().get_hot_folders();
+
+    let command = match &settings.hook {
+        Some(c) if !settings.directories.is_empty() => c.clone(),
+        _ => return Ok(()),
+    };
+
+    let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+
+    if fd < 0 {
+        return Err(format!(
+            "cannot watch hot folders: {}",
+            io::Error::last_os_error()
+        ));
+    }
+
+    let inotify = unsafe { OwnedFd::from_raw_fd(fd) };
+    let mut directories = HashMap::new();
+
+    for directory in &settings.directories {
+        let path = match fs::canonicalize(directory) {
+            Ok(p) => p,
+            Err(err) => return Err(format!("cannot watch hot folder {directory}: {err}")),
+        };
+
+        let c_path = match CString::new(path.as_os_str().as_bytes()) {
+            Ok(p) => p,
+            Err(_) => return Err(format!("cannot watch hot folder {directory}: invalid path")),
+        };
+
+        // Une copie locale déclenche IN_CLOSE_WRITE, un déplacement dans le dossier IN_MOVED_TO.
+        let wd = unsafe {
+            libc::inotify_add_watch(
+                inotify.as_raw_fd(),
+                c_path.as_ptr(),
+                libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_MODIFY,
+            )
+        };
+
+        if wd < 0 {
+            return Err(format!(
+                "cannot watch hot folder {directory}: {}",
+                io::Error::last_os_error()
+            ));
+        }
+
+        directories.insert(wd, path);
+    }
+
+    let settle = Duration::from_millis(settings.settle_ms);
+
+    thread::Builder::new()
+        .name("hot-folders".to_string())
+        .spawn(move || {
+            let mut pending = Pending::default();
+
+            while !shutdown.load(Ordering::Relaxed) {
+                let mut poll = libc::pollfd {
+                    fd: inotify.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+
+                // L'attente est bornée pour livrer les fichiers devenus stables sans autre
+                // événement, et pour remarquer l'arrêt du serveur.
+                if unsafe { libc::poll(&mut poll, 1, 200) } > 0 {
+                    for path in read_events(&inotify, &directories) {
+                        pending.record(path, Instant::now());
+                    }
+                }
+
+                for path in pending.take_settled(Instant::now(), settle, is_uploading) {
+                    deliver(&command, &path);
+                }
+            }
+        })
+        .map_err(|err| format!("cannot start hot folders watcher: {err}"))?;
+
+    Ok(())
+}
+
+/// Files of the watched `directories` named by the events waiting on `inotify`.
+#[cfg(target_os = "linux")]
+fn read_events(inotify: &OwnedFd, directories: &HashMap<i32, PathBuf>) -> Vec<PathBuf> {
+    let mut buffer = [0u8; 4096];
+    let mut paths = Vec::new();
+
+    loop {
+        let read = unsafe {
+            libc::read(
+                inotify.as_raw_fd(),
+                buffer.as_mut_ptr().cast(),
+                buffer.len(),
+            )
+        };
+
+        // Le descripteur est non bloquant : tout a été lu quand il n'y a plus rien à lire.
+        if read <= 0 {
+            return paths;
+        }
+
+        let mut offset = 0;
+
+        while offset + mem::size_of::<libc::inotify_event>() <= read as usize {
+            // Les événements se suivent sans être alignés dans le buffer.
+            let event: libc::inotify_event =
+                unsafe { ptr::read_unaligned(buffer[offset..].as_ptr().cast()) };
+
+            let name_start = offset + mem::size_of::<libc::inotify_event>();
+            let name_end = name_start + event.len as usize;
+            offset = name_end;
+
+            if event.mask & libc::IN_ISDIR != 0 || name_end > read as usize {
+                continue;
+            }
+
+            // Le nom est complété par des zéros jusqu'à la taille annoncée.
+            let name = &buffer[name_start..name_end];
+            let name = match name.iter().position(|&b| b == 0) {
+                Some(end) => &name[..end],
+                None => name,
+            };
+
+            if let Some(directory) = directories.get(&event.wd) {
+                paths.push(directory.join(OsStr::from_bytes(name)));
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn watch(_shutdown: Arc<AtomicBool>) -> Result<(), String> {
+    if !CONFIG
+        .get()
+        .unwrap()
+        .get_hot_folders()
+        .directories
+        .is_empty()
+    {
+        eprintln!("Hot folders are only watched on Linux, they are ignored.");
+    }
+
+    Ok(())
+}
+
+/// Hand the complete file at `path` to the hook `command`.
+fn deliver(command: &str, path: &Path) {
+    // Le fichier a pu être supprimé ou renommé depuis son dernier changement.
+    let metadata = match fs::metadata(path) {
+        Ok(m) if m.is_file() => m,
+        _ => return,
+    };
+
+    let sidecar = path
+        .file_name()
+        .is_some_and(|name| path_resolver::is_protected(&name.to_string_lossy()));
+
+    // Un upload interrompu n'est livré qu'une fois repris et terminé.
+    if sidecar || partial_upload::get(path).is_some() {
+        return;
+    }
+
+    let path = path.to_string_lossy();
+
+    println!("Hot folder file ready: {path}.");
+
+    let record = FileReady {
+        event: "file_ready",
+        path: &path,
+        size: metadata.len(),
+    };
+
+    hook::run(
+        "hot folder hook",
+        command,
+        serde_json::to_string(&record).unwrap_or_default(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_settle_once_unchanged_and_not_uploading() {
+        let mut pending = Pending::default();
+        let start = Instant::now();
+        let settle = Duration::from_secs(2);
+
+        pending.record(PathBuf::from("/drop/a.txt"), start);
+        pending.record(PathBuf::from("/drop/b.txt"), start);
+        pending.record(PathBuf::from("/drop/a.txt"), start + Duration::from_secs(1));
+
+        let busy = |path: &Path| path == Path::new("/drop/b.txt");
+
+        assert!(pending.take_settled(start, settle, busy).is_empty());
+        assert!(pending
+            .take_settled(start + settle, settle, busy)
+            .is_empty());
+        assert_eq!(
+            pending.take_settled(start + Duration::from_secs(3), settle, busy),
+            vec![PathBuf::from("/drop/a.txt")]
+        );
+        assert_eq!(
+            pending.take_settled(start + Duration::from_secs(3), settle, |_| false),
+            vec![PathBuf::from("/drop/b.txt")]
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! Counters of the times a session reached one of the memory limits of the config, shared by all
+//! the sessions.
+
+use std::{
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::config::SessionLimits;
+
+static REQUESTS_TOO_LONG: AtomicU64 = AtomicU64::new(0);
+static REPLIES_TRUNCATED: AtomicU64 = AtomicU64::new(0);
+static EXPANSIONS_TRUNCATED: AtomicU64 = AtomicU64::new(0);
+static LISTINGS_TRUNCATED: AtomicU64 = AtomicU64::new(0);
+static PATHS_TOO_DEEP: AtomicU64 = AtomicU64::new(0);
+
+/// Number of times each limit has been reached since the server started.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LimitHits {
+    pub requests_too_long: u64,
+    pub replies_truncated: u64,
+    pub expansions_truncated: u64,
+    pub listings_truncated: u64,
+    pub paths_too_deep: u64,
+}
+
+pub fn record_request_too_long() {
+    REQUESTS_TOO_LONG.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_reply_truncated() {
+    REPLIES_TRUNCATED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_expansion_truncated() {
+    EXPANSIONS_TRUNCATED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_listing_truncated() {
+    LISTINGS_TRUNCATED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn get_hits() -> LimitHits {
+    LimitHits {
+        requests_too_long: REQUESTS_TOO_LONG.load(Ordering::Relaxed),
+        replies_truncated: REPLIES_TRUNCATED.load(Ordering::Relaxed),
+        expansions_truncated: EXPANSIONS_TRUNCATED.load(Ordering::Relaxed),
+        listings_truncated: LISTINGS_TRUNCATED.load(Ordering::Relaxed),
+        paths_too_deep: PATHS_TOO_DEEP.load(Ordering::Relaxed),
+    }
+}
+
+/// Check that `virtual_path`, stored at `system_path`, is neither too deep nor too long for
+/// `limits`.
+///
+/// # Return
+/// The reason of the refusal if the path goes beyond a limit.
+pub fn check_path(
+    virtual_path: &str,
+    system_path: &Path,
+    limits: &SessionLimits,
+) -> Result<(), String> {
+    let depth = virtual_path.split('/').filter(|c| !c.is_empty()).count();
+    let length = system_path.as_os_str().len();
+
+    let reason = if limits.max_path_depth != 0 && depth > limits.max_path_depth {
+        format!("{virtual_path}: more than {} levels", limits.max_path_depth)
+    } else if limits.max_path_length != 0 && length > limits.max_path_length {
+        format!(
+            "{virtual_path}: path longer than {} bytes",
+            limits.max_path_length
+        )
+    } else {
+        return Ok(());
+    };
+
+    PATHS_TOO_DEEP.fetch_add(1, Ordering::Relaxed);
+
+    Err(reason)
+}
+
+/// Cut `message` after its last complete line fitting in `max_size` bytes, and tell the client
+/// that the reply has been truncated.
+pub fn cap_reply(message: String, max_size: usize) -> String {
+    if message.len() <= max_size {
+        return message;
+    }
+
+    record_reply_truncated();
+
+    let mut end = max_size;
+
+    // Coupe sur une limite de caractère UTF-8 puis, si possible, à la fin d'une ligne.
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    if let Some(line_end) = message[..end].rfind("\r\n") {
+        end = line_end;
+    }
+
+    format!("{}\r\n (reply truncated)", &message[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_replies_are_kept() {
+        assert_eq!(cap_reply("ok".to_string(), 2), "ok");
+    }
+
+    #[test]
+    fn long_replies_are_cut_on_a_line() {
+        assert_eq!(
+            cap_reply("Status:\r\n aaaa\r\n bbbb".to_string(), 18),
+            "Status:\r\n aaaa\r\n (reply truncated)"
+        );
+    }
+
+    #[test]
+    fn deep_and_long_paths_are_refused() {
+        let limits = SessionLimits {
+            max_path_depth: 2,
+            max_path_length: 16,
+            ..SessionLimits::default()
+        };
+
+        assert!(check_path("/a/b", Path::new("/srv/a/b"), &limits).is_ok());
+        assert_eq!(
+            check_path("/a/b/c", Path::new("/srv/a/b/c"), &limits),
+            Err("/a/b/c: more than 2 levels".to_string())
+        );
+        assert!(check_path("/abcdefghijkl", Path::new("/srv/abcdefghijkl"), &limits).is_err());
+
+        let unlimited = SessionLimits {
+            max_path_depth: 0,
+            max_path_length: 0,
+            ..limits
+        };
+
+        assert!(check_path("/a/b/c", Path::new("/srv/a/b/c"), &unlimited).is_ok());
+    }
+
+    #[test]
+    fn cut_respects_characters() {
+        assert_eq!(cap_reply("ééé".to_string(), 3), "é\r\n (reply truncated)");
+    }
+}
@@ -0,0 +1,606 @@
+use std::{
+    fmt,
+    fs::{self, DirEntry},
+    io, iter,
+    path::Path,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use chrono::{DateTime, Duration, Local, TimeZone, Utc};
+
+use crate::{
+    config::DirectoryIndex,
+    platform::{file_info::Details, FileInfo},
+    protocol::facts::{self, Fact, Facts},
+    server::path_resolver,
+    CONFIG,
+};
+
+/// Lines of a listing, produced one by one as they are sent.
+pub type Lines = Box<dyn Iterator<Item = String> + Send>;
+
+type Entries = Box<dyn Iterator<Item = DirEntry> + Send>;
+
+/// Entries of a directory whose metadata are read together, shared between the threads.
+const METADATA_BATCH_SIZE: usize = 128;
+
+/// Values of the `ls -l` lines that depend on the machine, replaced by fixed ones so that tests
+/// can compare the listings with golden outputs.
+///
+/// The entries are then sorted by name and the dates shown in UTC.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixedListing {
+    pub owner: String,
+    pub group: String,
+    /// Time the dates are compared with, instead of the current one.
+    pub now: DateTime<Utc>,
+}
+
+/// Size of the directories in the fixed listings, as it depends on the filesystem.
+const FIXED_DIRECTORY_SIZE: u64 = 4096;
+
+fn get_fixed_listing() -> Option<FixedListing> {
+    CONFIG.get().and_then(|config| config.get_fixed_listing())
+}
+
+/// Describe `system_path` the way `ls -l` does: one line per entry if it is a directory, or a
+/// single line for itself if it is a file.
+///
+/// The lines are produced while iterating, so that huge directories are not held in memory, with
+/// the metadata of the entries read by up to `threads` threads.
+pub fn list(system_path: &Path, threads: usize) -> io::Result<Lines> {
+    list_with(system_path, threads, get_fixed_listing())
+}
+
+fn list_with(system_path: &Path, threads: usize, fixed: Option<FixedListing>) -> io::Result<Lines> {
+    if !fs::metadata(system_path)?.is_dir() {
+        return Ok(Box::new(iter::once(describe_with(
+            system_path,
+            fixed.as_ref(),
+        )?)));
+    }
+
+    // Les propriétaires fixés n'ont pas besoin d'être cherchés.
+    let details = Details {
+        owner: fixed.is_none(),
+        unique: false,
+    };
+
+    Ok(describe_entries(
+        read_entries(system_path, fixed.is_some())?,
+        threads,
+        details,
+        move |name, info| format_entry(name, info, fixed.as_ref()),
+    ))
+}
+
+/// Describe `system_path` itself in a single `ls -l` line, even if it is a directory.
+pub fn describe(system_path: &Path) -> io::Result<String> {
+    describe_with(system_path, get_fixed_listing().as_ref())
+}
+
+fn describe_with(system_path: &Path, fixed: Option<&FixedListing>) -> io::Result<String> {
+    let info = FileInfo::read(
+        system_path,
+        Details {
+            owner: fixed.is_none(),
+            unique: false,
+        },
+    )?;
+
+    let name = match system_path.file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None => ".".to_string(),
+    };
+
+    if !path_resolver::is_safe_name(&name) {
+        return Err(io::Error::from(io::ErrorKind::InvalidData));
+    }
+
+    Ok(format_entry(&name, &info, fixed))
+}
+
+/// Entries of the directory `system_path`, sorted by name if `sorted`, otherwise in the order of
+/// the directory so that they are not all held in memory.
+fn read_entries(system_path: &Path, sorted: bool) -> io::Result<Entries> {
+    let entries = fs::read_dir(system_path)?.flatten().filter(is_listed);
+
+    if !sorted {
+        return Ok(Box::new(entries));
+    }
+
+    let mut entries: Vec<DirEntry> = entries.collect();
+    entries.sort_by_key(|entry| entry.file_name());
+
+    Ok(Box::new(entries.into_iter()))
+}
+
+/// Tell if `entry` is shown to the clients, the files managed by the server being hidden.
+fn is_listed(entry: &DirEntry) -> bool {
+    !path_resolver::is_protected(&entry.file_name().to_string_lossy())
+}
+
+/// Produce `lines` in a thread of their own, with at most `capacity` of them waiting to be read.
+///
+/// On a network filesystem, reading the metadata of a single entry can block for seconds. The
+/// session then keeps reading the control connection while it waits for the next line. The thread
+/// stops once the receiver is dropped.
+pub fn in_background(lines: Lines, capacity: usize) -> io::Result<Receiver<String>> {
+    let (sender, receiver) = mpsc::sync_channel(capacity);
+
+    thread::Builder::new()
+        .name("listing".to_string())
+        .spawn(move || {
+            for line in lines {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        })?;
+
+    Ok(receiver)
+}
+
+/// Keep `name` only if it can be written in a listing, the other entries are skipped.
+fn safe_name(name: String) -> Option<String> {
+    if path_resolver::is_safe_name(&name) {
+        return Some(name);
+    }
+
+    eprintln!("Skipping {name:?} in a listing: its name contains a line break.");
+
+    None
+}
+
+/// Format the `ls -l` line of the file `name`, without the line ending, with the values of
+/// `fixed` if given.
+pub fn format_entry(name: &str, info: &FileInfo, fixed: Option<&FixedListing>) -> String {
+    let (owner, group, size, date) = match fixed {
+        Some(fixed) => (
+            &fixed.owner,
+            &fixed.group,
+            if info.is_dir {
+                FIXED_DIRECTORY_SIZE
+            } else {
+                info.size
+            },
+            format_date(info.modified.map_or(fixed.now, DateTime::from), fixed.now),
+        ),
+        None => (
+            &info.owner,
+            &info.group,
+            info.size,
+            format_date(
+                info.modified.map_or_else(Local::now, DateTime::from),
+                Local::now(),
+            ),
+        ),
+    };
+
+    format!(
+        "{}{} {owner} {group} {size} {date:>5} {name}",
+        if info.is_dir { "d" } else { "-" },
+        info.permissions,
+    )
+}
+
+/// Format the date of an `ls -l` line: with the time for the files modified during the last six
+/// months, with the year for the older ones and the ones in the future.
+///
+/// Clients parse this column, the month is thus always in English whatever the locale of the
+/// system ('%b' of chrono does not depend on it).
+pub fn format_date<Tz: TimeZone>(date_time: DateTime<Tz>, now: DateTime<Tz>) -> String
+where
+    Tz::Offset: fmt::Display,
+{
+    // Six mois moyens, comme le fait 'ls'.
+    let six_months = Duration::seconds(365 * 24 * 3600 / 2 + 5 * 3600);
+
+    // Une légère avance tolère les horloges des machines montant les fichiers.
+    let recent = date_time > now.clone() - six_months && date_time <= now + Duration::hours(1);
+
+    if recent {
+        date_time.format("%b %e %H:%M").to_string()
+    } else {
+        date_time.format("%b %e  %Y").to_string()
+    }
+}
+
+/// Describe the entries of the directory `system_path` with the facts of `selection`, one line
+/// per entry, reading their metadata with up to `threads` threads.
+pub fn list_facts(system_path: &Path, selection: Vec<Fact>, threads: usize) -> io::Result<Lines> {
+    let fixed = get_fixed_listing().is_some();
+
+    list_facts_with(system_path, selection, threads, fixed)
+}
+
+/// Like [`list_facts`], with the entries sorted and the size of the directories fixed if
+/// `fixed`.
+fn list_facts_with(
+    system_path: &Path,
+    selection: Vec<Fact>,
+    threads: usize,
+    fixed: bool,
+) -> io::Result<Lines> {
+    if !fs::metadata(system_path)?.is_dir() {
+        return Err(io::Error::from(io::ErrorKind::NotADirectory));
+    }
+
+    let entries = read_entries(system_path, fixed)?;
+
+    // Obtenir l'identifiant peut demander d'ouvrir le fichier, il n'est cherché que si besoin.
+    let details = Details {
+        owner: false,
+        unique: selection.contains(&Fact::Unique),
+    };
+
+    Ok(describe_entries(
+        entries,
+        threads,
+        details,
+        move |name, info| match fixed && info.is_dir {
+            true => format_facts(
+                name,
+                &FileInfo {
+                    size: FIXED_DIRECTORY_SIZE,
+                    ..info.clone()
+                },
+                &selection,
+            ),
+            false => format_facts(name, info, &selection),
+        },
+    ))
+}
+
+/// Describe each entry of `entries` with `format`, in the order of the directory.
+///
+/// The entries are read by batches whose metadata are fetched by up to `threads` threads, which
+/// hides the latency of spinning disks and network mounts on big directories.
+fn describe_entries<F>(mut entries: Entries, threads: usize, details: Details, format: F) -> Lines
+where
+    F: Fn(&str, &FileInfo) -> String + Send + Sync + 'static,
+{
+    let batches = iter::from_fn(move || {
+        let batch: Vec<DirEntry> = entries.by_ref().take(METADATA_BATCH_SIZE).collect();
+
+        if batch.is_empty() {
+            return None;
+        }
+
+        Some(parallel_map(&batch, threads, |entry| {
+            let name = safe_name(entry.file_name().into_string().ok()?)?;
+
+            // Les informations d'un lien symbolique sont celles de sa cible.
+            let info = FileInfo::read(&entry.path(), details).ok()?;
+
+            Some(format(&name, &info))
+        }))
+    });
+
+    Box::new(batches.flatten().flatten())
+}
+
+/// Apply `f` to each of `items` with up to `threads` threads, keeping the order of the items.
+fn parallel_map<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    if threads <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    // Chaque thread reçoit une suite d'éléments, ce qui rend l'ordre trivial à conserver.
+    let chunk_size = items.len().div_ceil(threads);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(&f).collect::<Vec<R>>()))
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// Format the MLSx line of the file `name` with the facts of `selection`, without the line
+/// ending.
+///
+/// The `unique` fact is only given if `info` was read with [`Details::unique`].
+pub fn format_facts(name: &str, info: &FileInfo, selection: &[Fact]) -> String {
+    let facts = Facts {
+        is_dir: info.is_dir,
+        size: info.size,
+        modify: info.modified.map(DateTime::<Utc>::from),
+        perm: facts::perm_from_mode(info.is_dir, info.mode),
+        unique: info
+            .unique
+            .map(|(device, index)| facts::format_unique(device, index)),
+    };
+
+    facts.format_selected(name, selection)
+}
+
+/// Build the index of the directory `system_path`, shown to the clients as `path`, with at most
+/// `max_entries` entries, 0 meaning no limit.
+///
+/// The entries are sorted by name. `format` must not be [`DirectoryIndex::None`].
+pub fn index(
+    system_path: &Path,
+    path: &str,
+    format: DirectoryIndex,
+    max_entries: usize,
+) -> io::Result<String> {
+    let fixed = get_fixed_listing();
+
+    // Les propriétaires ne figurent que dans l'index au format de LIST.
+    let details = Details {
+        owner: format != DirectoryIndex::Html && fixed.is_none(),
+        unique: false,
+    };
+
+    let mut entries: Vec<(String, FileInfo)> = fs::read_dir(system_path)?
+        .flatten()
+        .filter(is_listed)
+        .filter_map(|entry| {
+            let name = safe_name(entry.file_name().into_string().ok()?)?;
+            let info = FileInfo::read(&entry.path(), details).ok()?;
+
+            Some((name, info))
+        })
+        .collect();
+
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    if max_entries != 0 {
+        entries.truncate(max_entries);
+    }
+
+    let index = match format {
+        DirectoryIndex::Html => {
+            let title = escape_html(path);
+
+            // Les liens sont absolus : l'index n'a pas de '/' final dans son URL, un lien relatif
+            // partirait donc du dossier parent.
+            let base: Vec<String> = path
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+                .map(encode_uri_component)
+                .collect();
+            let base = format!("/{}", base.join("/"));
+            let base = base.trim_end_matches('/');
+
+            let mut page = format!(
+                "<!DOCTYPE html>\n<html>\n<head><title>Index of {title}</title></head>\n<body>\n<h1>Index of {title}</h1>\n<ul>\n"
+            );
+
+            for (name, info) in &entries {
+                let suffix = if info.is_dir { "/" } else { "" };
+
+                page.push_str(&format!(
+                    "<li><a href=\"{base}/{}{suffix}\">{}{suffix}</a></li>\n",
+                    escape_html(&encode_uri_component(name)),
+                    escape_html(name)
+                ));
+            }
+
+            page.push_str("</ul>\n</body>\n</html>\n");
+            page
+        }
+        _ => entries
+            .iter()
+            .map(|(name, info)| format!("{}\r\n", format_entry(name, info, fixed.as_ref())))
+            .collect(),
+    };
+
+    Ok(index)
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Percent-encode `name` so that a browser following the link does not interpret characters like
+/// `?` or `#`.
+fn encode_uri_component(name: &str) -> String {
+    name.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn old_files_show_the_year() {
+        let now = Local.with_ymd_and_hms(2024, 8, 15, 12, 0, 0).unwrap();
+
+        let recent = Local.with_ymd_and_hms(2024, 3, 5, 9, 7, 0).unwrap();
+        assert_eq!(format_date(recent, now), "Mar  5 09:07");
+
+        let old = Local.with_ymd_and_hms(2023, 12, 24, 18, 30, 0).unwrap();
+        assert_eq!(format_date(old, now), "Dec 24  2023");
+
+        let future = Local.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(format_date(future, now), "Jan  1  2025");
+    }
+
+    /// Directory holding the entries described by the golden listings.
+    #[cfg(unix)]
+    fn golden_directory(name: &str) -> std::path::PathBuf {
+        use std::{fs::File, os::unix::fs::PermissionsExt};
+
+        let directory =
+            std::env::temp_dir().join(format!("ftp-paradise-{name}-{}", std::process::id()));
+        fs::create_dir_all(directory.join("sub dir")).unwrap();
+
+        let utc = |y, mo, d, h, mi| Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap();
+
+        let files = [
+            ("report.txt", 1234, 0o644, utc(2023, 12, 20, 10, 30)),
+            ("archive.tar", 0, 0o600, utc(2022, 5, 1, 23, 59)),
+            ("run.sh", 10, 0o755, utc(2023, 7, 5, 6, 0)),
+            ("été – notes.md", 42, 0o444, utc(2023, 9, 9, 9, 9)),
+            ("-dash", 7, 0o640, utc(2023, 12, 31, 23, 59)),
+            ("future.log", 99, 0o664, utc(2024, 3, 1, 12, 0)),
+            ("bad\nname", 1, 0o644, utc(2023, 12, 1, 0, 0)),
+        ];
+
+        for (name, size, mode, modified) in files {
+            let path = directory.join(name);
+            let file = File::create(&path).unwrap();
+
+            file.set_len(size).unwrap();
+            file.set_modified(modified.into()).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        }
+
+        std::os::unix::fs::symlink("report.txt", directory.join("link")).unwrap();
+
+        let sub_dir = directory.join("sub dir");
+        fs::set_permissions(&sub_dir, fs::Permissions::from_mode(0o750)).unwrap();
+        File::open(&sub_dir)
+            .unwrap()
+            .set_modified(utc(2023, 6, 1, 8, 0).into())
+            .unwrap();
+
+        directory
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn list_matches_golden_output() {
+        let directory = golden_directory("golden-list");
+
+        let fixed = FixedListing {
+            owner: "ftp".to_string(),
+            group: "users".to_string(),
+            now: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        };
+
+        let lines: Vec<String> = list_with(&directory, 4, Some(fixed)).unwrap().collect();
+
+        assert_eq!(
+            lines.join("\n") + "\n",
+            include_str!("../../tests/golden/list.txt")
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mlsd_matches_golden_output() {
+        let directory = golden_directory("golden-mlsd");
+
+        let selection = vec![Fact::Type, Fact::Size, Fact::Modify, Fact::Perm];
+        let lines: Vec<String> = list_facts_with(&directory, selection, 4, true)
+            .unwrap()
+            .collect();
+
+        assert_eq!(
+            lines.join("\n") + "\n",
+            include_str!("../../tests/golden/mlsd.txt")
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn parallel_map_keeps_the_order() {
+        let items: Vec<u32> = (0..1000).collect();
+
+        assert_eq!(
+            parallel_map(&items, 8, |i| i * 2),
+            parallel_map(&items, 1, |i| i * 2)
+        );
+        assert_eq!(parallel_map(&items, 8, |i| i * 2)[999], 1998);
+        assert!(parallel_map(&[] as &[u32], 8, |i| *i).is_empty());
+    }
+
+    #[test]
+    fn background_lines_keep_their_order() {
+        let lines = (0..1000).map(|i| i.to_string());
+        let receiver = in_background(Box::new(lines), 16).unwrap();
+
+        let received: Vec<String> = receiver.iter().collect();
+
+        assert_eq!(received.len(), 1000);
+        assert_eq!(received[999], "999");
+    }
+
+    #[test]
+    fn html_index_links_the_entries() {
+        let directory =
+            std::env::temp_dir().join(format!("ftp-paradise-index-{}", std::process::id()));
+        fs::create_dir_all(directory.join("sub dir")).unwrap();
+        fs::write(directory.join("a&b.txt"), b"").unwrap();
+
+        let page = index(&directory, "/<pub>", DirectoryIndex::Html, 0).unwrap();
+
+        assert!(page.contains("<title>Index of /&lt;pub&gt;</title>"));
+        assert!(page.contains("<li><a href=\"/%3Cpub%3E/a%26b.txt\">a&amp;b.txt</a></li>\n<li><a href=\"/%3Cpub%3E/sub%20dir/\">sub dir/</a></li>"));
+
+        let text = index(&directory, "/", DirectoryIndex::Text, 1).unwrap();
+        assert_eq!(text.lines().count(), 1);
+        assert!(text.ends_with(" a&b.txt\r\n"));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn hard_links_share_their_unique_fact() {
+        let directory =
+            std::env::temp_dir().join(format!("ftp-paradise-unique-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("a.txt"), b"a").unwrap();
+        fs::write(directory.join("other.txt"), b"a").unwrap();
+        fs::hard_link(directory.join("a.txt"), directory.join("link.txt")).unwrap();
+
+        let unique = |name: &str| {
+            let system_path = directory.join(name);
+            let details = Details {
+                owner: false,
+                unique: true,
+            };
+            let line = format_facts(
+                name,
+                &FileInfo::read(&system_path, details).unwrap(),
+                &[Fact::Unique],
+            );
+
+            facts::parse_line(&line).unwrap().1.unique.unwrap()
+        };
+
+        assert_eq!(unique("a.txt"), unique("link.txt"));
+        assert_ne!(unique("a.txt"), unique("other.txt"));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,246 @@
+//! Counters of the whole server since it started, reported by STAT, SITE UPTIME and SITE WHO.
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock, Mutex, OnceLock, PoisonError,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+
+use crate::{
+    server::session_state::SessionState,
+    thread_pool::{LoadCounters, PoolLoad},
+};
+
+static STARTED: OnceLock<DateTime<Local>> = OnceLock::new();
+static TOTAL_SESSIONS: AtomicU64 = AtomicU64::new(0);
+static CURRENT_SESSIONS: AtomicU64 = AtomicU64::new(0);
+static BYTES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+static POOL: OnceLock<LoadCounters> = OnceLock::new();
+static SESSIONS: LazyLock<Mutex<BTreeMap<String, ConnectedSession>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+/// Values of the counters at a given time.
+#[derive(Debug, Clone, Copy)]
+pub struct ServerMetrics {
+    pub started: DateTime<Local>,
+    pub total_sessions: u64,
+    pub current_sessions: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    /// Load of the threads handling the sessions, unknown before the server is started.
+    pub pool: Option<PoolLoad>,
+}
+
+/// Session currently connected, as listed by SITE WHO.
+#[derive(Debug, Clone)]
+pub struct ConnectedSession {
+    pub peer: SocketAddr,
+    pub username: Option<String>,
+    pub connected: DateTime<Local>,
+    pub state: Arc<SessionState>,
+}
+
+impl ServerMetrics {
+    /// Describe the counters, one line each.
+    pub fn format_lines(&self) -> Vec<String> {
+        let uptime = (Local::now() - self.started).to_std().unwrap_or_default();
+
+        let mut lines = vec![
+            format!(
+                "Up since {} ({})",
+                self.started.format("%Y-%m-%d %H:%M:%S"),
+                format_duration(uptime)
+            ),
+            format!(
+                "{} sessions handled, {} connected",
+                self.total_sessions, self.current_sessions
+            ),
+            format!(
+                "{} bytes received, {} bytes sent",
+                self.bytes_received, self.bytes_sent
+            ),
+        ];
+
+        if let Some(pool) = self.pool {
+            lines.push(format!(
+                "{} of {} threads busy, {} sessions queued",
+                pool.busy, pool.workers, pool.queued
+            ));
+        }
+
+        lines
+    }
+}
+
+/// Session counted as connected until dropped.
+pub struct SessionCounter {
+    session_id: String,
+}
+
+impl Drop for SessionCounter {
+    fn drop(&mut self) {
+        CURRENT_SESSIONS.fetch_sub(1, Ordering::Relaxed);
+
+        SESSIONS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.session_id);
+    }
+}
+
+/// Set the start time of the server, only the first call has an effect.
+pub fn record_start() {
+    STARTED.get_or_init(Local::now);
+}
+
+/// Follow the load of the threads of the server, only the first call has an effect.
+pub fn record_pool(counters: LoadCounters) {
+    let _ = POOL.set(counters);
+}
+
+pub fn open_session(
+    session_id: &str,
+    peer: SocketAddr,
+    state: Arc<SessionState>,
+) -> SessionCounter {
+    TOTAL_SESSIONS.fetch_add(1, Ordering::Relaxed);
+    CURRENT_SESSIONS.fetch_add(1, Ordering::Relaxed);
+
+    SESSIONS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(
+            session_id.to_string(),
+            ConnectedSession {
+                peer,
+                username: None,
+                connected: Local::now(),
+                state,
+            },
+        );
+
+    SessionCounter {
+        session_id: session_id.to_string(),
+    }
+}
+
+/// Record the account the session `session_id` is logged in with.
+pub fn record_login(session_id: &str, username: &str) {
+    if let Some(session) = SESSIONS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_mut(session_id)
+    {
+        session.username = Some(username.to_string());
+    }
+}
+
+/// Forget the account of the session `session_id`, logged out by REIN.
+pub fn record_logout(session_id: &str) {
+    if let Some(session) = SESSIONS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_mut(session_id)
+    {
+        session.username = None;
+    }
+}
+
+/// Sessions currently connected, by identifier.
+pub fn get_sessions() -> Vec<(String, ConnectedSession)> {
+    SESSIONS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .map(|(id, session)| (id.clone(), session.clone()))
+        .collect()
+}
+
+pub fn record_received(bytes: u64) {
+    BYTES_RECEIVED.fetch_add(bytes, Ordering::Relaxed);
+}
+
+pub fn record_sent(bytes: u64) {
+    BYTES_SENT.fetch_add(bytes, Ordering::Relaxed);
+}
+
+pub fn get() -> ServerMetrics {
+    ServerMetrics {
+        started: *STARTED.get_or_init(Local::now),
+        total_sessions: TOTAL_SESSIONS.load(Ordering::Relaxed),
+        current_sessions: CURRENT_SESSIONS.load(Ordering::Relaxed),
+        bytes_received: BYTES_RECEIVED.load(Ordering::Relaxed),
+        bytes_sent: BYTES_SENT.load(Ordering::Relaxed),
+        pool: POOL.get().map(LoadCounters::get),
+    }
+}
+
+/// Write `duration` as `2d 3h 4m 5s`, without the leading units that are zero.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+
+    let units = [
+        (seconds / 86400, "d"),
+        (seconds / 3600 % 24, "h"),
+        (seconds / 60 % 60, "m"),
+        (seconds % 60, "s"),
+    ];
+
+    let first = units
+        .iter()
+        .position(|&(value, _)| value > 0)
+        .unwrap_or(units.len() - 1);
+
+    units[first..]
+        .iter()
+        .map(|(value, unit)| format!("{value}{unit}"))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_are_readable() {
+        assert_eq!(format_duration(Duration::from_secs(0)), "0s");
+        assert_eq!(format_duration(Duration::from_secs(125)), "2m 5s");
+        assert_eq!(
+            format_duration(Duration::from_secs(2 * 86400 + 5)),
+            "2d 0h 0m 5s"
+        );
+
+        // Les compteurs sont partagés avec les autres tests, seules les différences comptent.
+        let before = get();
+        let session = open_session(
+            "metrics-test",
+            "127.0.0.1:2000".parse().unwrap(),
+            Arc::new(SessionState::new()),
+        );
+        record_received(10);
+        record_login("metrics-test", "alice");
+
+        let during = get();
+        assert_eq!(during.total_sessions, before.total_sessions + 1);
+        assert_eq!(during.bytes_received, before.bytes_received + 10);
+
+        let connected = get_sessions();
+        let (_, alice) = connected
+            .iter()
+            .find(|(id, _)| id == "metrics-test")
+            .unwrap();
+        assert_eq!(alice.username.as_deref(), Some("alice"));
+
+        drop(session);
+        assert_eq!(get().total_sessions, during.total_sessions);
+        assert!(!get_sessions().iter().any(|(id, _)| id == "metrics-test"));
+    }
+}
@@ -0,0 +1,101 @@
+//! Markers of the uploads interrupted by a shutdown of the server.
+//!
+//! The partial file is kept with the number of bytes it holds, so that the client can resume it
+//! with REST once the server is back instead of guessing what was written.
+//!
+//! Without extended attributes, the marker is a file whose name is reserved to the server (see
+//! [`crate::server::path_resolver::is_protected`]): the clients can neither forge one nor lose
+//! one of their files when it is cleared.
+
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::platform::crossplatform;
+
+const XATTR_NAME: &str = "user.ftp-paradise.partial";
+
+/// Prefix of the name of the file holding the marker next to the partial file, when the
+/// filesystem has no extended attributes.
+pub const SIDECAR_PREFIX: &str = ".ftp-paradise-partial.";
+
+/// Record that the file at `path` is a partial upload of `size` bytes.
+pub fn mark(path: &Path, size: u64) -> io::Result<()> {
+    let value = size.to_string();
+
+    match crossplatform::set_extended_attribute(path, XATTR_NAME, value.as_bytes()) {
+        Ok(()) => Ok(()),
+        Err(_) => fs::write(sidecar_path(path)?, format!("{value}\n")),
+    }
+}
+
+/// Size recorded by [`mark`] for the file at `path`, if it is a partial upload.
+pub fn get(path: &Path) -> Option<u64> {
+    let value = match crossplatform::get_extended_attribute(path, XATTR_NAME) {
+        Ok(Some(v)) => String::from_utf8(v).ok()?,
+        _ => fs::read_to_string(sidecar_path(path).ok()?).ok()?,
+    };
+
+    value.trim().parse().ok()
+}
+
+/// Remove the marker of the file at `path`, once its upload is complete.
+pub fn clear(path: &Path) {
+    // Le fichier n'a le plus souvent pas de marqueur, les erreurs ne sont donc pas signalées.
+    let _ = crossplatform::remove_extended_attribute(path, XATTR_NAME);
+
+    if let Ok(sidecar) = sidecar_path(path) {
+        let _ = fs::remove_file(sidecar);
+    }
+}
+
+fn sidecar_path(path: &Path) -> io::Result<PathBuf> {
+    let name = match path.file_name() {
+        Some(n) => n,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "file name not supported",
+            ))
+        }
+    };
+
+    let mut sidecar = OsString::from(SIDECAR_PREFIX);
+    sidecar.push(name);
+
+    Ok(path.with_file_name(sidecar))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn marker_is_read_back_then_cleared() {
+        let path = env::temp_dir().join(format!("ftp-paradise-partial-{}", process::id()));
+        fs::write(&path, b"hello").unwrap();
+
+        assert_eq!(get(&path), None);
+
+        // Un fichier de l'utilisateur au nom d'un ancien marqueur n'en est pas un.
+        let mut user_file = path.clone().into_os_string();
+        user_file.push(".partial");
+        fs::write(&user_file, "5\n").unwrap();
+        assert_eq!(get(&path), None);
+
+        mark(&path, 5).unwrap();
+        assert_eq!(get(&path), Some(5));
+
+        clear(&path);
+        assert_eq!(get(&path), None);
+        assert!(!sidecar_path(&path).unwrap().exists());
+        assert!(Path::new(&user_file).exists());
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&user_file).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    net::{IpAddr, SocketAddr, TcpListener},
+    sync::OnceLock,
+};
+
+use crate::{
+    config::{PassiveProfile, PortRange},
+    CONFIG,
+};
+
+/// Listener opened for a passive data connection.
+pub struct PassiveListener {
+    pub listener: TcpListener,
+    /// Address the client has to connect to, which can differ from the one the listener is bound
+    /// to when the server is behind a NAT.
+    pub advertised_address: String,
+    pub port: u16,
+    /// Port the client has to connect to, which can differ from `port` when a load balancer
+    /// forwards other ports to this instance.
+    pub advertised_port: u16,
+}
+
+/// Port of a passive listener, to be mapped to the one sent to the client.
+#[derive(Debug, Clone, Copy)]
+pub struct PortMapping {
+    /// Port the listener is bound to.
+    pub local_port: u16,
+    /// Range `local_port` has been taken from.
+    pub ports: PortRange,
+    /// First external port of the config for this range, if any.
+    pub external_start: Option<u16>,
+}
+
+/// Decide which port is sent to the client in the replies to PASV and EPSV.
+///
+/// When several instances share a load balancer, each one listens on its own passive range and
+/// the balancer forwards each external port to a single instance. The default mapper follows
+/// `external_start` from the config; an orchestration layer can install its own with
+/// [`set_port_mapper`] before the server starts.
+pub trait PassivePortMapper: Send + Sync {
+    fn map(&self, mapping: &PortMapping) -> u16;
+}
+
+/// Mapper shifting the passive range so that it starts at `external_start`, or leaving the ports
+/// unchanged without it.
+pub struct ConfigPortMapper;
+
+impl PassivePortMapper for ConfigPortMapper {
+    fn map(&self, mapping: &PortMapping) -> u16 {
+        match mapping.external_start {
+            // La config a vérifié que la plage décalée tient dans les ports existants.
+            Some(start) => start + (mapping.local_port - mapping.ports.start),
+            None => mapping.local_port,
+        }
+    }
+}
+
+static PORT_MAPPER: OnceLock<Box<dyn PassivePortMapper>> = OnceLock::new();
+
+/// Replace the [`ConfigPortMapper`] by `mapper` for the lifetime of the process.
+pub fn set_port_mapper(mapper: Box<dyn PassivePortMapper>) -> Result<(), &'static str> {
+    match PORT_MAPPER.set(mapper) {
+        Ok(()) => Ok(()),
+        Err(_) => Err("a passive port mapper is already installed"),
+    }
+}
+
+fn get_port_mapper() -> &'static dyn PassivePortMapper {
+    match PORT_MAPPER.get() {
+        Some(mapper) => mapper.as_ref(),
+        None => &ConfigPortMapper,
+    }
+}
+
+/// Open a listener for a passive data connection.
+///
+/// The listener is bound to `local_address`, the local address of the control connection, unless
+/// the profile gives another one. The advertised address and the ports depend on the first
+/// passive profile of the config matching `local_address` and the user of the session. Without
+/// matching profile, the hostname and the passive ports of the config are used.
+pub fn open_listener(local_address: IpAddr, username: Option<&str>) -> Option<PassiveListener> {
+    let config = CONFIG.get().unwrap();
+
+    let profile = config
+        .get_passive_profiles()
+        .iter()
+        .find(|profile| matches_profile(profile, local_address, username));
+
+    // Sans adresse dans le profil, c'est l'adresse d'écoute du serveur qui est utilisée.
+    let hostname = match config.get_listen_address() {
+        Ok(address) => address.ip().to_string(),
+        Err(_) => config.get_hostname(),
+    };
+
+    let (bind_address, advertised_address, ports, external_start) = match profile {
+        Some(profile) => (
+            profile.bind_address.clone(),
+            profile.address.clone().unwrap_or(hostname),
+            profile.ports.unwrap_or(config.get_passive_ports()),
+            profile
+                .external_start
+                .or(config.get_passive_external_start()),
+        ),
+        None => (
+            None,
+            hostname,
+            config.get_passive_ports(),
+            config.get_passive_external_start(),
+        ),
+    };
+
+    // Sur une machine à plusieurs interfaces, la connexion de données passe par celle de la
+    // connexion de contrôle. Le listener doit aussi être de la même famille d'adresses que
+    // celle-ci, sinon le client ne pourra pas s'y connecter.
+    let bind_address = match bind_address.map(|address| address.parse::<IpAddr>()) {
+        Some(Ok(ip)) if ip.is_ipv4() == local_address.is_ipv4() => ip,
+        _ => local_address,
+    };
+
+    // Une adresse comme '::' ou '0.0.0.0' n'a pas de sens pour le client, il reçoit alors celle
+    // sur laquelle il s'est connecté.
+    let advertised_address = match advertised_address.parse::<IpAddr>() {
+        Ok(ip) if ip.is_unspecified() || ip.is_ipv4() != local_address.is_ipv4() => {
+            local_address.to_string()
+        }
+        _ => advertised_address,
+    };
+
+    for port in ports.start..=ports.end {
+        if let Ok(listener) = TcpListener::bind(SocketAddr::new(bind_address, port)) {
+            let advertised_port = get_port_mapper().map(&PortMapping {
+                local_port: port,
+                ports,
+                external_start,
+            });
+
+            return Some(PassiveListener {
+                listener,
+                advertised_address,
+                port,
+                advertised_port,
+            });
+        }
+    }
+
+    None
+}
+
+/// An empty list in the profile matches anything.
+fn matches_profile(
+    profile: &PassiveProfile,
+    local_address: IpAddr,
+    username: Option<&str>,
+) -> bool {
+    let local_address = local_address.to_string();
+
+    let address_matches =
+        profile.local_addresses.is_empty() || profile.local_addresses.contains(&local_address);
+
+    let user_matches = profile.users.is_empty()
+        || username.is_some_and(|username| profile.users.iter().any(|u| u == username));
+
+    address_matches && user_matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ports_are_shifted_to_the_external_range() {
+        let mut mapping = PortMapping {
+            local_port: 50010,
+            ports: PortRange {
+                start: 50000,
+                end: 50099,
+            },
+            external_start: None,
+        };
+
+        assert_eq!(ConfigPortMapper.map(&mapping), 50010);
+
+        mapping.external_start = Some(61000);
+        assert_eq!(ConfigPortMapper.map(&mapping), 61010);
+    }
+}
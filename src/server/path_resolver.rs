@@ -0,0 +1,478 @@
+//! Translation of the pathnames sent by the clients into paths of the system.
+//!
+//! Wildcards (`*`, `?` and `[...]`) are only meaningful for listing commands: LIST and NLST
+//! expand them with [`expand`], while commands acting on a single file, such as RETR or DELE,
+//! refuse pathnames that contain them (see [`has_wildcards`]) instead of looking for a file
+//! literally named `*`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{checksum, partial_upload};
+
+/// Reason why a path cannot be created or replaced by MKD or RNTO.
+#[derive(Debug, PartialEq)]
+pub enum TargetError {
+    /// The path is `/` itself.
+    Root,
+    /// The entry would be created outside of the root once the symbolic links are followed.
+    OutsideRoot,
+    /// The directory that would contain the entry does not exist.
+    MissingParent,
+    /// The path names a file managed by the server, see [`is_protected`].
+    Protected,
+}
+
+/// Compute the absolute path, as seen by the client, of `path` requested from
+/// `working_directory`.
+///
+/// `.` and `..` are resolved here so that the result can never go above `/`.
+pub fn resolve(working_directory: &str, path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+
+    // Si le client n'envoie pas de chemin absolu, alors il faut partir du dossier actuel.
+    let full_path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("{working_directory}/{path}")
+    };
+
+    for component in full_path.split('/') {
+        match component {
+            "" | "." => (),
+            ".." => {
+                components.pop();
+            }
+            c => components.push(c),
+        }
+    }
+
+    format!("/{}", components.join("/"))
+}
+
+/// Get the location on the system of `virtual_path`, a path resolved with [`resolve`], for a
+/// session whose `/` is the directory `root`.
+pub fn to_system_path(root: &str, virtual_path: &str) -> PathBuf {
+    let mut system_path = PathBuf::from(root);
+
+    system_path.push(virtual_path.trim_start_matches('/'));
+
+    system_path
+}
+
+/// Tell if `system_path` is still inside `root` once the symbolic links are followed.
+///
+/// A path that does not exist yet, such as the target of an upload, is judged by its closest
+/// existing parent.
+pub fn is_inside_root(root: &str, system_path: &Path) -> bool {
+    let root = match fs::canonicalize(root) {
+        Ok(r) => r,
+        Err(_) => return false,
+    };
+
+    for ancestor in system_path.ancestors() {
+        if let Ok(real_path) = fs::canonicalize(ancestor) {
+            return real_path.starts_with(&root);
+        }
+    }
+
+    false
+}
+
+/// Check that the entry `path`, resolved with [`resolve`], can be created or replaced by MKD or
+/// RNTO in a session whose `/` is the directory `root`.
+///
+/// It is the directory containing the entry that must be inside the root: an existing entry is
+/// replaced by a rename, not followed, even if it is a symbolic link.
+///
+/// # Return
+/// The location of the entry on the system.
+pub fn check_target(root: &str, path: &str) -> Result<PathBuf, TargetError> {
+    if path == "/" {
+        return Err(TargetError::Root);
+    }
+
+    if is_protected(path) {
+        return Err(TargetError::Protected);
+    }
+
+    let system_path = to_system_path(root, path);
+
+    // Un seul niveau est créé à la fois, le dossier parent doit donc déjà exister.
+    let parent = match system_path.parent() {
+        Some(p) if p.is_dir() => p,
+        _ => return Err(TargetError::MissingParent),
+    };
+
+    if !is_inside_root(root, parent) {
+        return Err(TargetError::OutsideRoot);
+    }
+
+    Ok(system_path)
+}
+
+/// Tell if `path` names a file managed by the server, such as the checksums or the markers of
+/// partial uploads kept next to the files, which clients must not see, replace nor move.
+pub fn is_protected(path: &str) -> bool {
+    match path.rsplit('/').next() {
+        Some(name) => {
+            name == checksum::SIDECAR_NAME || name.starts_with(partial_upload::SIDECAR_PREFIX)
+        }
+        None => false,
+    }
+}
+
+/// Tell if `path` is `directory` or one of its descendants, both resolved with [`resolve`].
+pub fn is_within(path: &str, directory: &str) -> bool {
+    match path.strip_prefix(directory.trim_end_matches('/')) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// Tell if `name` can be sent to a client: a CR or a LF would be taken for the end of a line in
+/// listings and replies, as warned by the RFC 3659.
+pub fn is_safe_name(name: &str) -> bool {
+    !name.contains(['\r', '\n'])
+}
+
+/// Tell if the last component of `path` contains a wildcard.
+pub fn has_wildcards(path: &str) -> bool {
+    let name = match path.rsplit_once('/') {
+        Some((_, name)) => name,
+        None => path,
+    };
+
+    name.contains(['*', '?', '['])
+}
+
+/// Split `pattern` into the directory it is resolved in from `working_directory`, and the pattern
+/// of the names in that directory.
+pub fn split_pattern<'a>(working_directory: &str, pattern: &'a str) -> (String, &'a str) {
+    match pattern.rsplit_once('/') {
+        Some((dir, name)) => (resolve(working_directory, &format!("{dir}/")), name),
+        None => (working_directory.to_string(), pattern),
+    }
+}
+
+/// Resolve `pattern` from `working_directory` and expand the wildcards of its last component.
+///
+/// A directory led outside of `root` by a symbolic link is not read. At most `limit` entries are returned, so that a huge directory cannot exhaust the memory.
+///
+/// # Return
+/// The resolved paths of the matching entries sorted by name, and whether some have been left
+/// out because of the limit.
+pub fn expand(
+    root: &str,
+    working_directory: &str,
+    pattern: &str,
+    limit: usize,
+) -> (Vec<String>, bool) {
+    let (directory, name_pattern) = split_pattern(working_directory, pattern);
+    let system_directory = to_system_path(root, &directory);
+
+    if !is_inside_root(root, &system_directory) {
+        return (Vec::new(), false);
+    }
+
+    let entries = match fs::read_dir(system_directory) {
+        Ok(e) => e,
+        Err(_) => return (Vec::new(), false),
+    };
+
+    let mut matches: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| is_safe_name(name) && !is_protected(name) && matches(name_pattern, name))
+        .map(|name| resolve(&directory, &name))
+        .take(limit.saturating_add(1))
+        .collect();
+
+    let truncated = matches.len() > limit;
+    matches.truncate(limit);
+
+    matches.sort();
+
+    (matches, truncated)
+}
+
+/// Tell if `name` matches `pattern`, which can contain the wildcards `*` (any sequence of
+/// characters), `?` (any character) and `[...]` (a set or range of characters, negated by a
+/// leading `!` or `^`).
+///
+/// Like in a shell, the wildcards don't match a leading `.`, so hidden files must be asked
+/// explicitly.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    if name.starts_with('.') && !pattern.starts_with('.') {
+        return false;
+    }
+
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    matches_from(&pattern, &name)
+}
+
+/// Match `name` against `pattern` with a single pass over the name, going back only to the last
+/// `*` seen, so that patterns made of many `*` cannot take an exponential time.
+fn matches_from(pattern: &[char], name: &[char]) -> bool {
+    let mut p = 0;
+    let mut n = 0;
+    // Position suivant le dernier '*' rencontré, et caractère du nom où sa séquence s'arrête.
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if pattern.get(p) == Some(&'*') {
+            star = Some((p + 1, n));
+            p += 1;
+
+            continue;
+        }
+
+        if let Some(next) = match_one(pattern, p, name[n]) {
+            p = next;
+            n += 1;
+
+            continue;
+        }
+
+        // Le dernier '*' couvre un caractère de plus, les suivants sont oubliés.
+        match star {
+            Some((after_star, end)) => {
+                p = after_star;
+                n = end + 1;
+                star = Some((after_star, end + 1));
+            }
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Match `c` against the element of `pattern` at `p`, other than `*`.
+///
+/// # Return
+/// The position of the next element, or `None` if `c` does not match.
+fn match_one(pattern: &[char], p: usize, c: char) -> Option<usize> {
+    match pattern.get(p)? {
+        '?' => Some(p + 1),
+        '[' => match match_set(&pattern[p + 1..], c) {
+            Some((true, rest)) => Some(pattern.len() - rest.len()),
+            Some((false, _)) => None,
+            // Un '[' sans ']' est un caractère comme un autre.
+            None => (c == '[').then_some(p + 1),
+        },
+        &literal => (literal == c).then_some(p + 1),
+    }
+}
+
+/// Match `c` against the set starting just after a `[`.
+///
+/// # Return
+/// Whether the character is in the set, and the pattern following the closing `]`. `None` if
+/// the set is never closed.
+fn match_set(set: &[char], c: char) -> Option<(bool, &[char])> {
+    let (negated, mut i) = match set.first() {
+        Some('!') | Some('^') => (true, 1),
+        _ => (false, 0),
+    };
+
+    let mut found = false;
+    let mut first = true;
+
+    loop {
+        match set.get(i) {
+            None => return None,
+            // Un ']' placé en premier fait partie de l'ensemble.
+            Some(']') if !first => return Some((found != negated, &set[i + 1..])),
+            Some(&start) => {
+                if set.get(i + 1) == Some(&'-') && set.get(i + 2).is_some_and(|end| *end != ']') {
+                    found |= (start..=set[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    found |= start == c;
+                    i += 1;
+                }
+            }
+        }
+
+        first = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_stays_inside_root() {
+        assert_eq!(resolve("/", "a"), "/a");
+        assert_eq!(resolve("/a", "b/c"), "/a/b/c");
+        assert_eq!(resolve("/a", "/b"), "/b");
+        assert_eq!(resolve("/a/b", ".."), "/a");
+        assert_eq!(resolve("/a", "./b/../c/"), "/a/c");
+        assert_eq!(resolve("/", "../../.."), "/");
+        assert_eq!(resolve("/a", "b/../../../etc"), "/etc");
+    }
+
+    #[test]
+    fn unsafe_names() {
+        assert!(is_safe_name("report 2024.txt"));
+        assert!(!is_safe_name("evil\r\n226 done"));
+        assert!(!is_safe_name("line\nbreak"));
+    }
+
+    #[test]
+    fn wildcards_detection() {
+        assert!(has_wildcards("*.txt"));
+        assert!(has_wildcards("dir/file?"));
+        assert!(has_wildcards("/dir/[ab].txt"));
+        assert!(!has_wildcards("file.txt"));
+        assert!(!has_wildcards("di*r/file.txt"));
+    }
+
+    #[test]
+    fn star() {
+        assert!(matches("*", "file.txt"));
+        assert!(matches("*.txt", "file.txt"));
+        assert!(matches("f*e.txt", "file.txt"));
+        assert!(matches("*.*", "a.b"));
+        assert!(matches("file*", "file"));
+        assert!(!matches("*.txt", "file.txt.gz"));
+        assert!(!matches("*", ".hidden"));
+        assert!(matches(".*", ".hidden"));
+    }
+
+    #[test]
+    fn question_mark() {
+        assert!(matches("?", "a"));
+        assert!(matches("file?.txt", "file1.txt"));
+        assert!(!matches("file?.txt", "file.txt"));
+        assert!(!matches("file?.txt", "file12.txt"));
+    }
+
+    #[test]
+    fn sets() {
+        assert!(matches("[abc].txt", "b.txt"));
+        assert!(!matches("[abc].txt", "d.txt"));
+        assert!(matches("file[0-9]", "file7"));
+        assert!(!matches("file[0-9]", "filex"));
+        assert!(matches("[!a]*", "bcd"));
+        assert!(!matches("[!a]*", "abc"));
+        assert!(matches("[^a]*", "bcd"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("a[", "a["));
+        assert!(!matches("[ab]", ""));
+    }
+
+    #[test]
+    fn literal_characters() {
+        assert!(matches("file.txt", "file.txt"));
+        assert!(!matches("file.txt", "file.txT"));
+        assert!(!matches("file", "file.txt"));
+    }
+
+    #[test]
+    fn many_stars_match_in_linear_time() {
+        let name = "a".repeat(60);
+        let started = std::time::Instant::now();
+
+        assert!(!matches("*a*a*a*a*a*a*a*a*a*a*a*a*b", &name));
+        assert!(matches("*a*a*a*a*a*a*a*a*a*a*a*a*", &name));
+        assert!(matches("a*[a-c]*?", &name));
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn paths_within_directory() {
+        assert!(is_within("/incoming", "/incoming"));
+        assert!(is_within("/incoming/a.txt", "/incoming"));
+        assert!(is_within("/incoming/a.txt", "/"));
+        assert!(!is_within("/incomings", "/incoming"));
+        assert!(!is_within("/", "/incoming"));
+    }
+
+    #[test]
+    fn targets_stay_below_root() {
+        let directory =
+            std::env::temp_dir().join(format!("ftp-paradise-target-{}", std::process::id()));
+        let root = directory.join("root");
+        fs::create_dir_all(root.join("sub")).unwrap();
+
+        let root_str = root.to_str().unwrap();
+
+        // Les '..' en trop s'arrêtent à la racine, et un chemin absolu part de la racine.
+        for request in ["../../escaped", "/../../escaped", "sub/../../../escaped"] {
+            assert_eq!(
+                check_target(root_str, &resolve("/sub", request)),
+                Ok(root.join("escaped"))
+            );
+        }
+
+        assert_eq!(
+            check_target(root_str, &resolve("/sub", "/etc/passwd")),
+            Err(TargetError::MissingParent)
+        );
+        assert_eq!(
+            check_target(root_str, &resolve("/sub", "../..")),
+            Err(TargetError::Root)
+        );
+        assert_eq!(
+            check_target(root_str, &resolve("/", "sub/.checksums")),
+            Err(TargetError::Protected)
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symbolic_links_cannot_escape_root() {
+        let directory =
+            std::env::temp_dir().join(format!("ftp-paradise-jail-{}", std::process::id()));
+        let root = directory.join("root");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        std::os::unix::fs::symlink("/etc", root.join("etc")).unwrap();
+        std::os::unix::fs::symlink("sub", root.join("inside")).unwrap();
+
+        let root_str = root.to_str().unwrap();
+
+        assert!(is_inside_root(root_str, &to_system_path(root_str, "/sub")));
+        assert!(is_inside_root(
+            root_str,
+            &to_system_path(root_str, "/inside")
+        ));
+        assert!(is_inside_root(
+            root_str,
+            &to_system_path(root_str, "/new/file")
+        ));
+        assert!(!is_inside_root(root_str, &to_system_path(root_str, "/etc")));
+        assert!(!is_inside_root(
+            root_str,
+            &to_system_path(root_str, "/etc/passwd")
+        ));
+        assert!(!is_inside_root(
+            root_str,
+            &to_system_path(root_str, "/etc/new")
+        ));
+        assert_eq!(expand(root_str, "/", "/etc/*", 10), (Vec::new(), false));
+        fs::write(root.join("sub/a.txt"), "a").unwrap();
+        assert_eq!(
+            expand(root_str, "/", "/inside/*", 10),
+            (vec!["/inside/a.txt".to_string()], false)
+        );
+
+        // Un lien est remplacé par un renommage, seul le dossier qui le contient compte.
+        assert_eq!(
+            check_target(root_str, "/etc/new"),
+            Err(TargetError::OutsideRoot)
+        );
+        assert_eq!(check_target(root_str, "/etc"), Ok(root.join("etc")));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+//! Two classes of work sharing the machine: the interactive commands, such as listings and
+//! metadata requests, and the bulk transfers of RETR and STOR.
+//!
+//! While an interactive command runs, the bulk transfers pause briefly between their chunks, so
+//! that a client browsing the server stays responsive while others download large files.
+
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+use crate::CONFIG;
+
+/// Commands served before the chunks of the bulk transfers.
+const INTERACTIVE_COMMANDS: [&str; 8] =
+    ["LIST", "NLST", "MLSD", "MLST", "STAT", "MDTM", "CWD", "PWD"];
+
+/// Pause of a bulk transfer between two chunks while an interactive command runs.
+///
+/// It is short enough to bound the slowdown of the transfers, which are never stopped.
+const BULK_PAUSE: Duration = Duration::from_millis(1);
+
+/// Interactive commands running in the whole server.
+static INTERACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Interactive command running until dropped.
+pub struct InteractiveGuard;
+
+impl Drop for InteractiveGuard {
+    fn drop(&mut self) {
+        INTERACTIVE.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Mark the execution of `verb` as interactive, if it is one and the config asks for it.
+pub fn enter(verb: &str) -> Option<InteractiveGuard> {
+    if !CONFIG.get().unwrap().get_interactive_priority() || !INTERACTIVE_COMMANDS.contains(&verb) {
+        return None;
+    }
+
+    Some(start_interactive())
+}
+
+fn start_interactive() -> InteractiveGuard {
+    INTERACTIVE.fetch_add(1, Ordering::Relaxed);
+
+    InteractiveGuard
+}
+
+/// Give way to the interactive commands running, called by the bulk transfers before each chunk.
+pub fn yield_to_interactive() {
+    if INTERACTIVE.load(Ordering::Relaxed) > 0 {
+        thread::sleep(BULK_PAUSE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn bulk_transfers_pause_during_interactive_commands() {
+        let started = Instant::now();
+        yield_to_interactive();
+        let free = started.elapsed();
+
+        {
+            let _guard = start_interactive();
+
+            let started = Instant::now();
+            yield_to_interactive();
+            assert!(started.elapsed() >= BULK_PAUSE);
+        }
+
+        assert!(free < BULK_PAUSE);
+        assert_eq!(INTERACTIVE.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,56 @@
+//! Compatibility layer for the clients that do not follow the protocol, selected by the name they
+//! send with CLNT.
+
+use crate::config::{Config, QuirkProfile};
+
+/// First profile of `config` that applies to the client named `client_name` by CLNT.
+pub fn find<'a>(config: &'a Config, client_name: Option<&str>) -> Option<&'a QuirkProfile> {
+    let client_name = client_name.unwrap_or_default().to_ascii_lowercase();
+
+    config.get_quirks().iter().find(|profile| {
+        profile.clients.is_empty()
+            || profile
+                .clients
+                .iter()
+                .any(|client| client_name.starts_with(&client.to_ascii_lowercase()))
+    })
+}
+
+/// Command that `verb` stands for in `profile`, `verb` itself if it is not an alias.
+pub fn resolve_alias(profile: Option<&QuirkProfile>, verb: String) -> String {
+    let alias = profile.and_then(|profile| {
+        profile
+            .aliases
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(&verb))
+    });
+
+    match alias {
+        Some((_, command)) => command.to_ascii_uppercase(),
+        None => verb,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_are_selected_by_client_name() {
+        let config: Config = toml::from_str(
+            "[[quirks]]\nclients = [\"OldScan\"]\nsyst = \"Windows_NT\"\naliases = { xpwd = \"pwd\" }\n\n[[quirks]]\naliases = { LS = \"LIST\" }\n",
+        )
+        .unwrap();
+
+        let scanner = find(&config, Some("oldscan 2.1"));
+        assert_eq!(scanner.and_then(|p| p.syst.as_deref()), Some("Windows_NT"));
+        assert_eq!(resolve_alias(scanner, "XPWD".to_string()), "PWD");
+        assert_eq!(resolve_alias(scanner, "LS".to_string()), "LS");
+
+        // Sans CLNT, seul le profil qui s'applique à tous les clients est retenu.
+        let other = find(&config, None);
+        assert_eq!(other.and_then(|p| p.syst.as_deref()), None);
+        assert_eq!(resolve_alias(other, "LS".to_string()), "LIST");
+        assert_eq!(resolve_alias(None, "XPWD".to_string()), "XPWD");
+    }
+}
@@ -0,0 +1,127 @@
+//! Token bucket limiting the rate of the commands of a session.
+
+use std::time::{Duration, Instant};
+
+use crate::config::RateLimit;
+
+/// What to do with the command that has just been received.
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Allow,
+    /// Handle the command once this time has passed.
+    Delay(Duration),
+    /// The client kept sending commands too fast, the session must be closed.
+    Disconnect,
+}
+
+/// Commands a session can still send at once, refilled over time.
+pub struct RateLimiter {
+    settings: RateLimit,
+    tokens: f64,
+    updated: Instant,
+    // Commandes retardées d'affilée.
+    throttled: u32,
+}
+
+impl RateLimiter {
+    pub fn new(settings: RateLimit, now: Instant) -> RateLimiter {
+        RateLimiter {
+            settings,
+            tokens: f64::from(settings.burst),
+            updated: now,
+            throttled: 0,
+        }
+    }
+
+    /// Count a command received at `now`.
+    pub fn check(&mut self, now: Instant) -> Verdict {
+        let rate = self.settings.commands_per_second;
+
+        if rate <= 0.0 {
+            return Verdict::Allow;
+        }
+
+        // Après un retard, 'updated' est dans le futur et rien n'est rechargé avant.
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * rate).min(f64::from(self.settings.burst));
+        self.updated = self.updated.max(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.throttled = 0;
+
+            return Verdict::Allow;
+        }
+
+        self.throttled += 1;
+
+        if self.settings.max_throttled > 0 && self.throttled >= self.settings.max_throttled {
+            return Verdict::Disconnect;
+        }
+
+        // Le jeton de la commande est consommé dès qu'il est rechargé.
+        let delay = Duration::from_secs_f64((1.0 - self.tokens) / rate);
+
+        self.tokens = 0.0;
+        self.updated += delay;
+
+        Verdict::Delay(self.updated - now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bursts_are_delayed_then_cut() {
+        let settings = RateLimit {
+            commands_per_second: 10.0,
+            burst: 3,
+            max_throttled: 3,
+        };
+
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(settings, start);
+
+        for _ in 0..3 {
+            assert_eq!(limiter.check(start), Verdict::Allow);
+        }
+
+        // Les commandes suivantes attendent chacune leur jeton, 100 ms plus tard que la précédente.
+        assert_eq!(
+            limiter.check(start),
+            Verdict::Delay(Duration::from_millis(100))
+        );
+        assert_eq!(
+            limiter.check(start),
+            Verdict::Delay(Duration::from_millis(200))
+        );
+        assert_eq!(limiter.check(start), Verdict::Disconnect);
+
+        // Un client qui ralentit retrouve son rythme normal.
+        let mut limiter = RateLimiter::new(settings, start);
+
+        for _ in 0..3 {
+            limiter.check(start);
+        }
+
+        assert_eq!(
+            limiter.check(start),
+            Verdict::Delay(Duration::from_millis(100))
+        );
+        assert_eq!(
+            limiter.check(start + Duration::from_millis(300)),
+            Verdict::Allow
+        );
+        assert_eq!(
+            limiter.check(start + Duration::from_millis(300)),
+            Verdict::Allow
+        );
+        assert_eq!(
+            limiter.check(start + Duration::from_millis(300)),
+            Verdict::Delay(Duration::from_millis(100))
+        );
+    }
+}
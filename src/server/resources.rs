@@ -0,0 +1,123 @@
+//! File descriptors held by the transfers of every session, bounded so that a burst of transfers
+//! is refused with a 451 reply instead of making the workers fail with `EMFILE`.
+
+use std::{
+    cell::Cell,
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static OPEN_DESCRIPTORS: AtomicUsize = AtomicUsize::new(0);
+
+/// Descriptors counted in the global budget until dropped.
+#[derive(Debug)]
+pub struct Reservation {
+    count: usize,
+}
+
+impl Reservation {
+    /// Count `count` more descriptors in the reservation, if it fits in `budget`.
+    pub fn grow(&mut self, count: usize, budget: usize) -> bool {
+        match reserve(count, budget) {
+            Some(mut other) => {
+                self.count += other.count;
+                other.count = 0;
+
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        OPEN_DESCRIPTORS.fetch_sub(self.count, Ordering::Relaxed);
+    }
+}
+
+/// Count `count` descriptors as open, unless more than `budget` would be, 0 meaning no limit.
+pub fn reserve(count: usize, budget: usize) -> Option<Reservation> {
+    let reserved = OPEN_DESCRIPTORS.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |open| {
+        match budget == 0 || open + count <= budget {
+            true => Some(open + count),
+            false => None,
+        }
+    });
+
+    match reserved {
+        Ok(_) => Some(Reservation { count }),
+        Err(_) => None,
+    }
+}
+
+/// Number of descriptors currently held by transfers.
+pub fn get_open_descriptors() -> usize {
+    OPEN_DESCRIPTORS.load(Ordering::Relaxed)
+}
+
+/// Transfer of a session, from the opening of its passive listener to the end of its data
+/// connection.
+#[derive(Debug)]
+pub struct TransferSlot {
+    pub reservation: Reservation,
+    open_transfers: Rc<Cell<usize>>,
+}
+
+impl TransferSlot {
+    /// Open a transfer counted in `open_transfers`, the counter of its session, unless it already
+    /// reached `max_open_transfers`, 0 meaning no limit.
+    pub fn open(
+        open_transfers: &Rc<Cell<usize>>,
+        max_open_transfers: usize,
+        reservation: Reservation,
+    ) -> Option<TransferSlot> {
+        if max_open_transfers != 0 && open_transfers.get() >= max_open_transfers {
+            return None;
+        }
+
+        open_transfers.set(open_transfers.get() + 1);
+
+        Some(TransferSlot {
+            reservation,
+            open_transfers: Rc::clone(open_transfers),
+        })
+    }
+}
+
+impl Drop for TransferSlot {
+    fn drop(&mut self) {
+        self.open_transfers.set(self.open_transfers.get() - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_is_enforced() {
+        // Le compteur est partagé avec les autres tests, le budget part donc de sa valeur.
+        let budget = get_open_descriptors() + 3;
+
+        let mut first = reserve(2, budget).unwrap();
+        assert!(reserve(2, budget).is_none());
+        assert!(first.grow(1, budget));
+        assert!(!first.grow(1, budget));
+
+        drop(first);
+        assert!(reserve(3, budget).is_some());
+    }
+
+    #[test]
+    fn sessions_are_limited() {
+        let open_transfers = Rc::new(Cell::new(0));
+
+        let slot = TransferSlot::open(&open_transfers, 1, Reservation { count: 0 }).unwrap();
+        assert!(TransferSlot::open(&open_transfers, 1, Reservation { count: 0 }).is_none());
+
+        drop(slot);
+        assert_eq!(open_transfers.get(), 0);
+        assert!(TransferSlot::open(&open_transfers, 1, Reservation { count: 0 }).is_some());
+    }
+}
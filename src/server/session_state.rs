@@ -0,0 +1,162 @@
+//! What a session is doing right now, shared with SITE WHO while the session runs.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    time::Instant,
+};
+
+use chrono::{DateTime, Local};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+/// Transfer in progress on the data connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    pub direction: Direction,
+    pub command: String,
+    pub path: String,
+    pub bytes: u64,
+    pub started: Instant,
+}
+
+/// Copy of the state of a session at a given time.
+#[derive(Debug, Clone, Default)]
+pub struct Activity {
+    /// Verb of the last command executed, without its arguments which may hold a password.
+    pub last_command: Option<String>,
+    pub last_command_at: Option<DateTime<Local>>,
+    pub transfer: Option<Transfer>,
+}
+
+#[derive(Debug, Default)]
+pub struct SessionState {
+    activity: Mutex<Activity>,
+    // Compté à part pour ne pas prendre le verrou à chaque bloc copié.
+    transferred: AtomicU64,
+}
+
+/// Transfer followed by the session state until dropped.
+pub struct TransferGuard {
+    state: Arc<SessionState>,
+}
+
+impl Drop for TransferGuard {
+    fn drop(&mut self) {
+        self.state.lock().transfer = None;
+    }
+}
+
+impl SessionState {
+    pub fn new() -> SessionState {
+        SessionState::default()
+    }
+
+    pub fn record_command(&self, verb: &str) {
+        let mut activity = self.lock();
+
+        activity.last_command = Some(verb.to_string());
+        activity.last_command_at = Some(Local::now());
+    }
+
+    /// Follow the transfer of `path` by `command` until the returned guard is dropped.
+    pub fn start_transfer(
+        self: &Arc<Self>,
+        direction: Direction,
+        command: &str,
+        path: &str,
+    ) -> TransferGuard {
+        self.transferred.store(0, Ordering::Relaxed);
+
+        self.lock().transfer = Some(Transfer {
+            direction,
+            command: command.to_string(),
+            path: path.to_string(),
+            bytes: 0,
+            started: Instant::now(),
+        });
+
+        TransferGuard {
+            state: Arc::clone(self),
+        }
+    }
+
+    /// Count `bytes` more for the current transfer.
+    pub fn add_bytes(&self, bytes: u64) {
+        self.transferred.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> Activity {
+        let mut activity = self.lock().clone();
+
+        if let Some(transfer) = &mut activity.transfer {
+            transfer.bytes = self.transferred.load(Ordering::Relaxed);
+        }
+
+        activity
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Activity> {
+        self.activity.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Activity {
+    /// Describe the activity in a few words, like `RETR /a.txt (download, 1024 bytes)`.
+    pub fn describe(&self) -> String {
+        match (&self.transfer, &self.last_command) {
+            (Some(transfer), _) => format!(
+                "{} {} ({}, {} bytes, {}s)",
+                transfer.command,
+                transfer.path,
+                match transfer.direction {
+                    Direction::Upload => "upload",
+                    Direction::Download => "download",
+                },
+                transfer.bytes,
+                transfer.started.elapsed().as_secs()
+            ),
+            (None, Some(verb)) => format!("idle, last {verb}"),
+            (None, None) => "idle".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_ends_with_its_guard() {
+        let state = Arc::new(SessionState::new());
+
+        state.record_command("RETR");
+
+        {
+            let _transfer = state.start_transfer(Direction::Download, "RETR", "/a.txt");
+            state.add_bytes(100);
+            state.add_bytes(24);
+
+            let activity = state.get();
+            let transfer = activity.transfer.as_ref().unwrap();
+
+            assert_eq!(transfer.bytes, 124);
+            assert_eq!(transfer.path, "/a.txt");
+            assert!(activity
+                .describe()
+                .starts_with("RETR /a.txt (download, 124 bytes"));
+        }
+
+        let activity = state.get();
+
+        assert_eq!(activity.transfer, None);
+        assert_eq!(activity.last_command.as_deref(), Some("RETR"));
+        assert_eq!(activity.describe(), "idle, last RETR");
+    }
+}
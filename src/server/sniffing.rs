@@ -0,0 +1,201 @@
+//! Recognition of the type of the files uploaded from their first bytes, for the servers whose
+//! clients cannot be trusted to name their files honestly.
+
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+use crate::config::{ContentRules, ContentType};
+
+/// Bytes needed to recognize every type, the signature of tar being at offset 257.
+const HEADER_SIZE: usize = 262;
+
+/// Signatures found at the start of the files, and the type they reveal.
+const SIGNATURES: [(&[u8], ContentType); 15] = [
+    (b"\x7fELF", ContentType::Executable),
+    (b"MZ", ContentType::Executable),
+    (b"\xfe\xed\xfa\xce", ContentType::Executable),
+    (b"\xfe\xed\xfa\xcf", ContentType::Executable),
+    (b"\xce\xfa\xed\xfe", ContentType::Executable),
+    (b"\xcf\xfa\xed\xfe", ContentType::Executable),
+    (b"\xca\xfe\xba\xbe", ContentType::Executable),
+    (b"#!", ContentType::Script),
+    (b"PK\x03\x04", ContentType::Archive),
+    (b"\x1f\x8b", ContentType::Archive),
+    (b"BZh", ContentType::Archive),
+    (b"\xfd7zXZ\x00", ContentType::Archive),
+    (b"\x28\xb5\x2f\xfd", ContentType::Archive),
+    (b"7z\xbc\xaf\x27\x1c", ContentType::Archive),
+    (b"Rar!\x1a\x07", ContentType::Archive),
+];
+
+/// Error of an upload whose content is refused by the rules of the config.
+#[derive(Debug)]
+pub struct RejectedContent {
+    content_type: ContentType,
+    // Limite dépassée, si le type n'est refusé qu'au-delà d'une taille.
+    max_size: Option<u64>,
+}
+
+impl fmt::Display for RejectedContent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.content_type {
+            ContentType::Executable => "executable files",
+            ContentType::Script => "scripts",
+            ContentType::Archive => "archives",
+        };
+
+        match self.max_size {
+            Some(max_size) => write!(f, "{name} larger than {max_size} bytes are not accepted"),
+            None => write!(f, "{name} are not accepted"),
+        }
+    }
+}
+
+impl Error for RejectedContent {}
+
+/// Tell if `err` comes from a content refused by [`ContentSniffer`].
+pub fn is_rejected(err: &io::Error) -> bool {
+    err.get_ref()
+        .is_some_and(|inner| inner.is::<RejectedContent>())
+}
+
+/// Read the bytes of the file at `path` needed to recognize its type.
+pub fn read_header(path: &Path) -> io::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+
+    File::open(path)?
+        .take(HEADER_SIZE as u64)
+        .read_to_end(&mut header)?;
+
+    Ok(header)
+}
+
+/// Guess the type of a file from its first bytes.
+pub fn detect(header: &[u8]) -> Option<ContentType> {
+    if header.get(257..262) == Some(b"ustar") {
+        return Some(ContentType::Archive);
+    }
+
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| header.starts_with(signature))
+        .map(|&(_, content_type)| content_type)
+}
+
+/// Follows the bytes of an upload to refuse it as soon as its type is known.
+pub struct ContentSniffer {
+    rules: ContentRules,
+    header: Vec<u8>,
+    // Type reconnu une fois l'en-tête complet, 'None' tant qu'il ne l'est pas.
+    detected: Option<Option<ContentType>>,
+}
+
+impl ContentSniffer {
+    pub fn new(rules: ContentRules) -> ContentSniffer {
+        ContentSniffer {
+            rules,
+            header: Vec::with_capacity(HEADER_SIZE),
+            detected: None,
+        }
+    }
+
+    /// Check `buf`, about to be written after which the file will be `size` bytes long.
+    pub fn check(&mut self, buf: &[u8], size: u64) -> io::Result<()> {
+        if self.detected.is_none() {
+            let missing = HEADER_SIZE - self.header.len();
+            self.header
+                .extend_from_slice(&buf[..missing.min(buf.len())]);
+
+            if self.header.len() == HEADER_SIZE {
+                self.detected = Some(detect(&self.header));
+            }
+        }
+
+        match self.detected {
+            Some(content_type) => self.apply(content_type, size),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the files too short for their type to have been checked while written.
+    pub fn finish(&mut self, size: u64) -> io::Result<()> {
+        let content_type = *self.detected.get_or_insert_with(|| detect(&self.header));
+
+        self.apply(content_type, size)
+    }
+
+    fn apply(&self, content_type: Option<ContentType>, size: u64) -> io::Result<()> {
+        let content_type = match content_type {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+
+        let too_large = content_type == ContentType::Archive
+            && self.rules.max_archive_size != 0
+            && size > self.rules.max_archive_size;
+
+        if self.rules.reject.contains(&content_type) {
+            return Err(io::Error::other(RejectedContent {
+                content_type,
+                max_size: None,
+            }));
+        }
+
+        match too_large {
+            true => Err(io::Error::other(RejectedContent {
+                content_type,
+                max_size: Some(self.rules.max_archive_size),
+            })),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn types_are_recognized_from_their_bytes() {
+        let mut tar = vec![0; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+
+        assert_eq!(detect(b"\x7fELF\x02\x01"), Some(ContentType::Executable));
+        assert_eq!(detect(b"MZ\x90\x00"), Some(ContentType::Executable));
+        assert_eq!(detect(b"#!/bin/sh\n"), Some(ContentType::Script));
+        assert_eq!(detect(b"PK\x03\x04"), Some(ContentType::Archive));
+        assert_eq!(detect(&tar), Some(ContentType::Archive));
+        assert_eq!(detect(b"hello world"), None);
+
+        let rules = ContentRules {
+            reject: vec![ContentType::Executable],
+            max_archive_size: 1000,
+        };
+
+        // Un exécutable est refusé dès que son en-tête est complet.
+        let mut sniffer = ContentSniffer::new(rules.clone());
+        let mut program = b"\x7fELF".to_vec();
+        program.resize(300, 0);
+
+        let err = sniffer.check(&program, 300).unwrap_err();
+        assert!(is_rejected(&err));
+
+        // Une archive passe tant qu'elle reste sous la limite.
+        let mut sniffer = ContentSniffer::new(rules.clone());
+        assert!(sniffer.check(&tar, 512).is_ok());
+        assert_eq!(
+            sniffer.check(&tar, 1024).unwrap_err().to_string(),
+            "archives larger than 1000 bytes are not accepted"
+        );
+
+        // Un fichier plus court que l'en-tête est vérifié à la fin.
+        let mut sniffer = ContentSniffer::new(rules);
+        assert!(sniffer.check(b"MZ", 2).is_ok());
+        assert!(sniffer.finish(2).is_err());
+    }
+}
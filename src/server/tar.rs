@@ -0,0 +1,387 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::PathBuf,
+    time::UNIX_EPOCH,
+};
+
+use super::path_resolver;
+
+const BLOCK_SIZE: usize = 512;
+
+/// Largest size that fits in the 11 octal digits of a header, after which the GNU base-256
+/// encoding is used.
+const MAX_OCTAL_SIZE: u64 = 0o77777777777;
+
+/// Entry of the directory tree still to be added to the archive.
+struct PendingEntry {
+    system_path: PathBuf,
+    name: String,
+}
+
+/// Tar archive of a directory, produced while it is read so that it never sits in memory or on
+/// the disk.
+///
+/// Entries with a name that cannot be sent to a client, leading outside of the root of the
+/// session, or managed by the server, are left out. Symbolic links to directories are left out
+/// too, to avoid loops.
+pub struct TarStream {
+    root: String,
+    pending: Vec<PendingEntry>,
+    buffer: Vec<u8>,
+    position: usize,
+    file: Option<File>,
+    // Octets du fichier en cours encore dus d'après son en-tête.
+    remaining: u64,
+    padding: usize,
+    finished: bool,
+}
+
+impl TarStream {
+    /// Archive the directory `system_path`, whose entries are named after `name`.
+    ///
+    /// `root` is the directory of the system the session is restricted to.
+    pub fn new(root: &str, system_path: PathBuf, name: &str) -> TarStream {
+        TarStream {
+            root: root.to_string(),
+            pending: vec![PendingEntry {
+                system_path,
+                name: format!("{}/", name.trim_matches('/')),
+            }],
+            buffer: Vec::new(),
+            position: 0,
+            file: None,
+            remaining: 0,
+            padding: 0,
+            finished: false,
+        }
+    }
+
+    /// Put the header of the next entry in the buffer.
+    ///
+    /// # Return
+    /// `false` once every entry has been added.
+    fn next_entry(&mut self) -> bool {
+        while let Some(entry) = self.pending.pop() {
+            let metadata = match fs::metadata(&entry.system_path) {
+                Ok(m) => m,
+                Err(err) => {
+                    eprintln!("Cannot archive {}: {err}.", entry.name);
+                    continue;
+                }
+            };
+
+            let mtime = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0);
+
+            if metadata.is_dir() {
+                self.push_children(&entry);
+                self.buffer = header(&entry.name, 0, mtime, b'5');
+            } else {
+                let file = match File::open(&entry.system_path) {
+                    Ok(f) => f,
+                    Err(err) => {
+                        eprintln!("Cannot archive {}: {err}.", entry.name);
+                        continue;
+                    }
+                };
+
+                self.buffer = header(&entry.name, metadata.len(), mtime, b'0');
+                self.file = Some(file);
+                self.remaining = metadata.len();
+                self.padding = padding(metadata.len());
+            }
+
+            self.position = 0;
+
+            return true;
+        }
+
+        false
+    }
+
+    /// Add the entries of the directory `entry` to the ones still to archive, in the order of
+    /// their names.
+    fn push_children(&mut self, entry: &PendingEntry) {
+        let read_dir = match fs::read_dir(&entry.system_path) {
+            Ok(r) => r,
+            Err(err) => {
+                eprintln!("Cannot archive the content of {}: {err}.", entry.name);
+                return;
+            }
+        };
+
+        let mut children = Vec::new();
+
+        for child in read_dir.flatten() {
+            let file_name = child.file_name();
+
+            let file_name = match file_name.to_str() {
+                Some(n) if path_resolver::is_safe_name(n) => n.to_string(),
+                _ => {
+                    eprintln!(
+                        "Skipping {:?} in the archive of {}: unsupported name.",
+                        child.file_name(),
+                        entry.name
+                    );
+                    continue;
+                }
+            };
+
+            // Les empreintes et les marqueurs des envois interrompus restent au serveur.
+            if path_resolver::is_protected(&file_name) {
+                continue;
+            }
+
+            let system_path = child.path();
+
+            if !path_resolver::is_inside_root(&self.root, &system_path) {
+                continue;
+            }
+
+            // Suivre un lien vers un dossier pourrait faire tourner l'archive en boucle.
+            let is_link = child.file_type().is_ok_and(|t| t.is_symlink());
+            let is_dir = system_path.is_dir();
+
+            if is_link && is_dir {
+                continue;
+            }
+
+            if !is_dir && !system_path.is_file() {
+                continue;
+            }
+
+            let name = match is_dir {
+                true => format!("{}{file_name}/", entry.name),
+                false => format!("{}{file_name}", entry.name),
+            };
+
+            children.push(PendingEntry { system_path, name });
+        }
+
+        // La pile est dépilée par la fin, les noms sont donc triés à l'envers.
+        children.sort_by(|a, b| b.name.cmp(&a.name));
+
+        self.pending.extend(children);
+    }
+
+    /// Read the content of the current file, completed with zeros if it shrank since its header
+    /// was written.
+    fn read_file(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let wanted = buf
+            .len()
+            .min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+
+        let read = match self.file.as_mut() {
+            Some(file) => file.read(&mut buf[..wanted])?,
+            None => 0,
+        };
+
+        let read = match read {
+            0 => {
+                // Le fichier a rétréci : la taille annoncée doit tout de même être respectée.
+                self.file = None;
+                buf[..wanted].fill(0);
+                wanted
+            }
+            r => r,
+        };
+
+        self.remaining -= read as u64;
+
+        if self.remaining == 0 {
+            self.file = None;
+            self.buffer = vec![0; self.padding];
+            self.position = 0;
+        }
+
+        Ok(read)
+    }
+}
+
+impl Read for TarStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            if self.position < self.buffer.len() {
+                let count = buf.len().min(self.buffer.len() - self.position);
+                buf[..count].copy_from_slice(&self.buffer[self.position..self.position + count]);
+                self.position += count;
+
+                return Ok(count);
+            }
+
+            if self.remaining > 0 {
+                return self.read_file(buf);
+            }
+
+            if self.next_entry() {
+                continue;
+            }
+
+            if self.finished {
+                return Ok(0);
+            }
+
+            // L'archive se termine par deux blocs vides.
+            self.finished = true;
+            self.buffer = vec![0; 2 * BLOCK_SIZE];
+            self.position = 0;
+        }
+    }
+}
+
+/// Number of zeros that complete `size` bytes up to a whole block.
+fn padding(size: u64) -> usize {
+    (BLOCK_SIZE - (size % BLOCK_SIZE as u64) as usize) % BLOCK_SIZE
+}
+
+/// Build the header of an entry, preceded by a GNU long name entry if `name` does not fit.
+fn header(name: &str, size: u64, mtime: u64, kind: u8) -> Vec<u8> {
+    let mut blocks = Vec::new();
+
+    if name.len() > 100 {
+        let mut long_name = name.as_bytes().to_vec();
+        long_name.push(0);
+
+        blocks.extend(header_block(
+            "././@LongLink",
+            long_name.len() as u64,
+            0,
+            b'L',
+        ));
+        long_name.resize(long_name.len() + padding(long_name.len() as u64), 0);
+        blocks.extend(long_name);
+    }
+
+    blocks.extend(header_block(name, size, mtime, kind));
+
+    blocks
+}
+
+fn header_block(name: &str, size: u64, mtime: u64, kind: u8) -> [u8; BLOCK_SIZE] {
+    let mut block = [0u8; BLOCK_SIZE];
+
+    // Un nom trop long est tronqué ici, l'entrée précédente donne le nom complet.
+    let name = &name.as_bytes()[..name.len().min(100)];
+    block[..name.len()].copy_from_slice(name);
+
+    let mode = match kind {
+        b'5' => 0o755,
+        _ => 0o644,
+    };
+
+    write_octal(&mut block[100..108], mode);
+    write_octal(&mut block[108..116], 0);
+    write_octal(&mut block[116..124], 0);
+
+    if size > MAX_OCTAL_SIZE {
+        // Encodage base 256 de GNU tar : bit de poids fort à 1, puis la taille en big-endian.
+        block[124] = 0x80;
+        block[128..136].copy_from_slice(&size.to_be_bytes());
+    } else {
+        write_octal(&mut block[124..136], size);
+    }
+
+    write_octal(&mut block[136..148], mtime.min(MAX_OCTAL_SIZE));
+    block[156] = kind;
+    block[257..265].copy_from_slice(b"ustar  \0");
+
+    // La somme de contrôle est calculée avec son propre champ rempli d'espaces.
+    block[148..156].fill(b' ');
+    let checksum: u32 = block.iter().map(|&byte| byte as u32).sum();
+    block[148..155].copy_from_slice(format!("{checksum:06o}\0").as_bytes());
+
+    block
+}
+
+/// Write `value` in octal with leading zeros, followed by a NUL byte.
+fn write_octal(field: &mut [u8], value: u64) {
+    let length = field.len() - 1;
+    let digits = format!("{value:0length$o}");
+
+    field[..length].copy_from_slice(digits.as_bytes());
+    field[length] = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    /// Read the names and sizes of the entries of `archive`.
+    fn entries(archive: &[u8]) -> Vec<(String, u64)> {
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        let mut long_name = None;
+
+        while archive[offset..offset + BLOCK_SIZE].iter().any(|&b| b != 0) {
+            let block = &archive[offset..offset + BLOCK_SIZE];
+
+            let checksum: u32 = block[..148].iter().map(|&b| b as u32).sum::<u32>()
+                + 8 * b' ' as u32
+                + block[156..].iter().map(|&b| b as u32).sum::<u32>();
+            let stored = std::str::from_utf8(&block[148..154]).unwrap();
+            assert_eq!(u32::from_str_radix(stored, 8).unwrap(), checksum);
+
+            let size = std::str::from_utf8(&block[124..135]).unwrap();
+            let size = u64::from_str_radix(size, 8).unwrap();
+
+            let name = String::from_utf8(block[..100].to_vec()).unwrap();
+            let name = name.trim_end_matches('\0').to_string();
+
+            offset += BLOCK_SIZE;
+
+            if block[156] == b'L' {
+                let content = &archive[offset..offset + size as usize - 1];
+                long_name = Some(String::from_utf8(content.to_vec()).unwrap());
+            } else {
+                entries.push((long_name.take().unwrap_or(name), size));
+            }
+
+            offset += size as usize + padding(size);
+        }
+
+        assert_eq!(archive.len(), offset + 2 * BLOCK_SIZE);
+
+        entries
+    }
+
+    #[test]
+    fn archive_lists_the_tree() {
+        let directory = env::temp_dir().join(format!("ftp-paradise-tar-{}", process::id()));
+        let long_name = "x".repeat(120);
+
+        fs::create_dir_all(directory.join("data/nested")).unwrap();
+        fs::write(directory.join("data/b.txt"), b"hello").unwrap();
+        fs::write(directory.join("data/nested").join(&long_name), vec![7; 600]).unwrap();
+        fs::write(directory.join("data/bad\nname"), b"").unwrap();
+        fs::write(directory.join("data/.checksums"), b"").unwrap();
+        fs::write(directory.join("data/.ftp-paradise-partial.b.txt"), b"3\n").unwrap();
+
+        let mut archive = Vec::new();
+        TarStream::new(directory.to_str().unwrap(), directory.join("data"), "/data")
+            .read_to_end(&mut archive)
+            .unwrap();
+
+        assert_eq!(
+            entries(&archive),
+            vec![
+                ("data/".to_string(), 0),
+                ("data/b.txt".to_string(), 5),
+                ("data/nested/".to_string(), 0),
+                (format!("data/nested/{long_name}"), 600),
+            ]
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+//! Delay of the greeting of the new clients, to slow down the scanners that open a lot of
+//! connections.
+//!
+//! The connections wait in a single thread instead of each holding a thread of the pool, which
+//! would leave no thread to the real sessions during a scan.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Connection waiting for its greeting, with what to do once the delay is over.
+struct Delayed {
+    ready: Instant,
+    release: Box<dyn FnOnce() + Send + 'static>,
+}
+
+/// Thread holding the new connections for a fixed delay.
+pub struct Tarpit {
+    sender: mpsc::Sender<Delayed>,
+    delay: Duration,
+    capacity: usize,
+    // Connexions en attente, le thread ne les compte qu'une fois relâchées.
+    waiting: Arc<AtomicUsize>,
+}
+
+impl Tarpit {
+    /// Start the thread holding the connections for `delay`, at most `capacity` at a time.
+    pub fn start(delay: Duration, capacity: usize) -> Result<Tarpit, String> {
+        let (sender, receiver) = mpsc::channel::<Delayed>();
+        let waiting = Arc::new(AtomicUsize::new(0));
+        let released = Arc::clone(&waiting);
+
+        thread::Builder::new()
+            .name("tarpit".to_string())
+            .spawn(move || {
+                // Toutes les connexions ont le même délai, la file est donc rangée par échéance.
+                let mut queue: VecDeque<Delayed> = VecDeque::new();
+
+                loop {
+                    let received = match queue.front() {
+                        Some(first) => receiver
+                            .recv_timeout(first.ready.saturating_duration_since(Instant::now())),
+                        None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                    };
+
+                    match received {
+                        Ok(delayed) => queue.push_back(delayed),
+                        Err(RecvTimeoutError::Timeout) => (),
+                        // Le serveur est arrêté, les connexions en attente sont fermées.
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+
+                    let now = Instant::now();
+
+                    while queue.front().is_some_and(|first| first.ready <= now) {
+                        let delayed = queue.pop_front().unwrap();
+
+                        released.fetch_sub(1, Ordering::SeqCst);
+                        (delayed.release)();
+                    }
+                }
+            })
+            .map_err(|err| format!("cannot start tarpit: {err}"))?;
+
+        Ok(Tarpit {
+            sender,
+            delay,
+            capacity,
+            waiting,
+        })
+    }
+
+    /// Call `release` once the delay is over.
+    ///
+    /// # Return
+    /// `false`, without calling `release`, if `capacity` connections are already waiting.
+    pub fn hold(&self, release: impl FnOnce() + Send + 'static) -> bool {
+        if self.waiting.fetch_add(1, Ordering::SeqCst) >= self.capacity {
+            self.waiting.fetch_sub(1, Ordering::SeqCst);
+            return false;
+        }
+
+        let delayed = Delayed {
+            ready: Instant::now() + self.delay,
+            release: Box::new(release),
+        };
+
+        if self.sender.send(delayed).is_err() {
+            self.waiting.fetch_sub(1, Ordering::SeqCst);
+            return false;
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn connections_are_released_in_order_and_capped() {
+        let tarpit = Tarpit::start(Duration::from_millis(50), 2).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let start = Instant::now();
+
+        for id in 0..3 {
+            let sender = sender.clone();
+            let held = tarpit.hold(move || sender.send(id).unwrap());
+
+            assert_eq!(held, id < 2, "{id}");
+        }
+
+        assert_eq!(receiver.recv().unwrap(), 0);
+        assert_eq!(receiver.recv().unwrap(), 1);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        // Une place se libère dès qu'une connexion est relâchée.
+        let sender = sender.clone();
+        assert!(tarpit.hold(move || sender.send(2).unwrap()));
+        assert_eq!(receiver.recv().unwrap(), 2);
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use password_hash::rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// Prefix of the usernames given to temporary credentials, so they cannot shadow an account.
+pub const USERNAME_PREFIX: &str = "share-";
+
+/// Credentials minted by [`mint`], to be given to the person the directory is shared with.
+#[derive(Debug)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// Directory that a pair of temporary credentials gives access to.
+struct Grant {
+    password_hash: [u8; 32],
+    root: String,
+    expires_at: Instant,
+}
+
+/// Credentials that have not been used yet, shared by all the sessions.
+static GRANTS: LazyLock<Mutex<HashMap<String, Grant>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Create credentials that can log in once during `lifetime`, in a session whose root is the
+/// directory `root` of the system.
+pub fn mint(root: &str, lifetime: Duration) -> Credentials {
+    let username = format!("{USERNAME_PREFIX}{}", random_hex(8));
+    let password = random_hex(16);
+
+    let mut grants = GRANTS.lock().unwrap();
+
+    // Les accès jamais utilisés sont nettoyés ici, sinon ils resteraient en mémoire.
+    let now = Instant::now();
+    grants.retain(|_, grant| grant.expires_at > now);
+
+    grants.insert(
+        username.clone(),
+        Grant {
+            password_hash: hash(&password),
+            root: root.to_string(),
+            expires_at: now + lifetime,
+        },
+    );
+
+    Credentials { username, password }
+}
+
+/// Tell if `username` belongs to temporary credentials that can still be used.
+pub fn is_pending(username: &str) -> bool {
+    GRANTS
+        .lock()
+        .unwrap()
+        .get(username)
+        .is_some_and(|grant| grant.expires_at > Instant::now())
+}
+
+/// Use the temporary credentials `username` and `password`, which cannot log in again afterward.
+///
+/// # Return
+/// The directory of the system the session is restricted to, or `None` if the credentials are
+/// unknown, expired or wrong.
+pub fn redeem(username: &str, password: &str) -> Option<String> {
+    let mut grants = GRANTS.lock().unwrap();
+
+    let grant = grants.get(username)?;
+
+    if grant.expires_at <= Instant::now() {
+        grants.remove(username);
+
+        return None;
+    }
+
+    // Les empreintes ont une taille fixe, la comparaison ne dépend donc pas de la longueur du mot
+    // de passe envoyé.
+    let matches = grant
+        .password_hash
+        .iter()
+        .zip(hash(password))
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0;
+
+    if !matches {
+        return None;
+    }
+
+    grants.remove(username).map(|grant| grant.root)
+}
+
+fn hash(password: &str) -> [u8; 32] {
+    Sha256::digest(password.as_bytes()).into()
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut buffer = vec![0u8; bytes];
+    OsRng.fill_bytes(&mut buffer);
+
+    buffer.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn credentials_are_single_use() {
+        let credentials = mint("/srv/ftp/pub", Duration::from_secs(60));
+
+        assert!(credentials.username.starts_with(USERNAME_PREFIX));
+        assert!(is_pending(&credentials.username));
+
+        assert_eq!(redeem(&credentials.username, "wrong"), None);
+        assert_eq!(
+            redeem(&credentials.username, &credentials.password),
+            Some("/srv/ftp/pub".to_string())
+        );
+        assert_eq!(redeem(&credentials.username, &credentials.password), None);
+        assert!(!is_pending(&credentials.username));
+    }
+
+    #[test]
+    fn expired_credentials_are_refused() {
+        let credentials = mint("/srv/ftp/pub", Duration::ZERO);
+
+        assert!(!is_pending(&credentials.username));
+        assert_eq!(redeem(&credentials.username, &credentials.password), None);
+    }
+}
@@ -0,0 +1,149 @@
+//! What happened during a session, delivered as a single record to the session hook when the
+//! session ends.
+
+use std::time::Instant;
+
+use chrono::{Local, SecondsFormat};
+use serde::Serialize;
+
+use crate::server::hook;
+
+/// Events of one session, in the order they happened.
+#[derive(Debug, Serialize)]
+pub struct Timeline {
+    session_id: String,
+    peer: String,
+    events: Vec<TimedEvent>,
+}
+
+#[derive(Debug, Serialize)]
+struct TimedEvent {
+    /// RFC 3339 date, with milliseconds.
+    at: String,
+    #[serde(flatten)]
+    event: Event,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Connected,
+    Authenticated {
+        username: String,
+    },
+    Transfer {
+        command: String,
+        path: String,
+        bytes: u64,
+        duration_ms: u128,
+        outcome: TransferOutcome,
+    },
+    Disconnected {
+        /// Verb of the last command executed by the session.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        last_command: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferOutcome {
+    Completed,
+    /// Stopped by ABOR.
+    Aborted,
+    /// Stopped by an error of the data connection.
+    Failed,
+}
+
+impl Timeline {
+    /// Start the timeline of a session with its connection.
+    pub fn new(session_id: &str, peer: &str) -> Timeline {
+        let mut timeline = Timeline {
+            session_id: session_id.to_string(),
+            peer: peer.to_string(),
+            events: Vec::new(),
+        };
+
+        timeline.record(Event::Connected);
+
+        timeline
+    }
+
+    pub fn record(&mut self, event: Event) {
+        self.events.push(TimedEvent {
+            at: Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+            event,
+        });
+    }
+
+    /// Record a transfer started at `started`.
+    pub fn record_transfer(
+        &mut self,
+        command: &str,
+        path: &str,
+        bytes: u64,
+        started: Instant,
+        outcome: TransferOutcome,
+    ) {
+        self.record(Event::Transfer {
+            command: command.to_string(),
+            path: path.to_string(),
+            bytes,
+            duration_ms: started.elapsed().as_millis(),
+            outcome,
+        });
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Run `command` with the timeline as JSON on its standard input, without waiting for it.
+pub fn deliver(command: &str, timeline: &Timeline) {
+    hook::run("session hook", command, timeline.to_json());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn serializes_one_record() {
+        let mut timeline = Timeline::new("20240101000000-1", "127.0.0.1:4000");
+
+        timeline.record(Event::Authenticated {
+            username: "alice".to_string(),
+        });
+        timeline.record_transfer(
+            "RETR",
+            "/a.txt",
+            42,
+            Instant::now() - Duration::from_millis(5),
+            TransferOutcome::Completed,
+        );
+        timeline.record(Event::Disconnected {
+            last_command: Some("RETR".to_string()),
+        });
+
+        let json: serde_json::Value = serde_json::from_str(&timeline.to_json()).unwrap();
+
+        assert_eq!(json["session_id"], "20240101000000-1");
+        assert_eq!(json["peer"], "127.0.0.1:4000");
+
+        let events = json["events"].as_array().unwrap();
+
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0]["event"], "connected");
+        assert_eq!(events[1]["username"], "alice");
+        assert_eq!(events[2]["event"], "transfer");
+        assert_eq!(events[2]["outcome"], "completed");
+        assert_eq!(events[2]["bytes"], 42);
+        assert!(events[2]["duration_ms"].as_u64().unwrap() >= 5);
+        assert_eq!(events[3]["event"], "disconnected");
+        assert_eq!(events[3]["last_command"], "RETR");
+        assert!(events[3]["at"].is_string());
+    }
+}
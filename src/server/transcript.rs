@@ -0,0 +1,99 @@
+use std::{
+    borrow::Cow,
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+};
+
+use chrono::Local;
+
+/// Record of everything exchanged on the control connection of one session, kept so that a user
+/// reporting a client issue can attach the exact conversation.
+pub struct Transcript {
+    file: File,
+}
+
+impl Transcript {
+    /// Create the transcript of the session `session_id` in the directory `dir`.
+    pub fn create(dir: &str, session_id: &str) -> io::Result<Transcript> {
+        fs::create_dir_all(dir)?;
+
+        let file = File::create(Path::new(dir).join(format!("{session_id}.log")))?;
+
+        Ok(Transcript { file })
+    }
+
+    /// Record a request sent by the client.
+    pub fn record_request(&mut self, request: &str) {
+        self.record("C>", mask_password(request));
+    }
+
+    /// Record a reply sent by the server, which can span several lines.
+    pub fn record_reply(&mut self, reply: &str) {
+        for line in reply.lines() {
+            self.record("S>", &mask_reply_password(line));
+        }
+    }
+
+    /// Record the outcome of a transfer made on a data connection.
+    pub fn record_transfer(&mut self, summary: &str) {
+        self.record("--", summary);
+    }
+
+    fn record(&mut self, direction: &str, line: &str) {
+        // Une erreur d'écriture du transcript ne doit pas interrompre la session.
+        let _ = writeln!(
+            self.file,
+            "{} {direction} {line}",
+            Local::now().format("%Y-%m-%d %H:%M:%S%.3f")
+        );
+    }
+}
+
+/// Hide the password sent with PASS or SITE CHPASS, which must never be written in clear in a log.
+pub fn mask_password(request: &str) -> &str {
+    for (verb, masked) in [("PASS ", "PASS ****"), ("SITE CHPASS ", "SITE CHPASS ****")] {
+        if request
+            .get(..verb.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(verb))
+        {
+            return masked;
+        }
+    }
+
+    request
+}
+
+/// Hide the password given in a reply, such as the credentials created by SITE SHARE.
+pub fn mask_reply_password(reply: &str) -> Cow<'_, str> {
+    let (start, rest) = match reply.split_once(" password ") {
+        Some((start, rest)) if start.contains(" user ") => (start, rest),
+        _ => return Cow::Borrowed(reply),
+    };
+
+    // Le mot de passe s'arrête au premier espace.
+    let end = match rest.find(' ') {
+        Some(index) => &rest[index..],
+        None => "",
+    };
+
+    Cow::Owned(format!("{start} password ****{end}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passwords_are_masked() {
+        assert_eq!(mask_password("pass secret"), "PASS ****");
+        assert_eq!(
+            mask_reply_password("200 user share-1 password s3cr3t valid once for 2 hours"),
+            "200 user share-1 password **** valid once for 2 hours"
+        );
+        assert_eq!(
+            mask_reply_password("331 password required"),
+            "331 password required"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Writing of the files received by STOR, buffered, and made durable before the transfer is
+//! reported as complete if the config asks for it.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use crate::{config::ContentRules, server::sniffing::ContentSniffer};
+
+/// File being uploaded.
+pub struct UploadWriter {
+    writer: BufWriter<File>,
+    fsync: bool,
+    max_size: u64,
+    written: u64,
+    sniffer: Option<ContentSniffer>,
+}
+
+impl UploadWriter {
+    /// Write to `file` by blocks of `buffer_size` bytes, and sync it to the disk when finished if
+    /// `fsync` is set.
+    pub fn new(file: File, buffer_size: usize, fsync: bool) -> UploadWriter {
+        UploadWriter {
+            writer: BufWriter::with_capacity(buffer_size, file),
+            fsync,
+            max_size: 0,
+            written: 0,
+            sniffer: None,
+        }
+    }
+
+    /// Fail with [`io::ErrorKind::FileTooLarge`] as soon as more than `max_size` bytes are
+    /// written, 0 meaning no limit.
+    pub fn with_max_size(mut self, max_size: u64) -> UploadWriter {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Refuse the content that `rules` reject, recognized by its first bytes.
+    pub fn with_content_rules(mut self, rules: &ContentRules) -> UploadWriter {
+        if !rules.reject.is_empty() || rules.max_archive_size != 0 {
+            self.sniffer = Some(ContentSniffer::new(rules.clone()));
+        }
+
+        self
+    }
+
+    /// Continue a file whose first `offset` bytes are already written, starting with `header`.
+    ///
+    /// The size limit and the content rules then apply to the whole file, not only to the bytes
+    /// received.
+    pub fn resuming(mut self, offset: u64, header: &[u8]) -> UploadWriter {
+        self.written = offset;
+
+        // Un type refusé déjà présent dans le fichier est refusé dès le premier octet reçu.
+        if let Some(sniffer) = &mut self.sniffer {
+            let _ = sniffer.check(header, offset);
+        }
+
+        self
+    }
+
+    /// Write what is left in the buffer, then wait for the file to be on the disk if needed.
+    ///
+    /// The upload must not be reported as complete if this fails.
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(sniffer) = &mut self.sniffer {
+            sniffer.finish(self.written)?;
+        }
+
+        self.writer.flush()?;
+
+        if self.fsync {
+            self.writer.get_ref().sync_all()?;
+        }
+
+        Ok(())
+    }
+
+    /// Write what was received to the disk for a file left incomplete, so that its size can be
+    /// trusted to resume the upload.
+    ///
+    /// # Return
+    /// The size of the file.
+    pub fn keep_partial(mut self) -> io::Result<u64> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+
+        Ok(self.written)
+    }
+}
+
+impl Write for UploadWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.max_size != 0 && self.written + buf.len() as u64 > self.max_size {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                format!("file larger than {} bytes", self.max_size),
+            ));
+        }
+
+        if let Some(sniffer) = &mut self.sniffer {
+            sniffer.check(buf, self.written + buf.len() as u64)?;
+        }
+
+        let written = self.writer.write(buf)?;
+        self.written += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process, time::Instant};
+
+    use super::*;
+
+    #[test]
+    fn both_modes_write_the_whole_file() {
+        let data: Vec<u8> = (0..1_000_000u32).map(|i| i as u8).collect();
+
+        for fsync in [true, false] {
+            let path =
+                env::temp_dir().join(format!("ftp-paradise-upload-{}-{fsync}", process::id()));
+
+            let started = Instant::now();
+
+            let mut writer = UploadWriter::new(File::create(&path).unwrap(), 65536, fsync);
+
+            for chunk in data.chunks(8192) {
+                writer.write_all(chunk).unwrap();
+            }
+
+            writer.finish().unwrap();
+
+            eprintln!(
+                "fsync_on_close = {fsync}: {} bytes in {} µs",
+                data.len(),
+                started.elapsed().as_micros()
+            );
+
+            assert_eq!(fs::read(&path).unwrap(), data);
+
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn resumed_upload_counts_the_existing_bytes() {
+        let path = env::temp_dir().join(format!("ftp-paradise-upload-resume-{}", process::id()));
+
+        let mut writer = UploadWriter::new(File::create(&path).unwrap(), 16, false)
+            .with_max_size(10)
+            .resuming(8, b"");
+
+        writer.write_all(b"01").unwrap();
+        assert!(writer.write_all(b"2").is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn max_size_stops_the_upload() {
+        let path = env::temp_dir().join(format!("ftp-paradise-upload-max-{}", process::id()));
+
+        let mut writer =
+            UploadWriter::new(File::create(&path).unwrap(), 16, false).with_max_size(10);
+
+        writer.write_all(b"0123456789").unwrap();
+
+        let err = writer.write_all(b"a").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::FileTooLarge);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
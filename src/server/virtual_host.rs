@@ -0,0 +1,73 @@
+use std::net::IpAddr;
+
+use crate::{config::VirtualHost, user_store::UserStore, VIRTUAL_HOSTS};
+
+/// Virtual host of the config, with its accounts loaded.
+pub struct VirtualHostContext {
+    settings: VirtualHost,
+    store: Option<UserStore>,
+}
+
+impl VirtualHostContext {
+    /// Load the users file of each virtual host.
+    pub fn load_all(hosts: &[VirtualHost]) -> Result<Vec<VirtualHostContext>, String> {
+        let mut contexts = Vec::with_capacity(hosts.len());
+
+        for host in hosts {
+            let store = match &host.users_file {
+                Some(path) => Some(UserStore::load(path)?),
+                None => None,
+            };
+
+            contexts.push(VirtualHostContext {
+                settings: host.clone(),
+                store,
+            });
+        }
+
+        Ok(contexts)
+    }
+
+    pub fn get_root(&self) -> &str {
+        &self.settings.root
+    }
+
+    pub fn get_banner(&self) -> Option<&str> {
+        self.settings.banner.as_deref()
+    }
+
+    /// Accounts of this host, `None` when it uses the users file of the config.
+    pub fn get_user_store(&self) -> Option<&UserStore> {
+        self.store.as_ref()
+    }
+}
+
+/// Tell if at least one virtual host is configured.
+pub fn is_enabled() -> bool {
+    VIRTUAL_HOSTS.get().is_some_and(|hosts| !hosts.is_empty())
+}
+
+/// Get the virtual host at `index`, as returned by the functions of this module.
+pub fn get(index: usize) -> Option<&'static VirtualHostContext> {
+    VIRTUAL_HOSTS.get()?.get(index)
+}
+
+/// Find the virtual host called `name` by the HOST command.
+pub fn find_by_name(name: &str) -> Option<usize> {
+    VIRTUAL_HOSTS.get()?.iter().position(|host| {
+        host.settings
+            .names
+            .iter()
+            .any(|n| n.eq_ignore_ascii_case(name))
+    })
+}
+
+/// Find the virtual host served by default on the local address `address`.
+pub fn find_by_address(address: IpAddr) -> Option<usize> {
+    let address = address.to_string();
+
+    VIRTUAL_HOSTS
+        .get()?
+        .iter()
+        .position(|host| host.settings.local_addresses.contains(&address))
+}
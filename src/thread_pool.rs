@@ -1,48 +1,261 @@
 use std::{
-    sync::{mpsc, Arc, Mutex},
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex, PoisonError,
+    },
     thread,
+    time::{Duration, Instant},
 };
 
 pub struct Worker {
     id: usize,
-    thread: Option<thread::JoinHandle<()>>,
+    // Partagé avec le thread pour qu'il puisse y placer son remplaçant.
+    thread: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
 }
 
-pub type Job = Box<dyn FnOnce() + Send + 'static>;
+/// Function executed by a worker, with a label describing it in the logs.
+pub struct Job {
+    label: String,
+    queued: Instant,
+    function: Box<dyn FnOnce() + Send + 'static>,
+}
+
+/// Time spent by the jobs in the queue and in the workers since the pool was built.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PoolMetrics {
+    pub jobs_started: u64,
+    pub jobs_finished: u64,
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+    pub total_duration: Duration,
+    pub max_duration: Duration,
+}
+
+/// Workers running a job and jobs waiting for one, at a given time.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PoolLoad {
+    pub workers: usize,
+    pub busy: usize,
+    pub queued: usize,
+}
+
+/// Counters of the load of a pool, readable from any thread.
+#[derive(Debug, Clone)]
+pub struct LoadCounters {
+    workers: usize,
+    busy: Arc<AtomicUsize>,
+    pending: Arc<AtomicUsize>,
+}
+
+/// Tracks how long a pool stays saturated, to warn once it lasts longer than a threshold.
+pub struct SaturationMonitor {
+    threshold: Duration,
+    since: Option<Instant>,
+    warned: bool,
+}
 
 pub struct ThreadPool {
     workers: Vec<Worker>,
     sender: Option<mpsc::Sender<Job>>,
+    metrics: Arc<Mutex<PoolMetrics>>,
+    // Jobs en attente ou en cours d'exécution.
+    pending: Arc<AtomicUsize>,
+    // Jobs en cours d'exécution.
+    busy: Arc<AtomicUsize>,
 }
 
 impl Worker {
-    pub fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
-        // Crée un thread infini qui exécute un job à chaque tour de boucle.
-        let thread = thread::spawn(move || loop {
-            let message = receiver.lock().unwrap().recv();
+    pub fn new(
+        id: usize,
+        receiver: Arc<Mutex<mpsc::Receiver<Job>>>,
+        metrics: Arc<Mutex<PoolMetrics>>,
+    ) -> Result<Worker, String> {
+        let thread = Arc::new(Mutex::new(None));
 
-            match message {
-                Ok(job) => {
-                    // TODO: Retirer ce print du code final.
-                    println!("Worker {id} got a job. Executing it...");
+        Worker::spawn(id, receiver, metrics, Arc::clone(&thread))?;
 
-                    job();
-                }
+        Ok(Worker { id, thread })
+    }
+
+    /// Start the thread of the worker `id` and store its handle in `slot`.
+    fn spawn(
+        id: usize,
+        receiver: Arc<Mutex<mpsc::Receiver<Job>>>,
+        metrics: Arc<Mutex<PoolMetrics>>,
+        slot: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
+    ) -> Result<(), String> {
+        let thread_slot = Arc::clone(&slot);
+
+        // Le verrou est pris avant de lancer le thread pour qu'un remplaçant ne puisse pas
+        // enregistrer son handle avant celui-ci.
+        let mut handle = slot.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // Le nom du thread apparaît dans les messages de panique et les outils de débogage.
+        let builder = thread::Builder::new().name(format!("worker-{id}"));
+
+        // Crée un thread infini qui exécute un job à chaque tour de boucle.
+        let thread = builder.spawn(move || loop {
+            let message = receiver
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .recv();
+
+            let job = match message {
+                Ok(job) => job,
                 Err(err) => {
                     eprintln!("Worker {id} disconnected: {err}. Shutting down...");
 
+                    break;
+                }
+            };
+
+            let wait = job.queued.elapsed();
+            metrics
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .record_start(wait);
+
+            println!(
+                "Worker {id} started {} after {} ms in queue.",
+                job.label,
+                wait.as_millis()
+            );
+
+            let started = Instant::now();
+
+            // Un job qui panique ne doit pas faire disparaître le worker, sinon la capacité du
+            // serveur diminuerait à chaque panique.
+            let result = panic::catch_unwind(AssertUnwindSafe(job.function));
+
+            let duration = started.elapsed();
+            metrics
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .record_finish(duration);
+
+            match result {
+                Ok(()) => println!(
+                    "Worker {id} finished {} in {} ms.",
+                    job.label,
+                    duration.as_millis()
+                ),
+                Err(payload) => {
+                    eprintln!(
+                        "Worker {id} panicked while running {}: {}. Respawning it...",
+                        job.label,
+                        panic_message(&payload)
+                    );
+
+                    // L'état du thread peut être incohérent après une panique, on repart d'un
+                    // thread neuf.
+                    if let Err(err) = Worker::spawn(id, receiver, metrics, thread_slot) {
+                        eprintln!("Cannot respawn worker {id}: {err}.");
+                    }
+
                     break;
                 }
             }
         });
 
-        Worker {
-            id,
-            thread: Some(thread),
+        match thread {
+            Ok(thread) => {
+                *handle = Some(thread);
+
+                Ok(())
+            }
+            Err(err) => Err(format!("cannot spawn worker {id}: {err}")),
         }
     }
 }
 
+impl PoolMetrics {
+    fn record_start(&mut self, wait: Duration) {
+        self.jobs_started += 1;
+        self.total_wait += wait;
+        self.max_wait = self.max_wait.max(wait);
+    }
+
+    fn record_finish(&mut self, duration: Duration) {
+        self.jobs_finished += 1;
+        self.total_duration += duration;
+        self.max_duration = self.max_duration.max(duration);
+    }
+}
+
+impl LoadCounters {
+    pub fn get(&self) -> PoolLoad {
+        let busy = self.busy.load(Ordering::SeqCst);
+
+        PoolLoad {
+            workers: self.workers,
+            busy,
+            // Un job compté en attente peut déjà avoir démarré entre les deux lectures.
+            queued: self.pending.load(Ordering::SeqCst).saturating_sub(busy),
+        }
+    }
+}
+
+impl SaturationMonitor {
+    pub fn new(threshold: Duration) -> SaturationMonitor {
+        SaturationMonitor {
+            threshold,
+            since: None,
+            warned: false,
+        }
+    }
+
+    /// Record the `load` observed at `now`, and give the warning to log when the saturation has
+    /// lasted longer than the threshold, or the notice to log once it has ended.
+    pub fn observe(&mut self, load: PoolLoad, now: Instant) -> Option<String> {
+        if load.busy < load.workers {
+            self.since = None;
+
+            return match std::mem::take(&mut self.warned) {
+                true => Some("Thread pool no longer saturated.".to_string()),
+                false => None,
+            };
+        }
+
+        let since = *self.since.get_or_insert(now);
+        let duration = now.duration_since(since);
+
+        if self.warned || duration < self.threshold {
+            return None;
+        }
+
+        self.warned = true;
+
+        Some(format!(
+            "Thread pool saturated for {} s: {} workers busy, {} sessions queued.",
+            duration.as_secs(),
+            load.busy,
+            load.queued
+        ))
+    }
+}
+
+/// Decrement a counter of jobs when dropped, even if the job panicked.
+struct CountGuard(Arc<AtomicUsize>);
+
+impl Drop for CountGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Get the message given to `panic!`, when there is one.
+fn panic_message(payload: &Box<dyn Any + Send>) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown cause"
+    }
+}
+
 impl ThreadPool {
     pub fn build(size: usize) -> Result<ThreadPool, String> {
         assert!(size > 0, "ThreadPool need at least a size of 1");
@@ -58,26 +271,72 @@ impl ThreadPool {
         // travers les threads pour rendre le programme thread-safe.
         let rx = Arc::new(Mutex::new(rx));
 
+        let metrics = Arc::new(Mutex::new(PoolMetrics::default()));
+
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&rx)));
+            workers.push(Worker::new(id, Arc::clone(&rx), Arc::clone(&metrics))?);
         }
 
         Ok(ThreadPool {
             workers,
             sender: Some(tx),
+            metrics,
+            pending: Arc::new(AtomicUsize::new(0)),
+            busy: Arc::new(AtomicUsize::new(0)),
         })
     }
 
     /// Execute a function or a closure in the next available thread in the pool.
     /// If no thread is available, it will be queued.
-    pub fn execute<F>(&self, f: F)
+    ///
+    /// The `label` describes the job in the logs, for example `session 172.16.0.4:54321`.
+    pub fn execute<F>(&self, label: &str, f: F)
     where
         F: FnOnce() + Send + 'static,
     {
-        let job = Box::new(f);
+        let pending = Arc::clone(&self.pending);
+        pending.fetch_add(1, Ordering::SeqCst);
+
+        let busy = Arc::clone(&self.busy);
+
+        let job = Job {
+            label: label.to_string(),
+            queued: Instant::now(),
+            function: Box::new(move || {
+                busy.fetch_add(1, Ordering::SeqCst);
+
+                // Décrémente même si le job panique.
+                let _busy = CountGuard(busy);
+                let _pending = CountGuard(pending);
+
+                f()
+            }),
+        };
 
         self.sender.as_ref().unwrap().send(job).unwrap();
     }
+
+    /// Tell if every worker is busy and at least `max_queued` jobs are already waiting.
+    pub fn is_saturated(&self, max_queued: usize) -> bool {
+        self.pending.load(Ordering::SeqCst) >= self.workers.len() + max_queued
+    }
+
+    pub fn get_metrics(&self) -> PoolMetrics {
+        *self.metrics.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn get_load(&self) -> PoolLoad {
+        self.get_load_counters().get()
+    }
+
+    /// Counters following the load of the pool, which can be read after it has been moved.
+    pub fn get_load_counters(&self) -> LoadCounters {
+        LoadCounters {
+            workers: self.workers.len(),
+            busy: Arc::clone(&self.busy),
+            pending: Arc::clone(&self.pending),
+        }
+    }
 }
 
 impl Drop for ThreadPool {
@@ -96,10 +355,162 @@ impl Drop for ThreadPool {
             // Une technique permettant de récupérer l'ownership d'une valeur qui appartient déjà à
             // un autre élément est de la mettre dans une 'Option' et de la récupérer grâce à la
             // méthode 'take'.
-            if let Some(thread) = worker.thread.take() {
-                // TODO: Gérer les erreurs générées par 'join'.
-                thread.join().unwrap();
+            // Un job en cours peut encore paniquer et remplacer le thread, d'où la boucle.
+            loop {
+                let thread = worker
+                    .thread
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .take();
+
+                match thread {
+                    Some(thread) => {
+                        if thread.join().is_err() {
+                            eprintln!("Worker {} stopped abnormally.", worker.id);
+                        }
+                    }
+                    None => break,
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panicking_job_does_not_shrink_the_pool() {
+        let pool = ThreadPool::build(1).unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        pool.execute("failing job", || panic!("job failure"));
+
+        for i in 0..3 {
+            let tx = tx.clone();
+            pool.execute("sending job", move || tx.send(i).unwrap());
+        }
+
+        for i in 0..3 {
+            assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(i));
+        }
+    }
+
+    #[test]
+    fn metrics_count_jobs() {
+        let pool = ThreadPool::build(2).unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        for _ in 0..4 {
+            let tx = tx.clone();
+            pool.execute("sleeping job", move || {
+                thread::sleep(Duration::from_millis(10));
+                tx.send(()).unwrap();
+            });
+        }
+
+        for _ in 0..4 {
+            rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+
+        // Le dernier job peut avoir envoyé son message sans être encore compté comme terminé.
+        thread::sleep(Duration::from_millis(50));
+
+        let metrics = pool.get_metrics();
+
+        assert_eq!(metrics.jobs_started, 4);
+        assert_eq!(metrics.jobs_finished, 4);
+        assert!(metrics.max_duration >= Duration::from_millis(10));
+        assert!(metrics.total_duration >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn saturation_counts_running_and_queued_jobs() {
+        let pool = ThreadPool::build(1).unwrap();
+        let (tx, rx) = mpsc::channel::<()>();
+        let rx = Arc::new(Mutex::new(rx));
+
+        assert!(!pool.is_saturated(0));
+
+        for _ in 0..2 {
+            let rx = Arc::clone(&rx);
+            pool.execute("blocked job", move || {
+                let _ = rx.lock().unwrap().recv();
+            });
+        }
+
+        assert!(pool.is_saturated(0));
+        assert!(pool.is_saturated(1));
+        assert!(!pool.is_saturated(2));
+
+        // Le premier job peut ne pas avoir encore été pris par le worker.
+        for _ in 0..100 {
+            if pool.get_load().busy == 1 {
+                break;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(
+            pool.get_load(),
+            PoolLoad {
+                workers: 1,
+                busy: 1,
+                queued: 1
+            }
+        );
+
+        tx.send(()).unwrap();
+        tx.send(()).unwrap();
+
+        for _ in 0..100 {
+            if !pool.is_saturated(0) {
+                return;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        panic!("jobs still pending");
+    }
+
+    #[test]
+    fn saturation_is_reported_once_it_lasts() {
+        let mut monitor = SaturationMonitor::new(Duration::from_secs(30));
+        let start = Instant::now();
+
+        let saturated = PoolLoad {
+            workers: 2,
+            busy: 2,
+            queued: 3,
+        };
+
+        assert_eq!(monitor.observe(saturated, start), None);
+        assert_eq!(
+            monitor.observe(saturated, start + Duration::from_secs(10)),
+            None
+        );
+        assert_eq!(
+            monitor.observe(saturated, start + Duration::from_secs(31)),
+            Some("Thread pool saturated for 31 s: 2 workers busy, 3 sessions queued.".to_string())
+        );
+        assert_eq!(
+            monitor.observe(saturated, start + Duration::from_secs(40)),
+            None
+        );
+
+        let idle = PoolLoad {
+            workers: 2,
+            busy: 1,
+            queued: 0,
+        };
+
+        assert_eq!(
+            monitor.observe(idle, start + Duration::from_secs(41)),
+            Some("Thread pool no longer saturated.".to_string())
+        );
+        assert_eq!(monitor.observe(idle, start + Duration::from_secs(42)), None);
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    sync::{PoisonError, RwLock},
+};
+
+use argon2::{Argon2, PasswordHasher, PasswordVerifier};
+use password_hash::{rand_core::OsRng, PasswordHash, SaltString};
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+/// Accounts allowed to log into the server, loaded from a users file.
+///
+/// Each line of the file has the form `username:hash`, where the hash is either an argon2id hash
+/// in the PHC format (`$argon2id$...`) or a bcrypt hash (`$2b$...`). Empty lines and lines
+/// starting with `#` are ignored. Plaintext passwords are refused.
+///
+/// With the `sqlite` feature, a path written `sqlite:path` selects a SQLite database holding the
+/// same hashes instead.
+pub struct UserStore {
+    backend: Backend,
+    // Hash vérifié quand l'utilisateur n'existe pas, pour que le temps de réponse ne révèle pas
+    // quels comptes existent.
+    dummy_hash: String,
+}
+
+enum Backend {
+    File {
+        path: String,
+        // Les mots de passe changés par SITE CHPASS remplacent ceux chargés au démarrage.
+        users: RwLock<HashMap<String, String>>,
+    },
+    #[cfg(feature = "sqlite")]
+    Sqlite(sqlite::SqliteUsers),
+}
+
+impl UserStore {
+    /// Load the users file located at `path`, or open the database it names.
+    pub fn load(path: &str) -> Result<UserStore, String> {
+        if let Some(database) = path.strip_prefix("sqlite:") {
+            return UserStore::open_database(database);
+        }
+
+        let content = match fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(err) => return Err(format!("cannot read users file {path}: {err}")),
+        };
+
+        let mut users = HashMap::new();
+
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (username, hash) = match line.split_once(':') {
+                Some(entry) => entry,
+                None => return Err(format!("line {}: expected username:hash", number + 1)),
+            };
+
+            if !is_supported_hash(hash) {
+                return Err(format!(
+                    "line {}: password of {username} is not an argon2id or bcrypt hash",
+                    number + 1
+                ));
+            }
+
+            users.insert(username.to_string(), hash.to_string());
+        }
+
+        Ok(UserStore {
+            backend: Backend::File {
+                path: path.to_string(),
+                users: RwLock::new(users),
+            },
+            dummy_hash: hash_password("dummy password")?,
+        })
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn open_database(path: &str) -> Result<UserStore, String> {
+        Ok(UserStore {
+            backend: Backend::Sqlite(sqlite::SqliteUsers::open(path)?),
+            dummy_hash: hash_password("dummy password")?,
+        })
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn open_database(path: &str) -> Result<UserStore, String> {
+        Err(format!(
+            "users database {path} needs a server built with the sqlite feature"
+        ))
+    }
+
+    /// Tell if `password` is the password of `username`.
+    pub fn verify(&self, username: &str, password: &str) -> bool {
+        match self.get_hash(username) {
+            Some(hash) => verify_password(password, &hash),
+            None => {
+                verify_password(password, &self.dummy_hash);
+
+                false
+            }
+        }
+    }
+
+    pub fn has_user(&self, username: &str) -> bool {
+        self.get_hash(username).is_some()
+    }
+
+    pub fn get_usernames(&self) -> Vec<String> {
+        match &self.backend {
+            Backend::File { users, .. } => {
+                let mut usernames: Vec<String> = users
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .keys()
+                    .cloned()
+                    .collect();
+                usernames.sort();
+
+                usernames
+            }
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(database) => database.get_usernames().unwrap_or_else(|err| {
+                eprintln!("{err}");
+
+                Vec::new()
+            }),
+        }
+    }
+
+    /// Replace the password of `username` by `password`, hashed with argon2id, in memory and in
+    /// the users file or database.
+    pub fn change_password(&self, username: &str, password: &str) -> Result<(), String> {
+        let hash = hash_password(password)?;
+
+        let (path, users) = match &self.backend {
+            Backend::File { path, users } => (path, users),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(database) => return database.set_hash(username, &hash),
+        };
+
+        // Le verrou est gardé pendant l'écriture pour que deux changements ne se mélangent pas
+        // dans le fichier.
+        let mut users = users.write().unwrap_or_else(PoisonError::into_inner);
+
+        if !users.contains_key(username) {
+            return Err(format!("unknown user {username}"));
+        }
+
+        let content = match fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(err) => return Err(format!("cannot read users file {path}: {err}")),
+        };
+
+        let content = replace_hash(&content, username, &hash);
+
+        if let Err(err) = write_atomically(Path::new(path), &content) {
+            return Err(format!("cannot write users file {path}: {err}"));
+        }
+
+        users.insert(username.to_string(), hash);
+
+        Ok(())
+    }
+
+    fn get_hash(&self, username: &str) -> Option<String> {
+        match &self.backend {
+            // Le hash est copié pour ne pas bloquer les changements de mot de passe pendant la
+            // vérification, qui est lente exprès.
+            Backend::File { users, .. } => users
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(username)
+                .cloned(),
+            // Une base illisible refuse tout le monde plutôt que d'arrêter la session.
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(database) => database.get_hash(username).unwrap_or_else(|err| {
+                eprintln!("{err}");
+
+                None
+            }),
+        }
+    }
+}
+
+/// Give `content`, a users file, with `hash` as the password of `username`, the other lines being
+/// kept as they are.
+fn replace_hash(content: &str, username: &str, hash: &str) -> String {
+    let mut replaced = String::with_capacity(content.len());
+
+    for line in content.lines() {
+        match line.trim().split_once(':') {
+            Some((user, _)) if user == username && !line.trim().starts_with('#') => {
+                replaced.push_str(&format!("{username}:{hash}"))
+            }
+            _ => replaced.push_str(line),
+        }
+
+        replaced.push('\n');
+    }
+
+    replaced
+}
+
+/// Replace the file at `path` by `content` through a temporary file, so that the file is never
+/// seen half written, even if the server stops in the middle.
+fn write_atomically(path: &Path, content: &str) -> std::io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+
+    let mut file = File::create(&temporary)?;
+
+    // Le fichier contient des hashs, il garde les droits de l'original.
+    file.set_permissions(fs::metadata(path)?.permissions())?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+
+    fs::rename(&temporary, path)
+}
+
+/// Hash `password` with argon2id and a random salt, in the format expected by the users file.
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    match Argon2::default().hash_password(password.as_bytes(), &salt) {
+        Ok(hash) => Ok(hash.to_string()),
+        Err(err) => Err(format!("cannot hash password: {err}")),
+    }
+}
+
+fn is_supported_hash(hash: &str) -> bool {
+    hash.starts_with("$argon2id$")
+        || hash.starts_with("$2a$")
+        || hash.starts_with("$2b$")
+        || hash.starts_with("$2y$")
+}
+
+/// Both libraries compare the hashes in constant time.
+fn verify_password(password: &str, hash: &str) -> bool {
+    if hash.starts_with("$argon2id$") {
+        match PasswordHash::new(hash) {
+            Ok(parsed) => Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok(),
+            Err(_) => false,
+        }
+    } else {
+        bcrypt::verify(password, hash).unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn changed_password_is_saved() {
+        let path = env::temp_dir().join(format!("ftp-paradise-users-{}", process::id()));
+        let bob = hash_password("bob").unwrap();
+
+        fs::write(
+            &path,
+            format!(
+                "# comptes\nalice:{}\nbob:{bob}\n",
+                hash_password("old").unwrap()
+            ),
+        )
+        .unwrap();
+
+        let store = UserStore::load(path.to_str().unwrap()).unwrap();
+        store.change_password("alice", "new").unwrap();
+
+        assert!(store.verify("alice", "new"));
+        assert!(!store.verify("alice", "old"));
+        assert!(store.change_password("carol", "new").is_err());
+
+        // Le fichier rechargé garde les autres lignes telles quelles.
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# comptes\n"));
+        assert!(content.contains(&format!("bob:{bob}\n")));
+
+        let reloaded = UserStore::load(path.to_str().unwrap()).unwrap();
+        assert!(reloaded.verify("alice", "new"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn bcrypt_hashes_are_verified() {
+        let path = env::temp_dir().join(format!("ftp-paradise-users-bcrypt-{}", process::id()));
+        let hash = bcrypt::hash("secret", 4).unwrap();
+
+        fs::write(&path, format!("carol:{hash}\n")).unwrap();
+
+        let store = UserStore::load(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        let store = store.unwrap();
+
+        assert!(store.verify("carol", "secret"));
+        assert!(!store.verify("carol", "Secret"));
+    }
+
+    #[test]
+    fn plaintext_and_unsupported_hashes_are_refused() {
+        let path = env::temp_dir().join(format!("ftp-paradise-users-refused-{}", process::id()));
+
+        // Mot de passe en clair, argon2i, puis MD5 crypt.
+        for hash in [
+            "secret",
+            "$argon2i$v=19$m=16,t=2,p=1$c2FsdHNhbHQ$aGFzaA",
+            "$1$salt$hash",
+        ] {
+            fs::write(&path, format!("# comptes\ndave:{hash}\n")).unwrap();
+
+            let error = UserStore::load(path.to_str().unwrap()).err().unwrap();
+            assert!(error.starts_with("line 2: password of dave"), "{error}");
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unknown_users_are_checked_against_the_dummy_hash() {
+        let path = env::temp_dir().join(format!("ftp-paradise-users-unknown-{}", process::id()));
+
+        fs::write(&path, "").unwrap();
+
+        let store = UserStore::load(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        let store = store.unwrap();
+
+        // Le hash vérifié à la place est un vrai hash argon2id, aussi lent que ceux des comptes.
+        assert!(store.dummy_hash.starts_with("$argon2id$"));
+        assert!(verify_password("dummy password", &store.dummy_hash));
+
+        // Il ne sert qu'à perdre du temps, pas à ouvrir une session.
+        assert!(!store.has_user("nobody"));
+        assert!(!store.verify("nobody", "dummy password"));
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    #[test]
+    fn database_needs_the_feature() {
+        assert!(UserStore::load("sqlite:users.db").is_err());
+    }
+}
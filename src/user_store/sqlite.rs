@@ -0,0 +1,214 @@
+//! Accounts stored in a SQLite database, for the deployments that prefer managing them with SQL.
+//!
+//! The hashes are the same as in a users file, so an account is added with:
+//!
+//! ```sql
+//! INSERT INTO users (username, hash) VALUES ('alice', '<output of ftp-paradise hash-password>');
+//! ```
+
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use rusqlite::{Connection, OptionalExtension};
+
+/// Changes of the schema, in order. The number of those already applied is kept in the
+/// `user_version` of the database, so a new version of the server only runs the new ones.
+const MIGRATIONS: [&str; 1] = ["CREATE TABLE users (
+        username TEXT PRIMARY KEY NOT NULL,
+        hash TEXT NOT NULL
+    );"];
+
+pub struct SqliteUsers {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteUsers {
+    /// Open the database at `path`, creating it and its tables if needed.
+    pub fn open(path: &str) -> Result<SqliteUsers, String> {
+        let mut connection = match Connection::open(path) {
+            Ok(c) => c,
+            Err(err) => return Err(format!("cannot open users database {path}: {err}")),
+        };
+
+        if let Err(err) = migrate(&mut connection) {
+            return Err(format!("cannot migrate users database {path}: {err}"));
+        }
+
+        let users = SqliteUsers {
+            connection: Mutex::new(connection),
+        };
+
+        // Les hashs sont vérifiés au démarrage comme ceux d'un fichier d'utilisateurs.
+        for (username, hash) in users.get_users()? {
+            if !super::is_supported_hash(&hash) {
+                return Err(format!(
+                    "password of {username} in {path} is not an argon2id or bcrypt hash"
+                ));
+            }
+        }
+
+        Ok(users)
+    }
+
+    pub fn get_hash(&self, username: &str) -> Result<Option<String>, String> {
+        let connection = self.lock();
+
+        let hash = connection
+            .query_row(
+                "SELECT hash FROM users WHERE username = ?1",
+                [username],
+                |row| row.get(0),
+            )
+            .optional();
+
+        hash.map_err(|err| format!("cannot read users database: {err}"))
+    }
+
+    pub fn get_usernames(&self) -> Result<Vec<String>, String> {
+        Ok(self
+            .get_users()?
+            .into_iter()
+            .map(|(username, _)| username)
+            .collect())
+    }
+
+    /// Replace the hash of `username`, which must exist.
+    pub fn set_hash(&self, username: &str, hash: &str) -> Result<(), String> {
+        let connection = self.lock();
+
+        match connection.execute(
+            "UPDATE users SET hash = ?1 WHERE username = ?2",
+            [hash, username],
+        ) {
+            Ok(0) => Err(format!("unknown user {username}")),
+            Ok(_) => Ok(()),
+            Err(err) => Err(format!("cannot write users database: {err}")),
+        }
+    }
+
+    fn get_users(&self) -> Result<Vec<(String, String)>, String> {
+        let connection = self.lock();
+
+        let users = connection
+            .prepare("SELECT username, hash FROM users ORDER BY username")
+            .and_then(|mut statement| {
+                statement
+                    .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect()
+            });
+
+        users.map_err(|err| format!("cannot read users database: {err}"))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Apply the migrations the database has not seen yet, each one in its own transaction.
+fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+    let version: u32 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let transaction = connection.transaction()?;
+
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index as u32 + 1)?;
+        transaction.commit()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        path::PathBuf,
+        process,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use super::*;
+
+    /// Path of a database that no other run can share, removed even if the test fails.
+    struct TempDatabase {
+        path: PathBuf,
+    }
+
+    impl TempDatabase {
+        fn new() -> TempDatabase {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0);
+
+            TempDatabase {
+                path: env::temp_dir().join(format!(
+                    "ftp-paradise-users-{}-{nanos}-{}.db",
+                    process::id(),
+                    COUNTER.fetch_add(1, Ordering::Relaxed)
+                )),
+            }
+        }
+
+        fn get_path(&self) -> &str {
+            self.path.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TempDatabase {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    #[test]
+    fn database_is_created_and_updated() {
+        let database = TempDatabase::new();
+        let path = database.get_path();
+
+        let users = SqliteUsers::open(path).unwrap();
+        assert!(users.get_usernames().unwrap().is_empty());
+
+        users
+            .lock()
+            .execute(
+                "INSERT INTO users (username, hash) VALUES ('alice', '$2b$old')",
+                [],
+            )
+            .unwrap();
+        drop(users);
+
+        // Une base déjà migrée s'ouvre sans rejouer les migrations.
+        let users = SqliteUsers::open(path).unwrap();
+        users.set_hash("alice", "$2b$new").unwrap();
+
+        assert_eq!(users.get_hash("alice").unwrap().as_deref(), Some("$2b$new"));
+        assert_eq!(users.get_hash("bob").unwrap(), None);
+        assert!(users.set_hash("bob", "$2b$new").is_err());
+    }
+
+    #[test]
+    fn unsupported_hashes_are_refused_on_open() {
+        let database = TempDatabase::new();
+        let path = database.get_path();
+
+        let users = SqliteUsers::open(path).unwrap();
+        users
+            .lock()
+            .execute(
+                "INSERT INTO users (username, hash) VALUES ('mallory', 'plain-text')",
+                [],
+            )
+            .unwrap();
+        drop(users);
+
+        let err = SqliteUsers::open(path).err().unwrap();
+        assert!(err.contains("mallory") && err.contains("not an argon2id or bcrypt hash"));
+    }
+}
@@ -0,0 +1,587 @@
+use std::{
+    env, fs,
+    io::Write,
+    net::{SocketAddrV4, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::OnceLock,
+    thread,
+    time::Duration,
+};
+
+use ftp_paradise::{
+    client::Client,
+    config::Config,
+    protocol::{host_port, reply_code::ReplyCode},
+    server::{extensions::Extensions, file_error},
+};
+
+/// Start a server on a free port of the loopback, serving `root`, and return its address.
+fn start_server(root: &str) -> String {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let mut config = Config::new("127.0.0.1".to_string(), port.to_string());
+    config.set_root(root.to_string());
+    config.set_drop_directories(vec!["/incoming".to_string()]);
+    config.set_admins(vec!["admin".to_string()]);
+
+    // Une commande ajoutée par un programme qui embarque le serveur, qui donne la taille d'un
+    // fichier.
+    let mut extensions = Extensions::new();
+    extensions
+        .add_site_command("INGEST", |context, args| {
+            let path = args.get_raw();
+            let system_path = context.get_system_path(path)?;
+
+            match fs::metadata(system_path) {
+                Ok(metadata) => Ok((
+                    ReplyCode::CommandOk,
+                    format!("{path}: {} bytes ingested", metadata.len()),
+                )),
+                Err(err) => Err(file_error::from_io(path, &err, "cannot ingest")),
+            }
+        })
+        .unwrap();
+    extensions.add_alias("MAKE", "MKD").unwrap();
+
+    thread::spawn(move || ftp_paradise::run_with_extensions(config, extensions));
+
+    let address = format!("127.0.0.1:{port}");
+
+    // Attend que le serveur accepte les connexions.
+    for _ in 0..100 {
+        if TcpStream::connect(&address).is_ok() {
+            break;
+        }
+
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    address
+}
+
+/// Address of the data connection given by the reply to PASV.
+fn pasv_address(message: &str) -> SocketAddrV4 {
+    host_port::decode_pasv_reply(message).unwrap()
+}
+
+/// Server shared by the tests, its configuration being global to the process.
+struct Server {
+    address: String,
+    base: PathBuf,
+    root: PathBuf,
+}
+
+/// Start the server on the first call.
+fn server() -> &'static Server {
+    static SERVER: OnceLock<Server> = OnceLock::new();
+
+    SERVER.get_or_init(|| {
+        let base = env::temp_dir().join(format!("ftp-paradise-loopback-{}", std::process::id()));
+        let root = base.join("root");
+
+        // Un reste d'une exécution précédente avec le même pid fausserait les tests.
+        let _ = fs::remove_dir_all(&base);
+        fs::create_dir_all(&root).unwrap();
+
+        let address = start_server(root.to_str().unwrap());
+
+        Server {
+            address,
+            base,
+            root,
+        }
+    })
+}
+
+/// Empty directory `/name` of the root, where one test puts its files.
+fn test_directory(name: &str) -> PathBuf {
+    let directory = server().root.join(name);
+
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+
+    directory
+}
+
+/// Client logged in on the shared server.
+fn login() -> Client {
+    let mut client = Client::connect(&server().address).unwrap();
+    client.login("tester", "").unwrap();
+
+    client
+}
+
+#[test]
+fn drop_directories_accept_files_without_showing_them() {
+    let root = &server().root;
+    let mut client = login();
+
+    // Un dossier de dépôt accepte les fichiers sans jamais les montrer ni les rendre.
+    fs::create_dir_all(root.join("incoming")).unwrap();
+    assert_eq!(
+        client
+            .stor("/incoming/report.txt", &mut &b"secret"[..])
+            .unwrap(),
+        6
+    );
+    assert_eq!(
+        fs::read(root.join("incoming/report.txt")).unwrap(),
+        b"secret"
+    );
+    assert!(client.list(Some("/incoming")).unwrap().is_empty());
+    assert!(client
+        .list(Some("/incoming/report.txt"))
+        .unwrap()
+        .is_empty());
+    assert!(client.mlsd("/incoming").unwrap().is_empty());
+    assert!(client
+        .retr("/incoming/report.txt", &mut Vec::new())
+        .is_err());
+    assert_eq!(client.command("SITE GETTAR /").unwrap().code, 550);
+    for command in ["MLST", "MDTM", "HASH", "DELE"] {
+        let reply = client
+            .command(&format!("{command} /incoming/report.txt"))
+            .unwrap();
+        assert_eq!(reply.code, 550, "{command}");
+    }
+    assert!(root.join("incoming/report.txt").exists());
+
+    // Ni déplacés, ni remplacés, ni complétés.
+    assert_eq!(
+        client.command("RNFR /incoming/report.txt").unwrap().code,
+        550
+    );
+    assert_eq!(client.command("RNFR /incoming").unwrap().code, 550);
+    let directory = test_directory("dropping");
+    fs::write(directory.join("forged.txt"), "forged").unwrap();
+    assert_eq!(
+        client.command("RNFR /dropping/forged.txt").unwrap().code,
+        350
+    );
+    assert_eq!(
+        client.command("RNTO /incoming/report.txt").unwrap().code,
+        550
+    );
+    assert!(client
+        .stor("/incoming/report.txt", &mut &b"forged"[..])
+        .is_err());
+    assert_eq!(client.command("REST 0").unwrap().code, 350);
+    assert!(client.stor("/incoming/new.txt", &mut &b"part"[..]).is_err());
+    assert!(client
+        .appe("/incoming/report.txt", &mut &b"forged"[..])
+        .is_err());
+    assert_eq!(
+        fs::read(root.join("incoming/report.txt")).unwrap(),
+        b"secret"
+    );
+    assert!(!root.join("incoming/new.txt").exists());
+    let _ = fs::remove_dir_all(&directory);
+
+    assert!(client
+        .list(None)
+        .unwrap()
+        .iter()
+        .any(|line| line.ends_with(" incoming")));
+
+    client.quit().unwrap();
+}
+
+#[test]
+fn paths_stay_under_the_root() {
+    let Server { base, root, .. } = server();
+    let directory = test_directory("traversal");
+    fs::create_dir_all(directory.join("sub")).unwrap();
+    fs::write(directory.join("file.txt"), "moved").unwrap();
+
+    let mut client = login();
+    client.cwd("/traversal/sub").unwrap();
+
+    // Les '..' en trop et les chemins absolus restent sous la racine de la session.
+    assert_eq!(
+        client.command("MKD ../../traversal-escape").unwrap().code,
+        257
+    );
+    assert_eq!(
+        client.command("MKD /../../../traversal-top").unwrap().code,
+        257
+    );
+    assert!(root.join("traversal-escape").is_dir() && root.join("traversal-top").is_dir());
+    assert!(!base.join("traversal-escape").exists() && !base.join("traversal-top").exists());
+    assert_eq!(client.command("MKD /etc/passwd").unwrap().code, 550);
+
+    assert_eq!(client.command("RNFR ../file.txt").unwrap().code, 350);
+    assert_eq!(
+        client
+            .command("RNTO ../../../traversal-moved.txt")
+            .unwrap()
+            .code,
+        250
+    );
+    assert!(root.join("traversal-moved.txt").is_file());
+    assert!(!base.join("traversal-moved.txt").exists());
+
+    assert_eq!(
+        client.command("RNFR /traversal-moved.txt").unwrap().code,
+        350
+    );
+    assert_eq!(client.command("RNTO /").unwrap().code, 553);
+    assert_eq!(client.command("RNTO /again.txt").unwrap().code, 503);
+
+    assert_eq!(client.command("RNFR /traversal/sub").unwrap().code, 350);
+    assert_eq!(
+        client.command("RNTO /traversal/sub/inner").unwrap().code,
+        553
+    );
+
+    // Un lien vers l'extérieur de la racine ne permet ni de lister ni de décrire ce qu'il vise.
+    #[cfg(unix)]
+    {
+        fs::write(base.join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(base, directory.join("outside")).unwrap();
+
+        assert_eq!(client.command("STAT /traversal/outside").unwrap().code, 550);
+        assert_eq!(client.command("PASV").unwrap().code, 227);
+        assert_eq!(client.command("NLST /traversal/outside").unwrap().code, 550);
+        assert!(client.list(Some("/traversal/outside/*.txt")).is_err());
+        assert!(client.list(Some("/traversal/out*")).is_err());
+    }
+
+    client.quit().unwrap();
+
+    let _ = fs::remove_dir_all(root.join("traversal-escape"));
+    let _ = fs::remove_dir_all(root.join("traversal-top"));
+    let _ = fs::remove_file(root.join("traversal-moved.txt"));
+    let _ = fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn rest_applies_to_the_next_transfer_only() {
+    let directory = test_directory("rest");
+    let mut client = login();
+
+    assert_eq!(client.stor("/rest/a.txt", &mut &b"hello"[..]).unwrap(), 5);
+
+    // Le décalage de REST ne sert qu'au transfert suivant, PASV pouvant être envoyé entre les deux.
+    assert_eq!(client.command("REST 2").unwrap().code, 350);
+    let mut content = Vec::new();
+    assert_eq!(client.retr("/rest/a.txt", &mut content).unwrap(), 3);
+    assert_eq!(content, b"llo");
+    let mut content = Vec::new();
+    assert_eq!(client.retr("/rest/a.txt", &mut content).unwrap(), 5);
+
+    assert_eq!(client.command("REST 3").unwrap().code, 350);
+    assert_eq!(client.stor("/rest/a.txt", &mut &b"p!"[..]).unwrap(), 2);
+    assert_eq!(
+        fs::read_to_string(directory.join("a.txt")).unwrap(),
+        "help!"
+    );
+    assert_eq!(client.appe("/rest/a.txt", &mut &b"!"[..]).unwrap(), 1);
+    assert_eq!(
+        fs::read_to_string(directory.join("a.txt")).unwrap(),
+        "help!!"
+    );
+
+    assert_eq!(client.command("REST 2").unwrap().code, 350);
+    assert_eq!(client.command("APPE /rest/a.txt").unwrap().code, 503);
+    assert_eq!(client.command("REST 10").unwrap().code, 350);
+    assert!(client.stor("/rest/a.txt", &mut &b"x"[..]).is_err());
+    assert_eq!(client.command("REST x").unwrap().code, 501);
+
+    client.quit().unwrap();
+
+    let _ = fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn host_and_rein_reset_the_session() {
+    let mut client = Client::connect(&server().address).unwrap();
+
+    // Sans hôte virtuel, HOST est accepté quel que soit le nom, mais seulement avant USER.
+    assert_eq!(client.command("HOST ftp.example.com").unwrap().code, 220);
+
+    client.login("tester", "").unwrap();
+
+    assert_eq!(client.command("HOST ftp.example.com").unwrap().code, 503);
+
+    // REIN déconnecte l'utilisateur et remet la session dans son état initial.
+    assert_eq!(client.command("TYPE I").unwrap().code, 200);
+    assert_eq!(client.command("REIN").unwrap().code, 220);
+    let status = client.command("STAT").unwrap().message;
+    assert!(status.contains("Logged in as nobody") && status.contains("TYPE: ASCII"));
+    assert_eq!(client.command("HOST ftp.example.com").unwrap().code, 220);
+    client.login("tester", "").unwrap();
+
+    client.quit().unwrap();
+}
+
+#[test]
+fn upload_list_and_download() {
+    let directory = test_directory("transfers");
+    fs::create_dir_all(directory.join("sub")).unwrap();
+
+    let mut client = login();
+    client.cwd("/transfers").unwrap();
+
+    assert_eq!(client.stor("a.txt", &mut &b"hello"[..]).unwrap(), 5);
+
+    let listing = client.list(None).unwrap();
+    assert!(listing.iter().any(|line| line.ends_with(" a.txt")));
+    assert!(listing
+        .iter()
+        .any(|line| line.starts_with('d') && line.ends_with(" sub")));
+
+    let mut content = Vec::new();
+    assert_eq!(client.retr("a.txt", &mut content).unwrap(), 5);
+    assert_eq!(content, b"hello");
+
+    assert!(client.retr("missing.txt", &mut Vec::new()).is_err());
+
+    // Une erreur sur le fichier est donnée à la place du 150, sans ouvrir la connexion de données.
+    assert_eq!(client.command("PASV").unwrap().code, 227);
+    assert_eq!(client.command("RETR missing.txt").unwrap().code, 550);
+    let stor = client.command("STOR /missing/a.txt").unwrap();
+    assert_eq!(stor.code, 553);
+    assert_eq!(stor.message, "/missing/a.txt: No such file or directory");
+    assert_eq!(client.command("RETR missing.txt").unwrap().code, 550);
+
+    // Un transfert sans PASV ni PORT est refusé, et le canal d'un transfert ne sert pas au suivant.
+    let mut content = Vec::new();
+    assert_eq!(client.retr("a.txt", &mut content).unwrap(), 5);
+    assert_eq!(client.command("RETR a.txt").unwrap().code, 425);
+    assert_eq!(client.command("LIST").unwrap().code, 425);
+    assert_eq!(client.command("MLSD missing").unwrap().code, 425);
+    assert_eq!(client.command("STOR b.txt").unwrap().code, 425);
+    assert!(!directory.join("b.txt").exists());
+
+    // Les chemins sont pris en entier, espaces compris.
+    fs::create_dir_all(directory.join("My Documents")).unwrap();
+    assert_eq!(
+        client
+            .stor("My Documents/to do.txt", &mut &b"spaces"[..])
+            .unwrap(),
+        6
+    );
+    client.cwd("My Documents").unwrap();
+
+    let mut content = Vec::new();
+    assert_eq!(client.retr("to do.txt", &mut content).unwrap(), 6);
+    assert_eq!(content, b"spaces");
+
+    client.quit().unwrap();
+
+    let _ = fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn commands_sent_during_an_upload_are_queued() {
+    let directory = test_directory("burst");
+    let mut client = login();
+
+    // Les commandes envoyées pendant un envoi attendent sa fin et sont traitées dans l'ordre, même
+    // si la dernière n'est pas encore complète.
+    let reply = client.command("PASV").unwrap();
+    let mut data = TcpStream::connect(pasv_address(&reply.message)).unwrap();
+    assert_eq!(client.command("STOR /burst/1.txt").unwrap().code, 150);
+    data.write_all(b"first").unwrap();
+    client.send("PASV\r\nSTOR /burst/2.txt\r\nTY").unwrap();
+    thread::sleep(Duration::from_millis(100));
+    drop(data);
+
+    assert_eq!(client.read_reply().unwrap().code, 226);
+    let reply = client.read_reply().unwrap();
+    assert_eq!(reply.code, 227);
+    let mut data = TcpStream::connect(pasv_address(&reply.message)).unwrap();
+    assert_eq!(client.read_reply().unwrap().code, 150);
+    data.write_all(b"second").unwrap();
+    drop(data);
+    assert_eq!(client.read_reply().unwrap().code, 226);
+
+    client.send("PE I\r\n").unwrap();
+    assert_eq!(client.read_reply().unwrap().code, 200);
+    assert_eq!(
+        fs::read_to_string(directory.join("1.txt")).unwrap(),
+        "first"
+    );
+    assert_eq!(
+        fs::read_to_string(directory.join("2.txt")).unwrap(),
+        "second"
+    );
+
+    client.quit().unwrap();
+
+    let _ = fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn mirror_downloads_a_tree() {
+    let directory = test_directory("mirrored");
+    let mirror = server().base.join("mirror");
+    fs::create_dir_all(directory.join("sub")).unwrap();
+    fs::write(directory.join("a.txt"), "hello").unwrap();
+    fs::write(directory.join("sub/b.txt"), "nested").unwrap();
+
+    let mut client = login();
+
+    let summary = client.mirror("/mirrored", &mirror).unwrap();
+    assert_eq!(summary.files, 2);
+    assert_eq!(summary.bytes, 11);
+    assert_eq!(
+        fs::read_to_string(mirror.join("sub/b.txt")).unwrap(),
+        "nested"
+    );
+
+    client.quit().unwrap();
+
+    let _ = fs::remove_dir_all(&directory);
+    let _ = fs::remove_dir_all(&mirror);
+}
+
+#[test]
+fn commands_of_the_embedder() {
+    let directory = test_directory("embedder");
+    fs::write(directory.join("b.txt"), "nested").unwrap();
+
+    let mut client = login();
+
+    // Les commandes ajoutées par le programme qui embarque le serveur.
+    let ingest = client.command("SITE INGEST /embedder/b.txt").unwrap();
+    assert_eq!(ingest.code, 200);
+    assert_eq!(ingest.message, "/embedder/b.txt: 6 bytes ingested");
+    assert_eq!(client.command("site ingest /missing").unwrap().code, 550);
+    assert_eq!(client.command("MAKE /embedder/made").unwrap().code, 257);
+    assert!(directory.join("made").is_dir());
+
+    client.quit().unwrap();
+
+    let _ = fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn replies_follow_the_rfcs() {
+    let directory = test_directory("replies");
+    fs::create_dir_all(directory.join("sub")).unwrap();
+    fs::write(directory.join("a.txt"), "hello").unwrap();
+
+    let mut client = login();
+    client.cwd("/replies").unwrap();
+
+    // Les commandes de la RFC 775 sont celles d'aujourd'hui sous un autre nom.
+    assert_eq!(client.command("XMKD legacy").unwrap().code, 257);
+    assert_eq!(client.command("XCWD legacy").unwrap().code, 250);
+    assert!(client.command("XPWD").unwrap().message.contains("/legacy"));
+    assert_eq!(client.command("XCUP").unwrap().code, 250);
+    assert_eq!(client.command("XRMD legacy").unwrap().code, 250);
+    assert!(!directory.join("legacy").exists());
+
+    // Les codes suivent la RFC 959 : 257 avec les guillemets doublés, 212 pour un dossier, et
+    // jamais 553 pour MKD.
+    let mkd = client.command("MKD /replies/say \"hi\"").unwrap();
+    assert_eq!(mkd.code, 257);
+    assert!(mkd.message.starts_with("\"/replies/say \"\"hi\"\"\""));
+    client.cwd("say \"hi\"").unwrap();
+    let pwd = client.command("PWD").unwrap();
+    assert_eq!(pwd.code, 257);
+    assert!(pwd.message.starts_with("\"/replies/say \"\"hi\"\"\""));
+    assert_eq!(client.command("CDUP").unwrap().code, 250);
+    assert_eq!(client.command("STAT sub").unwrap().code, 212);
+    assert_eq!(client.command("STAT a.txt").unwrap().code, 213);
+    assert_eq!(client.command("SITE VERSION").unwrap().code, 211);
+    assert_eq!(
+        client.command("SITE HASHONUPLOAD SHA256").unwrap().code,
+        200
+    );
+    client.stor("hashed.txt", &mut &b""[..]).unwrap();
+    assert_eq!(client.command("SITE HASHONUPLOAD MD5").unwrap().code, 504);
+    assert_eq!(client.command("SITE HASHONUPLOAD OFF").unwrap().code, 200);
+    assert_eq!(client.command("MKD /").unwrap().code, 550);
+    assert_eq!(client.command("CWD /missing").unwrap().code, 550);
+    assert_eq!(client.command("TYPE X").unwrap().code, 504);
+    assert_eq!(client.command("SYST").unwrap().code, 215);
+
+    client.quit().unwrap();
+
+    let _ = fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn files_of_the_server_are_out_of_reach() {
+    let directory = test_directory("checksums");
+    fs::create_dir_all(directory.join("sub")).unwrap();
+    fs::write(directory.join("sub/.checksums"), "").unwrap();
+
+    let mut client = login();
+    client.cwd("/checksums").unwrap();
+
+    // Le cache des empreintes est géré par le serveur, les clients ne peuvent ni l'écrire ni le
+    // supprimer ni le lire.
+    assert!(client.stor(".checksums", &mut &b"forged"[..]).is_err());
+    assert!(!directory.join(".checksums").exists());
+    assert_eq!(client.command("STOR sub/.checksums").unwrap().code, 550);
+    assert_eq!(client.command("RETR sub/.checksums").unwrap().code, 550);
+    assert_eq!(client.command("DELE sub/.checksums").unwrap().code, 550);
+    assert!(directory.join("sub/.checksums").exists());
+
+    // De même pour les marqueurs des envois interrompus, alors qu'un fichier de l'utilisateur qui
+    // finit par '.partial' reste le sien.
+    assert_eq!(
+        client
+            .command("STOR .ftp-paradise-partial.a.txt")
+            .unwrap()
+            .code,
+        550
+    );
+    assert_eq!(client.stor("a.txt.partial", &mut &b"5"[..]).unwrap(), 1);
+    assert_eq!(client.stor("a.txt", &mut &b"hello"[..]).unwrap(), 5);
+    assert!(directory.join("a.txt.partial").exists());
+
+    fs::write(directory.join(".ftp-paradise-partial.a.txt"), "3\n").unwrap();
+    let listing = client.list(None).unwrap();
+    assert!(listing.iter().any(|line| line.ends_with(" a.txt.partial")));
+    assert!(!listing
+        .iter()
+        .any(|line| line.contains(".ftp-paradise-partial") || line.contains(".checksums")));
+
+    client.quit().unwrap();
+
+    let _ = fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn shared_directories_are_read_only() {
+    let directory = test_directory("shared");
+    fs::write(directory.join("a.txt"), "hello").unwrap();
+
+    let mut client = login();
+
+    // Seuls les administrateurs partagent, et jamais un dossier qui montrerait des dépôts.
+    assert_eq!(client.command("SITE SHARE /shared").unwrap().code, 550);
+
+    let mut admin = Client::connect(&server().address).unwrap();
+    admin.login("admin", "").unwrap();
+    assert_eq!(admin.command("SITE SHARE /").unwrap().code, 550);
+    assert_eq!(admin.command("SITE SHARE /incoming").unwrap().code, 550);
+
+    let reply = admin.command("SITE SHARE /shared 1").unwrap();
+    assert_eq!(reply.code, 200);
+    let words: Vec<&str> = reply.message.split(' ').collect();
+    let (username, password) = (words[1], words[3]);
+
+    let mut guest = Client::connect(&server().address).unwrap();
+    guest.login(username, password).unwrap();
+
+    let mut content = Vec::new();
+    assert_eq!(guest.retr("/a.txt", &mut content).unwrap(), 5);
+    assert!(guest.stor("/b.txt", &mut &b"forged"[..]).is_err());
+    assert_eq!(guest.command("DELE /a.txt").unwrap().code, 550);
+    assert!(directory.join("a.txt").exists() && !directory.join("b.txt").exists());
+
+    guest.quit().unwrap();
+    admin.quit().unwrap();
+    client.quit().unwrap();
+
+    let _ = fs::remove_dir_all(&directory);
+}